@@ -1,6 +1,7 @@
 use bevy_ecs::prelude::*;
 use modula_core::{PreInit, ScheduleBuilder};
 use modula_utils::HashMap;
+use std::fmt::{self, Debug};
 use std::hash::Hash;
 use std::marker::PhantomData;
 
@@ -34,6 +35,18 @@ impl<T: Send + Sync + 'static> Clone for AssetId<T> {
 
 impl<T: Send + Sync + 'static> Copy for AssetId<T> {}
 
+impl<T: Send + Sync + 'static> Debug for AssetId<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AssetId").field(&self.0).finish()
+    }
+}
+
+impl<T: Send + Sync + 'static> Default for Assets<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T: Send + Sync + 'static> Assets<T> {
     pub fn new() -> Self {
         Self {
@@ -74,6 +87,20 @@ impl<T: Send + Sync + 'static> Assets<T> {
     pub fn remove(&mut self, asset_id: AssetId<T>) -> Option<T> {
         self.assets.remove(&asset_id.0)
     }
+
+    /// Iterates over all existing assets in arbitrary order
+    pub fn iter(&self) -> impl Iterator<Item = (AssetId<T>, &T)> {
+        self.assets
+            .iter()
+            .map(|(id, asset)| (AssetId(*id, PhantomData), asset))
+    }
+
+    /// Mutably iterates over all existing assets in arbitrary order
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (AssetId<T>, &mut T)> {
+        self.assets
+            .iter_mut()
+            .map(|(id, asset)| (AssetId(*id, PhantomData), asset))
+    }
 }
 
 #[derive(SystemSet, Hash, PartialEq, Eq, Debug, Clone, Copy)]
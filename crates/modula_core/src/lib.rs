@@ -1,8 +1,8 @@
 use bevy_ecs::prelude::*;
 use bevy_ecs::schedule::ScheduleLabel;
 use wgpu::{
-    Adapter, Backends, Device, DeviceDescriptor, Features, Instance, InstanceDescriptor, Limits,
-    PowerPreference, Queue, RequestAdapterOptions, Surface, SurfaceConfiguration, TextureUsages,
+    Adapter, Backends, Device, DeviceDescriptor, Instance, InstanceDescriptor, PowerPreference,
+    Queue, RequestAdapterOptions, Surface, SurfaceConfiguration, TextureUsages,
 };
 use winit::application::ApplicationHandler;
 use winit::event::{DeviceEvent, DeviceId, Event as WinitEvent, StartCause, WindowEvent};
@@ -16,11 +16,17 @@ pub struct ScheduleBuilder {
     world: World,
 }
 
+impl Default for ScheduleBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ScheduleBuilder {
     pub fn new() -> Self {
         let mut world = World::new();
         world.init_resource::<Schedules>();
-        return Self { world };
+        Self { world }
     }
 
     /// ## Warning
@@ -143,7 +149,7 @@ impl<
             window_attribs,
         }) = self.initializer_data.take()
         {
-            let init_res = initializer(power_preference.clone(), window_attribs, &event_loop);
+            let init_res = initializer(power_preference, window_attribs, event_loop);
             add_resources(&mut self.world, init_res);
             self.world.run_and_apply_deferred(Init);
         }
@@ -156,13 +162,7 @@ impl<
         window_id: WindowId,
         event: WindowEvent,
     ) {
-        self.register_event(
-            event_loop,
-            WinitEvent::WindowEvent {
-                window_id: window_id,
-                event,
-            },
-        );
+        self.register_event(event_loop, WinitEvent::WindowEvent { window_id, event });
     }
 
     // Provided methods
@@ -228,6 +228,23 @@ impl App {
     }
 }
 
+/// Creates a device without a window, for tests, benchmarks and offscreen rendering.
+/// Falls back to a software adapter if there is no hardware one, returns None if there is neither
+pub fn request_headless_device() -> Option<(Device, Queue)> {
+    let instance = Instance::new(InstanceDescriptor {
+        backends: Backends::all(),
+        ..Default::default()
+    });
+    let adapter = pollster::block_on(instance.request_adapter(&RequestAdapterOptions::default()))
+        .or_else(|| {
+        pollster::block_on(instance.request_adapter(&RequestAdapterOptions {
+            force_fallback_adapter: true,
+            ..Default::default()
+        }))
+    })?;
+    pollster::block_on(adapter.request_device(&DeviceDescriptor::default(), None)).ok()
+}
+
 fn add_resources(world: &mut World, init_res: GraphicsInitializerResult) {
     world.insert_resource(WindowRes(init_res.window));
     world.insert_resource(SurfaceRes(init_res.surface));
@@ -275,8 +292,7 @@ fn default_initializer(
             .formats
             .iter()
             .copied()
-            .filter(|f| f.is_srgb())
-            .next()
+            .find(|f| f.is_srgb())
             .expect("SRGB not supported, this is strange..."),
         width: size.width,
         height: size.height,
@@ -286,7 +302,7 @@ fn default_initializer(
         view_formats: vec![],
     };
     surface.configure(&device, &surface_config);
-    return GraphicsInitializerResult {
+    GraphicsInitializerResult {
         window,
         surface,
         surface_config,
@@ -294,5 +310,5 @@ fn default_initializer(
         adapter,
         device,
         queue,
-    };
+    }
 }
@@ -0,0 +1,108 @@
+//! Compares encoding 100 trivial passes serially and in parallel, run with `cargo bench -p modula_render`
+
+use std::time::{Duration, Instant};
+
+use bevy_ecs::prelude::*;
+use modula_asset::{AssetId, Assets};
+use modula_core::{DeviceRes, PreInit, QueueRes, ScheduleBuilder, WorldExt};
+use modula_render::{
+    EmptyPass, Operation, OperationBuilder, ParallelOperation, ReadOnlyCtx, RenderTarget,
+    RenderTargetConfig, Sequence, SequenceBuilder, SequenceQueue,
+};
+use wgpu::{CommandBuffer, Device, Instance, InstanceDescriptor, Maintain, RequestAdapterOptions};
+
+const PASSES: usize = 100;
+const FRAMES: u32 = 200;
+
+struct ParallelEmptyPass {
+    render_target: AssetId<RenderTarget>,
+}
+
+impl Operation for ParallelEmptyPass {
+    fn run(&mut self, _world: &mut World, _command_encoder: &mut wgpu::CommandEncoder) {}
+
+    fn as_parallel(&self) -> Option<&dyn ParallelOperation> {
+        Some(self)
+    }
+}
+
+impl ParallelOperation for ParallelEmptyPass {
+    fn encode_parallel(&self, ctx: &ReadOnlyCtx) -> CommandBuffer {
+        let mut command_encoder = ctx.create_command_encoder(Some("ParallelEmptyPass"));
+        ctx.begin_pass(self.render_target, &mut command_encoder);
+        command_encoder.finish()
+    }
+}
+
+impl OperationBuilder for ParallelEmptyPass {
+    fn reading(&self) -> Vec<AssetId<RenderTarget>> {
+        Vec::new()
+    }
+
+    fn writing(&self) -> Vec<AssetId<RenderTarget>> {
+        vec![self.render_target]
+    }
+
+    fn finish(self, _device: &Device) -> impl Operation + 'static {
+        self
+    }
+}
+
+fn main() {
+    let instance = Instance::new(InstanceDescriptor::default());
+    let Some(adapter) = pollster::block_on(instance.request_adapter(&RequestAdapterOptions {
+        force_fallback_adapter: false,
+        ..Default::default()
+    })) else {
+        eprintln!("no adapter found, skipping benchmark");
+        return;
+    };
+    let (device, queue) =
+        pollster::block_on(adapter.request_device(&Default::default(), None)).expect("no device");
+
+    let mut schedule_builder = ScheduleBuilder::new();
+    modula_render::init_render(&mut schedule_builder);
+    let mut world = schedule_builder.finish();
+    world.try_add_schedule(PreInit);
+    world.run_and_apply_deferred(PreInit);
+
+    let mut target = RenderTarget::new(RenderTargetConfig {
+        size: (256, 256),
+        ..Default::default()
+    });
+    target.apply(&device);
+    let render_target = world.resource_mut::<Assets<RenderTarget>>().add(target);
+    world.insert_resource(DeviceRes(device));
+    world.insert_resource(QueueRes(queue));
+
+    let mut serial = SequenceBuilder::new();
+    let mut parallel = SequenceBuilder::new();
+    for _ in 0..PASSES {
+        serial = serial.add(EmptyPass { render_target });
+        parallel = parallel.add(ParallelEmptyPass { render_target });
+    }
+    let mut sequences = world.resource_mut::<Assets<Sequence>>();
+    let serial = serial.finish(&mut sequences);
+    let parallel = parallel.finish(&mut sequences);
+
+    let serial_time = measure(&mut world, serial);
+    let parallel_time = measure(&mut world, parallel);
+    println!("{PASSES} passes, serial:   {:?} per frame", serial_time);
+    println!("{PASSES} passes, parallel: {:?} per frame", parallel_time);
+}
+
+fn measure(world: &mut World, sequence: AssetId<Sequence>) -> Duration {
+    // first run initializes the sequence
+    run_frame(world, sequence);
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        run_frame(world, sequence);
+    }
+    start.elapsed() / FRAMES
+}
+
+fn run_frame(world: &mut World, sequence: AssetId<Sequence>) {
+    world.resource_mut::<SequenceQueue>().schedule(sequence);
+    modula_render::run_sequences(world);
+    world.resource::<DeviceRes>().0.poll(Maintain::Wait);
+}
@@ -1,17 +1,21 @@
 use bevy_ecs::{prelude::*, schedule::ScheduleLabel};
 use modula_asset::{init_assets, AssetId, AssetWorldExt, Assets, InitAssetsSet};
 use modula_core::{
-    self, DeviceRes, EventOccurred, EventRes, PreInit, ScheduleBuilder, ShuoldExit,
+    self, DeviceRes, EventOccurred, EventRes, Init, PreInit, ScheduleBuilder, ShuoldExit,
     SurfaceConfigRes, SurfaceRes, WindowRes, WorldExt,
 };
 use wgpu::SurfaceError;
 use winit::event::{Event, WindowEvent};
+mod pipeline;
 mod render_target;
 mod sequence;
 pub mod shader;
+mod stats;
 
+pub use pipeline::*;
 pub use render_target::*;
 pub use sequence::*;
+pub use stats::*;
 
 /// Used to extract / sync data for drawing
 #[derive(ScheduleLabel, Clone, Hash, PartialEq, Eq, Debug)]
@@ -44,6 +48,7 @@ pub fn init_render(schedule_builder: &mut ScheduleBuilder) {
         })
         .after(InitAssetsSet),
     );
+    schedule_builder.add_systems(Init, match_surface_format);
     schedule_builder.add_systems(
         EventOccurred,
         (handle_redraw_event, handle_resized).in_set(RenderSystemSet),
@@ -75,7 +80,22 @@ fn handle_resized(
     }
     surface_config.width = size.width;
     surface_config.height = size.height;
-    surface.configure(device, &surface_config);
+    surface.configure(device, surface_config);
+}
+
+/// The surface target is made before the surface exists, so its color format is set to the format of the surface here,
+/// otherwise pipelines built for it would not match the surface texture
+fn match_surface_format(
+    surface_config: Res<SurfaceConfigRes>,
+    surface_target: Res<SurfaceTargetRes>,
+    mut render_target_assets: ResMut<Assets<RenderTarget>>,
+) {
+    let target = render_target_assets
+        .get_mut(surface_target.0)
+        .expect("no render target");
+    if let Some(color_config) = &mut target.scheduled_config_mut().color_config {
+        color_config.format = surface_config.0.format;
+    }
 }
 
 #[derive(Resource)]
@@ -92,6 +112,7 @@ fn handle_redraw_event(world: &mut World) {
         } => {}
         _ => return,
     }
+    stats::begin_frame_stats(world);
     world.run_and_apply_deferred(DrawSetup);
     // if ShouldDraw exists it is removed, if not return
     if world.remove_resource::<ShouldDraw>().is_none() {
@@ -0,0 +1,280 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use wgpu::{
+    BindGroupLayout, BlendState, ColorTargetState, ColorWrites, CompareFunction, DepthStencilState,
+    Device, FragmentState, MultisampleState, PipelineCompilationOptions, PipelineLayout,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPipeline, RenderPipelineDescriptor,
+    ShaderModule, StencilState, TextureFormat, VertexBufferLayout, VertexState,
+};
+
+use crate::RenderTarget;
+
+/// The state of a [RenderTarget] that a [RenderPipeline] drawing to it must match
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TargetFormats {
+    pub color: Option<TextureFormat>,
+    pub depth_stencil: Option<TextureFormat>,
+    pub sample_count: u32,
+}
+
+impl TargetFormats {
+    /// The formats of the current config of the render target
+    pub fn of(render_target: &RenderTarget) -> Self {
+        let config = render_target.current_config();
+        Self {
+            color: config.color_config.as_ref().map(|c| c.format),
+            depth_stencil: config.depth_stencil_config.as_ref().map(|c| c.format),
+            sample_count: render_target.sample_count(),
+        }
+    }
+}
+
+/// Builds a [RenderPipeline] for drawing to a [RenderTarget], where the color format, depth/stencil format and sample count are taken from the target
+pub struct RenderPipelineBuilder<'a> {
+    label: Option<&'a str>,
+    module: &'a ShaderModule,
+    vertex_entry: &'a str,
+    fragment_entry: &'a str,
+    vertex_buffers: Vec<VertexBufferLayout<'a>>,
+    bind_group_layouts: Vec<&'a BindGroupLayout>,
+    pipeline_layout: Option<&'a PipelineLayout>,
+    blend: Option<BlendState>,
+    primitive: PrimitiveState,
+    depth_write: bool,
+    depth_compare: CompareFunction,
+}
+
+impl<'a> RenderPipelineBuilder<'a> {
+    /// A builder using the entry points 'vs_main' and 'fs_main' of module, for example a bundled shader
+    pub fn new(module: &'a ShaderModule) -> Self {
+        Self {
+            label: None,
+            module,
+            vertex_entry: "vs_main",
+            fragment_entry: "fs_main",
+            vertex_buffers: Vec::new(),
+            bind_group_layouts: Vec::new(),
+            pipeline_layout: None,
+            blend: None,
+            primitive: PrimitiveState::default(),
+            depth_write: true,
+            depth_compare: CompareFunction::Less,
+        }
+    }
+
+    pub fn with_label(mut self, label: &'a str) -> Self {
+        self.label = Some(label);
+        self
+    }
+
+    pub fn with_entry_points(mut self, vertex: &'a str, fragment: &'a str) -> Self {
+        self.vertex_entry = vertex;
+        self.fragment_entry = fragment;
+        self
+    }
+
+    /// Adds a vertex buffer, the slot is the number of buffers added before
+    pub fn with_vertex_buffer(mut self, layout: VertexBufferLayout<'a>) -> Self {
+        self.vertex_buffers.push(layout);
+        self
+    }
+
+    /// Adds a bind group layout, the group is the number of layouts added before
+    pub fn with_bind_group_layout(mut self, layout: &'a BindGroupLayout) -> Self {
+        self.bind_group_layouts.push(layout);
+        self
+    }
+
+    /// Uses layout instead of making one from the added bind group layouts, for example to build variants of a pipeline sharing a layout
+    pub fn with_pipeline_layout(mut self, layout: &'a PipelineLayout) -> Self {
+        self.pipeline_layout = Some(layout);
+        self
+    }
+
+    /// The blend state of the color target, by default it is replaced
+    pub fn with_blend(mut self, blend: BlendState) -> Self {
+        self.blend = Some(blend);
+        self
+    }
+
+    pub fn with_primitive(mut self, primitive: PrimitiveState) -> Self {
+        self.primitive = primitive;
+        self
+    }
+
+    /// Only used if the target has a depth/stencil buffer, by default depth is written and compared with [Less](CompareFunction::Less)
+    pub fn with_depth(mut self, write: bool, compare: CompareFunction) -> Self {
+        self.depth_write = write;
+        self.depth_compare = compare;
+        self
+    }
+
+    /// Creates the pipeline for drawing to render_target, or other targets with the same [TargetFormats]
+    pub fn build(&self, device: &Device, render_target: &RenderTarget) -> TargetPipeline {
+        self.build_for_formats(device, TargetFormats::of(render_target))
+    }
+
+    /// Creates the pipeline for drawing to targets with formats
+    pub fn build_for_formats(&self, device: &Device, formats: TargetFormats) -> TargetPipeline {
+        let created_layout;
+        let layout = match self.pipeline_layout {
+            Some(layout) => layout,
+            None => {
+                created_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                    label: self.label,
+                    bind_group_layouts: &self.bind_group_layouts,
+                    push_constant_ranges: &[],
+                });
+                &created_layout
+            }
+        };
+        let targets = [formats.color.map(|format| ColorTargetState {
+            format,
+            blend: self.blend,
+            write_mask: ColorWrites::ALL,
+        })];
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: self.label,
+            layout: Some(layout),
+            vertex: VertexState {
+                module: self.module,
+                entry_point: self.vertex_entry,
+                compilation_options: PipelineCompilationOptions::default(),
+                buffers: &self.vertex_buffers,
+            },
+            primitive: self.primitive,
+            depth_stencil: formats.depth_stencil.map(|format| DepthStencilState {
+                format,
+                depth_write_enabled: self.depth_write,
+                depth_compare: self.depth_compare,
+                stencil: StencilState::default(),
+                bias: Default::default(),
+            }),
+            multisample: MultisampleState {
+                count: formats.sample_count,
+                ..Default::default()
+            },
+            fragment: Some(FragmentState {
+                module: self.module,
+                entry_point: self.fragment_entry,
+                compilation_options: PipelineCompilationOptions::default(),
+                // no color target if the render target has no color buffer
+                targets: if formats.color.is_some() {
+                    &targets
+                } else {
+                    &[]
+                },
+            }),
+            multiview: None,
+            cache: None,
+        });
+        TargetPipeline {
+            pipeline,
+            formats,
+            warned: AtomicBool::new(false),
+        }
+    }
+}
+
+/// A [RenderPipeline] made by [RenderPipelineBuilder], remembering the [TargetFormats] it was built for
+pub struct TargetPipeline {
+    pipeline: RenderPipeline,
+    formats: TargetFormats,
+    // so the warning is not printed every frame
+    warned: AtomicBool,
+}
+
+impl TargetPipeline {
+    /// The pipeline, without checking if it matches a target
+    pub fn pipeline(&self) -> &RenderPipeline {
+        &self.pipeline
+    }
+
+    pub fn formats(&self) -> TargetFormats {
+        self.formats
+    }
+
+    /// Whether the pipeline can draw to render_target, false if the target was recreated with different formats
+    pub fn is_compatible(&self, render_target: &RenderTarget) -> bool {
+        TargetFormats::of(render_target) == self.formats
+    }
+
+    /// The pipeline for drawing to render_target, warns once if the target no longer matches and the pipeline should be rebuilt
+    pub fn get(&self, render_target: &RenderTarget) -> &RenderPipeline {
+        if !self.is_compatible(render_target) && !self.warned.swap(true, Ordering::Relaxed) {
+            log::warn!(
+                "render target changed from {:?} to {:?}, pipeline {:?} must be rebuilt",
+                self.formats,
+                TargetFormats::of(render_target),
+                self.pipeline.global_id()
+            );
+        }
+        &self.pipeline
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use modula_core::request_headless_device;
+    use wgpu::{ErrorFilter, ShaderModuleDescriptor, ShaderSource};
+
+    use super::*;
+    use crate::{RenderTargetColorConfig, RenderTargetConfig, RenderTargetMultisampleConfig};
+
+    const SHADER: &str = "
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(f32(vertex & 1u), f32(vertex >> 1u), 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0);
+}
+";
+
+    /// Builds the shader for render_target, panics on validation errors
+    fn build(device: &Device, render_target: &RenderTarget) -> TargetPipeline {
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: None,
+            source: ShaderSource::Wgsl(SHADER.into()),
+        });
+        device.push_error_scope(ErrorFilter::Validation);
+        let pipeline = RenderPipelineBuilder::new(&module)
+            .with_blend(BlendState::ALPHA_BLENDING)
+            .build(device, render_target);
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            panic!("{error}");
+        }
+        pipeline
+    }
+
+    #[test]
+    fn pipeline_with_default_config() {
+        let (device, _queue) =
+            request_headless_device().expect("no adapter found, not even the fallback adapter");
+        let render_target = RenderTarget::new(RenderTargetConfig::default());
+        let pipeline = build(&device, &render_target);
+        assert_eq!(
+            pipeline.formats(),
+            TargetFormats {
+                color: Some(TextureFormat::Rgba8UnormSrgb),
+                depth_stencil: Some(TextureFormat::Depth24PlusStencil8),
+                sample_count: 1,
+            }
+        );
+        assert!(pipeline.is_compatible(&render_target));
+
+        let multisampled = RenderTarget::new(RenderTargetConfig {
+            color_config: Some(RenderTargetColorConfig {
+                multisample_config: Some(RenderTargetMultisampleConfig::default()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        assert!(!pipeline.is_compatible(&multisampled));
+        let pipeline = build(&device, &multisampled);
+        assert_eq!(pipeline.formats().sample_count, 4);
+        assert!(pipeline.is_compatible(&multisampled));
+    }
+}
@@ -1,3 +1,8 @@
+use std::{
+    hash::Hash,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
 use wgpu::{
     Color, CommandEncoder, Device, Extent3d, LoadOp, Operations, RenderPass,
     RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor, StoreOp,
@@ -75,6 +80,9 @@ pub struct RenderTargetConfig {
     pub depth_stencil_config: Option<RenderTargetDepthStencilConfig>,
     /// The color config of the texture, if None the texture will not have a color buffer
     pub color_config: Option<RenderTargetColorConfig>,
+    /// If true the contents of the render target are only needed between its first and last use in a [Sequence](super::Sequence),
+    /// this allows the textures to be shared with other transient targets, see [with_aliasing](super::SequenceBuilder::with_aliasing)
+    pub transient: bool,
 }
 
 impl Default for RenderTargetConfig {
@@ -83,6 +91,7 @@ impl Default for RenderTargetConfig {
             size: (1, 1),
             depth_stencil_config: Some(Default::default()),
             color_config: Some(Default::default()),
+            transient: false,
         }
     }
 }
@@ -98,6 +107,9 @@ pub struct RenderTarget {
     resolve_next: bool,
     clear_next: bool,
     clear_next_depth_stencil: bool,
+
+    // atomic because passes can be begun from parallel operations
+    pass_counts: PassCounts,
 }
 
 impl RenderTarget {
@@ -113,6 +125,7 @@ impl RenderTarget {
             resolve_next: false,
             clear_next: false,
             clear_next_depth_stencil: false,
+            pass_counts: PassCounts::default(),
         }
     }
 
@@ -120,7 +133,7 @@ impl RenderTarget {
     pub fn current_config(&self) -> &RenderTargetConfig {
         self.current_config
             .as_ref()
-            .or_else(|| self.scheduled_config.as_ref())
+            .or(self.scheduled_config.as_ref())
             .expect("No current config, this should not happen")
     }
 
@@ -148,6 +161,12 @@ impl RenderTarget {
             .unwrap_or(false)
     }
 
+    /// If the render target is transient, see [RenderTargetConfig::transient]
+    #[inline]
+    pub fn is_transient(&self) -> bool {
+        self.current_config().transient
+    }
+
     /// Mutable version of [scheduled_config](Self::scheduled_config), if there are no changes planned this will return a copy of the current config
     pub fn scheduled_config_mut(&mut self) -> &mut RenderTargetConfig {
         if self.scheduled_config.is_none() {
@@ -231,8 +250,8 @@ impl RenderTarget {
     #[inline]
     pub fn set_clear_color(&mut self, color: Color) {
         let config = self.scheduled_config_mut();
-        if config.color_config.is_some() {
-            config.color_config.as_mut().unwrap().clear_color = color;
+        if let Some(c) = &mut config.color_config {
+            c.clear_color = color;
         }
     }
 
@@ -240,8 +259,8 @@ impl RenderTarget {
     #[inline]
     pub fn set_clear_depth(&mut self, depth: f32) {
         let config = self.scheduled_config_mut();
-        if config.depth_stencil_config.is_some() {
-            config.depth_stencil_config.as_mut().unwrap().clear_depth = depth;
+        if let Some(c) = &mut config.depth_stencil_config {
+            c.clear_depth = depth;
         }
     }
 
@@ -249,8 +268,8 @@ impl RenderTarget {
     #[inline]
     pub fn set_clear_stencil(&mut self, stencil: u32) {
         let config = self.scheduled_config_mut();
-        if config.depth_stencil_config.is_some() {
-            config.depth_stencil_config.as_mut().unwrap().clear_stencil = stencil;
+        if let Some(c) = &mut config.depth_stencil_config {
+            c.clear_stencil = stencil;
         }
     }
 
@@ -275,10 +294,9 @@ impl RenderTarget {
 
     /// Begins a render pass, the pass will be resolving if [resolve_next](Self::resolve_next) was called after the last call to this method
     #[inline]
-    pub fn begin_pass<'a>(&'a mut self, command_encoder: &'a mut CommandEncoder) -> RenderPass {
-        let old = self.resolve_next;
-        self.resolve_next = false;
-        self.create_pass(command_encoder, old)
+    pub fn begin_pass<'a>(&'a mut self, command_encoder: &'a mut CommandEncoder) -> RenderPass<'a> {
+        let state = self.take_pass_state();
+        self.begin_pass_with_state(command_encoder, state)
     }
 
     /// Begins a render pass, the pass will be resolving
@@ -286,8 +304,12 @@ impl RenderTarget {
     pub fn begin_resolving_pass<'a>(
         &'a mut self,
         command_encoder: &'a mut CommandEncoder,
-    ) -> RenderPass {
-        self.create_pass(command_encoder, true)
+    ) -> RenderPass<'a> {
+        let state = PassState {
+            resolve: true,
+            ..self.take_clear_state()
+        };
+        self.begin_pass_with_state(command_encoder, state)
     }
 
     /// Begins a render pass, the pass will not be resolving, this should be used for every pass except for the last if a [Operation](super::Operation) needs multiple passes
@@ -295,8 +317,29 @@ impl RenderTarget {
     pub fn begin_non_resolving_pass<'a>(
         &'a mut self,
         command_encoder: &'a mut CommandEncoder,
-    ) -> RenderPass {
-        self.create_pass(command_encoder, false)
+    ) -> RenderPass<'a> {
+        let state = self.take_clear_state();
+        self.begin_pass_with_state(command_encoder, state)
+    }
+
+    /// Takes the scheduled clears and resolve, as if a pass was begun using [begin_pass](Self::begin_pass).  
+    /// Used together with [begin_pass_with_state](Self::begin_pass_with_state) when the RenderTarget can not be borrowed mutably while encoding
+    pub fn take_pass_state(&mut self) -> PassState {
+        let resolve = self.resolve_next;
+        self.resolve_next = false;
+        PassState {
+            resolve,
+            ..self.take_clear_state()
+        }
+    }
+
+    /// Begins a render pass using the given state, this does not change the scheduled clears and resolve
+    pub fn begin_pass_with_state<'a>(
+        &self,
+        command_encoder: &'a mut CommandEncoder,
+        state: PassState,
+    ) -> RenderPass<'a> {
+        self.create_pass(command_encoder, state)
     }
 
     /// Apply the changes to the RenderTarget, this will recreate the textures if needed
@@ -314,6 +357,68 @@ impl RenderTarget {
         self.apply_changes(device, changes);
     }
 
+    /// Returns (passes, resolving passes, clearing passes) since last call, used for [RenderFrameStats](crate::RenderFrameStats)
+    pub(crate) fn take_pass_counts(&self) -> (u32, u32, u32) {
+        (
+            self.pass_counts.passes.swap(0, Ordering::Relaxed),
+            self.pass_counts.resolving.swap(0, Ordering::Relaxed),
+            self.pass_counts.clears.swap(0, Ordering::Relaxed),
+        )
+    }
+
+    pub(crate) fn has_textures(&self) -> bool {
+        self.main_texture.is_some() || self.depth_stencil_texture.is_some()
+    }
+
+    /// Moves the textures out of the render target, used when aliasing
+    pub(crate) fn take_textures(&mut self) -> RenderTargetTextures {
+        RenderTargetTextures {
+            main_texture: self.main_texture.take(),
+            multisampled_texture: self.multisampled_texture.take(),
+            depth_stencil_texture: self.depth_stencil_texture.take(),
+        }
+    }
+
+    pub(crate) fn set_textures(&mut self, textures: RenderTargetTextures) {
+        self.main_texture = textures.main_texture;
+        self.multisampled_texture = textures.multisampled_texture;
+        self.depth_stencil_texture = textures.depth_stencil_texture;
+    }
+
+    /// Textures can only be shared between render targets where this is equal
+    pub(crate) fn alias_key(&self) -> impl Hash + Eq {
+        let config = self.current_config();
+        (
+            config.size,
+            config
+                .color_config
+                .as_ref()
+                .map(|c| (c.usages, c.format, self.sample_count())),
+            config
+                .depth_stencil_config
+                .as_ref()
+                .map(|c| (c.usages, c.format)),
+        )
+    }
+
+    /// Estimated size of the textures in bytes
+    pub(crate) fn texture_bytes(&self) -> u64 {
+        [
+            &self.main_texture,
+            &self.multisampled_texture,
+            &self.depth_stencil_texture,
+        ]
+        .into_iter()
+        .flatten()
+        .map(|t| {
+            let tex = t.texture();
+            // depth formats are not copyable, so no block size, 4 bytes is a decent guess
+            let bytes = tex.format().block_copy_size(None).unwrap_or(4) as u64;
+            bytes * tex.width() as u64 * tex.height() as u64 * tex.sample_count() as u64
+        })
+        .sum()
+    }
+
     pub(crate) fn present(&mut self) {
         match self
             .main_texture
@@ -424,15 +529,34 @@ impl RenderTarget {
         }
     }
 
-    fn create_pass<'a>(
-        &'a mut self,
-        command_encoder: &'a mut CommandEncoder,
-        resolve: bool,
-    ) -> RenderPass {
-        let clear = self.clear_next;
-        let clear_depth_stencil = self.clear_next_depth_stencil;
+    fn take_clear_state(&mut self) -> PassState {
+        let state = PassState {
+            clear_color: self.clear_next,
+            clear_depth_stencil: self.clear_next_depth_stencil,
+            resolve: false,
+        };
         self.clear_next = false;
         self.clear_next_depth_stencil = false;
+        state
+    }
+
+    fn create_pass<'a>(
+        &self,
+        command_encoder: &'a mut CommandEncoder,
+        state: PassState,
+    ) -> RenderPass<'a> {
+        let PassState {
+            clear_color: clear,
+            clear_depth_stencil,
+            resolve,
+        } = state;
+        self.pass_counts.passes.fetch_add(1, Ordering::Relaxed);
+        if resolve {
+            self.pass_counts.resolving.fetch_add(1, Ordering::Relaxed);
+        }
+        if clear || clear_depth_stencil {
+            self.pass_counts.clears.fetch_add(1, Ordering::Relaxed);
+        }
         command_encoder.begin_render_pass(&RenderPassDescriptor {
             label: None,
             color_attachments: &[self.main_texture.as_ref().map(|tex_with_view| {
@@ -504,7 +628,18 @@ fn different<T, R: PartialEq>(a: Option<T>, b: Option<T>, val: impl Fn(T) -> R)
     if a.is_none() && b.is_none() {
         return false;
     }
-    a.map(|a| val(a)) != b.map(|b| val(b))
+    a.map(&val) != b.map(val)
+}
+
+/// Which clears and resolve to apply when beginning a pass, see [RenderTarget::take_pass_state]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct PassState {
+    /// The color texture will be cleared
+    pub clear_color: bool,
+    /// The depth/stencil texture will be cleared
+    pub clear_depth_stencil: bool,
+    /// The pass will be resolving
+    pub resolve: bool,
 }
 
 struct RenderTargetChanges {
@@ -513,6 +648,19 @@ struct RenderTargetChanges {
     multisample_changed: bool,
 }
 
+#[derive(Default)]
+struct PassCounts {
+    passes: AtomicU32,
+    resolving: AtomicU32,
+    clears: AtomicU32,
+}
+
+pub(crate) struct RenderTargetTextures {
+    main_texture: Option<TextureWithView>,
+    multisampled_texture: Option<TextureWithView>,
+    depth_stencil_texture: Option<TextureWithView>,
+}
+
 enum InnerTexture {
     Normal(Texture),
     Surface(SurfaceTexture),
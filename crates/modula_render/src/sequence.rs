@@ -1,13 +1,16 @@
-use std::iter;
+use std::{cell::RefCell, mem};
 
 use bevy_ecs::prelude::*;
 use modula_asset::{init_assets, AssetId, Assets};
 use modula_core::{DeviceRes, PreInit, QueueRes, ScheduleBuilder};
-use modula_utils::HashSet;
-use wgpu::{CommandEncoder, CommandEncoderDescriptor, Device};
+use modula_utils::{HashMap, HashSet};
+use rayon::prelude::*;
+use wgpu::{CommandBuffer, CommandEncoder, CommandEncoderDescriptor, Device, RenderPass};
 
-use crate::RenderTarget;
+use crate::{OperationStats, PassState, RenderFrameStats, RenderTarget};
+mod alias;
 mod basic;
+pub use alias::AliasReport;
 pub use basic::*;
 
 pub trait OperationBuilder: Send + Sync + 'static {
@@ -21,40 +24,142 @@ pub trait OperationBuilder: Send + Sync + 'static {
 
 pub trait Operation: Send + Sync {
     fn run(&mut self, world: &mut World, command_encoder: &mut CommandEncoder);
+
+    /// Returns self for operations implementing [ParallelOperation], they will be encoded using [encode_parallel](ParallelOperation::encode_parallel) instead of [run](Self::run).  
+    /// Checked once when the [Sequence] is first run
+    fn as_parallel(&self) -> Option<&dyn ParallelOperation> {
+        None
+    }
+
+    /// Numbers from the last time the operation ran, added to [RenderFrameStats] after running
+    fn stats(&self) -> OperationStats {
+        OperationStats::default()
+    }
+}
+
+/// An operation that can be encoded without mutable access to the world, used if [as_parallel](Operation::as_parallel) returns self.  
+/// Consecutive parallel operations in a [Sequence] are encoded at the same time, and submitted in their original order
+pub trait ParallelOperation: Operation {
+    /// Encodes the operation into its own [CommandBuffer]
+    fn encode_parallel(&self, ctx: &ReadOnlyCtx) -> CommandBuffer;
+}
+
+/// Given to parallel operations when encoding, see [ParallelOperation::encode_parallel]
+pub struct ReadOnlyCtx<'a> {
+    world: &'a World,
+    // taken from the render targets before encoding, as they can not be mutated while encoding
+    pass_states: RefCell<HashMap<AssetId<RenderTarget>, PassState>>,
+}
+
+impl<'a> ReadOnlyCtx<'a> {
+    #[inline]
+    pub fn world(&self) -> &'a World {
+        self.world
+    }
+
+    #[inline]
+    pub fn device(&self) -> &'a Device {
+        &self.world.resource::<DeviceRes>().0
+    }
+
+    pub fn create_command_encoder(&self, label: Option<&str>) -> CommandEncoder {
+        self.device()
+            .create_command_encoder(&CommandEncoderDescriptor { label })
+    }
+
+    #[inline]
+    pub fn render_target(&self, render_target: AssetId<RenderTarget>) -> Option<&'a RenderTarget> {
+        self.world
+            .resource::<Assets<RenderTarget>>()
+            .get(render_target)
+    }
+
+    /// Begins a pass like [RenderTarget::begin_pass].  
+    /// Only the first pass on a target written by the operation will use the scheduled clears and resolve, following passes will load
+    pub fn begin_pass<'e>(
+        &self,
+        render_target: AssetId<RenderTarget>,
+        command_encoder: &'e mut CommandEncoder,
+    ) -> Option<RenderPass<'e>> {
+        let state = self
+            .pass_states
+            .borrow_mut()
+            .remove(&render_target)
+            .unwrap_or_default();
+        Some(
+            self.render_target(render_target)?
+                .begin_pass_with_state(command_encoder, state),
+        )
+    }
 }
 
 pub struct Sequence {
     // to not have Sequence publicly be a enum
     inner: InnerSequence,
+    aliasing: bool,
+    alias_report: Option<AliasReport>,
 }
 
 impl Sequence {
-    fn run(&mut self, command_encoder: &mut CommandEncoder, world: &mut World) {
+    /// What was aliased, None if aliasing is not enabled or the sequence has not run yet
+    #[inline]
+    pub fn alias_report(&self) -> Option<&AliasReport> {
+        self.alias_report.as_ref()
+    }
+
+    fn run(
+        &mut self,
+        command_buffers: &mut CommandBuffers,
+        world: &mut World,
+        stats: &mut RenderFrameStats,
+    ) {
         if let InnerSequence::UnInitialized(builders) = &mut self.inner {
+            let mut alias_plan = if self.aliasing {
+                alias::plan_aliases(builders, world)
+            } else {
+                Default::default()
+            };
             let device = &world.resource::<DeviceRes>().0;
             let mut operations = Vec::new();
             let mut needs_resolving = HashSet::<AssetId<RenderTarget>>::new();
-            for builder in builders {
+            for (idx, builder) in builders.iter_mut().enumerate() {
+                for (from, to) in alias_plan.moves.remove(&idx).unwrap_or_default() {
+                    // the contents of aliased targets are not kept past their last use
+                    needs_resolving.remove(&from);
+                    operations.push(SequenceOperation::Alias(from, to));
+                }
                 for reading in builder.reading() {
                     if needs_resolving.contains(&reading) {
                         needs_resolving.remove(&reading);
                         operations.push(SequenceOperation::ResolveNext(reading));
                     }
                 }
-                for writing in builder.writing() {
-                    needs_resolving.insert(writing);
+                let writing = builder.writing();
+                for writing in &writing {
+                    needs_resolving.insert(*writing);
                 }
-                operations.push(SequenceOperation::Run(builder.finish(device)));
+                let op = builder.finish(device);
+                operations.push(if op.as_parallel().is_some() {
+                    SequenceOperation::RunParallel(op, writing)
+                } else {
+                    SequenceOperation::Run(op)
+                });
+            }
+            for (from, to) in alias_plan.restore {
+                needs_resolving.remove(&from);
+                operations.push(SequenceOperation::Alias(from, to));
             }
             for resolve in needs_resolving {
                 operations.push(SequenceOperation::ResolveNext(resolve));
             }
+            self.alias_report = self.aliasing.then_some(alias_plan.report);
             self.inner = InnerSequence::Ready(operations);
         }
         // should always be true, not using match as this will run after the other if let
         if let InnerSequence::Ready(ops) = &mut self.inner {
-            for op in ops.iter_mut() {
-                match op {
+            let mut i = 0;
+            while i < ops.len() {
+                match &mut ops[i] {
                     SequenceOperation::ResolveNext(target) => {
                         let mut resource_mut = world.resource_mut::<Assets<RenderTarget>>();
                         resource_mut
@@ -62,26 +167,101 @@ impl Sequence {
                             .expect("target to resolve was not found")
                             .schedule_resolve();
                     }
+                    SequenceOperation::Alias(from, to) => {
+                        alias::move_textures(world, *from, *to);
+                    }
                     SequenceOperation::Run(op) => {
+                        let command_encoder =
+                            command_buffers.encoder(&world.resource::<DeviceRes>().0);
                         op.run(world, command_encoder);
+                        stats.operations += 1;
+                        stats.operation_stats += op.stats();
+                    }
+                    SequenceOperation::RunParallel(..) => {
+                        let count = ops[i..]
+                            .iter()
+                            .take_while(|op| matches!(op, SequenceOperation::RunParallel(..)))
+                            .count();
+                        command_buffers.extend(encode_parallel(&ops[i..i + count], world));
+                        for op in &ops[i..i + count] {
+                            if let SequenceOperation::RunParallel(op, _) = op {
+                                stats.operations += 1;
+                                stats.operation_stats += op.stats();
+                            }
+                        }
+                        i += count;
+                        continue;
                     }
                 }
+                i += 1;
             }
         }
     }
 }
 
+/// Encodes a group of parallel operations, returning the command buffers in the same order as the operations
+fn encode_parallel(ops: &[SequenceOperation], world: &mut World) -> Vec<CommandBuffer> {
+    // the scheduled state of a target is given to the first operation writing to it
+    let mut render_targets = world.resource_mut::<Assets<RenderTarget>>();
+    let pass_states: Vec<_> = ops
+        .iter()
+        .map(|op| {
+            let SequenceOperation::RunParallel(_, writing) = op else {
+                unreachable!("only parallel operations should be encoded in parallel")
+            };
+            writing
+                .iter()
+                .filter_map(|id| Some((*id, render_targets.get_mut(*id)?.take_pass_state())))
+                .collect::<HashMap<_, _>>()
+        })
+        .collect();
+    let world: &World = world;
+    ops.par_iter()
+        .zip(pass_states)
+        .map(|(op, pass_states)| {
+            let SequenceOperation::RunParallel(op, _) = op else {
+                unreachable!("only parallel operations should be encoded in parallel")
+            };
+            let op = op
+                .as_parallel()
+                .expect("as_parallel returned None after returning self");
+            op.encode_parallel(&ReadOnlyCtx {
+                world,
+                pass_states: RefCell::new(pass_states),
+            })
+        })
+        .collect()
+}
+
 pub struct SequenceBuilder {
     operation_builders: Vec<Box<dyn DynOperationBuilder>>,
+    aliasing: bool,
+}
+
+impl Default for SequenceBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SequenceBuilder {
     pub fn new() -> SequenceBuilder {
-        return SequenceBuilder {
+        SequenceBuilder {
             operation_builders: vec![],
-        };
+            aliasing: false,
+        }
     }
 
+    /// Lets [transient](crate::RenderTargetConfig::transient) render targets with the same size, formats and sample count share textures,
+    /// if their lifetimes in the sequence do not overlap.  
+    /// The lifetimes are computed from [reading](OperationBuilder::reading) and [writing](OperationBuilder::writing) when the sequence first runs,
+    /// so configs of aliased targets should not be changed afterwards, see [Sequence::alias_report] for the result
+    pub fn with_aliasing(mut self) -> Self {
+        self.aliasing = true;
+        self
+    }
+
+    #[allow(clippy::should_implement_trait)]
     pub fn add(mut self, operation_builder: impl OperationBuilder) -> Self {
         self.operation_builders
             .push(Box::new(DynOperationBuilderImpl(Some(Box::new(
@@ -91,15 +271,21 @@ impl SequenceBuilder {
     }
 
     pub fn finish(self, assets: &mut Assets<Sequence>) -> AssetId<Sequence> {
-        return assets.add(Sequence {
+        assets.add(Sequence {
             inner: InnerSequence::UnInitialized(self.operation_builders),
-        });
+            aliasing: self.aliasing,
+            alias_report: None,
+        })
     }
 }
 
 pub enum SequenceOperation {
     Run(Box<dyn Operation>),
+    /// An operation where [as_parallel](Operation::as_parallel) returns self, along with the targets it writes to
+    RunParallel(Box<dyn Operation>, Vec<AssetId<RenderTarget>>),
     ResolveNext(AssetId<RenderTarget>),
+    /// Moves the textures of the first render target to the second, used by aliasing
+    Alias(AssetId<RenderTarget>, AssetId<RenderTarget>),
 }
 
 #[derive(Resource)]
@@ -139,28 +325,60 @@ enum InnerSequence {
     UnInitialized(Vec<Box<dyn DynOperationBuilder>>),
 }
 
-pub(crate) fn run_sequences(world: &mut World) {
+/// Keeps command buffers in order when serial and parallel operations are mixed
+struct CommandBuffers {
+    encoder: Option<CommandEncoder>,
+    buffers: Vec<CommandBuffer>,
+}
+
+impl CommandBuffers {
+    /// The encoder used by serial operations, created if the previous one was finished
+    fn encoder(&mut self, device: &Device) -> &mut CommandEncoder {
+        self.encoder.get_or_insert_with(|| {
+            device.create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Sequence runner encoder"),
+            })
+        })
+    }
+
+    fn extend(&mut self, buffers: Vec<CommandBuffer>) {
+        self.finish_encoder();
+        self.buffers.extend(buffers);
+    }
+
+    fn finish_encoder(&mut self) {
+        if let Some(encoder) = self.encoder.take() {
+            self.buffers.push(encoder.finish());
+        }
+    }
+
+    fn finish(mut self) -> Vec<CommandBuffer> {
+        self.finish_encoder();
+        self.buffers
+    }
+}
+
+/// Runs all scheduled [Sequences](Sequence) and submits them, this is done automatically after [Draw](crate::Draw)
+pub fn run_sequences(world: &mut World) {
     world.resource_scope(|world, mut sequence_assets: Mut<Assets<Sequence>>| {
         world.resource_scope(|world, mut sequence_queue: Mut<SequenceQueue>| {
-            // FIXME maybe use multiple command encoders and run in parallel??
-            let mut command_encoder =
-                world
-                    .resource::<DeviceRes>()
-                    .0
-                    .create_command_encoder(&CommandEncoderDescriptor {
-                        label: Some("Sequence runner encoder"),
-                    });
-            for asset_id in &sequence_queue.0 {
+            let mut command_buffers = CommandBuffers {
+                encoder: None,
+                buffers: Vec::new(),
+            };
+            let mut stats = RenderFrameStats::default();
+            for asset_id in mem::take(&mut sequence_queue.0) {
                 sequence_assets
-                    .get_mut(*asset_id)
+                    .get_mut(asset_id)
                     .expect("sequence was added to queue, but does not exist")
-                    .run(&mut command_encoder, world)
+                    .run(&mut command_buffers, world, &mut stats);
+                stats.sequences += 1;
             }
-            sequence_queue.0.clear();
-            world
-                .resource::<QueueRes>()
-                .0
-                .submit(iter::once(command_encoder.finish()));
+            let command_buffers = command_buffers.finish();
+            stats.command_buffers = command_buffers.len() as u32;
+            stats.submits = 1;
+            world.resource::<QueueRes>().0.submit(command_buffers);
+            crate::stats::finish_frame_stats(world, stats);
         });
     });
 }
@@ -168,6 +386,163 @@ pub(crate) fn run_sequences(world: &mut World) {
 pub(crate) fn init_sequences(schedule_builder: &mut ScheduleBuilder) {
     schedule_builder.add_systems(PreInit, |mut commands: Commands| {
         commands.insert_resource(SequenceQueue(Vec::new()));
+        commands.init_resource::<RenderFrameStats>();
     });
     init_assets::<Sequence>(schedule_builder);
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use modula_core::request_headless_device;
+    use wgpu::{Id, Texture};
+
+    use super::*;
+    use crate::RenderTargetConfig;
+
+    /// (target, texture of the target when an operation used it), in the order the operations ran
+    type Accesses = Arc<Mutex<Vec<(AssetId<RenderTarget>, Option<Id<Texture>>)>>>;
+
+    /// Records the textures of the targets it reads and writes
+    struct Touch {
+        reading: Vec<AssetId<RenderTarget>>,
+        writing: Vec<AssetId<RenderTarget>>,
+        accesses: Accesses,
+    }
+
+    impl OperationBuilder for Touch {
+        fn reading(&self) -> Vec<AssetId<RenderTarget>> {
+            self.reading.clone()
+        }
+
+        fn writing(&self) -> Vec<AssetId<RenderTarget>> {
+            self.writing.clone()
+        }
+
+        fn finish(self, _device: &Device) -> impl Operation + 'static {
+            self
+        }
+    }
+
+    impl Operation for Touch {
+        fn run(&mut self, world: &mut World, _command_encoder: &mut CommandEncoder) {
+            let render_targets = world.resource::<Assets<RenderTarget>>();
+            let mut accesses = self.accesses.lock().unwrap();
+            for &target in self.reading.iter().chain(&self.writing) {
+                let texture = render_targets.get(target).unwrap().texture();
+                accesses.push((target, texture.map(Texture::global_id)));
+            }
+        }
+    }
+
+    fn texture_id(world: &World, target: AssetId<RenderTarget>) -> Option<Id<Texture>> {
+        let render_targets = world.resource::<Assets<RenderTarget>>();
+        render_targets
+            .get(target)
+            .unwrap()
+            .texture()
+            .map(Texture::global_id)
+    }
+
+    /// Every operation of a frame saw the textures of its targets, and no texture went back to a target it left
+    fn assert_contents_not_shared(accesses: &[(AssetId<RenderTarget>, Option<Id<Texture>>)]) {
+        assert!(!accesses.is_empty());
+        let mut last_user = HashMap::new();
+        let mut left = HashSet::new();
+        for &(target, texture) in accesses {
+            let texture = texture.expect("an operation ran on a target without textures");
+            if let Some(previous) = last_user.insert(texture, target) {
+                if previous != target {
+                    left.insert((texture, previous));
+                }
+            }
+            assert!(!left.contains(&(texture, target)));
+        }
+    }
+
+    #[test]
+    fn aliased_textures_are_moved_and_restored() {
+        let (device, _queue) =
+            request_headless_device().expect("no adapter found, not even the fallback adapter");
+        let mut world = World::new();
+        let mut render_targets = Assets::<RenderTarget>::new();
+        let mut target = |transient| {
+            let mut render_target = RenderTarget::new(RenderTargetConfig {
+                size: (4, 4),
+                transient,
+                ..Default::default()
+            });
+            render_target.apply(&device);
+            render_targets.add(render_target)
+        };
+        let (a, b, c) = (target(true), target(true), target(true));
+        let kept = target(false);
+        world.insert_resource(render_targets);
+        world.insert_resource(DeviceRes(device));
+        let originals = [a, kept].map(|target| texture_id(&world, target).unwrap());
+
+        // a, b and c are used one after another, c is written last and never read
+        let accesses = Accesses::default();
+        let uses = [
+            (vec![], vec![a]),
+            (vec![a], vec![]),
+            (vec![], vec![b]),
+            (vec![b], vec![]),
+            (vec![], vec![c, kept]),
+        ];
+        let mut builder = SequenceBuilder::new().with_aliasing();
+        for (reading, writing) in uses {
+            builder = builder.add(Touch {
+                reading,
+                writing,
+                accesses: accesses.clone(),
+            });
+        }
+        let mut sequences = Assets::new();
+        let sequence = builder.finish(&mut sequences);
+        let sequence = sequences.get_mut(sequence).unwrap();
+        // the second frame runs the operations planned in the first
+        for _ in 0..2 {
+            let mut command_buffers = CommandBuffers {
+                encoder: None,
+                buffers: Vec::new(),
+            };
+            sequence.run(
+                &mut command_buffers,
+                &mut world,
+                &mut RenderFrameStats::default(),
+            );
+            assert_contents_not_shared(&mem::take(&mut *accesses.lock().unwrap()));
+        }
+        let report = sequence.alias_report().unwrap();
+        assert_eq!(report.aliases.len(), 2);
+        // resolves are only scheduled on targets that have their textures
+        let InnerSequence::Ready(ops) = &sequence.inner else {
+            panic!("the sequence was not initialized");
+        };
+        let mut without_textures: HashSet<_> =
+            report.aliases.iter().map(|(aliased, _)| *aliased).collect();
+        let mut resolved = Vec::new();
+        for op in ops {
+            match op {
+                SequenceOperation::Alias(from, to) => {
+                    without_textures.insert(*from);
+                    without_textures.remove(to);
+                }
+                SequenceOperation::ResolveNext(target) => {
+                    assert!(!without_textures.contains(target));
+                    resolved.push(*target);
+                }
+                _ => {}
+            }
+        }
+        // a and b are resolved before they are read, c is not resolved after its textures are given back
+        assert_eq!(resolved, [a, b, kept]);
+        // the textures of b and c were freed, a gets its textures back after every frame
+        assert_eq!(texture_id(&world, a), Some(originals[0]));
+        assert_eq!(texture_id(&world, kept), Some(originals[1]));
+        assert_eq!(texture_id(&world, b), None);
+        assert_eq!(texture_id(&world, c), None);
+    }
+}
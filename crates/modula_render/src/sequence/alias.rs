@@ -0,0 +1,197 @@
+use std::hash::Hash;
+
+use bevy_ecs::world::World;
+use modula_asset::{AssetId, Assets};
+use modula_utils::HashMap;
+
+use crate::RenderTarget;
+
+use super::DynOperationBuilder;
+
+/// Result of aliasing transient render targets, see [SequenceBuilder::with_aliasing](super::SequenceBuilder::with_aliasing)
+#[derive(Clone, Default)]
+pub struct AliasReport {
+    /// Pairs of (aliased target, target owning the textures outside of the sequence)
+    pub aliases: Vec<(AssetId<RenderTarget>, AssetId<RenderTarget>)>,
+    /// Estimated texture memory saved in bytes
+    pub saved_bytes: u64,
+}
+
+/// (from, to) texture move between render targets
+type TextureMove = (AssetId<RenderTarget>, AssetId<RenderTarget>);
+
+#[derive(Default)]
+pub(crate) struct AliasPlan {
+    /// Texture moves to do before the operation at the index
+    pub moves: HashMap<usize, Vec<TextureMove>>,
+    /// Texture moves done at the end of the sequence, to give textures back to their owners
+    pub restore: Vec<TextureMove>,
+    pub report: AliasReport,
+}
+
+struct Slot {
+    owner: AssetId<RenderTarget>,
+    last_user: AssetId<RenderTarget>,
+    end: usize,
+}
+
+/// Finds transient targets with disjoint lifetimes and compatible textures, and frees the textures of the aliased targets
+pub(crate) fn plan_aliases(
+    builders: &[Box<dyn DynOperationBuilder>],
+    world: &mut World,
+) -> AliasPlan {
+    // lifetime of every used target as (first use, last use)
+    let mut lifetimes = HashMap::<AssetId<RenderTarget>, (usize, usize)>::new();
+    for (idx, builder) in builders.iter().enumerate() {
+        for target in builder.reading().into_iter().chain(builder.writing()) {
+            lifetimes
+                .entry(target)
+                .and_modify(|(_, end)| *end = idx)
+                .or_insert((idx, idx));
+        }
+    }
+
+    let mut render_targets = world.resource_mut::<Assets<RenderTarget>>();
+    let mut candidates = Vec::new();
+    for (target, lifetime) in lifetimes {
+        let Some(render_target) = render_targets.get(target) else {
+            continue;
+        };
+        if !render_target.is_transient()
+            || render_target.is_surface()
+            || !render_target.has_textures()
+        {
+            continue;
+        }
+        candidates.push((target, render_target.alias_key(), lifetime));
+    }
+
+    let mut plan = assign_slots(candidates);
+    for (aliased, _) in &plan.report.aliases {
+        let render_target = render_targets
+            .get_mut(*aliased)
+            .expect("aliased target was not found");
+        plan.report.saved_bytes += render_target.texture_bytes();
+        // textures are given by the previous user when needed
+        render_target.take_textures();
+    }
+    plan
+}
+
+/// Assigns targets given as (target, alias key, (first use, last use)) to slots, targets in the same slot share textures.
+/// Only targets with equal keys share slots, and only if their lifetimes are disjoint, the saved bytes are not counted
+fn assign_slots<K: Hash + Eq>(
+    targets: impl IntoIterator<Item = (AssetId<RenderTarget>, K, (usize, usize))>,
+) -> AliasPlan {
+    let mut groups = HashMap::<_, Vec<_>>::new();
+    for (target, key, lifetime) in targets {
+        groups.entry(key).or_default().push((target, lifetime));
+    }
+
+    let mut plan = AliasPlan::default();
+    for mut group in groups.into_values() {
+        group.sort_by_key(|(_, lifetime)| *lifetime);
+        let mut slots: Vec<Slot> = Vec::new();
+        for (target, (start, end)) in group {
+            // strictly less, as a target used by the same operation is still alive
+            match slots.iter_mut().find(|slot| slot.end < start) {
+                Some(slot) => {
+                    plan.moves
+                        .entry(start)
+                        .or_default()
+                        .push((slot.last_user, target));
+                    plan.report.aliases.push((target, slot.owner));
+                    slot.last_user = target;
+                    slot.end = end;
+                }
+                None => slots.push(Slot {
+                    owner: target,
+                    last_user: target,
+                    end,
+                }),
+            }
+        }
+        for slot in slots {
+            if slot.last_user != slot.owner {
+                plan.restore.push((slot.last_user, slot.owner));
+            }
+        }
+    }
+    plan
+}
+
+/// Moves the textures of one render target to another
+pub(crate) fn move_textures(
+    world: &mut World,
+    from: AssetId<RenderTarget>,
+    to: AssetId<RenderTarget>,
+) {
+    let mut render_targets = world.resource_mut::<Assets<RenderTarget>>();
+    let textures = render_targets
+        .get_mut(from)
+        .expect("aliased target was not found")
+        .take_textures();
+    render_targets
+        .get_mut(to)
+        .expect("aliased target was not found")
+        .set_textures(textures);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(count: usize) -> Vec<AssetId<RenderTarget>> {
+        let mut assets = Assets::<RenderTarget>::new();
+        (0..count).map(|_| assets.add_empty()).collect()
+    }
+
+    #[test]
+    fn overlapping_lifetimes_do_not_share() {
+        let t = targets(3);
+        // b starts in the operation a ends in, so both are alive there
+        let plan = assign_slots([(t[0], 0, (0, 2)), (t[1], 0, (1, 3)), (t[2], 0, (2, 4))]);
+        assert!(plan.report.aliases.is_empty());
+        assert!(plan.moves.is_empty());
+        assert!(plan.restore.is_empty());
+    }
+
+    #[test]
+    fn disjoint_lifetimes_share() {
+        let t = targets(3);
+        let plan = assign_slots([(t[2], 0, (4, 5)), (t[0], 0, (0, 1)), (t[1], 0, (2, 3))]);
+        assert_eq!(plan.report.aliases, [(t[1], t[0]), (t[2], t[0])]);
+        assert_eq!(plan.moves[&2], [(t[0], t[1])]);
+        assert_eq!(plan.moves[&4], [(t[1], t[2])]);
+        assert_eq!(plan.moves.len(), 2);
+        // the textures are given back to the owner at the end
+        assert_eq!(plan.restore, [(t[2], t[0])]);
+    }
+
+    #[test]
+    fn slots_are_reused_when_free() {
+        let t = targets(4);
+        // a and b overlap, c can take the slot of b and d the slot of a
+        let plan = assign_slots([
+            (t[0], 0, (0, 3)),
+            (t[1], 0, (1, 2)),
+            (t[2], 0, (3, 5)),
+            (t[3], 0, (4, 6)),
+        ]);
+        assert_eq!(plan.report.aliases, [(t[2], t[1]), (t[3], t[0])]);
+        for (aliased, owner) in &plan.report.aliases {
+            assert_ne!(aliased, owner);
+        }
+    }
+
+    #[test]
+    fn different_keys_do_not_share() {
+        let t = targets(3);
+        let plan = assign_slots([(t[0], 0, (0, 1)), (t[1], 1, (2, 3)), (t[2], 2, (4, 5))]);
+        assert!(plan.report.aliases.is_empty());
+        assert!(plan.moves.is_empty());
+
+        let plan = assign_slots([(t[0], 0, (0, 1)), (t[1], 1, (2, 3)), (t[2], 0, (4, 5))]);
+        assert_eq!(plan.report.aliases, [(t[2], t[0])]);
+    }
+}
@@ -1,49 +1,210 @@
-use std::{borrow::Cow, mem};
+use std::{
+    borrow::Cow,
+    fmt, mem,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
+use bevy_ecs::system::Resource;
 use modula_utils::{hashbrown::HashSet, HashMap};
 use wgpu::ShaderSource;
 
-/// A shader module source, the start of a shader module should be lines with '//use mod_name' for dependencies.  
+mod cache;
+mod compute;
+mod error;
+mod flags;
+#[cfg(feature = "hot_reload")]
+mod hot_reload;
+mod loading;
+mod options;
+mod permutations;
+#[cfg(feature = "naga")]
+mod reflect;
+mod source_map;
+mod symbols;
+#[cfg(feature = "naga")]
+mod validate;
+pub use cache::ShaderCacheStats;
+pub use compute::*;
+pub use error::*;
+pub use flags::*;
+#[cfg(feature = "hot_reload")]
+pub use hot_reload::*;
+pub use loading::*;
+pub use options::*;
+#[cfg(feature = "naga")]
+pub use reflect::*;
+pub use source_map::*;
+#[cfg(feature = "naga")]
+pub use validate::*;
+
+/// A shader module source, the start of a shader module should be lines with '//use mod_name' for dependencies, blank lines and comments are allowed between them.  
 /// Lines can be included or excluded based on flags.  
 /// A line containing '//if(condition)' where condition is either a flag name or '!condition', '(condition)&(condition)' or '(condition)|(condition)', where whitespace is not allowed will start a conditional section.  
 /// This section should be ended by '//endif', conditional segments can be nested, and //else blocks can be added.  
+/// Tokens like '#{NAME}' are replaced by the value of the define NAME when bundling.  
+/// A line containing '//include name' is replaced by the source of the library name, with flags applied, respecting the '//if' it is in.  
+/// Unlike '//use' an include is not deduplicated and is not ordered as a dependency, the '//use' lines of the included library are not followed either,
+/// so the including module must use those itself. Includes can be nested up to a depth of 32.  
+/// [Passthrough](ShaderSourceKind::Passthrough) sources are not wgsl, they can be stored but not bundled.
 pub struct ShaderModuleSource {
-    source: String,
+    kind: ShaderSourceKind,
+    name: Option<String>,
+    /// unique for every source, used as cache key
+    id: u64,
+}
+
+/// What a [ShaderModuleSource] contains
+pub enum ShaderSourceKind {
+    /// Wgsl that can be bundled
+    Wgsl(String),
+    /// A source that is used as is, for example a precompiled shader.  
+    /// Bundling errors with [CannotBundlePassthrough](ShaderBundlerError::CannotBundlePassthrough) if it is used,
+    /// but a module can be created from it with [ShaderQueue::create]
+    Passthrough(ShaderSource<'static>),
 }
 
 impl ShaderModuleSource {
+    /// A wgsl source, same as [wgsl](Self::wgsl)
     pub fn new(source: String) -> Self {
-        Self { source }
+        Self::with_kind(ShaderSourceKind::Wgsl(source))
+    }
+
+    pub fn wgsl(source: String) -> Self {
+        Self::new(source)
+    }
+
+    pub fn passthrough(source: ShaderSource<'static>) -> Self {
+        Self::with_kind(ShaderSourceKind::Passthrough(source))
+    }
+
+    fn with_kind(kind: ShaderSourceKind) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self {
+            kind,
+            name: None,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Sets the name used in errors, for example the path of the source file
+    pub fn with_name(self, name: impl Into<String>) -> Self {
+        // a new id, as the name is part of cached source maps
+        Self {
+            name: Some(name.into()),
+            ..Self::with_kind(self.kind)
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn kind(&self) -> &ShaderSourceKind {
+        &self.kind
+    }
+
+    pub fn is_passthrough(&self) -> bool {
+        matches!(self.kind, ShaderSourceKind::Passthrough(_))
+    }
+
+    /// The wgsl code, empty for passthrough sources as they are checked before bundling
+    fn code(&self) -> &str {
+        match &self.kind {
+            ShaderSourceKind::Wgsl(code) => code,
+            ShaderSourceKind::Passthrough(_) => "",
+        }
+    }
+
+    /// Errors if the source can not be bundled
+    fn check_bundleable(&self, fallback_name: &str) -> Result<(), ShaderBundlerError> {
+        match self.is_passthrough() {
+            true => Err(ShaderBundlerError::CannotBundlePassthrough {
+                name: self.display_name(fallback_name).into(),
+            }),
+            false => Ok(()),
+        }
+    }
+
+    /// The name used in errors, fallback is used if the source is not named
+    fn display_name<'a>(&'a self, fallback: &'a str) -> &'a str {
+        self.name.as_deref().unwrap_or(fallback)
     }
 }
 
+/// A value that can be substituted into shader sources using '#{NAME}'
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShaderDefineValue {
+    Bool(bool),
+    Int(i32),
+    UInt(u32),
+    Float(f32),
+    /// Inserted as is
+    Text(String),
+}
+
+impl ShaderDefineValue {
+    /// Whether '//if(NAME)' is true for this value, meaning true, non-zero or non-empty
+    pub fn is_set(&self) -> bool {
+        match self {
+            Self::Bool(b) => *b,
+            Self::Int(i) => *i != 0,
+            Self::UInt(u) => *u != 0,
+            Self::Float(f) => *f != 0.0,
+            Self::Text(t) => !t.is_empty(),
+        }
+    }
+}
+
+/// Formats the value as a wgsl literal, numbers get a type suffix
+impl fmt::Display for ShaderDefineValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Int(i) => write!(f, "{i}i"),
+            Self::UInt(u) => write!(f, "{u}u"),
+            // debug formatting always includes a decimal point or exponent
+            Self::Float(v) => write!(f, "{v:?}f"),
+            Self::Text(t) => f.write_str(t),
+        }
+    }
+}
+
+#[derive(Resource)]
 pub struct ShaderBundler {
     libraries: HashMap<String, ShaderLibrary>,
+    /// bumped every time the libraries change, so cached bundles are not reused
+    generation: u64,
+    cache: cache::BundleCache,
+    check_duplicate_symbols: bool,
+    options: BundleOptions,
 }
 
-#[derive(Debug)]
-pub enum ShaderBundlerError {
-    ModuleAlreadyExists,
-    UnknownDependency(String),
-    InvalidCondition(String),
-    CommentError(String),
+impl Default for ShaderBundler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ShaderBundler {
     pub fn new() -> Self {
         Self {
             libraries: HashMap::new(),
+            generation: 0,
+            cache: Default::default(),
+            check_duplicate_symbols: false,
+            options: BundleOptions::default(),
         }
     }
 
     /// Adds a library to the bundler, modules can add dependencies by adding lines containing //use lib_name in the start of the source
-    /// Libraries are not supposed to add uniforms, however this is not checked by the bundler
+    /// Libraries are not supposed to add uniforms, however this is not checked by the bundler  
+    /// Returns the new [generation](Self::generation)
     pub fn add_library(
         &mut self,
         name: String,
         source: ShaderModuleSource,
-    ) -> Result<(), ShaderBundlerError> {
-        let dependencies = get_dependencies(&source);
+    ) -> Result<u64, ShaderBundlerError> {
+        let dependencies = get_dependencies(&source, &name)?;
         match self.libraries.try_insert(
             name,
             ShaderLibrary {
@@ -51,113 +212,480 @@ impl ShaderBundler {
                 dependencies,
             },
         ) {
-            Ok(_) => Ok(()),
-            Err(_) => Err(ShaderBundlerError::ModuleAlreadyExists),
+            Ok(_) => {
+                self.generation += 1;
+                Ok(self.generation)
+            }
+            Err(e) => Err(ShaderBundlerError::ModuleAlreadyExists(
+                e.entry.key().clone(),
+            )),
+        }
+    }
+
+    /// Adds or replaces a library, returning the previous source and the new [generation](Self::generation), the dependencies are read again from the new source.  
+    /// If the new source has invalid dependencies the previous library is kept, otherwise the generation is increased
+    pub fn replace_library(
+        &mut self,
+        name: String,
+        source: ShaderModuleSource,
+    ) -> Result<(Option<ShaderModuleSource>, u64), ShaderBundlerError> {
+        let dependencies = get_dependencies(&source, &name)?;
+        self.generation += 1;
+        let previous = self.libraries.insert(
+            name,
+            ShaderLibrary {
+                source,
+                dependencies,
+            },
+        );
+        Ok((previous.map(|lib| lib.source), self.generation))
+    }
+
+    /// Removes a library, errors if other libraries '//use' or '//include' it, includes are found even when they are in an '//if'.  
+    /// Does nothing if there is no library with the name, returns the new [generation](Self::generation)
+    pub fn remove_library(&mut self, name: &str) -> Result<u64, StillReferenced> {
+        let mut used_by: Vec<_> = self
+            .libraries
+            .iter()
+            .filter(|(_, lib)| {
+                lib.dependencies.iter().any(|dep| dep == name)
+                    || lib
+                        .source
+                        .code()
+                        .split('\n')
+                        .any(|line| include_name(line.trim()) == Some(name))
+            })
+            .map(|(lib_name, _)| lib_name.clone())
+            .collect();
+        if !used_by.is_empty() {
+            used_by.sort();
+            return Err(StillReferenced {
+                library: name.into(),
+                used_by,
+            });
+        }
+        if self.libraries.remove(name).is_some() {
+            self.generation += 1;
+        }
+        Ok(self.generation)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.libraries.contains_key(name)
+    }
+
+    /// The names of all libraries, in arbitrary order
+    pub fn library_names(&self) -> impl Iterator<Item = &str> {
+        self.libraries.keys().map(String::as_str)
+    }
+
+    /// Increases every time libraries are added, replaced or removed, or settings change, so it can be used to check if bundles are outdated
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Whether bundling errors with [DuplicateSymbol](ShaderBundlerError::DuplicateSymbol) when multiple libraries define the same top level name, disabled by default.  
+    /// Lines containing '//allow-duplicate name' in any bundled source allow the name to be defined multiple times.  
+    /// The check only looks at tokens, so it is not affected by the naga feature
+    pub fn set_check_duplicate_symbols(&mut self, check: bool) {
+        if self.check_duplicate_symbols != check {
+            self.check_duplicate_symbols = check;
+            self.generation += 1;
+        }
+    }
+
+    pub fn options(&self) -> BundleOptions {
+        self.options
+    }
+
+    /// Sets the options used for all following bundles, for example [`BundleOptions::release`]
+    pub fn set_options(&mut self, options: BundleOptions) {
+        if self.options != options {
+            self.options = options;
+            self.generation += 1;
         }
     }
 
     /// Bundles a shader
     /// Interface is supposed to implement the vertex and and fragment (or compute main), while depending on functions that must be implemented by implementor
-    /// No promises are checked while bundling
+    /// No promises are checked while bundling.  
+    /// The output contains the used libraries, with dependencies before the libraries using them, followed by implementor and then interface  
+    /// Defines are substituted for '#{NAME}' tokens, and count as flags when they are set (see [`ShaderDefineValue::is_set`])
     pub fn bundle(
+        &self,
+        interface: &ShaderModuleSource,
+        implementor: &ShaderModuleSource,
+        flags: &ShaderFlags,
+        defines: &[(&str, ShaderDefineValue)],
+    ) -> Result<ShaderSource<'_>, ShaderBundlerError> {
+        let (code, _) = self.bundle_with_source_map(interface, implementor, flags, defines)?;
+        Ok(ShaderSource::Wgsl(Cow::Owned(code)))
+    }
+
+    /// Same as [`bundle`](Self::bundle), but with the flags as a slice
+    pub fn bundle_str_flags(
         &self,
         interface: &ShaderModuleSource,
         implementor: &ShaderModuleSource,
         flags: &[&str],
-    ) -> Result<ShaderSource, ShaderBundlerError> {
+        defines: &[(&str, ShaderDefineValue)],
+    ) -> Result<ShaderSource<'_>, ShaderBundlerError> {
+        self.bundle(interface, implementor, &flags.into(), defines)
+    }
+
+    /// Same as [`bundle`](Self::bundle), but returns the code along with a map from its lines to the sources they came from
+    pub fn bundle_with_source_map(
+        &self,
+        interface: &ShaderModuleSource,
+        implementor: &ShaderModuleSource,
+        flags: &ShaderFlags,
+        defines: &[(&str, ShaderDefineValue)],
+    ) -> Result<(String, BundleSourceMap), ShaderBundlerError> {
         let mut res = String::new();
-        let flags = flags.iter().map(|f| (*f).into()).collect();
-        for dep in dependency_list(self, interface, implementor)? {
-            let code: Vec<_> = self.libraries[&dep].source.source.split("\n").collect();
-            let applied = apply_flags(&code, &flags, true)?.0.join("\n");
-            res.push_str(&applied);
-            res.push('\n');
+        let mut source_map = BundleSourceMap::default();
+        let flags = effective_flags(flags, defines);
+        let defines: HashMap<&str, String> =
+            defines.iter().map(|(n, v)| (*n, v.to_string())).collect();
+        let ctx = FlagContext {
+            bundler: self,
+            flags: &flags,
+        };
+        for (name, source) in self.bundle_sources(interface, implementor)? {
+            let lines = SourceLines::new(source, name);
+            let kept = apply_flags(&lines, &ctx, &mut vec![lines.name])?;
+            push_kept_lines(&mut res, &mut source_map, &kept, &defines)?;
+        }
+        self.finish_bundle(res, source_map)
+    }
+
+    /// Checks the whole bundle and applies the [options](Self::options)
+    fn finish_bundle(
+        &self,
+        code: String,
+        source_map: BundleSourceMap,
+    ) -> Result<(String, BundleSourceMap), ShaderBundlerError> {
+        if self.check_duplicate_symbols {
+            symbols::check_duplicate_symbols(&code, &source_map)?;
+        }
+        Ok(self.options.apply(code, source_map))
+    }
+
+    /// The sources of a bundle in the order they are output, with the names used for them if they are not named
+    fn bundle_sources<'a>(
+        &'a self,
+        interface: &'a ShaderModuleSource,
+        implementor: &'a ShaderModuleSource,
+    ) -> Result<Vec<(&'a str, &'a ShaderModuleSource)>, ShaderBundlerError> {
+        interface.check_bundleable("interface")?;
+        implementor.check_bundleable("implementor")?;
+        let libraries = dependency_list(self, interface, implementor)?;
+        let sources: Vec<_> = libraries
+            .iter()
+            .map(|dep| {
+                let (name, library) = self.libraries.get_key_value(dep).unwrap();
+                (name.as_str(), &library.source)
+            })
+            .chain([("implementor", implementor), ("interface", interface)])
+            .collect();
+        for (name, source) in &sources {
+            source.check_bundleable(name)?;
         }
-        Ok(ShaderSource::Wgsl(Cow::Owned(res)))
+        Ok(sources)
+    }
+}
+
+/// Appends kept lines to a bundle with defines substituted, adding them to the source map
+fn push_kept_lines(
+    res: &mut String,
+    source_map: &mut BundleSourceMap,
+    kept: &[KeptLine],
+    defines: &HashMap<&str, String>,
+) -> Result<(), ShaderBundlerError> {
+    for kept in kept {
+        let source_idx = source_map.source_index(kept.source);
+        source_map.push_line(res.len(), source_idx, kept.line);
+        res.push_str(&substitute_defines(kept, defines)?);
+        res.push('\n');
     }
+    Ok(())
+}
+
+/// The flags with the names of the defines that are set
+fn effective_flags(flags: &ShaderFlags, defines: &[(&str, ShaderDefineValue)]) -> HashSet<String> {
+    flags
+        .iter()
+        .chain(defines.iter().filter(|(_, v)| v.is_set()).map(|(n, _)| *n))
+        .map(String::from)
+        .collect()
 }
 
 struct ShaderLibrary {
     source: ShaderModuleSource,
     dependencies: Vec<String>,
 }
+
+/// A source split into lines, with the name used in errors
+struct SourceLines<'a> {
+    name: &'a str,
+    lines: Vec<&'a str>,
+}
+
+impl<'a> SourceLines<'a> {
+    fn new(source: &'a ShaderModuleSource, fallback_name: &'a str) -> Self {
+        Self {
+            name: source.display_name(fallback_name),
+            lines: source.code().split('\n').collect(),
+        }
+    }
+
+    /// The location of part in the line at idx
+    fn location(&self, idx: usize, part: &str) -> SourceLocation {
+        SourceLocation::new(self.name, idx, self.lines[idx], part)
+    }
+}
+
 enum ConditionToken {
     Parenthesie(bool),
     Operator(char),
     Literal(String),
 }
 
+/// All libraries used by interface and implementor, ordered so dependencies come before the libraries using them
 fn dependency_list(
     bundler: &ShaderBundler,
     interface: &ShaderModuleSource,
     implementor: &ShaderModuleSource,
 ) -> Result<Vec<String>, ShaderBundlerError> {
-    let mut queue = get_dependencies(interface);
-    queue.append(&mut get_dependencies(implementor));
-    let mut seen: HashSet<_> = queue.into_iter().collect();
-    // to removes repeating elements
-    let mut queue: Vec<_> = seen.clone().into_iter().collect();
     let mut res = Vec::new();
-    while let Some(e) = queue.pop() {
-        if seen.contains(&e) {
-            continue;
-        }
-        for dep in &bundler
-            .libraries
-            .get(&e)
-            .ok_or_else(|| ShaderBundlerError::UnknownDependency(e.clone()))?
-            .dependencies
-        {
-            queue.push(dep.clone());
+    let mut done = HashSet::new();
+    let mut path = Vec::new();
+    for (source, fallback) in [(interface, "interface"), (implementor, "implementor")] {
+        let used_by = source.display_name(fallback);
+        for root in &get_dependencies(source, fallback)? {
+            visit_dependency(bundler, root, used_by, &mut done, &mut path, &mut res)?;
         }
-        seen.insert(e.clone());
-        res.push(e);
     }
     Ok(res)
 }
 
-fn apply_flags(
-    code: &[&str],
-    flags: &HashSet<String>,
-    keep: bool,
-) -> Result<(Vec<String>, usize), ShaderBundlerError> {
-    let mut i = 0;
+/// Depth first visit for topological ordering, path is used to detect cycles
+fn visit_dependency(
+    bundler: &ShaderBundler,
+    name: &String,
+    used_by: &str,
+    done: &mut HashSet<String>,
+    path: &mut Vec<String>,
+    res: &mut Vec<String>,
+) -> Result<(), ShaderBundlerError> {
+    if done.contains(name) {
+        return Ok(());
+    }
+    if let Some(pos) = path.iter().position(|n| n == name) {
+        let mut cycle = path[pos..].to_vec();
+        cycle.push(name.clone());
+        return Err(ShaderBundlerError::CircularDependency(cycle));
+    }
+    let library =
+        bundler
+            .libraries
+            .get(name)
+            .ok_or_else(|| ShaderBundlerError::UnknownDependency {
+                name: name.clone(),
+                used_by: used_by.into(),
+            })?;
+    path.push(name.clone());
+    for dep in &library.dependencies {
+        visit_dependency(bundler, dep, name, done, path, res)?;
+    }
+    path.pop();
+    done.insert(name.clone());
+    res.push(name.clone());
+    Ok(())
+}
+
+/// The maximum number of nested '//include's
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+/// A line kept by flags
+struct KeptLine<'a> {
+    /// The name of the source the line is from, as used in errors
+    source: &'a str,
+    /// Index of the line in its source
+    line: usize,
+    text: &'a str,
+}
+
+impl KeptLine<'_> {
+    fn location(&self, part: &str) -> SourceLocation {
+        SourceLocation::new(self.source, self.line, self.text, part)
+    }
+}
+
+type KeptLines<'a> = Vec<KeptLine<'a>>;
+
+/// What flags are applied with, besides the source
+struct FlagContext<'a> {
+    bundler: &'a ShaderBundler,
+    flags: &'a HashSet<String>,
+}
+
+/// Removes lines excluded by flags and the conditional comments, and splices in '//include's, errors if the conditionals are malformed.  
+/// include_chain contains the names of the sources being included, starting with src
+fn apply_flags<'a>(
+    src: &SourceLines<'a>,
+    ctx: &FlagContext<'a>,
+    include_chain: &mut Vec<&'a str>,
+) -> Result<KeptLines<'a>, ShaderBundlerError> {
+    // top level errors on //endif, so the whole code is always consumed
+    Ok(apply_block(src, 0, ctx, include_chain, true, None)?.0)
+}
+
+/// Applies flags from start until an //endif or the end of the code, returning the kept lines and the index of the //endif (or the line count)
+/// active is whether the lines before an //else are kept, else_active is the same for lines after an //else and None if not in an //if
+fn apply_block<'a>(
+    src: &SourceLines<'a>,
+    start: usize,
+    ctx: &FlagContext<'a>,
+    include_chain: &mut Vec<&'a str>,
+    active: bool,
+    else_active: Option<bool>,
+) -> Result<(KeptLines<'a>, usize), ShaderBundlerError> {
+    let mut i = start;
     let mut res = Vec::new();
     let mut in_else = false;
 
-    while i < code.len() {
-        let inst = &code[i];
-        let trimmed = inst.trim();
+    while i < src.lines.len() {
+        let line = src.lines[i];
+        let trimmed = line.trim();
         if trimmed == "//endif" {
-            break;
+            return match else_active {
+                Some(_) => Ok((res, i)),
+                None => Err(ShaderBundlerError::UnmatchedEndif(src.location(i, trimmed))),
+            };
         }
+        let keep = if in_else {
+            else_active.unwrap_or(false)
+        } else {
+            active
+        };
         if trimmed == "//else" {
+            if else_active.is_none() {
+                return Err(ShaderBundlerError::UnmatchedElse(src.location(i, trimmed)));
+            }
             if in_else {
-                return Err(ShaderBundlerError::CommentError(
-                    "Found //else twice".into(),
-                ));
+                return Err(ShaderBundlerError::DuplicateElse(src.location(i, trimmed)));
             }
             in_else = true;
-        }
-        // calculating even if not keep, because it runs recursion to keep scopes
-        // very stupid indeed...
-        let mut sub = if is_if(trimmed) {
+        } else if is_if(trimmed) {
             let cond = &trimmed[5..trimmed.len() - 1];
-            let res = eval_condition(cond, flags)
-                .ok_or_else(|| ShaderBundlerError::InvalidCondition(cond.into()))?;
-            let block;
-            (block, i) = apply_flags(&code[i + 1..], flags, res)?;
-            block
-        } else {
-            vec![(*inst).into()]
-        };
-        // same as !=, should only run if in_else or keep, not if both
-        if keep ^ in_else {
-            res.append(&mut sub);
+            let cond_res = eval_condition(cond, ctx.flags).ok_or_else(|| {
+                ShaderBundlerError::InvalidCondition {
+                    condition: cond.into(),
+                    location: src.location(i, cond),
+                }
+            })?;
+            // nested blocks are still applied when not kept, to find the matching //endif
+            let (mut block, end) = apply_block(
+                src,
+                i + 1,
+                ctx,
+                include_chain,
+                keep && cond_res,
+                Some(keep && !cond_res),
+            )?;
+            if end == src.lines.len() {
+                return Err(ShaderBundlerError::UnbalancedIf {
+                    opened_at: src.location(i, trimmed),
+                });
+            }
+            if keep {
+                res.append(&mut block);
+            }
+            // skipping the block, i is now at the //endif
+            i = end;
+        } else if let Some(name) = include_name(trimmed) {
+            // includes in excluded blocks are not looked up at all
+            if keep {
+                res.append(&mut include(src, i, name, ctx, include_chain)?);
+            }
+        } else if keep {
+            res.push(KeptLine {
+                source: src.name,
+                line: i,
+                text: line,
+            });
         }
         i += 1;
     }
-    todo!()
+    Ok((res, i))
+}
+
+/// The name in an '//include name' line, '//includes' is just a comment
+fn include_name(line: &str) -> Option<&str> {
+    let name = line.strip_prefix("//include")?;
+    (name.is_empty() || name.starts_with(char::is_whitespace)).then(|| name.trim())
+}
+
+/// Applies flags to the library included at line i of src
+fn include<'a>(
+    src: &SourceLines<'a>,
+    i: usize,
+    name: &str,
+    ctx: &FlagContext<'a>,
+    include_chain: &mut Vec<&'a str>,
+) -> Result<KeptLines<'a>, ShaderBundlerError> {
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(ShaderBundlerError::InvalidDependencyName(
+            src.location(i, src.lines[i].trim()),
+        ));
+    }
+    let (name, library) = ctx.bundler.libraries.get_key_value(name).ok_or_else(|| {
+        ShaderBundlerError::UnknownDependency {
+            name: name.into(),
+            used_by: src.name.into(),
+        }
+    })?;
+    if include_chain.contains(&name.as_str()) || include_chain.len() > MAX_INCLUDE_DEPTH {
+        let mut chain: Vec<_> = include_chain.iter().map(|n| n.to_string()).collect();
+        chain.push(name.clone());
+        return Err(ShaderBundlerError::IncludeRecursion {
+            chain,
+            location: src.location(i, name),
+        });
+    }
+    library.source.check_bundleable(name)?;
+    include_chain.push(name);
+    let res = apply_flags(&SourceLines::new(&library.source, name), ctx, include_chain);
+    include_chain.pop();
+    res
+}
+
+/// Replaces '#{NAME}' tokens in a line
+fn substitute_defines(
+    line: &KeptLine,
+    defines: &HashMap<&str, String>,
+) -> Result<String, ShaderBundlerError> {
+    let mut res = String::new();
+    let mut rest = line.text;
+    while let Some(start) = rest.find("#{") {
+        let Some(len) = rest[start + 2..].find('}') else {
+            // not a token, left for the shader compiler to report
+            break;
+        };
+        let token = &rest[start..start + 3 + len];
+        let name = &token[2..token.len() - 1];
+        let value = defines
+            .get(name)
+            .ok_or_else(|| ShaderBundlerError::UndefinedDefine {
+                name: name.into(),
+                location: line.location(token),
+            })?;
+        res.push_str(&rest[..start]);
+        res.push_str(value);
+        rest = &rest[start + token.len()..];
+    }
+    res.push_str(rest);
+    Ok(res)
 }
 
 fn is_if(line: &str) -> bool {
@@ -176,23 +704,25 @@ fn eval_tokens(tokens: &[ConditionToken], flags: &HashSet<String>) -> Option<boo
         ConditionToken::Literal(lit) => (tokens.len() == 1).then(|| flags.contains(lit)),
         ConditionToken::Operator('!') => Some(!eval_tokens(&tokens[1..], flags)?),
         ConditionToken::Parenthesie(true) => {
-            // insane pattern abuse
-            if let ([_, first @ .., _], rest) = until_closing(tokens)? {
-                if let [ConditionToken::Operator(op), ConditionToken::Parenthesie(true), last @ .., ConditionToken::Parenthesie(false)] =
-                    rest
+            let (inner, rest) = until_closing(tokens)?;
+            let a = eval_tokens(inner, flags)?;
+            match rest {
+                [] => Some(a),
+                // the right side must also be parenthesized, or negated
+                [ConditionToken::Operator(op), rest @ ..]
+                    if matches!(
+                        rest.first(),
+                        Some(ConditionToken::Parenthesie(true) | ConditionToken::Operator('!'))
+                    ) =>
                 {
-                    let a = eval_tokens(first, flags)?;
-                    let b = eval_tokens(last, flags)?;
+                    let b = eval_tokens(rest, flags)?;
                     match op {
                         '&' => Some(a && b),
                         '|' => Some(a || b),
                         _ => None,
                     }
-                } else {
-                    None
                 }
-            } else {
-                None
+                _ => None,
             }
         }
         _ => None,
@@ -202,18 +732,15 @@ fn eval_tokens(tokens: &[ConditionToken], flags: &HashSet<String>) -> Option<boo
 fn until_closing(tokens: &[ConditionToken]) -> Option<(&[ConditionToken], &[ConditionToken])> {
     let mut counter = 0;
     for (idx, token) in tokens.iter().enumerate() {
-        match token {
-            ConditionToken::Parenthesie(open) => {
-                if *open {
-                    counter += 1;
-                } else {
-                    counter -= 1;
-                    if counter == 0 {
-                        return Some((&tokens[1..idx], &tokens[idx + 1..]));
-                    }
+        if let ConditionToken::Parenthesie(open) = token {
+            if *open {
+                counter += 1;
+            } else {
+                counter -= 1;
+                if counter == 0 {
+                    return Some((&tokens[1..idx], &tokens[idx + 1..]));
                 }
             }
-            _ => {}
         }
     }
     None
@@ -229,11 +756,12 @@ fn tokenize(condition: &str) -> Option<Vec<ConditionToken>> {
             Some(ConditionToken::Parenthesie(false))
         } else if "&|!".contains(c) {
             Some(ConditionToken::Operator(c))
-        } else if !c.is_alphanumeric() && c != '_' {
+        } else if c.is_alphanumeric() || c == '_' {
+            cur.push(c);
+            None
+        } else {
             // invalid character
             return None;
-        } else {
-            None
         };
         if let Some(token) = token {
             if !cur.is_empty() {
@@ -248,14 +776,353 @@ fn tokenize(condition: &str) -> Option<Vec<ConditionToken>> {
     Some(res)
 }
 
-fn get_dependencies(module: &ShaderModuleSource) -> Vec<String> {
+/// Reads the '//use' lines at the start of a module, blank lines and other comments are allowed between them.  
+/// fallback_name is used in errors if the module is not named
+fn get_dependencies(
+    module: &ShaderModuleSource,
+    fallback_name: &str,
+) -> Result<Vec<String>, ShaderBundlerError> {
+    let src = SourceLines::new(module, fallback_name);
     let mut dependencies = Vec::new();
-    for ln in module.source.split("\n") {
-        if ln.len() >= 6 && &ln[..6] == "//use " {
-            dependencies.push(ln[..6].trim().to_string());
-        } else {
+    // trim also removes \r from CRLF sources
+    for (i, ln) in src.lines.iter().map(|l| l.trim()).enumerate() {
+        if let Some(name) = ln.strip_prefix("//use") {
+            // '//user' is just a comment
+            if !name.is_empty() && !name.starts_with(char::is_whitespace) {
+                continue;
+            }
+            let name = name.trim();
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err(ShaderBundlerError::InvalidDependencyName(
+                    src.location(i, ln),
+                ));
+            }
+            dependencies.push(name.to_string());
+        } else if !ln.is_empty() && !ln.starts_with("//") {
             break;
         }
     }
-    dependencies
+    Ok(dependencies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The lines of code that are kept with flags, bundled as the implementor with an empty interface
+    fn kept(
+        bundler: &ShaderBundler,
+        code: &str,
+        flags: &[&str],
+    ) -> Result<Vec<String>, ShaderBundlerError> {
+        let interface = ShaderModuleSource::new(String::new());
+        let implementor = ShaderModuleSource::new(code.into());
+        let (code, _) =
+            bundler.bundle_with_source_map(&interface, &implementor, &flags.into(), &[])?;
+        Ok(code
+            .lines()
+            .filter(|l| !l.is_empty())
+            .map(String::from)
+            .collect())
+    }
+
+    fn kept_lines(code: &str, flags: &[&str]) -> Vec<String> {
+        kept(&ShaderBundler::new(), code, flags).unwrap()
+    }
+
+    fn condition_holds(condition: &str, flags: &[&str]) -> bool {
+        let code = format!("//if({condition})\nkept\n//endif");
+        !kept_lines(&code, flags).is_empty()
+    }
+
+    #[test]
+    fn nested_if() {
+        let code = "a\n//if(X)\nb\n//if(Y)\nc\n//endif\nd\n//endif\ne";
+        assert_eq!(kept_lines(code, &[]), ["a", "e"]);
+        assert_eq!(kept_lines(code, &["Y"]), ["a", "e"]);
+        assert_eq!(kept_lines(code, &["X"]), ["a", "b", "d", "e"]);
+        assert_eq!(kept_lines(code, &["X", "Y"]), ["a", "b", "c", "d", "e"]);
+    }
+
+    #[test]
+    fn else_in_nested_blocks() {
+        let code = "\
+//if(X)
+    //if(Y)
+    x_y
+    //else
+    x
+    //endif
+//else
+    //if(Y)
+    y
+    //else
+    none
+    //endif
+//endif";
+        let kept = |flags: &[&str]| -> Vec<String> {
+            kept_lines(code, flags)
+                .iter()
+                .map(|l| l.trim().to_string())
+                .collect()
+        };
+        assert_eq!(kept(&["X", "Y"]), ["x_y"]);
+        assert_eq!(kept(&["X"]), ["x"]);
+        assert_eq!(kept(&["Y"]), ["y"]);
+        assert_eq!(kept(&[]), ["none"]);
+    }
+
+    #[test]
+    fn operators() {
+        assert!(condition_holds("A", &["A"]));
+        assert!(!condition_holds("A", &["B"]));
+
+        assert!(condition_holds("!A", &[]));
+        assert!(!condition_holds("!A", &["A"]));
+        assert!(condition_holds("!!A", &["A"]));
+
+        assert!(condition_holds("(A)&(B)", &["A", "B"]));
+        assert!(!condition_holds("(A)&(B)", &["A"]));
+        assert!(!condition_holds("(A)&(B)", &["B"]));
+
+        assert!(condition_holds("(A)|(B)", &["A"]));
+        assert!(condition_holds("(A)|(B)", &["B"]));
+        assert!(!condition_holds("(A)|(B)", &[]));
+
+        assert!(condition_holds("(A)&!B", &["A"]));
+        assert!(!condition_holds("(A)&!B", &["A", "B"]));
+        // negation applies to everything after it
+        assert!(condition_holds("!(A)&(B)", &["A"]));
+        assert!(!condition_holds("!(A)&(B)", &["A", "B"]));
+        assert!(condition_holds("((A)|(B))&(C)", &["B", "C"]));
+        assert!(!condition_holds("((A)|(B))&(C)", &["A", "B"]));
+    }
+
+    #[test]
+    fn invalid_conditions() {
+        for condition in ["", "A&B", "(A)&B", "(A", "A)", "(A)^(B)", "A B"] {
+            let code = format!("//if({condition})\n//endif");
+            let res = kept(&ShaderBundler::new(), &code, &[]);
+            assert!(
+                matches!(res, Err(ShaderBundlerError::InvalidCondition { .. })),
+                "'{condition}' should be invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn unbalanced_if() {
+        let res = kept(&ShaderBundler::new(), "a\n//if(A)\nb", &[]);
+        let Err(ShaderBundlerError::UnbalancedIf { opened_at }) = res else {
+            panic!("expected UnbalancedIf");
+        };
+        assert_eq!(opened_at.line, 2);
+
+        // the inner //if is closed, the outer is reported
+        let res = kept(&ShaderBundler::new(), "//if(A)\n//if(B)\n//endif", &["A"]);
+        let Err(ShaderBundlerError::UnbalancedIf { opened_at }) = res else {
+            panic!("expected UnbalancedIf");
+        };
+        assert_eq!(opened_at.line, 1);
+    }
+
+    #[test]
+    fn stray_endif() {
+        let res = kept(&ShaderBundler::new(), "a\n//endif", &[]);
+        let Err(ShaderBundlerError::UnmatchedEndif(location)) = res else {
+            panic!("expected UnmatchedEndif");
+        };
+        assert_eq!(location.line, 2);
+
+        let res = kept(&ShaderBundler::new(), "//if(A)\n//endif\n//endif", &[]);
+        let Err(ShaderBundlerError::UnmatchedEndif(location)) = res else {
+            panic!("expected UnmatchedEndif");
+        };
+        assert_eq!(location.line, 3);
+    }
+
+    #[test]
+    fn stray_else() {
+        let res = kept(&ShaderBundler::new(), "a\n//else\nb", &[]);
+        let Err(ShaderBundlerError::UnmatchedElse(location)) = res else {
+            panic!("expected UnmatchedElse");
+        };
+        assert_eq!(location.line, 2);
+
+        let res = kept(
+            &ShaderBundler::new(),
+            "//if(A)\n//else\n//else\n//endif",
+            &[],
+        );
+        let Err(ShaderBundlerError::DuplicateElse(location)) = res else {
+            panic!("expected DuplicateElse");
+        };
+        assert_eq!(location.line, 3);
+    }
+
+    fn library(code: &str) -> ShaderModuleSource {
+        ShaderModuleSource::new(code.into())
+    }
+
+    /// The libraries of a bundle of an empty interface and implementor, in the order they are bundled
+    fn dependency_order(
+        bundler: &ShaderBundler,
+        implementor: &str,
+    ) -> Result<Vec<String>, ShaderBundlerError> {
+        dependency_list(bundler, &library(""), &library(implementor))
+    }
+
+    #[test]
+    fn diamond_dependency() {
+        let mut bundler = ShaderBundler::new();
+        bundler.add_library("base".into(), library("base")).unwrap();
+        bundler
+            .add_library("left".into(), library("//use base\nleft"))
+            .unwrap();
+        bundler
+            .add_library("right".into(), library("//use base\nright"))
+            .unwrap();
+        bundler
+            .add_library("top".into(), library("//use left\n//use right\ntop"))
+            .unwrap();
+
+        let order = dependency_order(&bundler, "//use top").unwrap();
+        assert_eq!(order, ["base", "left", "right", "top"]);
+
+        // also deduplicated between interface and implementor, with base still first
+        let order = dependency_list(
+            &bundler,
+            &library("//use right"),
+            &library("//use left\n//use base"),
+        )
+        .unwrap();
+        assert_eq!(order, ["base", "right", "left"]);
+
+        let lines = kept(&bundler, "//use top", &[]).unwrap();
+        assert_eq!(lines.iter().filter(|l| *l == "base").count(), 1);
+        let position = |name: &str| lines.iter().position(|l| l == name).unwrap();
+        assert!(position("base") < position("left"));
+        assert!(position("base") < position("right"));
+        assert!(position("left") < position("top"));
+        assert!(position("right") < position("top"));
+    }
+
+    #[test]
+    fn circular_dependency() {
+        let mut bundler = ShaderBundler::new();
+        bundler.add_library("a".into(), library("//use b")).unwrap();
+        bundler.add_library("b".into(), library("//use a")).unwrap();
+        let res = dependency_order(&bundler, "//use a");
+        let Err(ShaderBundlerError::CircularDependency(cycle)) = res else {
+            panic!("expected CircularDependency");
+        };
+        assert_eq!(cycle, ["a", "b", "a"]);
+    }
+
+    #[test]
+    fn self_dependency() {
+        let mut bundler = ShaderBundler::new();
+        bundler
+            .add_library("a".into(), library("//use a\na"))
+            .unwrap();
+        let res = dependency_order(&bundler, "//use a");
+        let Err(ShaderBundlerError::CircularDependency(cycle)) = res else {
+            panic!("expected CircularDependency");
+        };
+        assert_eq!(cycle, ["a", "a"]);
+    }
+
+    fn dependencies(code: &str) -> Result<Vec<String>, ShaderBundlerError> {
+        get_dependencies(&library(code), "test")
+    }
+
+    #[test]
+    fn use_lines() {
+        assert_eq!(
+            dependencies("//use a\n//use b\n//use c").unwrap(),
+            ["a", "b", "c"]
+        );
+        // blank lines, comments and surrounding whitespace are allowed
+        let code = "// header\n\n  //use a  \n\t//use\tb\n//user is a comment\n\nfn main() {}";
+        assert_eq!(dependencies(code).unwrap(), ["a", "b"]);
+        assert!(dependencies("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn use_lines_crlf() {
+        let code = "//use a\r\n//use b\r\n\r\nfn main() {}\r\n";
+        assert_eq!(dependencies(code).unwrap(), ["a", "b"]);
+    }
+
+    #[test]
+    fn use_after_code() {
+        let code = "//use a\nconst X = 1;\n//use b";
+        assert_eq!(dependencies(code).unwrap(), ["a"]);
+        assert!(dependencies("fn main() {}\n//use a").unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_use_names() {
+        for code in ["//use", "//use   ", "//use a b", "//use a\n//use\r\n"] {
+            assert!(
+                matches!(
+                    dependencies(code),
+                    Err(ShaderBundlerError::InvalidDependencyName(_))
+                ),
+                "{code:?} should be invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn replace_library_returns_the_generation() {
+        let mut bundler = ShaderBundler::new();
+        let (previous, generation) = bundler
+            .replace_library("lib".into(), library("old"))
+            .unwrap();
+        assert!(previous.is_none());
+        assert_eq!(generation, bundler.generation());
+
+        let (previous, new_generation) = bundler
+            .replace_library("lib".into(), library("new"))
+            .unwrap();
+        assert_eq!(previous.unwrap().code(), "old");
+        assert!(new_generation > generation);
+        assert_eq!(new_generation, bundler.generation());
+    }
+
+    #[test]
+    fn remove_used_library() {
+        let mut bundler = ShaderBundler::new();
+        bundler.add_library("base".into(), library("base")).unwrap();
+        bundler
+            .add_library(
+                "user".into(),
+                library(
+                    "//use base
+user",
+                ),
+            )
+            .unwrap();
+        // an include in a block that may be excluded still counts
+        bundler
+            .add_library(
+                "includer".into(),
+                library("//if(a)\n  //include base\n//endif"),
+            )
+            .unwrap();
+        let err = bundler.remove_library("base").unwrap_err();
+        assert_eq!(err.used_by, ["includer", "user"]);
+
+        bundler.remove_library("user").unwrap();
+        let err = bundler.remove_library("base").unwrap_err();
+        assert_eq!(err.used_by, ["includer"]);
+
+        let generation = bundler.generation();
+        assert_eq!(bundler.remove_library("includer"), Ok(generation + 1));
+        assert_eq!(bundler.remove_library("base"), Ok(generation + 2));
+        assert!(!bundler.contains("base"));
+        // removing a library that does not exist does nothing
+        assert_eq!(bundler.remove_library("base"), Ok(generation + 2));
+    }
 }
@@ -0,0 +1,228 @@
+use std::borrow::Cow;
+
+use modula_utils::HashMap;
+use wgpu::ShaderSource;
+
+use super::{
+    effective_flags, BundleSourceMap, ShaderBundler, ShaderBundlerError, ShaderDefineValue,
+    ShaderFlags, ShaderModuleSource,
+};
+
+/// Statistics for [`ShaderBundler::bundle_cached`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ShaderCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// The number of bundles currently in the cache
+    pub entries: usize,
+}
+
+/// Everything the output of a bundle depends on
+#[derive(Hash, PartialEq, Eq)]
+struct BundleKey {
+    interface: u64,
+    implementor: u64,
+    /// including the defines that are set
+    flags: ShaderFlags,
+    /// sorted by name, with the substituted value
+    defines: Vec<(String, String)>,
+    generation: u64,
+}
+
+impl BundleKey {
+    fn new(
+        interface: &ShaderModuleSource,
+        implementor: &ShaderModuleSource,
+        flags: &ShaderFlags,
+        defines: &[(&str, ShaderDefineValue)],
+        generation: u64,
+    ) -> Self {
+        // collecting into a map first, so later defines replace earlier ones like when bundling
+        let mut key_defines: Vec<_> = defines
+            .iter()
+            .map(|(n, v)| (*n, v.to_string()))
+            .collect::<HashMap<_, _>>()
+            .into_iter()
+            .map(|(n, v)| (n.to_string(), v))
+            .collect();
+        key_defines.sort();
+        Self {
+            interface: interface.id,
+            implementor: implementor.id,
+            flags: effective_flags(flags, defines).into_iter().collect(),
+            defines: key_defines,
+            generation,
+        }
+    }
+}
+
+#[derive(Default)]
+pub(super) struct BundleCache {
+    entries: HashMap<BundleKey, (String, BundleSourceMap)>,
+    hits: u64,
+    misses: u64,
+}
+
+impl ShaderBundler {
+    /// Same as [`bundle`](Self::bundle), but the output is cached, so bundling the same sources with the same flags and defines again is cheap.
+    /// The cache is invalidated when libraries change.
+    pub fn bundle_cached(
+        &mut self,
+        interface: &ShaderModuleSource,
+        implementor: &ShaderModuleSource,
+        flags: &ShaderFlags,
+        defines: &[(&str, ShaderDefineValue)],
+    ) -> Result<ShaderSource<'_>, ShaderBundlerError> {
+        let (code, _) =
+            self.bundle_cached_with_source_map(interface, implementor, flags, defines)?;
+        Ok(ShaderSource::Wgsl(Cow::Borrowed(code)))
+    }
+
+    /// Same as [`bundle_with_source_map`](Self::bundle_with_source_map), but cached like [`bundle_cached`](Self::bundle_cached)
+    pub fn bundle_cached_with_source_map(
+        &mut self,
+        interface: &ShaderModuleSource,
+        implementor: &ShaderModuleSource,
+        flags: &ShaderFlags,
+        defines: &[(&str, ShaderDefineValue)],
+    ) -> Result<(&str, &BundleSourceMap), ShaderBundlerError> {
+        let key = BundleKey::new(interface, implementor, flags, defines, self.generation);
+        if self.cache.entries.contains_key(&key) {
+            self.cache.hits += 1;
+            let (code, source_map) = &self.cache.entries[&key];
+            return Ok((code, source_map));
+        }
+        let bundle = self.bundle_with_source_map(interface, implementor, flags, defines)?;
+        self.cache.misses += 1;
+        // entries from older generations can never be used again
+        let generation = self.generation;
+        self.cache
+            .entries
+            .retain(|key, _| key.generation == generation);
+        // the key was checked above
+        let (_, (code, source_map)) = self.cache.entries.insert_unique_unchecked(key, bundle);
+        Ok((code, source_map))
+    }
+
+    /// Removes all cached bundles, the hit and miss counts are kept
+    pub fn clear_cache(&mut self) {
+        self.cache.entries.clear();
+    }
+
+    pub fn cache_stats(&self) -> ShaderCacheStats {
+        ShaderCacheStats {
+            hits: self.cache.hits,
+            misses: self.cache.misses,
+            entries: self.cache.entries.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wgsl(source: &ShaderSource) -> String {
+        match source {
+            ShaderSource::Wgsl(code) => code.to_string(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn replacing_library_evicts_bundles() {
+        let mut bundler = ShaderBundler::new();
+        bundler
+            .add_library("lib".into(), ShaderModuleSource::new("old".into()))
+            .unwrap();
+        let interface = ShaderModuleSource::new("//use lib\ninterface".into());
+        let implementor = ShaderModuleSource::new("implementor".into());
+        let flags = ShaderFlags::new();
+
+        let code = wgsl(
+            &bundler
+                .bundle_cached(&interface, &implementor, &flags, &[])
+                .unwrap(),
+        );
+        assert!(code.contains("old"));
+        bundler
+            .bundle_cached(&interface, &implementor, &flags, &[])
+            .unwrap();
+        let stats = bundler.cache_stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+
+        let generation = bundler.generation();
+        bundler
+            .replace_library("lib".into(), ShaderModuleSource::new("new".into()))
+            .unwrap();
+        assert!(bundler.generation() > generation);
+
+        let code = wgsl(
+            &bundler
+                .bundle_cached(&interface, &implementor, &flags, &[])
+                .unwrap(),
+        );
+        assert!(code.contains("new") && !code.contains("old"));
+        let stats = bundler.cache_stats();
+        // the bundle of the old generation was removed, not kept next to the new one
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 2, 1));
+    }
+
+    #[test]
+    fn invalid_replacement_keeps_bundles() {
+        let mut bundler = ShaderBundler::new();
+        bundler
+            .add_library("lib".into(), ShaderModuleSource::new("lib".into()))
+            .unwrap();
+        let interface = ShaderModuleSource::new("//use lib".into());
+        let implementor = ShaderModuleSource::new(String::new());
+        let flags = ShaderFlags::new();
+        bundler
+            .bundle_cached(&interface, &implementor, &flags, &[])
+            .unwrap();
+
+        let generation = bundler.generation();
+        assert!(bundler
+            .replace_library("lib".into(), ShaderModuleSource::new("//use".into()))
+            .is_err());
+        assert_eq!(bundler.generation(), generation);
+        bundler
+            .bundle_cached(&interface, &implementor, &flags, &[])
+            .unwrap();
+        assert_eq!(bundler.cache_stats().hits, 1);
+    }
+
+    #[test]
+    fn define_order_does_not_change_key() {
+        use std::hash::BuildHasher;
+
+        let interface = ShaderModuleSource::new(String::new());
+        let implementor = ShaderModuleSource::new(String::new());
+        let flags = ShaderFlags::from(["B", "A"]);
+        let defines = [
+            ("SIZE", ShaderDefineValue::UInt(4)),
+            ("SCALE", ShaderDefineValue::Float(0.5)),
+            ("ENABLED", ShaderDefineValue::Bool(true)),
+            ("NAME", ShaderDefineValue::Text("main".into())),
+        ];
+        let mut reversed = defines.clone();
+        reversed.reverse();
+
+        let key = BundleKey::new(&interface, &implementor, &flags, &defines, 3);
+        let reversed_flags = ShaderFlags::from(["A", "B"]);
+        let reversed_key = BundleKey::new(&interface, &implementor, &reversed_flags, &reversed, 3);
+        assert!(key == reversed_key);
+        let hasher = modula_utils::hashbrown::hash_map::DefaultHashBuilder::default();
+        assert_eq!(hasher.hash_one(&key), hasher.hash_one(&reversed_key));
+
+        // a later define replaces an earlier one with the same name, like when bundling
+        let mut overridden = defines.to_vec();
+        overridden.insert(0, ("SIZE", ShaderDefineValue::UInt(8)));
+        let overridden_key = BundleKey::new(&interface, &implementor, &flags, &overridden, 3);
+        assert!(key == overridden_key);
+
+        let changed = [("SIZE", ShaderDefineValue::UInt(8))];
+        assert!(key != BundleKey::new(&interface, &implementor, &flags, &changed, 3));
+        assert!(key != BundleKey::new(&interface, &implementor, &flags, &defines, 4));
+    }
+}
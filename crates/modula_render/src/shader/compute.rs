@@ -0,0 +1,208 @@
+use std::borrow::Cow;
+
+use wgpu::{Limits, ShaderSource};
+
+use super::{
+    ShaderBundler, ShaderBundlerError, ShaderDefineValue, ShaderFlags, ShaderModuleSource,
+};
+
+/// A compute shader made by [ShaderBundler::bundle_compute]
+#[derive(Clone, Debug)]
+pub struct ComputeBundle {
+    code: String,
+    entry_point: String,
+    workgroup_size: (u32, u32, u32),
+}
+
+impl ComputeBundle {
+    pub fn source(&self) -> ShaderSource<'_> {
+        ShaderSource::Wgsl(Cow::Borrowed(&self.code))
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// The name of the '@compute' function
+    pub fn entry_point(&self) -> &str {
+        &self.entry_point
+    }
+
+    pub fn workgroup_size(&self) -> (u32, u32, u32) {
+        self.workgroup_size
+    }
+
+    /// The workgroups to dispatch so at least one invocation runs for every element of size, see [workgroup_count]
+    #[inline]
+    pub fn workgroup_count(&self, size: (u32, u32, u32)) -> (u32, u32, u32) {
+        workgroup_count(size, self.workgroup_size)
+    }
+}
+
+/// The workgroups of workgroup_size needed to cover size, rounded up along every axis.  
+/// Invocations past the edge of sizes that are not a multiple of the workgroup size should return early
+pub fn workgroup_count(size: (u32, u32, u32), workgroup_size: (u32, u32, u32)) -> (u32, u32, u32) {
+    (
+        size.0.div_ceil(workgroup_size.0),
+        size.1.div_ceil(workgroup_size.1),
+        size.2.div_ceil(workgroup_size.2),
+    )
+}
+
+/// The workgroup size within the limits of a device, every axis is clamped to its limit,
+/// then the largest axis is halved until the invocations fit in max_compute_invocations_per_workgroup
+pub fn fit_workgroup_size(requested: (u32, u32, u32), limits: &Limits) -> (u32, u32, u32) {
+    let (x, y, z) = requested;
+    let mut size = [
+        x.clamp(1, limits.max_compute_workgroup_size_x.max(1)),
+        y.clamp(1, limits.max_compute_workgroup_size_y.max(1)),
+        z.clamp(1, limits.max_compute_workgroup_size_z.max(1)),
+    ];
+    while size.iter().product::<u32>() > limits.max_compute_invocations_per_workgroup.max(1) {
+        let largest = (0..3).max_by_key(|&i| size[i]).unwrap();
+        size[largest] = size[largest].div_ceil(2);
+    }
+    (size[0], size[1], size[2])
+}
+
+impl ShaderBundler {
+    /// Bundles a compute shader, the bundle must contain exactly one '@compute' entry point.
+    /// '#{WORKGROUP_SIZE}' is replaced by the workgroup size, like '@workgroup_size(#{WORKGROUP_SIZE})',
+    /// and '#{WORKGROUP_SIZE_X}', '#{WORKGROUP_SIZE_Y}' and '#{WORKGROUP_SIZE_Z}' by the individual sizes
+    pub fn bundle_compute(
+        &self,
+        interface: &ShaderModuleSource,
+        implementor: &ShaderModuleSource,
+        flags: &ShaderFlags,
+        defines: &[(&str, ShaderDefineValue)],
+        workgroup_size: (u32, u32, u32),
+    ) -> Result<ComputeBundle, ShaderBundlerError> {
+        let (x, y, z) = workgroup_size;
+        let (x, y, z) = (
+            ShaderDefineValue::UInt(x),
+            ShaderDefineValue::UInt(y),
+            ShaderDefineValue::UInt(z),
+        );
+        let size = ShaderDefineValue::Text(format!("{x}, {y}, {z}"));
+        // after the user defines, so they can not be replaced
+        let defines: Vec<_> = defines
+            .iter()
+            .cloned()
+            .chain([
+                ("WORKGROUP_SIZE", size),
+                ("WORKGROUP_SIZE_X", x),
+                ("WORKGROUP_SIZE_Y", y),
+                ("WORKGROUP_SIZE_Z", z),
+            ])
+            .collect();
+        let (code, _) = self.bundle_with_source_map(interface, implementor, flags, &defines)?;
+        let mut entry_points = compute_entry_points(&code);
+        if entry_points.len() != 1 {
+            return Err(ShaderBundlerError::ComputeEntryPoints(entry_points));
+        }
+        Ok(ComputeBundle {
+            code,
+            entry_point: entry_points.remove(0),
+            workgroup_size,
+        })
+    }
+}
+
+/// The names of the functions with a '@compute' attribute, ignoring line comments
+fn compute_entry_points(code: &str) -> Vec<String> {
+    let code: String = code
+        .lines()
+        .map(|l| l.split("//").next().unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let mut res = Vec::new();
+    let mut rest = code.as_str();
+    while let Some(idx) = rest.find("@compute") {
+        rest = &rest[idx + "@compute".len()..];
+        if rest.starts_with(is_ident) {
+            continue;
+        }
+        // the name is the identifier after the next 'fn'
+        let name = rest
+            .match_indices("fn")
+            .find(|(i, _)| {
+                !rest[..*i].ends_with(is_ident) && rest[i + 2..].starts_with(char::is_whitespace)
+            })
+            .map(|(i, _)| rest[i + 2..].trim_start())
+            .map(|after| after.split(|c| !is_ident(c)).next().unwrap_or(""));
+        res.push(name.unwrap_or("").to_string());
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundle(
+        code: &str,
+        workgroup_size: (u32, u32, u32),
+    ) -> Result<ComputeBundle, ShaderBundlerError> {
+        ShaderBundler::new().bundle_compute(
+            &ShaderModuleSource::new(String::new()),
+            &ShaderModuleSource::new(code.into()),
+            &ShaderFlags::default(),
+            &[],
+            workgroup_size,
+        )
+    }
+
+    #[test]
+    fn workgroup_size_is_substituted() {
+        let code = "@compute @workgroup_size(#{WORKGROUP_SIZE})\nfn downsample(@builtin(global_invocation_id) id: vec3<u32>) {\n    let x = #{WORKGROUP_SIZE_X};\n}";
+        let bundle = bundle(code, (8, 4, 1)).unwrap();
+        // the sizes are u32 literals
+        assert!(bundle.code().contains("@workgroup_size(8u, 4u, 1u)"));
+        assert!(bundle.code().contains("let x = 8u;"));
+        assert_eq!(bundle.entry_point(), "downsample");
+        assert_eq!(bundle.workgroup_size(), (8, 4, 1));
+    }
+
+    #[test]
+    fn one_entry_point_is_required() {
+        let none = bundle("// @compute fn commented() {}\nfn helper() {}", (1, 1, 1));
+        assert!(
+            matches!(none, Err(ShaderBundlerError::ComputeEntryPoints(found)) if found.is_empty())
+        );
+        let two = bundle(
+            "@compute @workgroup_size(1)\nfn a() {}\n@compute @workgroup_size(1)\nfn b() {}",
+            (1, 1, 1),
+        );
+        assert!(
+            matches!(two, Err(ShaderBundlerError::ComputeEntryPoints(found)) if found == ["a", "b"])
+        );
+    }
+
+    #[test]
+    fn workgroup_count_covers_odd_sizes() {
+        // the mip chain of a 13x7 texture, halved and rounded down until 1x1
+        let counts: Vec<_> = (0..4)
+            .map(|level| workgroup_count(((13 >> level).max(1), (7 >> level).max(1), 1), (8, 8, 1)))
+            .collect();
+        assert_eq!(counts, [(2, 1, 1), (1, 1, 1), (1, 1, 1), (1, 1, 1)]);
+        assert_eq!(workgroup_count((100, 33, 3), (16, 16, 1)), (7, 3, 3));
+        assert_eq!(workgroup_count((64, 64, 1), (8, 8, 1)), (8, 8, 1));
+        assert_eq!(workgroup_count((65, 1, 1), (64, 1, 1)), (2, 1, 1));
+    }
+
+    #[test]
+    fn workgroup_size_fits_limits() {
+        let limits = Limits::downlevel_defaults();
+        assert_eq!(fit_workgroup_size((8, 8, 1), &limits), (8, 8, 1));
+        // 256 invocations at most, with z limited to 64
+        assert_eq!(fit_workgroup_size((32, 32, 1), &limits), (16, 16, 1));
+        assert_eq!(fit_workgroup_size((1, 1, 100), &limits), (1, 1, 64));
+        assert_eq!(fit_workgroup_size((0, 3, 1), &limits), (1, 3, 1));
+        // WebGL2 has no compute shaders, so every size is 1
+        assert_eq!(
+            fit_workgroup_size((8, 8, 1), &Limits::downlevel_webgl2_defaults()),
+            (1, 1, 1)
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use std::{error::Error, fmt, ops::Range};
+
+/// A line in a shader source, used to point at the cause of a [`ShaderBundlerError`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// The name of the source, or the library name if the source is not named
+    pub library: String,
+    /// 1-based line number
+    pub line: usize,
+    /// The content of the line
+    pub text: String,
+    /// The byte range in text that caused the error
+    pub span: Range<usize>,
+}
+
+impl SourceLocation {
+    pub(crate) fn new(library: &str, line_idx: usize, text: &str, part: &str) -> Self {
+        let start = text.find(part).unwrap_or(0);
+        Self::with_span(library, line_idx, text, start..start + part.len())
+    }
+
+    pub(crate) fn with_span(
+        library: &str,
+        line_idx: usize,
+        text: &str,
+        span: Range<usize>,
+    ) -> Self {
+        Self {
+            library: library.into(),
+            line: line_idx + 1,
+            text: text.trim_end().into(),
+            span,
+        }
+    }
+}
+
+/// Prints the location followed by the line with carets below the span
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let num = self.line.to_string();
+        let pad = " ".repeat(num.len());
+        writeln!(f, "{pad}--> {}:{}", self.library, self.line)?;
+        writeln!(f, "{pad} |")?;
+        writeln!(f, "{num} | {}", self.text)?;
+        // keeping tabs so the carets line up with the text
+        let indent: String = self
+            .text
+            .get(..self.span.start)
+            .unwrap_or("")
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let carets = "^".repeat(
+            self.text
+                .get(self.span.clone())
+                .map_or(1, |s| s.chars().count().max(1)),
+        );
+        write!(f, "{pad} | {indent}{carets}")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ShaderBundlerError {
+    /// A library with the name was already added
+    ModuleAlreadyExists(String),
+    /// A library used by used_by was never added
+    UnknownDependency { name: String, used_by: String },
+    /// A '//use' or '//include' line without a valid library name
+    InvalidDependencyName(SourceLocation),
+    /// Libraries using each other, the first and last element are the same library
+    CircularDependency(Vec<String>),
+    /// An '//if' with a condition that could not be parsed
+    InvalidCondition {
+        condition: String,
+        location: SourceLocation,
+    },
+    /// An '//if' without a matching '//endif'
+    UnbalancedIf { opened_at: SourceLocation },
+    /// An '//endif' without a matching '//if'
+    UnmatchedEndif(SourceLocation),
+    /// An '//else' outside of an '//if' block
+    UnmatchedElse(SourceLocation),
+    /// A second '//else' in the same '//if' block
+    DuplicateElse(SourceLocation),
+    /// No longer returned, unbalanced '//if', '//else' and '//endif' lines are reported with their location
+    #[deprecated(note = "use UnbalancedIf, UnmatchedEndif, UnmatchedElse or DuplicateElse")]
+    CommentError(String),
+    /// Libraries including each other, or includes nested too deeply, chain starts with the source that was bundled
+    IncludeRecursion {
+        chain: Vec<String>,
+        location: SourceLocation,
+    },
+    /// A '#{NAME}' token without a define
+    UndefinedDefine {
+        name: String,
+        location: SourceLocation,
+    },
+    /// A compute bundle did not have exactly one '@compute' entry point, contains the names of the found entry points
+    ComputeEntryPoints(Vec<String>),
+    /// A top level function, struct, const, override or alias defined by more than one library, in the order they appear in the bundle.
+    /// Only checked if enabled with [ShaderBundler::set_check_duplicate_symbols](crate::shader::ShaderBundler::set_check_duplicate_symbols)
+    DuplicateSymbol {
+        name: String,
+        libraries: Vec<String>,
+    },
+    /// A [passthrough](crate::shader::ShaderSourceKind::Passthrough) source was bundled, as interface, implementor, '//use' dependency or '//include'
+    CannotBundlePassthrough { name: String },
+}
+
+impl fmt::Display for ShaderBundlerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ModuleAlreadyExists(name) => write!(f, "library '{name}' already exists"),
+            Self::UnknownDependency { name, used_by } => {
+                write!(f, "unknown library '{name}' used by '{used_by}'")
+            }
+            Self::InvalidDependencyName(location) => {
+                write!(f, "invalid library name in //use or //include\n{location}")
+            }
+            Self::CircularDependency(cycle) => {
+                write!(f, "circular dependency: {}", cycle.join(" -> "))
+            }
+            Self::InvalidCondition {
+                condition,
+                location,
+            } => write!(f, "invalid condition '{condition}'\n{location}"),
+            Self::UnbalancedIf { opened_at } => {
+                write!(f, "missing //endif for //if\n{opened_at}")
+            }
+            Self::UnmatchedEndif(location) => {
+                write!(f, "found //endif without matching //if\n{location}")
+            }
+            Self::UnmatchedElse(location) => {
+                write!(f, "found //else without matching //if\n{location}")
+            }
+            Self::DuplicateElse(location) => write!(f, "found //else twice\n{location}"),
+            #[allow(deprecated)]
+            Self::CommentError(message) => write!(f, "{message}"),
+            Self::IncludeRecursion { chain, location } => {
+                write!(f, "include recursion: {}\n{location}", chain.join(" -> "))
+            }
+            Self::UndefinedDefine { name, location } => {
+                write!(f, "undefined define '{name}'\n{location}")
+            }
+            Self::ComputeEntryPoints(found) => write!(
+                f,
+                "expected exactly one @compute entry point, found [{}]",
+                found.join(", ")
+            ),
+            Self::DuplicateSymbol { name, libraries } => write!(
+                f,
+                "'{name}' is defined by multiple libraries: {}, add '//allow-duplicate {name}' if this is intended",
+                libraries.join(", ")
+            ),
+            Self::CannotBundlePassthrough { name } => {
+                write!(f, "'{name}' is a passthrough source and can not be bundled")
+            }
+        }
+    }
+}
+
+impl Error for ShaderBundlerError {}
+
+/// Returned when removing a library that other libraries use
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StillReferenced {
+    pub library: String,
+    /// The libraries with a '//use' or '//include' for the library, sorted
+    pub used_by: Vec<String>,
+}
+
+impl fmt::Display for StillReferenced {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "library '{}' is still used by {}",
+            self.library,
+            self.used_by.join(", ")
+        )
+    }
+}
+
+impl Error for StillReferenced {}
@@ -0,0 +1,177 @@
+use std::fmt;
+
+/// A set of flags for bundling, see [ShaderBundler::bundle](super::ShaderBundler::bundle).
+/// Flags are kept sorted and deduplicated, so sets with the same flags are equal and hash the same no matter the order they were added in.
+/// Flags are case sensitive, like the conditions using them
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "Vec<String>", into = "Vec<String>")
+)]
+pub struct ShaderFlags(Vec<String>);
+
+impl ShaderFlags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, flag: impl Into<String>) -> Self {
+        self.insert(flag);
+        self
+    }
+
+    /// Adds a flag, returns false if it was already set
+    pub fn insert(&mut self, flag: impl Into<String>) -> bool {
+        let flag = flag.into();
+        match self.0.binary_search(&flag) {
+            Ok(_) => false,
+            Err(idx) => {
+                self.0.insert(idx, flag);
+                true
+            }
+        }
+    }
+
+    /// Removes a flag, returns false if it was not set
+    pub fn remove(&mut self, flag: &str) -> bool {
+        match self.0.binary_search_by(|f| f.as_str().cmp(flag)) {
+            Ok(idx) => {
+                self.0.remove(idx);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    pub fn contains(&self, flag: &str) -> bool {
+        self.0.binary_search_by(|f| f.as_str().cmp(flag)).is_ok()
+    }
+
+    /// The flags in sorted order
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<S: Into<String>> FromIterator<S> for ShaderFlags {
+    fn from_iter<T: IntoIterator<Item = S>>(iter: T) -> Self {
+        let mut flags: Vec<String> = iter.into_iter().map(Into::into).collect();
+        flags.sort_unstable();
+        flags.dedup();
+        Self(flags)
+    }
+}
+
+impl<S: Into<String>> Extend<S> for ShaderFlags {
+    fn extend<T: IntoIterator<Item = S>>(&mut self, iter: T) {
+        for flag in iter {
+            self.insert(flag);
+        }
+    }
+}
+
+impl From<Vec<String>> for ShaderFlags {
+    fn from(value: Vec<String>) -> Self {
+        value.into_iter().collect()
+    }
+}
+
+impl From<&[&str]> for ShaderFlags {
+    fn from(value: &[&str]) -> Self {
+        value.iter().copied().collect()
+    }
+}
+
+impl<S: Into<String>, const N: usize> From<[S; N]> for ShaderFlags {
+    fn from(value: [S; N]) -> Self {
+        value.into_iter().collect()
+    }
+}
+
+impl From<ShaderFlags> for Vec<String> {
+    fn from(value: ShaderFlags) -> Self {
+        value.0
+    }
+}
+
+impl<'a> IntoIterator for &'a ShaderFlags {
+    type Item = &'a str;
+    type IntoIter = std::iter::Map<std::slice::Iter<'a, String>, fn(&String) -> &str>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter().map(String::as_str)
+    }
+}
+
+/// Formats as '[FLAG_A, FLAG_B]'
+impl fmt::Display for ShaderFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}]", self.0.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasher;
+
+    use modula_utils::hashbrown::hash_map::DefaultHashBuilder;
+
+    use super::*;
+
+    fn hash(flags: &ShaderFlags) -> u64 {
+        DefaultHashBuilder::default().hash_one(flags)
+    }
+
+    #[test]
+    fn order_does_not_matter() {
+        let mut inserted = ShaderFlags::new();
+        for flag in ["SHADOW", "ALPHA", "MASK"] {
+            inserted.insert(flag);
+        }
+        let built = ShaderFlags::new().with("MASK").with("SHADOW").with("ALPHA");
+        let collected: ShaderFlags = ["ALPHA", "MASK", "SHADOW"].into();
+        let mut extended = ShaderFlags::new();
+        extended.extend(["MASK", "ALPHA", "SHADOW"]);
+        for flags in [&built, &collected, &extended] {
+            assert_eq!(&inserted, flags);
+            assert_eq!(hash(&inserted), hash(flags));
+        }
+        assert_eq!(
+            inserted.iter().collect::<Vec<_>>(),
+            ["ALPHA", "MASK", "SHADOW"]
+        );
+    }
+
+    #[test]
+    fn duplicates_are_ignored() {
+        let mut flags = ShaderFlags::new().with("A");
+        assert!(!flags.insert("A"));
+        let collected: ShaderFlags = ["A", "B", "A"].into();
+        assert_eq!(collected, ShaderFlags::from(["B", "A"]));
+        assert_eq!(hash(&collected), hash(&["A", "B"].into()));
+        assert_eq!(collected.len(), 2);
+        assert!(flags.insert("B"));
+        assert_eq!(flags, collected);
+    }
+
+    #[test]
+    fn removed_flags_compare_equal() {
+        let mut flags = ShaderFlags::from(["A", "B", "C"]);
+        assert!(flags.remove("B"));
+        assert!(!flags.remove("B"));
+        assert_eq!(flags, ["C", "A"].into());
+        assert_eq!(hash(&flags), hash(&["C", "A"].into()));
+        // flags are case sensitive
+        assert_ne!(flags, ["a", "c"].into());
+        assert!(flags.contains("A") && !flags.contains("a"));
+    }
+}
@@ -0,0 +1,320 @@
+use std::{
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bevy_ecs::prelude::*;
+use modula_core::{PreInit, ScheduleBuilder};
+use modula_utils::{HashMap, HashSet};
+
+use super::{
+    dependency_list, include_name, ShaderBundler, ShaderBundlerError, ShaderLoadSet,
+    ShaderModuleSource, ShaderQueue,
+};
+use crate::PreDraw;
+
+/// Inserts a [ShaderWatcher] resource, and reloads its changed libraries during [PreDraw] before [ShaderLoadSet].
+/// The modules bundled by the [ShaderQueue] that use a reloaded library are bundled again, and keep their current module if that fails.
+/// Pipelines made from those modules are not rebuilt, owners of pipelines can read [ShaderLibraryChanged] to do that.  
+/// Must be added after [init_shader_bundling](super::init_shader_bundling)
+pub fn init_shader_hot_reload(schedule_builder: &mut ScheduleBuilder) {
+    schedule_builder.add_systems(PreInit, |mut commands: Commands| {
+        commands.insert_resource(ShaderWatcher::new());
+        commands.insert_resource(Events::<ShaderLibraryChanged>::default());
+    });
+    schedule_builder.add_systems(
+        PreDraw,
+        (
+            |mut changed: ResMut<Events<ShaderLibraryChanged>>| changed.update(),
+            reload_shader_libraries,
+        )
+            .chain()
+            .before(ShaderLoadSet),
+    );
+}
+
+/// Sent when a library was reloaded by a [`ShaderWatcher`], shaders using it should be bundled again.  
+/// Events can be read until the end of the next frame
+#[derive(Event, Clone, Debug, PartialEq, Eq)]
+pub struct ShaderLibraryChanged {
+    pub name: String,
+}
+
+#[derive(Debug)]
+pub enum ShaderWatchError {
+    Io(PathBuf, io::Error),
+    Bundler(ShaderBundlerError),
+}
+
+impl fmt::Display for ShaderWatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderWatchError::Io(path, e) => {
+                write!(f, "failed to read '{}': {e}", path.display())
+            }
+            ShaderWatchError::Bundler(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for ShaderWatchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ShaderWatchError::Io(_, e) => Some(e),
+            ShaderWatchError::Bundler(e) => Some(e),
+        }
+    }
+}
+
+/// Reloads libraries from files when they change, by polling their modification time.  
+/// Used as a resource by [init_shader_hot_reload], but can also be polled manually
+#[derive(Resource, Default)]
+pub struct ShaderWatcher {
+    files: HashMap<String, WatchedFile>,
+}
+
+struct WatchedFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ShaderWatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads a library from a file and watches it, the source is named after the path
+    pub fn add_library_file(
+        &mut self,
+        bundler: &mut ShaderBundler,
+        name: String,
+        path: impl Into<PathBuf>,
+    ) -> Result<(), ShaderWatchError> {
+        let path = path.into();
+        let modified = modified(&path);
+        let source = load(&path)?;
+        bundler
+            .add_library(name.clone(), source)
+            .map_err(ShaderWatchError::Bundler)?;
+        self.files.insert(name, WatchedFile { path, modified });
+        Ok(())
+    }
+
+    /// Reloads the files that changed since the last poll, returning an event for every replaced library.
+    /// Files that fail to load are logged, and the previous library is kept so shaders can still be bundled
+    pub fn poll(&mut self, bundler: &mut ShaderBundler) -> Vec<ShaderLibraryChanged> {
+        let mut changed = Vec::new();
+        for (name, file) in self.files.iter_mut() {
+            let modified = modified(&file.path);
+            if modified == file.modified {
+                continue;
+            }
+            // not retrying until the file changes again
+            file.modified = modified;
+            let res = load(&file.path).and_then(|source| {
+                bundler
+                    .replace_library(name.clone(), source)
+                    .map_err(ShaderWatchError::Bundler)
+            });
+            match res {
+                Ok(_) => changed.push(ShaderLibraryChanged { name: name.clone() }),
+                Err(e) => log::error!("failed to reload shader library '{name}': {e}"),
+            }
+        }
+        changed
+    }
+}
+
+fn reload_shader_libraries(
+    mut watcher: ResMut<ShaderWatcher>,
+    mut bundler: ResMut<ShaderBundler>,
+    mut shader_queue: ResMut<ShaderQueue>,
+    mut changed: EventWriter<ShaderLibraryChanged>,
+) {
+    let reloaded = watcher.poll(&mut bundler);
+    if reloaded.is_empty() {
+        return;
+    }
+    let names: HashSet<&str> = reloaded.iter().map(|e| e.name.as_str()).collect();
+    let rebundled = shader_queue.rebundle_using(&bundler, &names);
+    log::info!(
+        "reloaded shader libraries {names:?}, bundling {} shader modules again",
+        rebundled.len()
+    );
+    // names borrows the events
+    drop(names);
+    changed.send_batch(reloaded);
+}
+
+impl ShaderBundler {
+    /// Whether bundling interface and implementor reads any of the libraries, through '//use' or '//include'.  
+    /// Includes in excluded '//if' blocks are also counted, and sources with invalid dependencies count as using them, so the error is reported when bundling again
+    pub(crate) fn uses_any_library(
+        &self,
+        interface: &ShaderModuleSource,
+        implementor: &ShaderModuleSource,
+        libraries: &HashSet<&str>,
+    ) -> bool {
+        let Ok(used) = dependency_list(self, interface, implementor) else {
+            return true;
+        };
+        if used.iter().any(|name| libraries.contains(name.as_str())) {
+            return true;
+        }
+        let mut sources: Vec<_> = used
+            .iter()
+            .map(|name| &self.libraries[name].source)
+            .chain([interface, implementor])
+            .collect();
+        let mut included = HashSet::new();
+        while let Some(source) = sources.pop() {
+            for name in source.code().lines().filter_map(|l| include_name(l.trim())) {
+                if libraries.contains(name) {
+                    return true;
+                }
+                if included.insert(name) {
+                    sources.extend(self.libraries.get(name).map(|lib| &lib.source));
+                }
+            }
+        }
+        false
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn load(path: &Path) -> Result<ShaderModuleSource, ShaderWatchError> {
+    let source =
+        fs::read_to_string(path).map_err(|e| ShaderWatchError::Io(path.to_path_buf(), e))?;
+    Ok(ShaderModuleSource::new(source).with_name(path.display().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use modula_asset::Assets;
+    use wgpu::ShaderModule;
+
+    use super::*;
+    use crate::shader::ShaderFlags;
+
+    fn source(code: &str) -> ShaderModuleSource {
+        ShaderModuleSource::new(code.into())
+    }
+
+    fn names<'a>(names: &[&'a str]) -> HashSet<&'a str> {
+        names.iter().copied().collect()
+    }
+
+    #[test]
+    fn uses_libraries() {
+        let mut bundler = ShaderBundler::new();
+        bundler.add_library("base".into(), source("base")).unwrap();
+        bundler
+            .add_library("mid".into(), source("//use base\n//include part"))
+            .unwrap();
+        bundler.add_library("part".into(), source("part")).unwrap();
+        bundler
+            .add_library("other".into(), source("other"))
+            .unwrap();
+
+        let interface = source("//use mid");
+        let implementor = source("//if(A)\n//include other\n//endif");
+        let uses = |libraries: &[&str]| {
+            bundler.uses_any_library(&interface, &implementor, &names(libraries))
+        };
+        assert!(uses(&["mid"]));
+        assert!(uses(&["base"]));
+        assert!(uses(&["part"]));
+        // counted even though the include is excluded without A
+        assert!(uses(&["other"]));
+        assert!(!uses(&["unused"]));
+
+        // unresolved dependencies are bundled again to report the error
+        assert!(bundler.uses_any_library(&source("//use missing"), &source(""), &names(&["x"])));
+    }
+
+    #[test]
+    fn missing_file_error() {
+        let path = std::env::temp_dir().join("modula_hot_reload_missing.wgsl");
+        let mut bundler = ShaderBundler::new();
+        let err = ShaderWatcher::new()
+            .add_library_file(&mut bundler, "lib".into(), &path)
+            .unwrap_err();
+        assert!(matches!(err, ShaderWatchError::Io(..)));
+        assert!(err.to_string().contains(&path.display().to_string()));
+        assert!(err.source().is_some());
+        assert!(!bundler.contains("lib"));
+    }
+
+    #[test]
+    fn reload_rebundles_dependents() {
+        let dir = std::env::temp_dir().join(format!("modula_hot_reload_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("lib.wgsl");
+        fs::write(&path, "const A = 1;").unwrap();
+
+        let mut world = World::new();
+        let mut bundler = ShaderBundler::new();
+        let mut watcher = ShaderWatcher::new();
+        watcher
+            .add_library_file(&mut bundler, "lib".into(), &path)
+            .unwrap();
+        bundler.add_library("unrelated".into(), source("")).unwrap();
+        let mut shader_queue = ShaderQueue::default();
+        let mut modules = Assets::<ShaderModule>::new();
+        let (dependent, independent) = (modules.add_empty(), modules.add_empty());
+        let flags = ShaderFlags::new();
+        shader_queue.bundle(
+            dependent,
+            Arc::new(source("//use lib")),
+            Arc::new(source("")),
+            &flags,
+            &[],
+        );
+        shader_queue.bundle(
+            independent,
+            Arc::new(source("//use unrelated")),
+            Arc::new(source("")),
+            &flags,
+            &[],
+        );
+        assert_eq!(
+            shader_queue.rebundle_using(&bundler, &names(&["lib"])),
+            [dependent]
+        );
+
+        world.insert_resource(bundler);
+        world.insert_resource(watcher);
+        world.insert_resource(shader_queue);
+        world.insert_resource(Events::<ShaderLibraryChanged>::default());
+        let mut system = IntoSystem::into_system(reload_shader_libraries);
+        system.initialize(&mut world);
+
+        // unchanged files are not reloaded
+        system.run((), &mut world);
+        assert!(world.resource::<Events<ShaderLibraryChanged>>().is_empty());
+
+        fs::write(&path, "const A = 2;").unwrap();
+        // the modification time may not change if written too fast
+        let later = SystemTime::now() + std::time::Duration::from_secs(10);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(later))
+            .unwrap();
+        let generation = world.resource::<ShaderBundler>().generation();
+        system.run((), &mut world);
+        let events = world.resource::<Events<ShaderLibraryChanged>>();
+        let sent: Vec<_> = events.get_reader().read(events).cloned().collect();
+        assert_eq!(sent, [ShaderLibraryChanged { name: "lib".into() }]);
+        assert!(world.resource::<ShaderBundler>().generation() > generation);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
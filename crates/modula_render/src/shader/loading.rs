@@ -0,0 +1,269 @@
+use std::{
+    future::Future,
+    pin::pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+};
+
+use bevy_ecs::{prelude::*, system::SystemParam};
+use modula_asset::{AssetId, Assets};
+use modula_core::{DeviceRes, PreInit, ScheduleBuilder};
+#[cfg(feature = "hot_reload")]
+use modula_utils::HashMap;
+use modula_utils::HashSet;
+use wgpu::{Device, ErrorFilter, ShaderModule, ShaderModuleDescriptor, ShaderSource};
+
+use super::{ShaderBundler, ShaderDefineValue, ShaderFlags, ShaderModuleSource, ShaderSourceKind};
+use crate::PreDraw;
+
+/// Systems that create shader modules during [PreDraw], anything that runs in [PreDraw] and needs shader modules should run after this
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ShaderLoadSet;
+
+/// Inserts a [ShaderBundler] resource, and makes [ShaderModule] assets from the [ShaderQueue] during [PreDraw]
+pub fn init_shader_bundling(schedule_builder: &mut ScheduleBuilder) {
+    modula_asset::init_assets::<ShaderModule>(schedule_builder);
+    schedule_builder.add_systems(PreInit, |mut commands: Commands| {
+        commands.insert_resource(ShaderBundler::new());
+        commands.insert_resource(ShaderQueue::default());
+    });
+    schedule_builder.add_systems(PreDraw, load_shaders.in_set(ShaderLoadSet));
+}
+
+/// Used to put bundled shader modules in assets, if the goal is to just load a shader consider [ShaderLoader]
+#[derive(Resource, Default)]
+pub struct ShaderQueue {
+    queue: Vec<ShaderRequest>,
+    failed: HashSet<AssetId<ShaderModule>>,
+    /// The last bundle request of every asset, to bundle it again when its libraries are reloaded
+    #[cfg(feature = "hot_reload")]
+    bundled: HashMap<AssetId<ShaderModule>, ShaderRequestKind>,
+}
+
+struct ShaderRequest {
+    asset_id: AssetId<ShaderModule>,
+    kind: ShaderRequestKind,
+}
+
+#[derive(Clone)]
+enum ShaderRequestKind {
+    Bundle {
+        interface: Arc<ShaderModuleSource>,
+        implementor: Arc<ShaderModuleSource>,
+        flags: ShaderFlags,
+        defines: Vec<(String, ShaderDefineValue)>,
+    },
+    /// Used without bundling
+    Source(Arc<ShaderModuleSource>),
+}
+
+impl ShaderQueue {
+    /// Bundles a shader using [ShaderBundler::bundle] and puts the module in the given asset, replacing the current module.
+    /// If bundling or compiling fails the current module is kept, and the asset is marked as failed
+    pub fn bundle(
+        &mut self,
+        asset_id: AssetId<ShaderModule>,
+        interface: Arc<ShaderModuleSource>,
+        implementor: Arc<ShaderModuleSource>,
+        flags: &ShaderFlags,
+        defines: &[(&str, ShaderDefineValue)],
+    ) {
+        let kind = ShaderRequestKind::Bundle {
+            interface,
+            implementor,
+            flags: flags.clone(),
+            defines: defines
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.clone()))
+                .collect(),
+        };
+        #[cfg(feature = "hot_reload")]
+        self.bundled.insert(asset_id, kind.clone());
+        self.queue.push(ShaderRequest { asset_id, kind });
+    }
+
+    /// Same as [bundle](Self::bundle), but the module is created from the source without bundling, for example for [passthrough](ShaderModuleSource::passthrough) sources.
+    /// Wgsl sources are used as is, so their '//use' and '//if' lines are ignored
+    pub fn create(&mut self, asset_id: AssetId<ShaderModule>, source: Arc<ShaderModuleSource>) {
+        #[cfg(feature = "hot_reload")]
+        self.bundled.remove(&asset_id);
+        self.queue.push(ShaderRequest {
+            asset_id,
+            kind: ShaderRequestKind::Source(source),
+        });
+    }
+
+    /// Whether the last module bundled for the asset failed to bundle or compile
+    pub fn has_failed(&self, asset_id: AssetId<ShaderModule>) -> bool {
+        self.failed.contains(&asset_id)
+    }
+
+    /// Queues the modules bundled from any of the libraries again, returning their assets.
+    /// Modules that fail to bundle keep their current module like with [bundle](Self::bundle)
+    #[cfg(feature = "hot_reload")]
+    pub(super) fn rebundle_using(
+        &mut self,
+        bundler: &ShaderBundler,
+        libraries: &HashSet<&str>,
+    ) -> Vec<AssetId<ShaderModule>> {
+        let mut rebundled = Vec::new();
+        for (asset_id, kind) in &self.bundled {
+            let ShaderRequestKind::Bundle {
+                interface,
+                implementor,
+                ..
+            } = kind
+            else {
+                continue;
+            };
+            if bundler.uses_any_library(interface, implementor, libraries) {
+                self.queue.push(ShaderRequest {
+                    asset_id: *asset_id,
+                    kind: kind.clone(),
+                });
+                rebundled.push(*asset_id);
+            }
+        }
+        rebundled
+    }
+
+    /// Stops bundling the modules of removed assets again when their libraries are reloaded.
+    /// Failed assets are kept, as they are missing because they never got a module
+    #[cfg(feature = "hot_reload")]
+    fn forget_removed(&mut self, exists: impl Fn(AssetId<ShaderModule>) -> bool) {
+        let failed = &self.failed;
+        self.bundled
+            .retain(|asset_id, _| exists(*asset_id) || failed.contains(asset_id));
+    }
+}
+
+#[derive(SystemParam)]
+pub struct ShaderLoader<'w> {
+    shader_queue: ResMut<'w, ShaderQueue>,
+    shader_assets: ResMut<'w, Assets<ShaderModule>>,
+}
+
+impl ShaderLoader<'_> {
+    /// loads a bundled shader, the asset is empty until the next [PreDraw]
+    pub fn load_shader(
+        &mut self,
+        interface: Arc<ShaderModuleSource>,
+        implementor: Arc<ShaderModuleSource>,
+        flags: &ShaderFlags,
+        defines: &[(&str, ShaderDefineValue)],
+    ) -> AssetId<ShaderModule> {
+        let asset_id = self.shader_assets.add_empty();
+        self.shader_queue
+            .bundle(asset_id, interface, implementor, flags, defines);
+        asset_id
+    }
+
+    /// loads a shader without bundling, see [ShaderQueue::create]
+    pub fn load_source(&mut self, source: Arc<ShaderModuleSource>) -> AssetId<ShaderModule> {
+        let asset_id = self.shader_assets.add_empty();
+        self.shader_queue.create(asset_id, source);
+        asset_id
+    }
+}
+
+fn load_shaders(
+    mut shader_queue: ResMut<ShaderQueue>,
+    mut shader_assets: ResMut<Assets<ShaderModule>>,
+    mut bundler: ResMut<ShaderBundler>,
+    device: Res<DeviceRes>,
+) {
+    let shader_queue = &mut *shader_queue;
+    for request in shader_queue.queue.drain(..) {
+        match load_shader(&request.kind, &mut bundler, &device.0) {
+            Ok(module) => {
+                shader_queue.failed.remove(&request.asset_id);
+                shader_assets.replace(request.asset_id, module);
+            }
+            Err(e) => {
+                log::error!("failed to load shader: {e}");
+                shader_queue.failed.insert(request.asset_id);
+            }
+        }
+    }
+    // the queue is empty, so every module bundled before has been made
+    #[cfg(feature = "hot_reload")]
+    shader_queue.forget_removed(|asset_id| shader_assets.get(asset_id).is_some());
+}
+
+fn load_shader(
+    request: &ShaderRequestKind,
+    bundler: &mut ShaderBundler,
+    device: &Device,
+) -> Result<ShaderModule, String> {
+    let (label, source) = match request {
+        ShaderRequestKind::Bundle {
+            interface,
+            implementor,
+            flags,
+            defines,
+        } => {
+            let defines: Vec<_> = defines
+                .iter()
+                .map(|(n, v)| (n.as_str(), v.clone()))
+                .collect();
+            let (code, _source_map) = bundler
+                .bundle_cached_with_source_map(interface, implementor, flags, &defines)
+                .map_err(|e| e.to_string())?;
+            // naga gives errors pointing into the libraries, wgpu points into the bundle
+            #[cfg(feature = "naga")]
+            super::validate::validate(code, _source_map).map_err(|e| e.to_string())?;
+            (interface.name(), ShaderSource::Wgsl(code.into()))
+        }
+        ShaderRequestKind::Source(source) => {
+            let module_source = match source.kind() {
+                ShaderSourceKind::Wgsl(code) => ShaderSource::Wgsl(code.as_str().into()),
+                ShaderSourceKind::Passthrough(module_source) => module_source.clone(),
+            };
+            (source.name(), module_source)
+        }
+    };
+    device.push_error_scope(ErrorFilter::Validation);
+    let module = device.create_shader_module(ShaderModuleDescriptor { label, source });
+    // the error scope is resolved immediately on native
+    let mut error = pin!(device.pop_error_scope());
+    match error.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(Some(e)) => Err(e.to_string()),
+        _ => Ok(module),
+    }
+}
+
+#[cfg(all(test, feature = "hot_reload"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed_assets_are_forgotten() {
+        let mut modules = Assets::<ShaderModule>::new();
+        let (kept, removed, failed) = (
+            modules.add_empty(),
+            modules.add_empty(),
+            modules.add_empty(),
+        );
+        let mut shader_queue = ShaderQueue::default();
+        for asset_id in [kept, removed, failed] {
+            shader_queue.bundle(
+                asset_id,
+                Arc::new(ShaderModuleSource::new("//use lib".into())),
+                Arc::new(ShaderModuleSource::new(String::new())),
+                &ShaderFlags::new(),
+                &[],
+            );
+        }
+        shader_queue.queue.clear();
+        shader_queue.failed.insert(failed);
+        shader_queue.forget_removed(|asset_id| asset_id == kept);
+
+        let mut remaining: Vec<_> = shader_queue.bundled.keys().copied().collect();
+        remaining.sort_by_key(|asset_id| *asset_id != kept);
+        assert_eq!(remaining, [kept, failed]);
+
+        // a source replaces the bundle, so it is not bundled again either
+        shader_queue.create(kept, Arc::new(ShaderModuleSource::new(String::new())));
+        assert_eq!(shader_queue.bundled.keys().collect::<Vec<_>>(), [&failed]);
+    }
+}
@@ -0,0 +1,202 @@
+use super::BundleSourceMap;
+
+/// Changes to the output of a [ShaderBundler](super::ShaderBundler), set with [set_options](super::ShaderBundler::set_options).
+/// The default keeps the bundle unchanged
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BundleOptions {
+    /// Removes '//' comments after flags are applied, including the '//use' lines, along with the whitespace before them
+    pub strip_comments: bool,
+    /// Removes lines that are empty or only whitespace, including lines that only contained a comment if comments are stripped
+    pub strip_blank_lines: bool,
+    /// Inserts '// library:line' lines before the first line of every source, and every [line_marker_interval](Self::line_marker_interval) lines,
+    /// so errors pointing into the bundle can be traced without a [BundleSourceMap]
+    pub emit_line_markers: bool,
+    /// 0 only inserts markers where sources start, 32 by default
+    pub line_marker_interval: usize,
+}
+
+impl Default for BundleOptions {
+    fn default() -> Self {
+        Self {
+            strip_comments: false,
+            strip_blank_lines: false,
+            emit_line_markers: false,
+            line_marker_interval: 32,
+        }
+    }
+}
+
+impl BundleOptions {
+    /// Strips comments and blank lines, for smaller shaders in release builds
+    pub fn release() -> Self {
+        Self {
+            strip_comments: true,
+            strip_blank_lines: true,
+            ..Default::default()
+        }
+    }
+
+    /// Emits line markers, to find the source of driver errors in debug builds
+    pub fn debug() -> Self {
+        Self {
+            emit_line_markers: true,
+            ..Default::default()
+        }
+    }
+
+    /// Applies the options to a bundle, returning the new code and source map
+    pub(super) fn apply(
+        &self,
+        code: String,
+        source_map: BundleSourceMap,
+    ) -> (String, BundleSourceMap) {
+        if !self.strip_comments && !self.strip_blank_lines && !self.emit_line_markers {
+            return (code, source_map);
+        }
+        let mut res = String::with_capacity(code.len());
+        let mut res_map = BundleSourceMap::default();
+        // the source of the last marker and the number of lines after it
+        let mut marked: Option<(&str, usize)> = None;
+        for (i, mut line) in code.split_terminator('\n').enumerate() {
+            let (source, source_line) = source_map
+                .map_line(i + 1)
+                .expect("every line of a bundle is mapped");
+            if self.strip_comments {
+                if let Some((before, _)) = line.split_once("//") {
+                    line = before.trim_end();
+                }
+            }
+            if self.strip_blank_lines && line.trim().is_empty() {
+                continue;
+            }
+            let source_idx = res_map.source_index(source);
+            if self.emit_line_markers {
+                let mark = marked.is_none_or(|(marked_source, lines)| {
+                    marked_source != source
+                        || (self.line_marker_interval != 0 && lines >= self.line_marker_interval)
+                });
+                if mark {
+                    // markers are mapped to the line they mark
+                    res_map.push_line(res.len(), source_idx, source_line - 1);
+                    res.push_str(&format!("// {source}:{source_line}\n"));
+                    marked = Some((source, 0));
+                }
+            }
+            res_map.push_line(res.len(), source_idx, source_line - 1);
+            res.push_str(line);
+            res.push('\n');
+            if let Some((_, lines)) = &mut marked {
+                *lines += 1;
+            }
+        }
+        (res, res_map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{ShaderBundler, ShaderFlags, ShaderModuleSource};
+    use super::*;
+
+    /// The bundle of an implementor using a library, with the options
+    fn bundle(options: BundleOptions) -> (Vec<String>, BundleSourceMap) {
+        let mut bundler = ShaderBundler::new();
+        bundler
+            .add_library(
+                "lib".into(),
+                ShaderModuleSource::new("// the library\n\nfn lib() {} // trailing\n".into()),
+            )
+            .unwrap();
+        bundler.set_options(options);
+        let (code, source_map) = bundler
+            .bundle_with_source_map(
+                &ShaderModuleSource::new(String::new()),
+                &ShaderModuleSource::new("//use lib\nfn main() {\n\n    lib();\n}".into()),
+                &ShaderFlags::new(),
+                &[],
+            )
+            .unwrap();
+        (code.lines().map(String::from).collect(), source_map)
+    }
+
+    #[test]
+    fn default_keeps_the_bundle() {
+        let (lines, _) = bundle(BundleOptions::default());
+        assert!(lines.contains(&"// the library".to_string()));
+        assert!(lines.contains(&"fn lib() {} // trailing".to_string()));
+        assert!(lines.contains(&"//use lib".to_string()));
+        assert!(lines.iter().any(String::is_empty));
+    }
+
+    #[test]
+    fn release_strips_comments_and_blank_lines() {
+        let (lines, source_map) = bundle(BundleOptions::release());
+        assert_eq!(lines, ["fn lib() {}", "fn main() {", "    lib();", "}"]);
+        // the source map points at the lines they came from
+        assert_eq!(source_map.map_line(1), Some(("lib", 3)));
+        assert_eq!(source_map.map_line(3), Some(("implementor", 4)));
+    }
+
+    #[test]
+    fn only_blank_lines_stripped() {
+        let (lines, _) = bundle(BundleOptions {
+            strip_blank_lines: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            lines,
+            [
+                "// the library",
+                "fn lib() {} // trailing",
+                "//use lib",
+                "fn main() {",
+                "    lib();",
+                "}"
+            ]
+        );
+    }
+
+    #[test]
+    fn markers_are_inserted_where_sources_start() {
+        let (lines, source_map) = bundle(BundleOptions {
+            emit_line_markers: true,
+            strip_blank_lines: true,
+            line_marker_interval: 0,
+            ..Default::default()
+        });
+        assert_eq!(
+            lines,
+            [
+                "// lib:1",
+                "// the library",
+                "fn lib() {} // trailing",
+                "// implementor:1",
+                "//use lib",
+                "fn main() {",
+                "    lib();",
+                "}"
+            ]
+        );
+        // markers map to the line they mark
+        assert_eq!(source_map.map_line(4), Some(("implementor", 1)));
+        assert_eq!(source_map.map_line(5), Some(("implementor", 1)));
+    }
+
+    #[test]
+    fn markers_are_repeated_every_interval() {
+        let (lines, _) = bundle(BundleOptions {
+            emit_line_markers: true,
+            strip_blank_lines: true,
+            line_marker_interval: 2,
+            ..Default::default()
+        });
+        let markers: Vec<_> = lines
+            .iter()
+            .filter(|l| l.starts_with("// ") && l.contains(':'))
+            .collect();
+        assert_eq!(
+            markers,
+            ["// lib:1", "// implementor:1", "// implementor:4"]
+        );
+    }
+}
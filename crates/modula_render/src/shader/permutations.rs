@@ -0,0 +1,282 @@
+use std::borrow::Cow;
+
+use modula_utils::HashMap;
+use wgpu::ShaderSource;
+
+#[cfg(feature = "naga")]
+use super::ShaderValidationError;
+use super::{
+    apply_flags, effective_flags, include_name, is_if, push_kept_lines, tokenize, BundleSourceMap,
+    ConditionToken, FlagContext, KeptLines, ShaderBundler, ShaderBundlerError, ShaderDefineValue,
+    ShaderFlags, ShaderModuleSource, SourceLines,
+};
+
+/// The result of every bundled permutation
+type Permutations<T> = Vec<(ShaderFlags, Result<T, ShaderBundlerError>)>;
+
+impl ShaderBundler {
+    /// Bundles every combination of flags, see [`bundle_permutations_filtered`](Self::bundle_permutations_filtered)
+    pub fn bundle_permutations(
+        &self,
+        interface: &ShaderModuleSource,
+        implementor: &ShaderModuleSource,
+        flags: &ShaderFlags,
+        defines: &[(&str, ShaderDefineValue)],
+    ) -> Permutations<ShaderSource<'static>> {
+        self.bundle_permutations_filtered(interface, implementor, flags, defines, |_| true)
+    }
+
+    /// Bundles the combinations of flags for which filter returns true, returning the result of each with its flags.
+    /// The defines are used for every permutation.
+    /// Flags are only applied again to a source if the flags used by its conditions changed, so libraries without conditions are only processed once.
+    /// Panics with more than 63 flags
+    pub fn bundle_permutations_filtered(
+        &self,
+        interface: &ShaderModuleSource,
+        implementor: &ShaderModuleSource,
+        flags: &ShaderFlags,
+        defines: &[(&str, ShaderDefineValue)],
+        filter: impl Fn(&ShaderFlags) -> bool,
+    ) -> Permutations<ShaderSource<'static>> {
+        self.permutations(interface, implementor, flags, defines, filter)
+            .into_iter()
+            .map(|(set, res)| {
+                let res = res.map(|(code, _)| ShaderSource::Wgsl(Cow::Owned(code)));
+                (set, res)
+            })
+            .collect()
+    }
+
+    /// Bundles and validates the combinations of flags for which filter returns true, like [`bundle_validated`](Self::bundle_validated).
+    /// Returns the errors of the invalid permutations, so every permutation is valid if it is empty.
+    /// Useful in tests, to find invalid combinations before they are used
+    #[cfg(feature = "naga")]
+    pub fn validate_permutations(
+        &self,
+        interface: &ShaderModuleSource,
+        implementor: &ShaderModuleSource,
+        flags: &ShaderFlags,
+        defines: &[(&str, ShaderDefineValue)],
+        filter: impl Fn(&ShaderFlags) -> bool,
+    ) -> Vec<(ShaderFlags, ShaderValidationError)> {
+        self.permutations(interface, implementor, flags, defines, filter)
+            .into_iter()
+            .filter_map(|(set, res)| {
+                let err = match res {
+                    Ok((code, source_map)) => {
+                        super::validate::validate(&code, &source_map).err()?
+                    }
+                    Err(e) => e.into(),
+                };
+                Some((set, err))
+            })
+            .collect()
+    }
+
+    fn permutations(
+        &self,
+        interface: &ShaderModuleSource,
+        implementor: &ShaderModuleSource,
+        flags: &ShaderFlags,
+        defines: &[(&str, ShaderDefineValue)],
+        filter: impl Fn(&ShaderFlags) -> bool,
+    ) -> Permutations<(String, BundleSourceMap)> {
+        let flags: Vec<_> = flags.iter().collect();
+        assert!(flags.len() < 64, "at most 63 flags can be permuted");
+        // bit i of a mask is set if flags[i] is enabled
+        let variants: Vec<_> = (0..1u64 << flags.len())
+            .map(|mask| {
+                let enabled = flags.iter().enumerate().filter(|(i, _)| mask & 1 << i != 0);
+                (mask, enabled.map(|(_, f)| *f).collect::<ShaderFlags>())
+            })
+            .filter(|(_, set)| filter(set))
+            .map(|(mask, set)| {
+                let effective = effective_flags(&set, defines);
+                (mask, set, effective)
+            })
+            .collect();
+        let sources = match self.bundle_sources(interface, implementor) {
+            Ok(sources) => sources,
+            Err(e) => {
+                return variants
+                    .into_iter()
+                    .map(|(_, set, _)| (set, Err(e.clone())))
+                    .collect()
+            }
+        };
+        let sources: Vec<_> = sources
+            .iter()
+            .map(|(name, source)| {
+                let lines = SourceLines::new(source, name);
+                let relevant = relevant_flags(&lines, &flags);
+                (lines, relevant)
+            })
+            .collect();
+        let defines: HashMap<&str, String> =
+            defines.iter().map(|(n, v)| (*n, v.to_string())).collect();
+        // keyed by the index of the source and the enabled flags it uses
+        let mut applied: HashMap<(usize, u64), Result<KeptLines, ShaderBundlerError>> =
+            HashMap::new();
+        variants
+            .iter()
+            .map(|(mask, set, effective)| {
+                let ctx = FlagContext {
+                    bundler: self,
+                    flags: effective,
+                };
+                let mut code = String::new();
+                let mut source_map = BundleSourceMap::default();
+                let res = sources
+                    .iter()
+                    .enumerate()
+                    .try_for_each(|(idx, (lines, relevant))| {
+                        let kept = applied
+                            .entry((idx, mask & relevant))
+                            .or_insert_with(|| apply_flags(lines, &ctx, &mut vec![lines.name]));
+                        let kept = kept.as_ref().map_err(Clone::clone)?;
+                        push_kept_lines(&mut code, &mut source_map, kept, &defines)
+                    });
+                let res = res.and_then(|_| self.finish_bundle(code, source_map));
+                (set.clone(), res)
+            })
+            .collect()
+    }
+}
+
+/// A mask of the flags used by the conditions of a source.
+/// All flags if it has includes, as the included libraries can use any of them
+fn relevant_flags(src: &SourceLines, flags: &[&str]) -> u64 {
+    let all = (1 << flags.len()) - 1;
+    let mut res = 0;
+    for line in src.lines.iter().map(|l| l.trim()) {
+        if include_name(line).is_some() {
+            return all;
+        }
+        if !is_if(line) {
+            continue;
+        }
+        // invalid conditions error no matter the flags
+        let Some(tokens) = tokenize(&line[5..line.len() - 1]) else {
+            continue;
+        };
+        for token in tokens {
+            if let ConditionToken::Literal(lit) = token {
+                if let Some(i) = flags.iter().position(|f| *f == lit) {
+                    res |= 1 << i;
+                }
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(code: &str) -> ShaderModuleSource {
+        ShaderModuleSource::new(code.into())
+    }
+
+    fn wgsl(source: &ShaderSource) -> String {
+        match source {
+            ShaderSource::Wgsl(code) => code.to_string(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn bundler() -> ShaderBundler {
+        let mut bundler = ShaderBundler::new();
+        bundler
+            .add_library("plain".into(), source("plain"))
+            .unwrap();
+        bundler
+            .add_library(
+                "shadow".into(),
+                source("//use plain\n//if(SHADOW)\nshadow\n//else\nno_shadow\n//endif"),
+            )
+            .unwrap();
+        bundler
+    }
+
+    #[test]
+    fn every_combination_is_bundled() {
+        let bundler = bundler();
+        let interface = source("//use shadow");
+        let implementor = source("//use plain\n//if((ALPHA)&(SHADOW))\nalpha_shadow\n//endif");
+        let flags = ShaderFlags::from(["SHADOW", "ALPHA"]);
+        let permutations = bundler.bundle_permutations(&interface, &implementor, &flags, &[]);
+        let sets: Vec<_> = permutations.iter().map(|(set, _)| set.clone()).collect();
+        assert_eq!(
+            sets,
+            [
+                ShaderFlags::new(),
+                ["ALPHA"].into(),
+                ["SHADOW"].into(),
+                ["ALPHA", "SHADOW"].into()
+            ]
+        );
+        for (set, res) in &permutations {
+            let expected = bundler.bundle(&interface, &implementor, set, &[]).unwrap();
+            let code = wgsl(res.as_ref().unwrap());
+            assert_eq!(code, wgsl(&expected), "{set}");
+            // plain is used by the interface and the implementor, but only bundled once
+            assert_eq!(code.lines().filter(|l| *l == "plain").count(), 1);
+        }
+        let code = wgsl(permutations[3].1.as_ref().unwrap());
+        assert!(code.contains("alpha_shadow") && !code.contains("no_shadow"));
+    }
+
+    #[test]
+    fn filtered_permutations() {
+        let bundler = bundler();
+        let interface = source("//use shadow");
+        let implementor = source("");
+        let flags = ShaderFlags::from(["A", "B", "SHADOW"]);
+        let permutations =
+            bundler.bundle_permutations_filtered(&interface, &implementor, &flags, &[], |set| {
+                set.contains("SHADOW") && set.len() < 3
+            });
+        let sets: Vec<_> = permutations.into_iter().map(|(set, _)| set).collect();
+        assert_eq!(
+            sets,
+            [
+                ["SHADOW"].into(),
+                ["A", "SHADOW"].into(),
+                ["B", "SHADOW"].into()
+            ]
+        );
+    }
+
+    #[test]
+    fn bundle_errors_fail_every_permutation() {
+        let bundler = bundler();
+        let permutations =
+            bundler.bundle_permutations(&source("//use missing"), &source(""), &["A"].into(), &[]);
+        assert_eq!(permutations.len(), 2);
+        for (_, res) in permutations {
+            assert!(matches!(
+                res,
+                Err(ShaderBundlerError::UnknownDependency { .. })
+            ));
+        }
+    }
+
+    fn relevant(code: &str, flags: &[&str]) -> u64 {
+        let module = source(code);
+        relevant_flags(&SourceLines::new(&module, "test"), flags)
+    }
+
+    #[test]
+    fn sources_are_only_applied_again_for_their_flags() {
+        let flags = ["A", "B", "C"];
+        assert_eq!(relevant("plain", &flags), 0);
+        assert_eq!(relevant("//if(C)\nc\n//endif", &flags), 0b100);
+        assert_eq!(
+            relevant("//if((A)|!C)\na\n//endif\n//if(UNKNOWN)\n//endif", &flags),
+            0b101
+        );
+        // included libraries can use any flag
+        assert_eq!(relevant("//include plain", &flags), 0b111);
+    }
+}
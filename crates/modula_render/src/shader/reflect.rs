@@ -0,0 +1,382 @@
+use std::num::{NonZeroU32, NonZeroU64};
+
+use naga::{
+    AddressSpace, ImageClass, ImageDimension, Module, ScalarKind, ShaderStage, StorageAccess,
+    StorageFormat, TypeInner,
+};
+use wgpu::{
+    BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType,
+    BufferBindingType, Device, SamplerBindingType, ShaderStages, StorageTextureAccess,
+    TextureFormat, TextureSampleType, TextureViewDimension,
+};
+
+use super::{validate::parse_and_validate, ShaderBundler, ShaderValidationError};
+
+/// A resource binding used by a shader, found by [ShaderBundler::reflect]
+#[derive(Clone, Debug)]
+pub struct ReflectedBinding {
+    pub group: u32,
+    pub binding: u32,
+    /// The name of the global variable
+    pub name: Option<String>,
+    pub ty: BindingType,
+    /// Some for binding arrays
+    pub count: Option<NonZeroU32>,
+    /// The stages of the entry points using the binding, directly or through functions they call
+    pub visibility: ShaderStages,
+}
+
+impl ReflectedBinding {
+    pub fn layout_entry(&self) -> BindGroupLayoutEntry {
+        BindGroupLayoutEntry {
+            binding: self.binding,
+            visibility: self.visibility,
+            ty: self.ty,
+            count: self.count,
+        }
+    }
+}
+
+/// The resource bindings of a shader
+#[derive(Clone, Debug, Default)]
+pub struct ShaderInterface {
+    /// Sorted by group and then binding
+    pub bindings: Vec<ReflectedBinding>,
+}
+
+impl ShaderInterface {
+    /// The number of bind groups a pipeline layout needs, this is the highest used group + 1
+    pub fn group_count(&self) -> u32 {
+        self.bindings.last().map_or(0, |b| b.group + 1)
+    }
+
+    /// The layout entries of a group, empty if the group is not used
+    pub fn group_entries(&self, group: u32) -> Vec<BindGroupLayoutEntry> {
+        self.bindings
+            .iter()
+            .filter(|b| b.group == group)
+            .map(ReflectedBinding::layout_entry)
+            .collect()
+    }
+
+    /// The binding at group and binding, to change what reflection can not know, such as textures that are not filterable
+    pub fn binding_mut(&mut self, group: u32, binding: u32) -> Option<&mut ReflectedBinding> {
+        self.bindings
+            .iter_mut()
+            .find(|b| b.group == group && b.binding == binding)
+    }
+
+    /// Creates a layout for every group up to [group_count](Self::group_count), unused groups get empty layouts so the result can be used for a pipeline layout
+    pub fn create_bind_group_layouts(&self, device: &Device) -> Vec<BindGroupLayout> {
+        (0..self.group_count())
+            .map(|group| {
+                device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label: None,
+                    entries: &self.group_entries(group),
+                })
+            })
+            .collect()
+    }
+}
+
+impl ShaderBundler {
+    /// Finds the resource bindings of bundled wgsl code.
+    /// Float textures are assumed to be filterable, and samplers to be filtering, as this can not be known from the shader
+    pub fn reflect(code: &str) -> Result<ShaderInterface, ShaderValidationError> {
+        let (module, info) = parse_and_validate(code, None)?;
+        let mut bindings = Vec::new();
+        for (handle, var) in module.global_variables.iter() {
+            let Some(res_binding) = &var.binding else {
+                continue;
+            };
+            let Some((ty, count)) = binding_type(&module, var.space, var.ty) else {
+                continue;
+            };
+            let visibility = module
+                .entry_points
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !info.get_entry_point(*i)[handle].is_empty())
+                .fold(ShaderStages::NONE, |stages, (_, ep)| {
+                    stages
+                        | match ep.stage {
+                            ShaderStage::Vertex => ShaderStages::VERTEX,
+                            ShaderStage::Fragment => ShaderStages::FRAGMENT,
+                            ShaderStage::Compute => ShaderStages::COMPUTE,
+                        }
+                });
+            bindings.push(ReflectedBinding {
+                group: res_binding.group,
+                binding: res_binding.binding,
+                name: var.name.clone(),
+                ty,
+                count,
+                visibility,
+            });
+        }
+        bindings.sort_by_key(|b| (b.group, b.binding));
+        Ok(ShaderInterface { bindings })
+    }
+}
+
+/// None if the variable is not a resource
+fn binding_type(
+    module: &Module,
+    space: AddressSpace,
+    ty: naga::Handle<naga::Type>,
+) -> Option<(BindingType, Option<NonZeroU32>)> {
+    let mut inner = &module.types[ty].inner;
+    let mut count = None;
+    if let TypeInner::BindingArray { base, size } = inner {
+        inner = &module.types[*base].inner;
+        count = match size {
+            naga::ArraySize::Constant(c) => Some(*c),
+            naga::ArraySize::Dynamic => None,
+        };
+    }
+    let ty = match space {
+        AddressSpace::Uniform | AddressSpace::Storage { .. } => BindingType::Buffer {
+            ty: match space {
+                AddressSpace::Storage { access } => BufferBindingType::Storage {
+                    read_only: !access.contains(StorageAccess::STORE),
+                },
+                _ => BufferBindingType::Uniform,
+            },
+            has_dynamic_offset: false,
+            min_binding_size: NonZeroU64::new(inner.size(module.to_ctx()) as u64),
+        },
+        AddressSpace::Handle => match *inner {
+            TypeInner::Sampler { comparison } => BindingType::Sampler(if comparison {
+                SamplerBindingType::Comparison
+            } else {
+                SamplerBindingType::Filtering
+            }),
+            TypeInner::Image {
+                dim,
+                arrayed,
+                class,
+            } => {
+                let view_dimension = view_dimension(dim, arrayed);
+                match class {
+                    ImageClass::Sampled { kind, multi } => BindingType::Texture {
+                        sample_type: match kind {
+                            ScalarKind::Sint => TextureSampleType::Sint,
+                            ScalarKind::Uint => TextureSampleType::Uint,
+                            _ => TextureSampleType::Float { filterable: true },
+                        },
+                        view_dimension,
+                        multisampled: multi,
+                    },
+                    ImageClass::Depth { multi } => BindingType::Texture {
+                        sample_type: TextureSampleType::Depth,
+                        view_dimension,
+                        multisampled: multi,
+                    },
+                    ImageClass::Storage { format, access } => BindingType::StorageTexture {
+                        access: match (
+                            access.contains(StorageAccess::LOAD),
+                            access.contains(StorageAccess::STORE),
+                        ) {
+                            (true, true) => StorageTextureAccess::ReadWrite,
+                            (true, false) => StorageTextureAccess::ReadOnly,
+                            _ => StorageTextureAccess::WriteOnly,
+                        },
+                        format: texture_format(format),
+                        view_dimension,
+                    },
+                }
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some((ty, count))
+}
+
+fn view_dimension(dim: ImageDimension, arrayed: bool) -> TextureViewDimension {
+    match (dim, arrayed) {
+        (ImageDimension::D1, _) => TextureViewDimension::D1,
+        (ImageDimension::D2, false) => TextureViewDimension::D2,
+        (ImageDimension::D2, true) => TextureViewDimension::D2Array,
+        (ImageDimension::D3, _) => TextureViewDimension::D3,
+        (ImageDimension::Cube, false) => TextureViewDimension::Cube,
+        (ImageDimension::Cube, true) => TextureViewDimension::CubeArray,
+    }
+}
+
+fn texture_format(format: StorageFormat) -> TextureFormat {
+    match format {
+        StorageFormat::R8Unorm => TextureFormat::R8Unorm,
+        StorageFormat::R8Snorm => TextureFormat::R8Snorm,
+        StorageFormat::R8Uint => TextureFormat::R8Uint,
+        StorageFormat::R8Sint => TextureFormat::R8Sint,
+        StorageFormat::R16Uint => TextureFormat::R16Uint,
+        StorageFormat::R16Sint => TextureFormat::R16Sint,
+        StorageFormat::R16Float => TextureFormat::R16Float,
+        StorageFormat::Rg8Unorm => TextureFormat::Rg8Unorm,
+        StorageFormat::Rg8Snorm => TextureFormat::Rg8Snorm,
+        StorageFormat::Rg8Uint => TextureFormat::Rg8Uint,
+        StorageFormat::Rg8Sint => TextureFormat::Rg8Sint,
+        StorageFormat::R32Uint => TextureFormat::R32Uint,
+        StorageFormat::R32Sint => TextureFormat::R32Sint,
+        StorageFormat::R32Float => TextureFormat::R32Float,
+        StorageFormat::Rg16Uint => TextureFormat::Rg16Uint,
+        StorageFormat::Rg16Sint => TextureFormat::Rg16Sint,
+        StorageFormat::Rg16Float => TextureFormat::Rg16Float,
+        StorageFormat::Rgba8Unorm => TextureFormat::Rgba8Unorm,
+        StorageFormat::Rgba8Snorm => TextureFormat::Rgba8Snorm,
+        StorageFormat::Rgba8Uint => TextureFormat::Rgba8Uint,
+        StorageFormat::Rgba8Sint => TextureFormat::Rgba8Sint,
+        StorageFormat::Bgra8Unorm => TextureFormat::Bgra8Unorm,
+        StorageFormat::Rgb10a2Uint => TextureFormat::Rgb10a2Uint,
+        StorageFormat::Rgb10a2Unorm => TextureFormat::Rgb10a2Unorm,
+        StorageFormat::Rg11b10Float => TextureFormat::Rg11b10Float,
+        StorageFormat::Rg32Uint => TextureFormat::Rg32Uint,
+        StorageFormat::Rg32Sint => TextureFormat::Rg32Sint,
+        StorageFormat::Rg32Float => TextureFormat::Rg32Float,
+        StorageFormat::Rgba16Uint => TextureFormat::Rgba16Uint,
+        StorageFormat::Rgba16Sint => TextureFormat::Rgba16Sint,
+        StorageFormat::Rgba16Float => TextureFormat::Rgba16Float,
+        StorageFormat::Rgba32Uint => TextureFormat::Rgba32Uint,
+        StorageFormat::Rgba32Sint => TextureFormat::Rgba32Sint,
+        StorageFormat::Rgba32Float => TextureFormat::Rgba32Float,
+        StorageFormat::R16Unorm => TextureFormat::R16Unorm,
+        StorageFormat::R16Snorm => TextureFormat::R16Snorm,
+        StorageFormat::Rg16Unorm => TextureFormat::Rg16Unorm,
+        StorageFormat::Rg16Snorm => TextureFormat::Rg16Snorm,
+        StorageFormat::Rgba16Unorm => TextureFormat::Rgba16Unorm,
+        StorageFormat::Rgba16Snorm => TextureFormat::Rgba16Snorm,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHADER: &str = "
+struct Params {
+    color: vec4<f32>,
+    scale: f32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> offsets: array<vec2<f32>>;
+@group(2) @binding(3) var color_texture: texture_2d_array<f32>;
+@group(2) @binding(0) var color_sampler: sampler;
+@group(2) @binding(1) var depth: texture_depth_2d;
+@group(2) @binding(2) var<storage, read_write> unused: array<u32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(offsets[index] * params.scale, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let depth = textureLoad(depth, vec2<i32>(position.xy), 0);
+    return textureSample(color_texture, color_sampler, position.xy, 0) * params.color * depth;
+}
+";
+
+    #[test]
+    fn bindings_are_sorted() {
+        let interface = ShaderBundler::reflect(SHADER).unwrap();
+        let slots: Vec<_> = interface
+            .bindings
+            .iter()
+            .map(|b| (b.group, b.binding, b.name.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            slots,
+            [
+                (0, 0, "params"),
+                (0, 1, "offsets"),
+                (2, 0, "color_sampler"),
+                (2, 1, "depth"),
+                (2, 2, "unused"),
+                (2, 3, "color_texture"),
+            ]
+        );
+        assert_eq!(interface.group_count(), 3);
+        assert!(interface.group_entries(1).is_empty());
+    }
+
+    #[test]
+    fn binding_types_and_visibility() {
+        let interface = ShaderBundler::reflect(SHADER).unwrap();
+        let entries = interface.group_entries(0);
+        assert_eq!(entries[0].visibility, ShaderStages::VERTEX_FRAGMENT);
+        assert_eq!(
+            entries[0].ty,
+            BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: NonZeroU64::new(32),
+            }
+        );
+        assert_eq!(entries[1].visibility, ShaderStages::VERTEX);
+        assert!(matches!(
+            entries[1].ty,
+            BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: true },
+                ..
+            }
+        ));
+
+        let entries = interface.group_entries(2);
+        assert_eq!(
+            entries[0].ty,
+            BindingType::Sampler(SamplerBindingType::Filtering)
+        );
+        assert_eq!(
+            entries[1].ty,
+            BindingType::Texture {
+                sample_type: TextureSampleType::Depth,
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            }
+        );
+        // declared but not used by any entry point
+        assert_eq!(entries[2].visibility, ShaderStages::NONE);
+        assert!(matches!(
+            entries[2].ty,
+            BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: false },
+                ..
+            }
+        ));
+        assert_eq!(entries[3].visibility, ShaderStages::FRAGMENT);
+        assert_eq!(
+            entries[3].ty,
+            BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2Array,
+                multisampled: false,
+            }
+        );
+    }
+
+    #[test]
+    fn binding_mut_changes_the_entries() {
+        let mut interface = ShaderBundler::reflect(SHADER).unwrap();
+        assert!(interface.binding_mut(1, 0).is_none());
+        let binding = interface.binding_mut(2, 3).unwrap();
+        binding.ty = BindingType::Texture {
+            sample_type: TextureSampleType::Float { filterable: false },
+            view_dimension: TextureViewDimension::D2Array,
+            multisampled: false,
+        };
+        assert!(matches!(
+            interface.group_entries(2)[3].ty,
+            BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: false },
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn invalid_code_is_an_error() {
+        assert!(ShaderBundler::reflect("fn main( {}").is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use std::ops::Range;
+
+use super::SourceLocation;
+
+/// Maps lines of a bundled shader back to the sources they came from.
+/// Useful for finding the library causing an error reported by wgpu, as those errors point into the bundle.
+#[derive(Clone, Debug, Default)]
+pub struct BundleSourceMap {
+    sources: Vec<String>,
+    lines: Vec<MappedLine>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct MappedLine {
+    /// byte offset of the line in the bundle
+    start: usize,
+    source: usize,
+    /// index of the line in its source
+    line: usize,
+}
+
+impl BundleSourceMap {
+    /// The index of a source to use with push_line, adding the source if needed
+    pub(crate) fn source_index(&mut self, name: &str) -> usize {
+        match self.sources.iter().position(|s| s == name) {
+            Some(idx) => idx,
+            None => {
+                self.sources.push(name.into());
+                self.sources.len() - 1
+            }
+        }
+    }
+
+    /// Adds the next line of the bundle, lines must be pushed in order
+    pub(crate) fn push_line(&mut self, start: usize, source: usize, line: usize) {
+        self.lines.push(MappedLine {
+            start,
+            source,
+            line,
+        });
+    }
+
+    /// The names of the sources in the order they first appear in the bundle
+    pub fn sources(&self) -> &[String] {
+        &self.sources
+    }
+
+    /// The number of lines in the bundle
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// The source name and 1-based line in that source, for a 1-based line in the bundle
+    pub fn map_line(&self, line: usize) -> Option<(&str, usize)> {
+        let mapped = self.lines.get(line.checked_sub(1)?)?;
+        Some((&self.sources[mapped.source], mapped.line + 1))
+    }
+
+    /// The source name and 1-based line in that source, for a byte offset in the bundle
+    pub fn map_offset(&self, offset: usize) -> Option<(&str, usize)> {
+        self.map_line(self.line_index(offset)? + 1)
+    }
+
+    /// The location of a byte range in the bundle, with the bundled line as excerpt.
+    /// bundle must be the code this map was created with, the span is cut off at the end of the first line
+    pub fn location(&self, bundle: &str, span: Range<usize>) -> Option<SourceLocation> {
+        let idx = self.line_index(span.start)?;
+        let mapped = self.lines[idx];
+        let end = self
+            .lines
+            .get(idx + 1)
+            // excluding the newline
+            .map_or(bundle.len(), |next| next.start - 1);
+        let text = bundle.get(mapped.start..end)?;
+        let col = span.start - mapped.start;
+        let len = span.len().min(text.len().saturating_sub(col));
+        Some(SourceLocation::with_span(
+            &self.sources[mapped.source],
+            mapped.line,
+            text,
+            col..col + len,
+        ))
+    }
+
+    /// Index of the bundle line containing offset
+    fn line_index(&self, offset: usize) -> Option<usize> {
+        // the first line starts at 0, so this is only None for an empty map
+        self.lines
+            .partition_point(|l| l.start <= offset)
+            .checked_sub(1)
+    }
+}
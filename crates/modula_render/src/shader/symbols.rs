@@ -0,0 +1,150 @@
+use modula_utils::{hashbrown::HashSet, HashMap};
+
+use super::{BundleSourceMap, ShaderBundlerError};
+
+/// Keywords followed by the name of a top level declaration
+const DECLARATIONS: [&str; 5] = ["fn", "struct", "const", "override", "alias"];
+
+/// Errors on the first top level name declared by multiple libraries, unless allowed by an '//allow-duplicate name' line.
+/// Only line comments are skipped, names in block comments may be found
+pub(super) fn check_duplicate_symbols(
+    code: &str,
+    source_map: &BundleSourceMap,
+) -> Result<(), ShaderBundlerError> {
+    let mut allowed = HashSet::new();
+    // the libraries declaring each name, and the names in the order they are first declared
+    let mut declared: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut order = Vec::new();
+    let mut depth = 0usize;
+    let mut expect_name = false;
+    for (i, line) in code.lines().enumerate() {
+        let (code, comment) = line.split_once("//").unwrap_or((line, ""));
+        if let Some(name) = comment.trim_start().strip_prefix("allow-duplicate") {
+            allowed.insert(name.trim());
+        }
+        let library = source_map
+            .map_line(i + 1)
+            .map_or("", |(library, _)| library);
+        for token in tokens(code) {
+            match token {
+                "{" => depth += 1,
+                "}" => depth = depth.saturating_sub(1),
+                name if expect_name => {
+                    expect_name = false;
+                    let libraries = declared.entry(name).or_insert_with(|| {
+                        order.push(name);
+                        Vec::new()
+                    });
+                    if !libraries.contains(&library) {
+                        libraries.push(library);
+                    }
+                }
+                keyword => expect_name = depth == 0 && DECLARATIONS.contains(&keyword),
+            }
+        }
+    }
+    match order
+        .into_iter()
+        .find(|name| declared[name].len() > 1 && !allowed.contains(name))
+    {
+        Some(name) => Err(ShaderBundlerError::DuplicateSymbol {
+            name: name.into(),
+            libraries: declared[name].iter().map(|l| l.to_string()).collect(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Splits code into identifiers and single other characters, skipping whitespace
+fn tokens(code: &str) -> impl Iterator<Item = &str> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let mut rest = code;
+    std::iter::from_fn(move || {
+        rest = rest.trim_start();
+        let first = rest.chars().next()?;
+        let len = if is_ident(first) {
+            rest.find(|c| !is_ident(c)).unwrap_or(rest.len())
+        } else {
+            first.len_utf8()
+        };
+        let (token, tail) = rest.split_at(len);
+        rest = tail;
+        Some(token)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{ShaderBundler, ShaderFlags, ShaderModuleSource};
+    use super::*;
+
+    fn source(code: &str) -> ShaderModuleSource {
+        ShaderModuleSource::new(code.into())
+    }
+
+    /// Bundles the implementor with duplicate symbols checked, the libraries are a and b
+    fn bundle(a: &str, b: &str, implementor: &str) -> Result<(), ShaderBundlerError> {
+        let mut bundler = ShaderBundler::new();
+        bundler.add_library("a".into(), source(a)).unwrap();
+        bundler.add_library("b".into(), source(b)).unwrap();
+        bundler.set_check_duplicate_symbols(true);
+        let interface = source("//use a\n//use b");
+        bundler
+            .bundle(&interface, &source(implementor), &ShaderFlags::new(), &[])
+            .map(|_| ())
+    }
+
+    #[test]
+    fn tokens_split_identifiers() {
+        let found: Vec<_> = tokens("fn  add_2(x:f32)->f32{").collect();
+        assert_eq!(
+            found,
+            ["fn", "add_2", "(", "x", ":", "f32", ")", "-", ">", "f32", "{"]
+        );
+        assert_eq!(tokens("   ").count(), 0);
+    }
+
+    #[test]
+    fn distinct_names_are_allowed() {
+        let a = "struct A { x: f32 }\nfn a() -> f32 { return 1.0; }";
+        let b = "const B: f32 = 1.0;\nalias BFloat = f32;";
+        assert!(bundle(a, b, "fn main() {}").is_ok());
+    }
+
+    #[test]
+    fn collisions_name_every_library() {
+        let a = "fn shared() {}\nstruct Other {}";
+        let b = "fn shared() {}";
+        let err = bundle(a, b, "override shared: f32;").unwrap_err();
+        let ShaderBundlerError::DuplicateSymbol { name, libraries } = err else {
+            panic!("expected DuplicateSymbol, got {err}");
+        };
+        assert_eq!(name, "shared");
+        assert_eq!(libraries, ["a", "b", "implementor"]);
+
+        // the first name declared twice is reported
+        let err = bundle(
+            "const X = 1;\nconst Y = 1;",
+            "const Y = 2;\nconst X = 2;",
+            "",
+        );
+        assert!(matches!(
+            err,
+            Err(ShaderBundlerError::DuplicateSymbol { name, .. }) if name == "X"
+        ));
+    }
+
+    #[test]
+    fn nested_and_commented_names_are_skipped() {
+        let a = "fn a() {\n    const local = 1.0;\n    let x = local;\n}";
+        let b = "fn b() {\n    const local = 2.0;\n}\n// fn a() {}";
+        assert!(bundle(a, b, "").is_ok());
+    }
+
+    #[test]
+    fn allowed_duplicates() {
+        let a = "//allow-duplicate helper\nfn helper() {}";
+        assert!(bundle(a, "fn helper() {}", "").is_ok());
+        assert!(bundle("fn helper() {}", "fn helper() {}", "").is_err());
+    }
+}
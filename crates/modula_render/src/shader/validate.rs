@@ -0,0 +1,198 @@
+use std::{borrow::Cow, error::Error, fmt, ops::Range};
+
+use naga::{
+    front::wgsl,
+    valid::{Capabilities, ModuleInfo, ValidationFlags, Validator},
+    Module,
+};
+use wgpu::ShaderSource;
+
+use super::{
+    BundleSourceMap, ShaderBundler, ShaderBundlerError, ShaderDefineValue, ShaderFlags,
+    ShaderModuleSource, SourceLocation,
+};
+
+#[derive(Debug)]
+pub enum ShaderValidationError {
+    Bundle(ShaderBundlerError),
+    /// The bundled code is not valid wgsl, location is None if naga did not report a span
+    Parse {
+        message: String,
+        location: Option<SourceLocation>,
+    },
+    /// The bundled code was parsed but is not a valid module
+    Validation {
+        message: String,
+        location: Option<SourceLocation>,
+    },
+}
+
+impl From<ShaderBundlerError> for ShaderValidationError {
+    fn from(value: ShaderBundlerError) -> Self {
+        Self::Bundle(value)
+    }
+}
+
+impl fmt::Display for ShaderValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (kind, message, location) = match self {
+            Self::Bundle(e) => return e.fmt(f),
+            Self::Parse { message, location } => ("parse", message, location),
+            Self::Validation { message, location } => ("validation", message, location),
+        };
+        write!(f, "shader {kind} error: {message}")?;
+        if let Some(location) = location {
+            write!(f, "\n{location}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ShaderValidationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Bundle(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl ShaderBundler {
+    /// Same as [`bundle`](Self::bundle), but the output is parsed and validated by naga.
+    /// Errors point into the libraries instead of the bundle.
+    /// All capabilities are allowed, so this does not check if the shader is supported by the device.
+    pub fn bundle_validated(
+        &self,
+        interface: &ShaderModuleSource,
+        implementor: &ShaderModuleSource,
+        flags: &ShaderFlags,
+        defines: &[(&str, ShaderDefineValue)],
+    ) -> Result<ShaderSource<'_>, ShaderValidationError> {
+        let (code, source_map) =
+            self.bundle_with_source_map(interface, implementor, flags, defines)?;
+        validate(&code, &source_map)?;
+        Ok(ShaderSource::Wgsl(Cow::Owned(code)))
+    }
+}
+
+pub(super) fn validate(
+    code: &str,
+    source_map: &BundleSourceMap,
+) -> Result<(), ShaderValidationError> {
+    parse_and_validate(code, Some(source_map)).map(|_| ())
+}
+
+/// Errors only have a location if there is a source map
+pub(super) fn parse_and_validate(
+    code: &str,
+    source_map: Option<&BundleSourceMap>,
+) -> Result<(Module, ModuleInfo), ShaderValidationError> {
+    let location = |span: Range<usize>| source_map?.location(code, span);
+    let module = wgsl::parse_str(code).map_err(|e| ShaderValidationError::Parse {
+        message: e.message().into(),
+        location: e
+            .labels()
+            .find_map(|(span, _)| span.to_range())
+            .and_then(location),
+    })?;
+    let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(&module)
+        .map_err(|e| {
+            // the inner errors say what is actually wrong
+            let mut message = e.as_inner().to_string();
+            let mut source: &dyn Error = e.as_inner();
+            while let Some(next) = source.source() {
+                message.push_str(": ");
+                message.push_str(&next.to_string());
+                source = next;
+            }
+            ShaderValidationError::Validation {
+                message,
+                // the last span is the most specific one
+                location: e
+                    .spans()
+                    .filter_map(|(span, _)| span.to_range())
+                    .last()
+                    .and_then(location),
+            }
+        })?;
+    Ok((module, info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(code: &str) -> ShaderModuleSource {
+        ShaderModuleSource::new(code.into())
+    }
+
+    fn bundler() -> ShaderBundler {
+        let mut bundler = ShaderBundler::new();
+        bundler
+            .add_library(
+                "math".into(),
+                source("fn double(x: f32) -> f32 {\n    return x * 2.0;\n}"),
+            )
+            .unwrap();
+        bundler
+    }
+
+    fn validated(
+        bundler: &ShaderBundler,
+        implementor: &str,
+    ) -> Result<String, ShaderValidationError> {
+        let interface = source("//use math");
+        let flags = ShaderFlags::new();
+        match bundler.bundle_validated(&interface, &source(implementor), &flags, &[])? {
+            ShaderSource::Wgsl(code) => Ok(code.into_owned()),
+            _ => unreachable!("bundles are wgsl"),
+        }
+    }
+
+    #[test]
+    fn valid_module() {
+        let code = validated(
+            &bundler(),
+            "@fragment\nfn fs_main() -> @location(0) vec4<f32> {\n    return vec4<f32>(double(0.25));\n}",
+        )
+        .unwrap();
+        assert!(code.contains("fn double") && code.contains("fn fs_main"));
+    }
+
+    #[test]
+    fn invalid_library_reports_its_line() {
+        let mut bundler = bundler();
+        bundler
+            .add_library(
+                "broken".into(),
+                source("//use math\n\nfn half(x: f32) -> f32 {\n    return double(x) > 1.0;\n}"),
+            )
+            .unwrap();
+        let err = validated(&bundler, "//use broken").unwrap_err();
+        let ShaderValidationError::Validation { message, location } = &err else {
+            panic!("expected a validation error, got {err}");
+        };
+        assert!(!message.is_empty());
+        let location = location.as_ref().expect("the error has a span");
+        assert_eq!(location.library, "broken");
+        assert_eq!(location.line, 4);
+        assert!(err.to_string().starts_with("shader validation error"));
+    }
+
+    #[test]
+    fn parse_error_reports_its_line() {
+        let err = validated(&bundler(), "fn main() {\n    let x = ;\n}").unwrap_err();
+        let ShaderValidationError::Parse { location, .. } = &err else {
+            panic!("expected a parse error, got {err}");
+        };
+        assert_eq!(location.as_ref().unwrap().line, 2);
+    }
+
+    #[test]
+    fn bundle_errors_are_passed_on() {
+        let err = validated(&bundler(), "//use missing").unwrap_err();
+        assert!(matches!(err, ShaderValidationError::Bundle(_)));
+        assert!(err.source().is_some());
+    }
+}
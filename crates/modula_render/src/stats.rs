@@ -0,0 +1,60 @@
+use std::ops::AddAssign;
+
+use bevy_ecs::prelude::*;
+use modula_asset::Assets;
+
+use crate::RenderTarget;
+
+/// Numbers about the last rendered frame, updated after sequences are run, so [Draw](crate::Draw) systems see the previous frame.  
+/// Passes begun outside of sequences are also counted
+#[derive(Resource, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct RenderFrameStats {
+    /// Render passes begun on any [RenderTarget]
+    pub passes: u32,
+    /// Passes that were resolving
+    pub resolving_passes: u32,
+    /// Passes that cleared color or depth/stencil
+    pub clears: u32,
+    /// [Operations](crate::Operation) run, parallel or not
+    pub operations: u32,
+    /// [Sequences](crate::Sequence) run
+    pub sequences: u32,
+    /// Command buffers submitted
+    pub command_buffers: u32,
+    /// Queue submits
+    pub submits: u32,
+    /// Sum of the stats contributed by operations
+    pub operation_stats: OperationStats,
+}
+
+/// Numbers an [Operation](crate::Operation) can contribute to [RenderFrameStats], see [Operation::stats](crate::Operation::stats)
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct OperationStats {
+    pub draw_calls: u32,
+    pub instances: u32,
+}
+
+impl AddAssign for OperationStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.draw_calls += rhs.draw_calls;
+        self.instances += rhs.instances;
+    }
+}
+
+/// Resets pass counters of render targets, so passes from the previous frame are not counted
+pub(crate) fn begin_frame_stats(world: &mut World) {
+    for (_, target) in world.resource::<Assets<RenderTarget>>().iter() {
+        target.take_pass_counts();
+    }
+}
+
+/// Adds pass counts of render targets and replaces the [RenderFrameStats] resource
+pub(crate) fn finish_frame_stats(world: &mut World, mut stats: RenderFrameStats) {
+    for (_, target) in world.resource::<Assets<RenderTarget>>().iter() {
+        let (passes, resolving, clears) = target.take_pass_counts();
+        stats.passes += passes;
+        stats.resolving_passes += resolving;
+        stats.clears += clears;
+    }
+    world.insert_resource(stats);
+}
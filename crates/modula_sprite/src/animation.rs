@@ -0,0 +1,408 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy_ecs::{
+    component::Component,
+    entity::Entity,
+    event::{Event, EventWriter, Events},
+    schedule::{IntoSystemConfigs, SystemSet},
+    system::{Commands, Query, Res, ResMut},
+};
+use modula_asset::{init_assets, AssetId, Assets};
+use modula_core::{PreInit, ScheduleBuilder};
+use modula_render::PreDraw;
+use modula_texture::atlas::AtlasGroupEntry;
+use modula_time::Time;
+
+/// Animators are advanced during [PreDraw] in this set, so the entries read during [Draw](modula_render::Draw) are of the current frame
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AnimationSet;
+
+/// Inits [AnimationClip] assets and the [AnimationFinished] event, and advances every [Animator] component by [Time::delta] in [AnimationSet].
+/// Animators are only advanced if [init_time](modula_time::init_time) is used as well
+pub fn init_sprite_animation(schedule_builder: &mut ScheduleBuilder) {
+    init_assets::<AnimationClip>(schedule_builder);
+    schedule_builder.add_systems(PreInit, |mut commands: Commands| {
+        commands.insert_resource(Events::<AnimationFinished>::default());
+    });
+    schedule_builder.add_systems(
+        PreDraw,
+        (
+            |mut finished: ResMut<Events<AnimationFinished>>| finished.update(),
+            advance_animators,
+        )
+            .chain()
+            .in_set(AnimationSet),
+    );
+}
+
+/// Frames of an animation, each shown for frame_time
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnimationClip {
+    pub frames: Vec<AtlasGroupEntry>,
+    pub frame_time: Duration,
+    /// If false the clip stops on its last frame
+    pub looping: bool,
+}
+
+impl AnimationClip {
+    /// A looping clip
+    pub fn new(frames: Vec<AtlasGroupEntry>, frame_time: Duration) -> Self {
+        Self {
+            frames,
+            frame_time,
+            looping: true,
+        }
+    }
+
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    /// The time it takes to show every frame once
+    pub fn duration(&self) -> Duration {
+        self.frame_time * self.frames.len() as u32
+    }
+
+    /// The frame shown after elapsed, the last frame if elapsed is past the end. None if there are no frames
+    pub fn frame_at(&self, elapsed: Duration) -> Option<AtlasGroupEntry> {
+        let last = self.frames.len().checked_sub(1)?;
+        let index = if self.frame_time.is_zero() {
+            0
+        } else {
+            (elapsed.as_nanos() / self.frame_time.as_nanos()).min(last as u128) as usize
+        };
+        Some(self.frames[index])
+    }
+}
+
+/// Sent during [PreDraw] in [AnimationSet] when a clip that is not [looping](AnimationClip::looping) was played to the end by an [Animator] component.
+/// Events can be read until the end of the next frame
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationFinished {
+    pub entity: Entity,
+    pub clip: AssetId<AnimationClip>,
+}
+
+/// The playback state of an [AnimationClip], as a component it is advanced by [init_sprite_animation], otherwise by [advance](Self::advance).
+/// The frame to draw is given by [entry](Self::entry)
+#[derive(Component, Clone, Debug, PartialEq)]
+pub struct Animator {
+    clip: Option<AssetId<AnimationClip>>,
+    queued: VecDeque<AssetId<AnimationClip>>,
+    elapsed: Duration,
+    playing: bool,
+    finished: bool,
+    speed: f32,
+}
+
+impl Default for Animator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Animator {
+    /// An animator without a clip
+    pub fn new() -> Self {
+        Self {
+            clip: None,
+            queued: VecDeque::new(),
+            elapsed: Duration::ZERO,
+            playing: false,
+            finished: false,
+            speed: 1.0,
+        }
+    }
+
+    /// An animator playing clip from the start
+    pub fn playing(clip: AssetId<AnimationClip>) -> Self {
+        let mut animator = Self::new();
+        animator.play(clip);
+        animator
+    }
+
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Switches to clip right away, and removes the queued clips.
+    /// If clip is already playing it continues where it is, so this can be called every frame
+    pub fn play(&mut self, clip: AssetId<AnimationClip>) {
+        self.queued.clear();
+        if self.playing && self.clip == Some(clip) {
+            return;
+        }
+        self.clip = Some(clip);
+        self.elapsed = Duration::ZERO;
+        self.playing = true;
+        self.finished = false;
+    }
+
+    /// Plays clip when the current and earlier queued clips end, a looping clip ends at the end of the loop it is in.
+    /// Played right away if nothing is playing
+    pub fn queue(&mut self, clip: AssetId<AnimationClip>) {
+        if self.playing {
+            self.queued.push_back(clip);
+        } else {
+            self.play(clip);
+        }
+    }
+
+    /// Plays the current clip from the start
+    pub fn restart(&mut self) {
+        self.elapsed = Duration::ZERO;
+        self.playing = self.clip.is_some();
+        self.finished = false;
+    }
+
+    /// Stops advancing, keeping the current frame
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    /// Continues after [pause](Self::pause), a finished clip stays on its last frame
+    pub fn resume(&mut self) {
+        self.playing = self.clip.is_some() && !self.finished;
+    }
+
+    /// False if paused, or if a clip that is not looping has finished
+    #[inline]
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// True if a clip that is not looping was played to the end, and nothing was queued after it
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    #[inline]
+    pub fn clip(&self) -> Option<AssetId<AnimationClip>> {
+        self.clip
+    }
+
+    /// The clips that play after the current one
+    #[inline]
+    pub fn queued(&self) -> impl Iterator<Item = AssetId<AnimationClip>> + '_ {
+        self.queued.iter().copied()
+    }
+
+    /// Time into the current clip, scaled by the speed
+    #[inline]
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    #[inline]
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Multiplied with the time advanced, negative and NaN values are treated as 0
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    /// The entry of the current frame, None without a clip or if the clip is not loaded or empty
+    pub fn entry(&self, clips: &Assets<AnimationClip>) -> Option<AtlasGroupEntry> {
+        clips.get(self.clip?)?.frame_at(self.elapsed)
+    }
+
+    /// Advances the animation by delta times the speed, moving on to queued clips.
+    /// finished is called with every clip that is not looping and was played to the end.
+    /// Nothing happens while the current clip is not loaded
+    pub fn advance(
+        &mut self,
+        delta: Duration,
+        clips: &Assets<AnimationClip>,
+        mut finished: impl FnMut(AssetId<AnimationClip>),
+    ) {
+        if !self.playing {
+            return;
+        }
+        let Some(mut id) = self.clip else {
+            return;
+        };
+        self.elapsed = self.elapsed.saturating_add(scale(delta, self.speed));
+        while let Some(clip) = clips.get(id) {
+            let duration = clip.duration();
+            if self.elapsed < duration || (clip.looping && duration.is_zero()) {
+                return;
+            }
+            if !clip.looping {
+                finished(id);
+            }
+            if let Some(next) = self.queued.pop_front() {
+                self.elapsed -= duration;
+                self.clip = Some(next);
+                id = next;
+            } else if clip.looping {
+                self.elapsed =
+                    Duration::from_nanos((self.elapsed.as_nanos() % duration.as_nanos()) as u64);
+                return;
+            } else {
+                self.elapsed = duration;
+                self.playing = false;
+                self.finished = true;
+                return;
+            }
+        }
+    }
+}
+
+/// delta times speed, 0 for negative and NaN speeds and saturating for speeds too large for a [Duration]
+fn scale(delta: Duration, speed: f32) -> Duration {
+    let scaled = delta.as_secs_f64() * speed as f64;
+    if scaled > 0.0 {
+        Duration::try_from_secs_f64(scaled).unwrap_or(Duration::MAX)
+    } else {
+        Duration::ZERO
+    }
+}
+
+fn advance_animators(
+    time: Option<Res<Time>>,
+    clips: Res<Assets<AnimationClip>>,
+    mut animators: Query<(Entity, &mut Animator)>,
+    mut finished: EventWriter<AnimationFinished>,
+) {
+    let Some(time) = time else {
+        return;
+    };
+    for (entity, mut animator) in &mut animators {
+        // paused animators are not marked as changed
+        if !animator.is_playing() {
+            continue;
+        }
+        animator.advance(time.delta(), &clips, |clip| {
+            finished.send(AnimationFinished { entity, clip });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(100);
+
+    /// A clip of frames entries starting at first
+    fn clip(first: usize, frames: usize, looping: bool) -> AnimationClip {
+        let frames = (first..first + frames)
+            .map(AtlasGroupEntry::from_index)
+            .collect();
+        AnimationClip::new(frames, FRAME).with_looping(looping)
+    }
+
+    /// Advances by delta, returning the finished clips
+    fn advance(
+        animator: &mut Animator,
+        delta: Duration,
+        clips: &Assets<AnimationClip>,
+    ) -> Vec<AssetId<AnimationClip>> {
+        let mut finished = Vec::new();
+        animator.advance(delta, clips, |clip| finished.push(clip));
+        finished
+    }
+
+    fn frame(animator: &Animator, clips: &Assets<AnimationClip>) -> usize {
+        animator.entry(clips).unwrap().index()
+    }
+
+    #[test]
+    fn looping_wraps_around() {
+        let mut clips = Assets::new();
+        let walk = clips.add(clip(0, 4, true));
+        let mut animator = Animator::playing(walk);
+        assert_eq!(frame(&animator, &clips), 0);
+        assert!(advance(&mut animator, FRAME * 3, &clips).is_empty());
+        assert_eq!(frame(&animator, &clips), 3);
+        advance(&mut animator, FRAME * 2 + FRAME / 2, &clips);
+        assert_eq!(frame(&animator, &clips), 1);
+        assert_eq!(animator.elapsed(), FRAME + FRAME / 2);
+        assert!(animator.is_playing() && !animator.is_finished());
+    }
+
+    #[test]
+    fn finishing_stops_on_the_last_frame() {
+        let mut clips = Assets::new();
+        let attack = clips.add(clip(0, 3, false));
+        let mut animator = Animator::playing(attack);
+        assert!(advance(&mut animator, FRAME * 2, &clips).is_empty());
+        assert_eq!(advance(&mut animator, FRAME * 5, &clips), [attack]);
+        assert_eq!(frame(&animator, &clips), 2);
+        assert!(animator.is_finished() && !animator.is_playing());
+        // finished animators are not advanced or finished again
+        assert!(advance(&mut animator, FRAME, &clips).is_empty());
+        animator.resume();
+        assert!(!animator.is_playing());
+
+        animator.restart();
+        assert_eq!(frame(&animator, &clips), 0);
+        assert!(animator.is_playing());
+    }
+
+    #[test]
+    fn queued_clips_play_in_order() {
+        let mut clips = Assets::new();
+        let attack = clips.add(clip(0, 2, false));
+        let walk = clips.add(clip(10, 2, true));
+        let idle = clips.add(clip(20, 1, false));
+        let mut animator = Animator::playing(attack);
+        animator.queue(walk);
+        animator.queue(idle);
+        assert_eq!(animator.queued().collect::<Vec<_>>(), [walk, idle]);
+
+        // the time past the end of attack is spent on walk
+        assert_eq!(advance(&mut animator, FRAME * 3, &clips), [attack]);
+        assert_eq!(animator.clip(), Some(walk));
+        assert_eq!(frame(&animator, &clips), 11);
+        // walk loops until the end of the loop it is in
+        assert_eq!(advance(&mut animator, FRAME * 2, &clips), [idle]);
+        assert!(animator.is_finished());
+        assert_eq!(frame(&animator, &clips), 20);
+        assert_eq!(animator.queued().count(), 0);
+
+        // play clears the queue, queue plays right away if nothing is playing
+        animator.queue(walk);
+        assert_eq!(animator.clip(), Some(walk));
+        animator.queue(attack);
+        animator.play(idle);
+        assert_eq!(animator.queued().count(), 0);
+    }
+
+    #[test]
+    fn speed_scales_the_time() {
+        let mut clips = Assets::new();
+        let walk = clips.add(clip(0, 4, true));
+        let mut animator = Animator::playing(walk).with_speed(2.0);
+        advance(&mut animator, FRAME, &clips);
+        assert_eq!(frame(&animator, &clips), 2);
+
+        for speed in [-1.0, f32::NAN] {
+            animator.set_speed(speed);
+            advance(&mut animator, FRAME, &clips);
+            assert_eq!(frame(&animator, &clips), 2);
+        }
+    }
+
+    #[test]
+    fn huge_speeds_do_not_panic() {
+        let mut clips = Assets::new();
+        let attack = clips.add(clip(0, 3, false));
+        let walk = clips.add(clip(10, 3, true));
+        for speed in [f32::INFINITY, f32::MAX] {
+            let mut animator = Animator::playing(attack).with_speed(speed);
+            assert_eq!(advance(&mut animator, FRAME, &clips), [attack]);
+            assert_eq!(frame(&animator, &clips), 2);
+
+            let mut animator = Animator::playing(walk).with_speed(speed);
+            advance(&mut animator, FRAME, &clips);
+            assert!(animator.is_playing());
+            assert!(animator.entry(&clips).is_some());
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    Buffer, BufferAddress, BufferUsages, Device,
+};
+
+use crate::{SpriteBatch, SpriteQueue};
+
+/// Batches with their instances kept in a buffer of their own, made with [SpriteQueue::bake], for static sprites such as tile maps that should not be submitted and uploaded every frame.
+/// Drawn by the [SpriteOperations](crate::SpriteOperation) it is [added to](crate::SpriteOperation::with_baked) every frame without batching,
+/// and as the camera is applied when drawing, only changes to the sprites themselves need a new bake.
+/// The buffer is made when the batches are first drawn, and freed when the baked sprites are [invalidated](Self::invalidate) or dropped.
+/// Baked sprites are not recorded by the [SpritePicker](crate::SpritePicker), and [screen space](crate::CoordinateSpace::Screen) sprites keep the surface size they were batched with
+#[derive(Default)]
+pub struct BakedSprites {
+    batches: Vec<SpriteBatch>,
+    /// Instances waiting to be written to the buffer
+    data: Vec<u8>,
+    buffer: Option<Buffer>,
+    size: BufferAddress,
+    depth_sorted: bool,
+    valid: bool,
+}
+
+impl BakedSprites {
+    /// The batches, where [start](SpriteBatch::start) is relative to the buffer of the baked sprites and [buffer](SpriteBatch::buffer) is not used
+    #[inline]
+    pub fn batches(&self) -> &[SpriteBatch] {
+        &self.batches
+    }
+
+    /// Size of the instances in bytes
+    #[inline]
+    pub fn size(&self) -> BufferAddress {
+        self.size
+    }
+
+    /// Whether the baked batches use a [depth sorted](crate::SpritePipeline::is_depth_sorted) pipeline, see [SpriteQueue::is_depth_sorted]
+    #[inline]
+    pub fn is_depth_sorted(&self) -> bool {
+        self.depth_sorted
+    }
+
+    /// False if made with [Default] or [invalidated](Self::invalidate), until replaced by a new bake
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        self.valid
+    }
+
+    /// Frees the batches and the buffer, so nothing is drawn until this is replaced by a new bake.
+    /// Used when the baked sprites change, for example when a tile of a map is replaced, after which the sprites are submitted again and baked once they are batched
+    pub fn invalidate(&mut self) {
+        *self = Self::default();
+    }
+
+    /// None before the baked sprites are first drawn
+    #[inline]
+    pub(crate) fn buffer(&self) -> Option<&Buffer> {
+        self.buffer.as_ref()
+    }
+
+    /// Makes the buffer if the instances were not written yet
+    pub(crate) fn upload(&mut self, device: &Device) {
+        if self.data.is_empty() {
+            return;
+        }
+        self.buffer = Some(device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Baked sprite instance buffer"),
+            contents: &std::mem::take(&mut self.data),
+            usage: BufferUsages::VERTEX,
+        }));
+    }
+}
+
+impl SpriteQueue {
+    /// Takes the batches made when the queue was last batched along with their instances, so they can be drawn without batching them again.
+    /// Should be called after [SpriteBatchSet](crate::SpriteBatchSet), for example in [Draw](modula_render::Draw) of the frame the sprites were batched in.
+    /// The queue draws none of the taken batches until it is batched again, batches pushed with [push_batch](Self::push_batch) are kept in the queue.
+    /// Only the batches of the [Main](crate::SpritePass::Main) pass are taken, so shadows and outlines of the sprites are not baked.
+    /// The baked sprites are empty if nothing was batched
+    pub fn bake(&mut self) -> BakedSprites {
+        let Some(offset) = self.instance_offset.take() else {
+            return BakedSprites {
+                valid: true,
+                ..Default::default()
+            };
+        };
+        let count = std::mem::take(&mut self.batched).min(self.batches().len());
+        let mut batches: Vec<_> = self.batches_mut().drain(..count).collect();
+        for batch in &mut batches {
+            batch.start -= offset;
+        }
+        let data = std::mem::take(&mut self.instance_data);
+        BakedSprites {
+            batches,
+            size: data.len() as BufferAddress,
+            data,
+            buffer: None,
+            depth_sorted: self.depth_sorted,
+            valid: true,
+        }
+    }
+}
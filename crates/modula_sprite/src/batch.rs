@@ -0,0 +1,651 @@
+use bevy_ecs::{
+    schedule::SystemSet,
+    system::{Res, ResMut, SystemParam},
+};
+use modula_asset::{AssetId, Assets};
+use modula_core::DeviceRes;
+use modula_texture::atlas::{AtlasGroup, AtlasGroupEntry};
+use modula_utils::HashMap;
+use wgpu::{Buffer, BufferAddress, Device};
+
+use crate::{
+    nine_slice::push_nine_slice, pick::PickRecord, stats::PendingSpriteStats, BakedSprites,
+    Camera2D, InstanceBufferPool, NineSliceInsets, ScreenSpace, SpriteBatch, SpriteBlend,
+    SpriteCulling, SpriteInstance, SpriteMaterials, SpritePicker, SpritePipeline, SpriteQueue,
+    SpriteStats, Submission, SubmissionKind,
+};
+
+/// The sprites submitted to [SpriteQueues](SpriteQueue) are batched during [PreDraw](modula_render::PreDraw) in this set, after [AtlasLoadSet](modula_texture::atlas::AtlasLoadSet).
+/// [Camera2Ds](crate::Camera2D) are updated in this set as well
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SpriteBatchSet;
+
+/// What batching reads besides the queues
+#[derive(SystemParam)]
+pub(crate) struct BatchSources<'w> {
+    atlas_groups: Res<'w, Assets<AtlasGroup>>,
+    cameras: Res<'w, Assets<Camera2D>>,
+    materials: Res<'w, SpriteMaterials>,
+    screen: Res<'w, ScreenSpace>,
+    baked: Res<'w, Assets<BakedSprites>>,
+}
+
+pub(crate) fn batch_sprites(
+    mut queues: ResMut<Assets<SpriteQueue>>,
+    mut pool: ResMut<InstanceBufferPool>,
+    mut pipelines: ResMut<Assets<SpritePipeline>>,
+    mut stats: ResMut<PendingSpriteStats>,
+    mut picker: ResMut<SpritePicker>,
+    sources: BatchSources,
+    device: Res<DeviceRes>,
+) {
+    let stats = &mut stats.0;
+    stats.baked_bytes = sources.baked.iter().map(|(_, baked)| baked.size()).sum();
+    let mut picks = Vec::new();
+    picker.set_screen_height(sources.screen.size().1 as f32);
+    for (id, sprite_queue) in queues.iter_mut() {
+        stats.submitted += sprite_queue.submissions.len();
+        for submission in &mut sprite_queue.submissions {
+            sources.screen.resolve(submission);
+        }
+        if let Some(culling) = sprite_queue.culling() {
+            stats.culled += sprite_queue.cull(culling, &sources.cameras, &sources.atlas_groups);
+        }
+        sprite_queue.apply_materials(&sources.materials);
+        sprite_queue.batch(
+            &sources.atlas_groups,
+            &mut pipelines,
+            &mut pool,
+            &device.0,
+            stats,
+            &mut picks,
+        );
+        stats.batches += sprite_queue.batches().len()
+            + sprite_queue.shadow_batches().len()
+            + sprite_queue.outline_batches().len();
+        picker.record(id, &mut picks);
+    }
+}
+
+impl SpriteQueue {
+    /// Removes the submissions outside the view of the camera, returning how many were removed.
+    /// Nothing is removed if the camera was not updated
+    fn cull(
+        &mut self,
+        culling: SpriteCulling,
+        cameras: &Assets<Camera2D>,
+        atlas_groups: &Assets<AtlasGroup>,
+    ) -> usize {
+        let Some(mut view) = cameras.get(culling.camera).and_then(Camera2D::visible_rect) else {
+            return 0;
+        };
+        for i in 0..2 {
+            view.min[i] -= culling.margin;
+            view.max[i] += culling.margin;
+        }
+        let count = self.submissions.len();
+        self.submissions.retain(|submission| {
+            // sprites without known bounds are skipped when batching anyway
+            submission.space.is_screen()
+                || atlas_groups
+                    .get(submission.atlas)
+                    .and_then(|group| submission.bounds(group))
+                    .is_none_or(|bounds| bounds.intersects(&view))
+        });
+        count - self.submissions.len()
+    }
+
+    /// Replaces the pipelines of submissions with a material by the pipeline of the material,
+    /// and removes the submissions with materials that are not registered
+    fn apply_materials(&mut self, materials: &SpriteMaterials) {
+        self.submissions.retain_mut(|submission| {
+            let Some(material) = submission.material else {
+                return true;
+            };
+            let Some(material) = materials.get(material) else {
+                return false;
+            };
+            submission.pipeline = material.pipeline;
+            true
+        });
+    }
+
+    /// Replaces the batches with the sorted submissions, and pushes their instances to the pool.
+    /// Shadows and outlined sprites are batched the same way into batches of their own, with their instances pushed after those of the main batches.
+    /// If the pool is full the sprites are not drawn, counted or picked. Missing pipeline variants are made, and pickable sprites are pushed to picks
+    fn batch(
+        &mut self,
+        atlas_groups: &Assets<AtlasGroup>,
+        pipelines: &mut Assets<SpritePipeline>,
+        pool: &mut InstanceBufferPool,
+        device: &Device,
+        stats: &mut SpriteStats,
+        picks: &mut Vec<PickRecord>,
+    ) {
+        self.batches_mut().clear();
+        let (shadow_batches, outline_batches) = self.effect_batches_mut();
+        shadow_batches.clear();
+        outline_batches.clear();
+        self.depth_sorted = false;
+        self.instance_offset = None;
+        self.batched = 0;
+        if self.submissions.is_empty() {
+            return;
+        }
+        let mut submissions = std::mem::take(&mut self.submissions);
+        sort_submissions(
+            &mut submissions,
+            self.has_bind_group_sorting(),
+            |submission| {
+                atlas_groups
+                    .get(submission.atlas)
+                    .and_then(|group| submission.bind_group_index(group))
+            },
+        );
+        // later sprites are closer, the depth is only used by depth sorted pipelines
+        let step = 1.0 / (submissions.len() + 1) as f32;
+        for (i, submission) in submissions.iter_mut().enumerate() {
+            submission.depth = 1.0 - (i + 1) as f32 * step;
+        }
+        let depth_sorted = |submission: &Submission| {
+            pipelines
+                .get(submission.pipeline)
+                .is_some_and(SpritePipeline::is_depth_sorted)
+        };
+        self.depth_sorted = submissions.iter().any(depth_sorted);
+        if self.depth_sorted {
+            // opaque sprites are ordered by depth, so they are drawn first in their layer grouped by material and atlas
+            let mut groups = HashMap::new();
+            submissions.sort_by_cached_key(|submission| {
+                if submission.blend != SpriteBlend::Opaque || !depth_sorted(submission) {
+                    return (submission.layer, usize::MAX);
+                }
+                let next = groups.len();
+                let group = *groups
+                    .entry((submission.pipeline, submission.material, submission.atlas))
+                    .or_insert(next);
+                (submission.layer, group)
+            });
+        }
+        let buffer = pool.buffer();
+        // kept in the queue, so the allocation is reused and the instances can be baked
+        let mut data = std::mem::take(&mut self.instance_data);
+        data.clear();
+        let mut count: BufferAddress = 0;
+        // only counted and picked once the instances are pushed
+        let (mut drawn, mut drawn_picks) = (0, Vec::new());
+        let (mut shadow_data, mut outline_data) = (Vec::new(), Vec::new());
+        let (mut shadow_count, mut outline_count): (BufferAddress, BufferAddress) = (0, 0);
+        let shadow = self.shadow();
+        let shadow_color = [
+            shadow.color.r,
+            shadow.color.g,
+            shadow.color.b,
+            shadow.color.a,
+        ]
+        .map(|c| c as f32);
+        let mut instances = Vec::new();
+        for submission in &submissions {
+            // nested clips that do not overlap
+            if submission.clip.is_some_and(|clip| clip.is_empty()) {
+                continue;
+            }
+            let (Some(group), Some(pipeline)) = (
+                atlas_groups.get(submission.atlas),
+                pipelines.get_mut(submission.pipeline),
+            ) else {
+                continue;
+            };
+            instances.clear();
+            let Some(bind_group_index) = submission.instances(group, &mut instances) else {
+                continue;
+            };
+            if instances.is_empty() {
+                continue;
+            }
+            for instance in &mut instances {
+                instance.depth = submission.depth;
+                instance.write_gpu(&mut data);
+            }
+            drawn += 1;
+            if let Some(id) = submission.pick_id {
+                drawn_picks.extend(submission.quad(group).map(|quad| PickRecord {
+                    id,
+                    layer: submission.layer,
+                    z: submission.z,
+                    depth: submission.depth,
+                    quad,
+                    clip: submission.clip,
+                    screen_space: submission.space.is_screen(),
+                    image: match &submission.kind {
+                        SubmissionKind::Entry {
+                            entry, transform, ..
+                        } => Some((
+                            submission.atlas,
+                            *entry,
+                            [transform.flip_x, transform.flip_y],
+                        )),
+                        _ => None,
+                    },
+                }));
+            }
+            let added = instances.len() as u32;
+            let batch = (buffer, bind_group_index, added);
+            if extend_batches(
+                self.batches_mut(),
+                submission,
+                submission.blend,
+                batch,
+                count,
+            ) {
+                pipeline.create_variant(device, submission.blend);
+            }
+            count += added as BufferAddress;
+            if submission.outline {
+                for instance in &instances {
+                    instance.write_gpu(&mut outline_data);
+                }
+                let batches = self.effect_batches_mut().1;
+                if extend_batches(batches, submission, submission.blend, batch, outline_count) {
+                    pipeline.create_variant(device, submission.blend);
+                }
+                outline_count += added as BufferAddress;
+            }
+            if submission.shadow {
+                let anchor = submission.shadow_anchor();
+                for instance in &mut instances {
+                    instance.position = [0, 1].map(|i| {
+                        anchor[i]
+                            + (instance.position[i] - anchor[i]) * shadow.scale[i]
+                            + shadow.offset[i]
+                    });
+                    instance.size = [0, 1].map(|i| instance.size[i] * shadow.scale[i]);
+                    instance.tint = [
+                        shadow_color[0],
+                        shadow_color[1],
+                        shadow_color[2],
+                        shadow_color[3] * instance.tint[3],
+                    ];
+                    instance.write_gpu(&mut shadow_data);
+                }
+                let batches = self.effect_batches_mut().0;
+                if extend_batches(batches, submission, SpriteBlend::Alpha, batch, shadow_count) {
+                    pipeline.create_variant(device, SpriteBlend::Alpha);
+                }
+                shadow_count += added as BufferAddress;
+            }
+        }
+        // keeps the allocation for the next frame
+        submissions.clear();
+        self.submissions = submissions;
+        let pushed = (!data.is_empty()).then(|| pool.push(&data));
+        self.instance_data = data;
+        match pushed {
+            None => {}
+            Some(None) => self.batches_mut().clear(),
+            Some(Some(slice)) => {
+                stats.instance_bytes += self.instance_data.len() as u64;
+                stats.drawn += drawn;
+                picks.append(&mut drawn_picks);
+                for batch in self.batches_mut() {
+                    batch.start += slice.range.start;
+                }
+                self.instance_offset = Some(slice.range.start);
+                self.batched = self.batches().len();
+            }
+        }
+        let (shadow_batches, outline_batches) = self.effect_batches_mut();
+        for (batches, data) in [
+            (shadow_batches, shadow_data),
+            (outline_batches, outline_data),
+        ] {
+            if data.is_empty() {
+                continue;
+            }
+            match pool.push(&data) {
+                None => batches.clear(),
+                Some(slice) => {
+                    stats.instance_bytes += data.len() as u64;
+                    for batch in batches {
+                        batch.start += slice.range.start;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Sorts submissions by layer and z, keeping the order of submission for equal layer and z.
+/// With bind_group_sorting, sprites at equal layer and z are grouped by atlas and bind_group, in the order the groups first appear
+fn sort_submissions(
+    submissions: &mut [Submission],
+    bind_group_sorting: bool,
+    bind_group: impl Fn(&Submission) -> Option<usize>,
+) {
+    submissions.sort_by(|a, b| a.layer.cmp(&b.layer).then(a.z.total_cmp(&b.z)));
+    if !bind_group_sorting {
+        return;
+    }
+    for run in submissions.chunk_by_mut(|a, b| a.layer == b.layer && a.z.total_cmp(&b.z).is_eq()) {
+        let mut groups = HashMap::new();
+        run.sort_by_cached_key(|submission| {
+            let next = groups.len();
+            *groups
+                .entry((submission.atlas, bind_group(submission)))
+                .or_insert(next)
+        });
+    }
+}
+
+/// Adds the instances of batch, which are the buffer, the index of the atlas bind group and the number of instances, to the last of batches if the submission can be merged into it.
+/// Otherwise a batch drawn with blend is pushed starting at instance start, in which case true is returned
+fn extend_batches(
+    batches: &mut Vec<SpriteBatch>,
+    submission: &Submission,
+    blend: SpriteBlend,
+    (buffer, bind_group_index, added): (AssetId<Buffer>, usize, u32),
+    start: BufferAddress,
+) -> bool {
+    match batches.last_mut() {
+        Some(batch)
+            if batch.layer == submission.layer
+                && batch.atlas == submission.atlas
+                && batch.bind_group_index == bind_group_index
+                && batch.pipeline == submission.pipeline
+                && batch.material == submission.material
+                && batch.blend == blend
+                && batch.clip == submission.clip
+                && batch.screen_space == submission.space.is_screen() =>
+        {
+            batch.count += added;
+            false
+        }
+        _ => {
+            batches.push(SpriteBatch {
+                layer: submission.layer,
+                atlas: submission.atlas,
+                bind_group_index,
+                pipeline: submission.pipeline,
+                material: submission.material,
+                blend,
+                clip: submission.clip,
+                screen_space: submission.space.is_screen(),
+                buffer,
+                // offset by the start of the slice when pushed
+                start: start * SpriteInstance::SIZE,
+                size: SpriteInstance::SIZE,
+                count: added,
+            });
+            true
+        }
+    }
+}
+
+impl Submission {
+    /// The point the shadow of the sprite is scaled around, see [SpriteShadow](crate::SpriteShadow)
+    fn shadow_anchor(&self) -> [f32; 2] {
+        match &self.kind {
+            SubmissionKind::Entry { transform, .. } => transform.position,
+            SubmissionKind::NineSlice { rect, .. } => [rect.center()[0], rect.min[1]],
+            SubmissionKind::Raw { instance, .. } => instance.position,
+        }
+    }
+
+    /// The index of the bind group of the atlas the sprite is drawn with, None if the entry was removed
+    fn bind_group_index(&self, group: &AtlasGroup) -> Option<usize> {
+        match &self.kind {
+            SubmissionKind::Entry { entry, .. } | SubmissionKind::NineSlice { entry, .. } => {
+                (!group.is_removed(*entry))
+                    .then(|| group.entry_uvs(*entry).bind_group_index as usize)
+            }
+            SubmissionKind::Raw {
+                bind_group_index, ..
+            } => Some(*bind_group_index),
+        }
+    }
+
+    /// Pushes the instances of the submission and returns the index of the bind group of the atlas, None if the entry was removed
+    fn instances(&self, group: &AtlasGroup, out: &mut Vec<SpriteInstance>) -> Option<usize> {
+        match &self.kind {
+            SubmissionKind::Entry {
+                entry,
+                transform,
+                tint,
+            } => {
+                if group.is_removed(*entry) {
+                    return None;
+                }
+                let uv = group.entry_uvs(*entry);
+                let (width, height) = image_size(group, *entry);
+                let size = [
+                    width as f32 * transform.scale[0],
+                    height as f32 * transform.scale[1],
+                ];
+                let mut instance = SpriteInstance::from_entry(*entry, transform.position, size);
+                instance.pivot = transform.pivot;
+                instance.rotation = transform.rotation;
+                if transform.flip_x {
+                    instance.flags |= SpriteInstance::FLIP_X;
+                }
+                if transform.flip_y {
+                    instance.flags |= SpriteInstance::FLIP_Y;
+                }
+                instance.tint = *tint;
+                out.push(instance);
+                Some(uv.bind_group_index as usize)
+            }
+            SubmissionKind::NineSlice {
+                entry,
+                rect,
+                insets,
+                border_scale,
+                tint,
+            } => {
+                if group.is_removed(*entry) {
+                    return None;
+                }
+                let uv = group.entry_uvs(*entry);
+                let insets = insets
+                    .or_else(|| group.meta::<NineSliceInsets>(*entry).copied())
+                    .unwrap_or_default();
+                push_nine_slice(
+                    *entry,
+                    image_size(group, *entry),
+                    *rect,
+                    (insets, *border_scale),
+                    *tint,
+                    out,
+                );
+                Some(uv.bind_group_index as usize)
+            }
+            SubmissionKind::Raw {
+                instance,
+                bind_group_index,
+            } => {
+                out.push(*instance);
+                Some(*bind_group_index)
+            }
+        }
+    }
+}
+
+/// The size of an entry in pixels, as the image is and not as it is stored in the atlas
+pub(crate) fn image_size(group: &AtlasGroup, entry: AtlasGroupEntry) -> (u32, u32) {
+    let (atlas, sub_texture) = group.entry_map()[entry.index()];
+    let sub_texture = &group.atlases()[atlas].layout().0[sub_texture];
+    if sub_texture.rotated {
+        (sub_texture.height, sub_texture.width)
+    } else {
+        (sub_texture.width, sub_texture.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Anchor, CoordinateSpace, SpriteRect};
+
+    const STRESS_COUNT: usize = 50_000;
+
+    /// Ids for the atlases and pipelines of submissions, which are never looked up
+    struct Ids {
+        atlases: Vec<AssetId<AtlasGroup>>,
+        pipeline: AssetId<SpritePipeline>,
+        buffer: AssetId<Buffer>,
+    }
+
+    impl Ids {
+        fn new(atlas_count: usize) -> Self {
+            let mut atlases = Assets::<AtlasGroup>::new();
+            Self {
+                atlases: (0..atlas_count).map(|_| atlases.add_empty()).collect(),
+                pipeline: Assets::<SpritePipeline>::new().add_empty(),
+                buffer: Assets::<Buffer>::new().add_empty(),
+            }
+        }
+
+        fn submission(&self, atlas: usize, bind_group_index: usize, z: f32) -> Submission {
+            Submission {
+                layer: 0,
+                z,
+                depth: 0.0,
+                atlas: self.atlases[atlas],
+                pipeline: self.pipeline,
+                material: None,
+                blend: SpriteBlend::Alpha,
+                clip: None,
+                space: CoordinateSpace::World,
+                pick_id: None,
+                shadow: false,
+                outline: false,
+                kind: SubmissionKind::Raw {
+                    instance: SpriteInstance::default(),
+                    bind_group_index,
+                },
+            }
+        }
+
+        /// Sorts the submissions and merges them into batches of one instance each
+        fn batch(
+            &self,
+            submissions: &mut [Submission],
+            bind_group_sorting: bool,
+        ) -> Vec<SpriteBatch> {
+            sort_submissions(submissions, bind_group_sorting, raw_bind_group);
+            let mut batches = Vec::new();
+            for (i, submission) in submissions.iter().enumerate() {
+                let bind_group_index = raw_bind_group(submission).unwrap();
+                let batch = (self.buffer, bind_group_index, 1);
+                extend_batches(
+                    &mut batches,
+                    submission,
+                    submission.blend,
+                    batch,
+                    i as BufferAddress,
+                );
+            }
+            batches
+        }
+    }
+
+    fn raw_bind_group(submission: &Submission) -> Option<usize> {
+        match submission.kind {
+            SubmissionKind::Raw {
+                bind_group_index, ..
+            } => Some(bind_group_index),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn sprites_of_one_atlas_make_one_batch() {
+        let ids = Ids::new(1);
+        let mut submissions: Vec<_> = (0..STRESS_COUNT)
+            .map(|_| ids.submission(0, 0, 0.0))
+            .collect();
+        let batches = ids.batch(&mut submissions, false);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].count as usize, STRESS_COUNT);
+        assert_eq!(
+            batches[0].byte_range(),
+            0..STRESS_COUNT as u64 * SpriteInstance::SIZE
+        );
+    }
+
+    #[test]
+    fn bind_group_sorting_groups_interleaved_atlases() {
+        let ids = Ids::new(2);
+        let interleaved = || -> Vec<_> {
+            (0..STRESS_COUNT)
+                .map(|i| ids.submission(i % 2, (i / 2) % 3, 0.0))
+                .collect()
+        };
+        // every sprite switches atlas or bind group
+        assert_eq!(ids.batch(&mut interleaved(), false).len(), STRESS_COUNT);
+        let batches = ids.batch(&mut interleaved(), true);
+        assert_eq!(batches.len(), 6);
+        let total: usize = batches.iter().map(|batch| batch.count as usize).sum();
+        assert_eq!(total, STRESS_COUNT);
+    }
+
+    #[test]
+    fn bind_group_sorting_keeps_z_order() {
+        let ids = Ids::new(2);
+        // 5 z values, each with sprites of both atlases
+        let mut submissions: Vec<_> = (0..STRESS_COUNT)
+            .map(|i| ids.submission(i % 2, 0, (i % 10 / 2) as f32))
+            .collect();
+        let batches = ids.batch(&mut submissions, true);
+        assert_eq!(batches.len(), 10);
+        assert!(submissions.windows(2).all(|pair| pair[0].z <= pair[1].z));
+    }
+
+    /// The number of instances in each batch, for submissions given as (atlas, bind group) in order
+    fn merged(ids: &Ids, order: &[(usize, usize)]) -> Vec<u32> {
+        let mut submissions: Vec<_> = order
+            .iter()
+            .map(|&(atlas, bind_group)| ids.submission(atlas, bind_group, 0.0))
+            .collect();
+        let batches = ids.batch(&mut submissions, false);
+        batches.iter().map(|batch| batch.count).collect()
+    }
+
+    #[test]
+    fn equal_atlas_and_bind_group_merge() {
+        let ids = Ids::new(2);
+        assert_eq!(merged(&ids, &[(0, 0), (0, 0), (0, 0)]), [3]);
+        assert_eq!(merged(&ids, &[(0, 1), (0, 1), (1, 1), (1, 1)]), [2, 2]);
+    }
+
+    #[test]
+    fn different_atlases_or_bind_groups_do_not_merge() {
+        let ids = Ids::new(2);
+        // the same atlas interleaved with another
+        assert_eq!(
+            merged(&ids, &[(0, 0), (1, 0), (0, 0), (0, 0), (1, 0)]),
+            [1, 1, 2, 1]
+        );
+        // the same atlas with other bind groups
+        assert_eq!(merged(&ids, &[(0, 0), (0, 1), (0, 0)]), [1, 1, 1]);
+        // the same bind group index of other atlases
+        assert_eq!(merged(&ids, &[(0, 2), (1, 2)]), [1, 1]);
+    }
+
+    #[test]
+    fn other_state_does_not_merge() {
+        let ids = Ids::new(1);
+        let base = || ids.submission(0, 0, 0.0);
+        let changes: [fn(&mut Submission); 4] = [
+            |submission| submission.layer = 1,
+            |submission| submission.blend = SpriteBlend::Additive,
+            |submission| submission.clip = Some(SpriteRect::new([0.0; 2], [1.0; 2])),
+            |submission| submission.space = CoordinateSpace::screen(Anchor::BottomLeft),
+        ];
+        for change in changes {
+            let mut changed = base();
+            change(&mut changed);
+            let mut submissions = [base(), changed, base()];
+            assert!(ids.batch(&mut submissions, false).len() > 1);
+        }
+    }
+}
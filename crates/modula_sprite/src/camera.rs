@@ -0,0 +1,456 @@
+use bevy_ecs::system::{Res, ResMut, Resource};
+use modula_asset::{AssetId, Assets};
+use modula_core::{DeviceRes, QueueRes};
+use modula_render::{RenderTarget, SurfaceTargetRes};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, Buffer, BufferAddress, BufferBindingType, BufferSize,
+    BufferUsages, Device, ShaderStages,
+};
+
+use crate::SpriteRect;
+
+/// The uniform of the camera bind group of sprite pipelines
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraUniform {
+    /// Column major, like wgsl
+    pub clip_from_world: [[f32; 4]; 4],
+}
+
+impl CameraUniform {
+    /// Size of the uniform in bytes
+    pub const SIZE: BufferAddress = 64;
+
+    /// Shows the world rect with center and size, where y points up
+    pub fn orthographic(center: [f32; 2], size: [f32; 2]) -> Self {
+        let (sx, sy) = (2.0 / size[0], 2.0 / size[1]);
+        Self {
+            clip_from_world: [
+                [sx, 0.0, 0.0, 0.0],
+                [0.0, sy, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [-center[0] * sx, -center[1] * sy, 0.0, 1.0],
+            ],
+        }
+    }
+
+    /// Shows a world rect with center and half_extent, rotated counter clockwise by rotation
+    fn view(center: [f32; 2], half_extent: [f32; 2], rotation: f32) -> Self {
+        let (sin, cos) = rotation.sin_cos();
+        let (sx, sy) = (1.0 / half_extent[0], 1.0 / half_extent[1]);
+        // scale * rotate(-rotation) * translate(-center)
+        let x = [cos * sx, -sin * sy];
+        let y = [sin * sx, cos * sy];
+        Self {
+            clip_from_world: [
+                [x[0], x[1], 0.0, 0.0],
+                [y[0], y[1], 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [
+                    -(x[0] * center[0] + y[0] * center[1]),
+                    -(x[1] * center[0] + y[1] * center[1]),
+                    0.0,
+                    1.0,
+                ],
+            ],
+        }
+    }
+
+    /// The uniform as laid out in the buffer
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.clip_from_world
+            .iter()
+            .flatten()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect()
+    }
+
+    /// A uniform buffer holding this, which can be written to with [to_bytes](Self::to_bytes)
+    pub fn create_buffer(&self, device: &Device) -> Buffer {
+        device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Camera uniform"),
+            contents: &self.to_bytes(),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        })
+    }
+}
+
+/// Used as a singleton for the layout of the camera bind group of sprite pipelines, bound to group 1 after the atlas group.
+/// Binding 0 is a uniform buffer holding a [CameraUniform], visible to the vertex stage
+#[derive(Resource)]
+pub struct CameraBindGroupLayout {
+    layout: BindGroupLayout,
+}
+
+impl CameraBindGroupLayout {
+    pub fn new(device: &Device) -> Self {
+        Self {
+            layout: device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("Camera bind group layout"),
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(CameraUniform::SIZE),
+                    },
+                    count: None,
+                }],
+            }),
+        }
+    }
+
+    #[inline]
+    pub fn layout(&self) -> &BindGroupLayout {
+        &self.layout
+    }
+
+    /// A bind group using buffer as the uniform, for example one made with [CameraUniform::create_buffer]
+    pub fn create_bind_group(&self, device: &Device, buffer: &Buffer) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Camera bind group"),
+            layout: &self.layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        })
+    }
+}
+
+/// Where the viewport size of a [Camera2D] comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CameraViewport {
+    /// The size of the [surface target](SurfaceTargetRes)
+    Surface,
+    Target(AssetId<RenderTarget>),
+    /// Width and height in pixels
+    Size(u32, u32),
+}
+
+/// How the world units of a [Camera2D] map to pixels of the viewport
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CameraScaling {
+    /// A world unit is a pixel at zoom 1, and the position is rounded to whole pixels so texels line up with pixels
+    #[default]
+    PixelPerfect,
+    /// The whole virtual resolution is visible, with more shown along one axis if the aspect ratio differs
+    Fit { width: f32, height: f32 },
+    /// The virtual resolution covers the viewport, cropped along one axis if the aspect ratio differs
+    Fill { width: f32, height: f32 },
+}
+
+/// A 2D camera with an orthographic projection where y points up, its bind group is bound to group 1 by [SpriteOperations](crate::SpriteOperation) using it.
+/// The uniform is updated during [PreDraw](modula_render::PreDraw) in [SpriteBatchSet](crate::SpriteBatchSet), so the bind group exists after the first [PreDraw](modula_render::PreDraw).
+/// Every camera is an asset, so multiple cameras can be used for example for split screen
+pub struct Camera2D {
+    /// The world position in the center of the viewport
+    pub position: [f32; 2],
+    /// Larger values show less of the world
+    pub zoom: f32,
+    /// Counter clockwise, in radians
+    pub rotation: f32,
+    pub viewport: CameraViewport,
+    pub scaling: CameraScaling,
+    /// Of the last update
+    viewport_size: (u32, u32),
+    center: [f32; 2],
+    half_extent: [f32; 2],
+    buffer: Option<Buffer>,
+    bind_group: Option<BindGroup>,
+}
+
+impl Camera2D {
+    /// A pixel perfect camera at the origin
+    pub fn new(viewport: CameraViewport) -> Self {
+        Self {
+            position: [0.0; 2],
+            zoom: 1.0,
+            rotation: 0.0,
+            viewport,
+            scaling: CameraScaling::default(),
+            viewport_size: (0, 0),
+            center: [0.0; 2],
+            half_extent: [1.0; 2],
+            buffer: None,
+            bind_group: None,
+        }
+    }
+
+    pub fn with_position(mut self, position: [f32; 2]) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn with_zoom(mut self, zoom: f32) -> Self {
+        self.zoom = zoom;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scaling(mut self, scaling: CameraScaling) -> Self {
+        self.scaling = scaling;
+        self
+    }
+
+    /// The size of the viewport when the camera was last updated
+    #[inline]
+    pub fn viewport_size(&self) -> (u32, u32) {
+        self.viewport_size
+    }
+
+    /// None before the camera is first updated
+    #[inline]
+    pub fn bind_group(&self) -> Option<&BindGroup> {
+        self.bind_group.as_ref()
+    }
+
+    /// The uniform for a viewport of the given size
+    pub fn uniform(&self, viewport_size: (u32, u32)) -> CameraUniform {
+        let (center, half_extent) = self.view(viewport_size);
+        CameraUniform::view(center, half_extent, self.rotation)
+    }
+
+    /// The world position in the center, and half the world size of the viewport
+    fn view(&self, viewport_size: (u32, u32)) -> ([f32; 2], [f32; 2]) {
+        let (width, height) = (viewport_size.0.max(1) as f32, viewport_size.1.max(1) as f32);
+        let pixels_per_unit = self.zoom
+            * match self.scaling {
+                CameraScaling::PixelPerfect => 1.0,
+                CameraScaling::Fit {
+                    width: w,
+                    height: h,
+                } => (width / w).min(height / h),
+                CameraScaling::Fill {
+                    width: w,
+                    height: h,
+                } => (width / w).max(height / h),
+            };
+        let mut center = self.position;
+        if self.scaling == CameraScaling::PixelPerfect {
+            // pixel edges land on whole world units, also for odd viewport sizes
+            let offset = [width % 2.0 / 2.0, height % 2.0 / 2.0];
+            center = [0, 1]
+                .map(|i| ((center[i] * pixels_per_unit).round() + offset[i]) / pixels_per_unit);
+        }
+        (
+            center,
+            [
+                width / 2.0 / pixels_per_unit,
+                height / 2.0 / pixels_per_unit,
+            ],
+        )
+    }
+
+    /// Updates the view used by the conversions and [visible_rect](Self::visible_rect) for a viewport of the given size
+    pub(crate) fn update_view(&mut self, viewport_size: (u32, u32)) {
+        let (center, half_extent) = self.view(viewport_size);
+        self.viewport_size = viewport_size;
+        self.center = center;
+        self.half_extent = half_extent;
+    }
+
+    /// Converts a world position to a position in the viewport in pixels, where the origin is the top left and y points down.
+    /// Uses the viewport size of the last update
+    pub fn world_to_screen(&self, world: [f32; 2]) -> [f32; 2] {
+        let (sin, cos) = self.rotation.sin_cos();
+        let relative = [world[0] - self.center[0], world[1] - self.center[1]];
+        let local = [
+            relative[0] * cos + relative[1] * sin,
+            -relative[0] * sin + relative[1] * cos,
+        ];
+        let (width, height) = (self.viewport_size.0 as f32, self.viewport_size.1 as f32);
+        [
+            (local[0] / self.half_extent[0] + 1.0) * width / 2.0,
+            (1.0 - local[1] / self.half_extent[1]) * height / 2.0,
+        ]
+    }
+
+    /// The world space bounds of what is visible, of the last update. None before the camera is first updated
+    pub fn visible_rect(&self) -> Option<SpriteRect> {
+        if self.viewport_size == (0, 0) {
+            return None;
+        }
+        // the bounds of the viewport rotated by the camera
+        let (sin, cos) = self.rotation.sin_cos();
+        let extent = [
+            self.half_extent[0] * cos.abs() + self.half_extent[1] * sin.abs(),
+            self.half_extent[0] * sin.abs() + self.half_extent[1] * cos.abs(),
+        ];
+        Some(SpriteRect::new(
+            [0, 1].map(|i| self.center[i] - extent[i]),
+            [0, 1].map(|i| self.center[i] + extent[i]),
+        ))
+    }
+
+    /// The inverse of [world_to_screen](Self::world_to_screen), for example to find the world position under the cursor
+    pub fn screen_to_world(&self, screen: [f32; 2]) -> [f32; 2] {
+        let (width, height) = (
+            self.viewport_size.0.max(1) as f32,
+            self.viewport_size.1.max(1) as f32,
+        );
+        let local = [
+            (screen[0] / width * 2.0 - 1.0) * self.half_extent[0],
+            (1.0 - screen[1] / height * 2.0) * self.half_extent[1],
+        ];
+        let (sin, cos) = self.rotation.sin_cos();
+        [
+            self.center[0] + local[0] * cos - local[1] * sin,
+            self.center[1] + local[0] * sin + local[1] * cos,
+        ]
+    }
+}
+
+pub(crate) fn update_cameras(
+    mut cameras: ResMut<Assets<Camera2D>>,
+    render_targets: Res<Assets<RenderTarget>>,
+    surface_target: Res<SurfaceTargetRes>,
+    layout: Res<CameraBindGroupLayout>,
+    device: Res<DeviceRes>,
+    queue: Res<QueueRes>,
+) {
+    for (_, camera) in cameras.iter_mut() {
+        let viewport_size = match camera.viewport {
+            CameraViewport::Surface => render_targets.get(surface_target.0).map(RenderTarget::size),
+            CameraViewport::Target(target) => render_targets.get(target).map(RenderTarget::size),
+            CameraViewport::Size(width, height) => Some((width, height)),
+        };
+        let Some(viewport_size) = viewport_size else {
+            continue;
+        };
+        camera.update_view(viewport_size);
+        let uniform = CameraUniform::view(camera.center, camera.half_extent, camera.rotation);
+        match &camera.buffer {
+            Some(buffer) => queue.0.write_buffer(buffer, 0, &uniform.to_bytes()),
+            None => {
+                let buffer = uniform.create_buffer(&device.0);
+                camera.bind_group = Some(layout.create_bind_group(&device.0, &buffer));
+                camera.buffer = Some(buffer);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+
+    /// A camera of a viewport of the given size, updated as if it was batched
+    fn camera(camera: Camera2D, viewport_size: (u32, u32)) -> Camera2D {
+        let mut camera = Camera2D {
+            viewport: CameraViewport::Size(viewport_size.0, viewport_size.1),
+            ..camera
+        };
+        camera.update_view(viewport_size);
+        camera
+    }
+
+    fn assert_near(actual: [f32; 2], expected: [f32; 2]) {
+        assert!(
+            (0..2).all(|i| (actual[i] - expected[i]).abs() < 1e-3),
+            "{actual:?} is not {expected:?}"
+        );
+    }
+
+    #[test]
+    fn world_to_screen_maps_the_view() {
+        let size = (100, 60);
+        let plain = camera(Camera2D::new(CameraViewport::Surface), size);
+        assert_near(plain.world_to_screen([0.0, 0.0]), [50.0, 30.0]);
+        // y points up in the world and down on the screen
+        assert_near(plain.world_to_screen([10.0, 5.0]), [60.0, 25.0]);
+        let zoomed = camera(
+            Camera2D::new(CameraViewport::Surface)
+                .with_position([10.0, 0.0])
+                .with_zoom(2.0),
+            size,
+        );
+        assert_near(zoomed.world_to_screen([10.0, 0.0]), [50.0, 30.0]);
+        assert_near(zoomed.world_to_screen([20.0, 5.0]), [70.0, 20.0]);
+        // turning the camera counter clockwise turns the world clockwise on the screen
+        let rotated = camera(
+            Camera2D::new(CameraViewport::Surface).with_rotation(FRAC_PI_2),
+            size,
+        );
+        assert_near(rotated.world_to_screen([10.0, 0.0]), [50.0, 40.0]);
+    }
+
+    #[test]
+    fn screen_to_world_inverts_world_to_screen() {
+        let cameras = || {
+            [
+                Camera2D::new(CameraViewport::Surface),
+                Camera2D::new(CameraViewport::Surface)
+                    .with_position([13.5, -4.0])
+                    .with_zoom(2.5)
+                    .with_rotation(0.7),
+                Camera2D::new(CameraViewport::Surface)
+                    .with_position([-40.0, 8.0])
+                    .with_rotation(-2.0)
+                    .with_scaling(CameraScaling::Fit {
+                        width: 320.0,
+                        height: 180.0,
+                    }),
+            ]
+        };
+        // odd sizes offset the center by half a pixel
+        for size in [(100, 60), (101, 61)] {
+            for camera in cameras().map(|c| camera(c, size)) {
+                for world in [[0.0, 0.0], [12.0, -7.5], [-30.0, 21.0]] {
+                    assert_near(camera.screen_to_world(camera.world_to_screen(world)), world);
+                }
+                for screen in [[0.0, 0.0], [25.0, 40.0], [100.0, 60.0]] {
+                    assert_near(
+                        camera.world_to_screen(camera.screen_to_world(screen)),
+                        screen,
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn visible_rect_bounds_the_viewport() {
+        assert!(Camera2D::new(CameraViewport::Size(100, 60))
+            .visible_rect()
+            .is_none());
+        let zoomed = camera(
+            Camera2D::new(CameraViewport::Surface)
+                .with_position([10.0, 0.0])
+                .with_zoom(2.0),
+            (100, 60),
+        );
+        let rect = zoomed.visible_rect().unwrap();
+        assert_near(rect.min, [-15.0, -15.0]);
+        assert_near(rect.max, [35.0, 15.0]);
+        let rotated = camera(
+            Camera2D::new(CameraViewport::Surface)
+                .with_position([13.5, -4.0])
+                .with_zoom(2.5)
+                .with_rotation(0.7),
+            (101, 61),
+        );
+        let rect = rotated.visible_rect().unwrap();
+        // the corners of the viewport lie on the edges of the bounds
+        let corners = [[0.0, 0.0], [101.0, 0.0], [0.0, 61.0], [101.0, 61.0]]
+            .map(|corner| rotated.screen_to_world(corner));
+        for i in 0..2 {
+            let min = corners.iter().map(|c| c[i]).fold(f32::INFINITY, f32::min);
+            let max = corners
+                .iter()
+                .map(|c| c[i])
+                .fold(f32::NEG_INFINITY, f32::max);
+            assert!((rect.min[i] - min).abs() < 1e-3);
+            assert!((rect.max[i] - max).abs() < 1e-3);
+        }
+    }
+}
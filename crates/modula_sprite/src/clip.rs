@@ -0,0 +1,178 @@
+use crate::{Camera2D, SpriteRect};
+
+/// Nested clip rects, where the top is the intersection of every pushed rect
+#[derive(Default)]
+pub(crate) struct ClipStack {
+    clips: Vec<SpriteRect>,
+}
+
+impl ClipStack {
+    pub fn push(&mut self, rect: SpriteRect) {
+        let clip = match self.current() {
+            Some(current) => current.intersection(&rect),
+            None => rect,
+        };
+        self.clips.push(clip);
+    }
+
+    pub fn pop(&mut self) -> Option<SpriteRect> {
+        self.clips.pop()
+    }
+
+    #[inline]
+    pub fn current(&self) -> Option<SpriteRect> {
+        self.clips.last().copied()
+    }
+
+    pub fn clear(&mut self) {
+        self.clips.clear();
+    }
+}
+
+/// The scissor rect of a clip as x, y, width and height in pixels of a target of target_size, None if no pixels are inside.
+/// With a camera the clip is in world space and the scissor is the bounds of its corners on the target,
+/// otherwise the clip is in pixels of the target where the origin is the bottom left and y points up
+pub(crate) fn scissor_rect(
+    clip: SpriteRect,
+    camera: Option<&Camera2D>,
+    target_size: (u32, u32),
+) -> Option<[u32; 4]> {
+    let (width, height) = (target_size.0 as f32, target_size.1 as f32);
+    let (min, max) = match camera {
+        Some(camera) => {
+            // the viewport of the camera is stretched over the whole target
+            let (viewport_width, viewport_height) = camera.viewport_size();
+            let scale = [
+                width / viewport_width.max(1) as f32,
+                height / viewport_height.max(1) as f32,
+            ];
+            let corners = [
+                clip.min,
+                [clip.max[0], clip.min[1]],
+                clip.max,
+                [clip.min[0], clip.max[1]],
+            ]
+            .map(|corner| {
+                let screen = camera.world_to_screen(corner);
+                [screen[0] * scale[0], screen[1] * scale[1]]
+            });
+            let mut min = corners[0];
+            let mut max = corners[0];
+            for corner in &corners[1..] {
+                for i in 0..2 {
+                    min[i] = min[i].min(corner[i]);
+                    max[i] = max[i].max(corner[i]);
+                }
+            }
+            (min, max)
+        }
+        None => (
+            [clip.min[0], height - clip.max[1]],
+            [clip.max[0], height - clip.min[1]],
+        ),
+    };
+    // pixels whose centers are inside the clip are kept
+    let x = [min[0], max[0]].map(|x| x.round().clamp(0.0, width) as u32);
+    let y = [min[1], max[1]].map(|y| y.round().clamp(0.0, height) as u32);
+    if x[1] <= x[0] || y[1] <= y[0] {
+        return None;
+    }
+    Some([x[0], y[0], x[1] - x[0], y[1] - y[0]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CameraViewport;
+
+    fn rect(min: [f32; 2], max: [f32; 2]) -> SpriteRect {
+        SpriteRect::new(min, max)
+    }
+
+    #[test]
+    fn nested_clips_intersect() {
+        let mut clips = ClipStack::default();
+        assert_eq!(clips.current(), None);
+        clips.push(rect([0.0, 0.0], [100.0, 50.0]));
+        clips.push(rect([20.0, -10.0], [150.0, 30.0]));
+        assert_eq!(clips.current(), Some(rect([20.0, 0.0], [100.0, 30.0])));
+        clips.push(rect([40.0, 10.0], [60.0, 20.0]));
+        assert_eq!(clips.current(), Some(rect([40.0, 10.0], [60.0, 20.0])));
+        // popping returns the clips as they were intersected
+        assert_eq!(clips.pop(), Some(rect([40.0, 10.0], [60.0, 20.0])));
+        assert_eq!(clips.pop(), Some(rect([20.0, 0.0], [100.0, 30.0])));
+        assert_eq!(clips.current(), Some(rect([0.0, 0.0], [100.0, 50.0])));
+        clips.clear();
+        assert_eq!(clips.pop(), None);
+    }
+
+    #[test]
+    fn disjoint_clips_stay_empty() {
+        let mut clips = ClipStack::default();
+        clips.push(rect([0.0, 0.0], [10.0, 10.0]));
+        clips.push(rect([20.0, 0.0], [30.0, 10.0]));
+        assert!(clips.current().unwrap().is_empty());
+        // a clip inside an empty one has nothing to add back
+        clips.push(rect([0.0, 0.0], [30.0, 10.0]));
+        assert!(clips.current().unwrap().is_empty());
+        // touching edges have no area either
+        let mut clips = ClipStack::default();
+        clips.push(rect([0.0, 0.0], [10.0, 10.0]));
+        clips.push(rect([10.0, 0.0], [20.0, 10.0]));
+        assert!(clips.current().unwrap().is_empty());
+    }
+
+    #[test]
+    fn scissor_rects_of_target_pixels() {
+        let size = (100, 50);
+        // y points up in the clip and down in the scissor
+        assert_eq!(
+            scissor_rect(rect([10.0, 5.0], [30.0, 20.0]), None, size),
+            Some([10, 30, 20, 15])
+        );
+        // clamped to the target, and rounded to the pixels whose centers are inside
+        assert_eq!(
+            scissor_rect(rect([-10.0, 0.4], [30.6, 80.0]), None, size),
+            Some([0, 0, 31, 50])
+        );
+        assert_eq!(
+            scissor_rect(rect([10.0, 5.0], [10.0, 20.0]), None, size),
+            None
+        );
+        assert_eq!(
+            scissor_rect(rect([200.0, 5.0], [300.0, 20.0]), None, size),
+            None
+        );
+        assert_eq!(
+            scissor_rect(rect([10.0, 5.0], [10.4, 20.0]), None, size),
+            None
+        );
+    }
+
+    #[test]
+    fn scissor_rects_of_world_clips() {
+        let mut camera = Camera2D::new(CameraViewport::Size(100, 50)).with_position([50.0, 25.0]);
+        camera.update_view((100, 50));
+        let clip = rect([10.0, 5.0], [30.0, 20.0]);
+        assert_eq!(
+            scissor_rect(clip, Some(&camera), (100, 50)),
+            Some([10, 30, 20, 15])
+        );
+        // the viewport is stretched over a target of another size
+        assert_eq!(
+            scissor_rect(clip, Some(&camera), (200, 100)),
+            Some([20, 60, 40, 30])
+        );
+        // a zoomed camera shows less of the world, so the clip covers more pixels
+        let mut zoomed = Camera2D::new(CameraViewport::Size(100, 50))
+            .with_position([50.0, 25.0])
+            .with_zoom(2.0);
+        zoomed.update_view((100, 50));
+        assert_eq!(
+            scissor_rect(rect([40.0, 20.0], [60.0, 30.0]), Some(&zoomed), (100, 50)),
+            Some([30, 15, 40, 20])
+        );
+        let outside = rect([200.0, 5.0], [300.0, 20.0]);
+        assert_eq!(scissor_rect(outside, Some(&camera), (100, 50)), None);
+    }
+}
@@ -0,0 +1,160 @@
+use modula_asset::AssetId;
+use modula_texture::atlas::AtlasGroup;
+
+use crate::{batch::image_size, Camera2D, SpriteRect, Submission, SubmissionKind};
+
+/// Skips the sprites of a [SpriteQueue](crate::SpriteQueue) outside the view of a camera when batching, see [set_culling](crate::SpriteQueue::set_culling)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpriteCulling {
+    pub camera: AssetId<Camera2D>,
+    /// World units added to every side of the visible rect of the camera,
+    /// for sprites that reach into view in ways the bounds do not cover, such as vertex shader offsets
+    pub margin: f32,
+}
+
+impl SpriteCulling {
+    pub fn new(camera: AssetId<Camera2D>) -> Self {
+        Self {
+            camera,
+            margin: 0.0,
+        }
+    }
+
+    pub fn with_margin(mut self, margin: f32) -> Self {
+        self.margin = margin;
+        self
+    }
+}
+
+impl Submission {
+    /// The world space bounds of the sprite, None if they are not known because the entry was removed
+    pub(crate) fn bounds(&self, group: &AtlasGroup) -> Option<SpriteRect> {
+        match &self.kind {
+            SubmissionKind::NineSlice { rect, .. } => Some(*rect),
+            _ => self.quad(group).map(|quad| quad.bounds()),
+        }
+    }
+
+    /// The quad of the sprite as placed by the default shader, None if the entry was removed
+    pub(crate) fn quad(&self, group: &AtlasGroup) -> Option<SpriteQuad> {
+        match &self.kind {
+            SubmissionKind::Entry {
+                entry, transform, ..
+            } => {
+                if group.is_removed(*entry) {
+                    return None;
+                }
+                let (width, height) = image_size(group, *entry);
+                Some(SpriteQuad {
+                    position: transform.position,
+                    size: [
+                        width as f32 * transform.scale[0],
+                        height as f32 * transform.scale[1],
+                    ],
+                    pivot: transform.pivot,
+                    rotation: transform.rotation,
+                })
+            }
+            SubmissionKind::NineSlice { rect, .. } => Some(SpriteQuad {
+                position: rect.min,
+                size: [rect.width(), rect.height()],
+                pivot: [0.0; 2],
+                rotation: 0.0,
+            }),
+            SubmissionKind::Raw { instance, .. } => Some(SpriteQuad {
+                position: instance.position,
+                size: instance.size,
+                pivot: instance.pivot,
+                rotation: instance.rotation,
+            }),
+        }
+    }
+}
+
+/// A quad placed like in the sprite shader
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct SpriteQuad {
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+    pub pivot: [f32; 2],
+    pub rotation: f32,
+}
+
+impl SpriteQuad {
+    pub fn bounds(&self) -> SpriteRect {
+        let (sin, cos) = self.rotation.sin_cos();
+        let mut bounds = SpriteRect::new([f32::INFINITY; 2], [f32::NEG_INFINITY; 2]);
+        for corner in [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]] {
+            let local = [0, 1].map(|i| (corner[i] - self.pivot[i]) * self.size[i]);
+            let world = [
+                self.position[0] + local[0] * cos - local[1] * sin,
+                self.position[1] + local[0] * sin + local[1] * cos,
+            ];
+            bounds.min = [0, 1].map(|i| bounds.min[i].min(world[i]));
+            bounds.max = [0, 1].map(|i| bounds.max[i].max(world[i]));
+        }
+        bounds
+    }
+
+    /// The position of a world point in the quad, where [0, 0] is the bottom left corner and [1, 1] the top right
+    pub fn local(&self, world: [f32; 2]) -> [f32; 2] {
+        let (sin, cos) = self.rotation.sin_cos();
+        let relative = [world[0] - self.position[0], world[1] - self.position[1]];
+        // rotated back by the rotation of the quad
+        let unrotated = [
+            relative[0] * cos + relative[1] * sin,
+            -relative[0] * sin + relative[1] * cos,
+        ];
+        [0, 1].map(|i| unrotated[i] / self.size[i] + self.pivot[i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+
+    fn quad(pivot: [f32; 2], rotation: f32) -> SpriteQuad {
+        SpriteQuad {
+            position: [10.0, 20.0],
+            size: [8.0, 4.0],
+            pivot,
+            rotation,
+        }
+    }
+
+    #[test]
+    fn bounds_follow_the_pivot_and_rotation() {
+        let centered = quad([0.5; 2], 0.0).bounds();
+        assert_eq!(centered, SpriteRect::new([6.0, 18.0], [14.0, 22.0]));
+        let corner = quad([0.0; 2], 0.0).bounds();
+        assert_eq!(corner, SpriteRect::new([10.0, 20.0], [18.0, 24.0]));
+        // a quarter turn swaps the extents, so a sprite can reach into a view it did not before
+        let rotated = quad([0.5; 2], FRAC_PI_2).bounds();
+        let view = SpriteRect::new([0.0, 23.0], [20.0, 30.0]);
+        assert!(!centered.intersects(&view));
+        assert!(rotated.intersects(&view));
+        for (actual, expected) in rotated.min.into_iter().zip([8.0, 16.0]) {
+            assert!((actual - expected).abs() < 1e-5);
+        }
+        for (actual, expected) in rotated.max.into_iter().zip([12.0, 24.0]) {
+            assert!((actual - expected).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn local_positions_span_the_quad() {
+        let quad = quad([0.25, 0.5], 0.6);
+        let (sin, cos) = quad.rotation.sin_cos();
+        for corner in [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0], [0.5, 0.25]] {
+            let offset = [0, 1].map(|i| (corner[i] - quad.pivot[i]) * quad.size[i]);
+            let world = [
+                quad.position[0] + offset[0] * cos - offset[1] * sin,
+                quad.position[1] + offset[0] * sin + offset[1] * cos,
+            ];
+            let local = quad.local(world);
+            assert!((0..2).all(|i| (local[i] - corner[i]).abs() < 1e-5));
+        }
+    }
+}
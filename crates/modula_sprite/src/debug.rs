@@ -0,0 +1,218 @@
+use bevy_ecs::{
+    schedule::SystemSet,
+    system::{Res, ResMut, Resource},
+};
+use modula_asset::{AssetId, Assets};
+use modula_core::{DeviceRes, QueueRes};
+use modula_texture::{
+    atlas::{AtlasGroup, AtlasGroupBindGroupLayout, AtlasGroupEntry},
+    Image,
+};
+use wgpu::Color;
+
+use crate::{
+    Anchor, CoordinateSpace, SpriteBlend, SpriteInstance, SpritePipeline, SpriteQueue, SpriteRect,
+    Submission, SubmissionKind,
+};
+
+/// Shapes drawn with [DebugDraw] are submitted during [PreDraw](modula_render::PreDraw) in this set,
+/// after [AtlasLoadSet](modula_texture::atlas::AtlasLoadSet) and before [SpriteBatchSet](crate::SpriteBatchSet)
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DebugDrawSet;
+
+struct DebugLine {
+    from: [f32; 2],
+    to: [f32; 2],
+    color: [f32; 4],
+    thickness: f32,
+    screen_space: bool,
+}
+
+/// Resource drawing lines, rects and circles as sprites for one frame, for example to debug gameplay.
+/// The shapes are made of quads of a white pixel in an atlas group owned by this, submitted to the [queue](Self::set_queue) at [WORLD_LAYER](Self::WORLD_LAYER),
+/// or at [SCREEN_LAYER](Self::SCREEN_LAYER) while [screen space](Self::set_screen_space) is set.
+/// These are the top-most layers, and screen space shapes are in logical pixels from the bottom left corner of the surface, see [CoordinateSpace].
+/// Like sprites, shapes drawn during [Draw](modula_render::Draw) are drawn the next frame.
+/// Shapes are dropped while disabled or without a queue
+#[derive(Resource)]
+pub struct DebugDraw {
+    enabled: bool,
+    queue: Option<(AssetId<SpriteQueue>, AssetId<SpritePipeline>)>,
+    atlas: Option<(AssetId<AtlasGroup>, AtlasGroupEntry)>,
+    thickness: f32,
+    screen_space: bool,
+    circle_segments: u32,
+    lines: Vec<DebugLine>,
+}
+
+impl Default for DebugDraw {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            queue: None,
+            atlas: None,
+            thickness: 1.0,
+            screen_space: false,
+            circle_segments: 32,
+            lines: Vec::new(),
+        }
+    }
+}
+
+impl DebugDraw {
+    /// The layer of the shapes in world space
+    pub const WORLD_LAYER: i32 = i32::MAX - 1;
+    /// The layer of the shapes drawn while [screen space](Self::set_screen_space) is set
+    pub const SCREEN_LAYER: i32 = i32::MAX;
+
+    /// The queue the shapes are submitted to with pipeline, which must sample atlas groups like [SpritePipelineBuilder](crate::SpritePipelineBuilder)
+    pub fn set_queue(&mut self, queue: AssetId<SpriteQueue>, pipeline: AssetId<SpritePipeline>) {
+        self.queue = Some((queue, pipeline));
+    }
+
+    /// Enabled by default
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.lines.clear();
+        }
+    }
+
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The width of the lines drawn after this, 1 by default
+    pub fn set_thickness(&mut self, thickness: f32) {
+        self.thickness = thickness;
+    }
+
+    /// Whether the shapes drawn after this are in [screen space](CoordinateSpace::Screen) from the [bottom left](Anchor::BottomLeft) corner of the surface,
+    /// at [SCREEN_LAYER](Self::SCREEN_LAYER) instead of [WORLD_LAYER](Self::WORLD_LAYER)
+    pub fn set_screen_space(&mut self, screen_space: bool) {
+        self.screen_space = screen_space;
+    }
+
+    /// The number of lines of the circles drawn after this, 32 by default
+    pub fn set_circle_segments(&mut self, segments: u32) {
+        self.circle_segments = segments.max(3);
+    }
+
+    pub fn line(&mut self, from: [f32; 2], to: [f32; 2], color: Color) {
+        if !self.enabled {
+            return;
+        }
+        self.lines.push(DebugLine {
+            from,
+            to,
+            color: [color.r, color.g, color.b, color.a].map(|c| c as f32),
+            thickness: self.thickness,
+            screen_space: self.screen_space,
+        });
+    }
+
+    /// The outline of rect
+    pub fn rect(&mut self, rect: SpriteRect, color: Color) {
+        let corners = [
+            rect.min,
+            [rect.max[0], rect.min[1]],
+            rect.max,
+            [rect.min[0], rect.max[1]],
+        ];
+        for i in 0..4 {
+            self.line(corners[i], corners[(i + 1) % 4], color);
+        }
+    }
+
+    /// The outline of a circle, made of [circle_segments](Self::set_circle_segments) lines
+    pub fn circle(&mut self, center: [f32; 2], radius: f32, color: Color) {
+        let segments = self.circle_segments;
+        let point = |i: u32| {
+            let angle = i as f32 / segments as f32 * std::f32::consts::TAU;
+            [
+                center[0] + angle.cos() * radius,
+                center[1] + angle.sin() * radius,
+            ]
+        };
+        for i in 0..segments {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    /// Removes the shapes drawn since they were last submitted
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+}
+
+pub(crate) fn submit_debug_draw(
+    mut debug: ResMut<DebugDraw>,
+    mut atlas_groups: ResMut<Assets<AtlasGroup>>,
+    mut sprite_queues: ResMut<Assets<SpriteQueue>>,
+    atlas_layout: Res<AtlasGroupBindGroupLayout>,
+    device: Res<DeviceRes>,
+    queue: Res<QueueRes>,
+) {
+    let Some((queue_id, pipeline)) = debug.queue.filter(|_| !debug.lines.is_empty()) else {
+        debug.lines.clear();
+        return;
+    };
+    let (atlas_id, entry) = match debug.atlas {
+        Some(atlas) => atlas,
+        None => {
+            let mut group = AtlasGroup::new(Vec::new(), Vec::new(), &device.0, &atlas_layout);
+            let white = Image::from_raw_rgba8(1, 1, vec![255; 4]).expect("the pixel has its size");
+            let Ok(entry) = group.insert(white, &device.0, &queue.0, &atlas_layout) else {
+                debug.lines.clear();
+                return;
+            };
+            *debug.atlas.insert((atlas_groups.add(group), entry))
+        }
+    };
+    let (Some(group), Some(sprite_queue)) =
+        (atlas_groups.get(atlas_id), sprite_queues.get_mut(queue_id))
+    else {
+        debug.lines.clear();
+        return;
+    };
+    let uv = group.entry_uvs(entry);
+    for line in debug.lines.drain(..) {
+        let delta = [line.to[0] - line.from[0], line.to[1] - line.from[1]];
+        // extended by half the thickness at both ends, so lines meet at corners
+        let length = (delta[0] * delta[0] + delta[1] * delta[1]).sqrt() + line.thickness;
+        let mut instance = SpriteInstance::from_entry(entry, line.from, [length, line.thickness]);
+        instance.pivot = [line.thickness / 2.0 / length, 0.5];
+        instance.rotation = delta[1].atan2(delta[0]);
+        // every point of the quad samples the center of the pixel, so filtering does not blend in the padding
+        instance.region_min = [0.5; 2];
+        instance.region_max = [0.5; 2];
+        instance.tint = line.color;
+        let (layer, space) = if line.screen_space {
+            (
+                DebugDraw::SCREEN_LAYER,
+                CoordinateSpace::screen(Anchor::BottomLeft),
+            )
+        } else {
+            (DebugDraw::WORLD_LAYER, CoordinateSpace::World)
+        };
+        sprite_queue.push_submission(Submission {
+            layer,
+            z: 0.0,
+            depth: 0.0,
+            atlas: atlas_id,
+            pipeline,
+            material: None,
+            blend: SpriteBlend::Alpha,
+            clip: None,
+            space,
+            pick_id: None,
+            shadow: false,
+            outline: false,
+            kind: SubmissionKind::Raw {
+                instance,
+                bind_group_index: uv.bind_group_index as usize,
+            },
+        });
+    }
+}
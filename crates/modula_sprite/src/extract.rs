@@ -0,0 +1,326 @@
+use std::collections::BTreeMap;
+
+use bevy_ecs::{
+    change_detection::DetectChanges,
+    component::Component,
+    entity::Entity,
+    query::{Changed, Or, QueryItem, With},
+    removal_detection::RemovedComponents,
+    schedule::SystemSet,
+    system::{Query, Res, ResMut, Resource, SystemParam},
+};
+use modula_asset::{AssetId, Assets};
+use modula_texture::atlas::{AtlasGroup, AtlasGroupEntry};
+use wgpu::Color;
+
+use crate::{
+    AnimationClip, Animator, CoordinateSpace, MaterialId, PickId, SpriteBlend, SpriteQueue,
+    SpriteTransform, Submission, SubmissionKind,
+};
+
+/// [Sprite] entities are submitted to their queues during [PreDraw](modula_render::PreDraw) in this set,
+/// after [AnimationSet](crate::AnimationSet) and before [SpriteBatchSet](crate::SpriteBatchSet)
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SpriteExtractSet;
+
+/// An entity drawn as a sprite while it has a [Transform2D] as well, submitted to queue every frame during [SpriteExtractSet].
+/// It is drawn with the [pipeline](SpriteQueue::set_pipeline) of the queue, and skipped while the queue has none.
+/// If the entity has an [Animator] its current frame is drawn instead of entry.
+/// With a [material](crate::SpriteMaterials) it is drawn with the pipeline of the material instead
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct Sprite {
+    pub queue: AssetId<SpriteQueue>,
+    pub atlas: AssetId<AtlasGroup>,
+    pub entry: AtlasGroupEntry,
+    pub tint: Color,
+    pub blend: SpriteBlend,
+    pub material: Option<MaterialId>,
+    /// See [SpriteTransform::pivot]
+    pub pivot: [f32; 2],
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Whether the entity is recorded by the [SpritePicker](crate::SpritePicker), found as [PickId::Entity]
+    pub pickable: bool,
+    /// See [SpriteStyle::shadow](crate::SpriteStyle::shadow)
+    pub shadow: bool,
+    /// See [SpriteStyle::outline](crate::SpriteStyle::outline)
+    pub outline: bool,
+}
+
+impl Sprite {
+    pub fn new(
+        queue: AssetId<SpriteQueue>,
+        atlas: AssetId<AtlasGroup>,
+        entry: AtlasGroupEntry,
+    ) -> Self {
+        Self {
+            queue,
+            atlas,
+            entry,
+            tint: Color::WHITE,
+            blend: SpriteBlend::default(),
+            material: None,
+            pivot: [0.5; 2],
+            flip_x: false,
+            flip_y: false,
+            pickable: false,
+            shadow: false,
+            outline: false,
+        }
+    }
+
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn with_blend(mut self, blend: SpriteBlend) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn with_material(mut self, material: MaterialId) -> Self {
+        self.material = Some(material);
+        self
+    }
+
+    pub fn with_pivot(mut self, pivot: [f32; 2]) -> Self {
+        self.pivot = pivot;
+        self
+    }
+
+    pub fn with_flip(mut self, flip_x: bool, flip_y: bool) -> Self {
+        self.flip_x = flip_x;
+        self.flip_y = flip_y;
+        self
+    }
+
+    pub fn with_pickable(mut self, pickable: bool) -> Self {
+        self.pickable = pickable;
+        self
+    }
+
+    pub fn with_shadow(mut self, shadow: bool) -> Self {
+        self.shadow = shadow;
+        self
+    }
+
+    pub fn with_outline(mut self, outline: bool) -> Self {
+        self.outline = outline;
+        self
+    }
+}
+
+/// The position, rotation and scale of a [Sprite] entity in world space
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct Transform2D {
+    pub translation: [f32; 2],
+    /// Counter clockwise around the pivot, in radians
+    pub rotation: f32,
+    /// Multiplied with the size of the entry in pixels
+    pub scale: [f32; 2],
+}
+
+impl Default for Transform2D {
+    fn default() -> Self {
+        Self {
+            translation: [0.0; 2],
+            rotation: 0.0,
+            scale: [1.0; 2],
+        }
+    }
+}
+
+impl Transform2D {
+    pub fn from_translation(translation: [f32; 2]) -> Self {
+        Self {
+            translation,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: [f32; 2]) -> Self {
+        self.scale = scale;
+        self
+    }
+}
+
+/// Whether a [Sprite] entity is drawn, entities without it are visible
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Visible(pub bool);
+
+impl Default for Visible {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+/// The [layer](SpriteQueue::set_layer) and z a [Sprite] entity is submitted at, both are 0 for entities without it
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Layer {
+    pub layer: i32,
+    pub z: f32,
+}
+
+impl Layer {
+    pub fn new(layer: i32, z: f32) -> Self {
+        Self { layer, z }
+    }
+
+    /// At layer 0
+    pub fn from_z(z: f32) -> Self {
+        Self::new(0, z)
+    }
+}
+
+/// A sprite entity as it was last extracted
+struct ExtractedSprite {
+    queue: AssetId<SpriteQueue>,
+    atlas: AssetId<AtlasGroup>,
+    layer: i32,
+    z: f32,
+    material: Option<MaterialId>,
+    blend: SpriteBlend,
+    pick_id: Option<PickId>,
+    shadow: bool,
+    outline: bool,
+    kind: SubmissionKind,
+}
+
+/// The submissions of the sprite entities, only remade for entities whose components changed.
+/// Ordered by entity so sprites at the same layer and z are drawn in the same order every frame
+#[derive(Resource, Default)]
+pub(crate) struct ExtractedSprites {
+    sprites: BTreeMap<Entity, ExtractedSprite>,
+}
+
+type SpriteComponents = (
+    &'static Sprite,
+    &'static Transform2D,
+    Option<&'static Visible>,
+    Option<&'static Layer>,
+    Option<&'static Animator>,
+);
+
+type ChangedSprite = Or<(
+    Changed<Sprite>,
+    Changed<Transform2D>,
+    Changed<Visible>,
+    Changed<Layer>,
+    Changed<Animator>,
+)>;
+
+#[derive(SystemParam)]
+pub(crate) struct SpriteChanges<'w, 's> {
+    changed: Query<'w, 's, Entity, (With<Sprite>, ChangedSprite)>,
+    animated: Query<'w, 's, Entity, (With<Sprite>, With<Animator>)>,
+    removed_sprites: RemovedComponents<'w, 's, Sprite>,
+    removed_transforms: RemovedComponents<'w, 's, Transform2D>,
+    removed_visible: RemovedComponents<'w, 's, Visible>,
+    removed_layers: RemovedComponents<'w, 's, Layer>,
+    removed_animators: RemovedComponents<'w, 's, Animator>,
+}
+
+impl SpriteChanges<'_, '_> {
+    /// The entities that must be extracted again, animated entities are included if the clips changed
+    fn entities(&mut self, clips_changed: bool) -> Vec<Entity> {
+        let mut entities: Vec<Entity> = self.changed.iter().collect();
+        if clips_changed {
+            entities.extend(self.animated.iter());
+        }
+        entities.extend(self.removed_sprites.read());
+        entities.extend(self.removed_transforms.read());
+        entities.extend(self.removed_visible.read());
+        entities.extend(self.removed_layers.read());
+        entities.extend(self.removed_animators.read());
+        entities
+    }
+}
+
+pub(crate) fn extract_sprites(
+    mut extracted: ResMut<ExtractedSprites>,
+    mut changes: SpriteChanges,
+    sprites: Query<SpriteComponents>,
+    clips: Option<Res<Assets<AnimationClip>>>,
+    mut queues: ResMut<Assets<SpriteQueue>>,
+) {
+    let clips_changed = clips.as_ref().is_some_and(|clips| clips.is_changed());
+    for entity in changes.entities(clips_changed) {
+        let sprite = sprites
+            .get(entity)
+            .ok()
+            .and_then(|components| extract(entity, components, clips.as_deref()));
+        match sprite {
+            Some(sprite) => extracted.sprites.insert(entity, sprite),
+            None => extracted.sprites.remove(&entity),
+        };
+    }
+    for sprite in extracted.sprites.values() {
+        let Some(queue) = queues.get_mut(sprite.queue) else {
+            continue;
+        };
+        let Some(pipeline) = queue.pipeline() else {
+            continue;
+        };
+        queue.push_submission(Submission {
+            layer: sprite.layer,
+            z: sprite.z,
+            depth: 0.0,
+            atlas: sprite.atlas,
+            pipeline,
+            material: sprite.material,
+            blend: sprite.blend,
+            clip: None,
+            space: CoordinateSpace::World,
+            pick_id: sprite.pick_id,
+            shadow: sprite.shadow,
+            outline: sprite.outline,
+            kind: sprite.kind.clone(),
+        });
+    }
+}
+
+/// None if the sprite is not drawn
+fn extract(
+    entity: Entity,
+    (sprite, transform, visible, layer, animator): QueryItem<SpriteComponents>,
+    clips: Option<&Assets<AnimationClip>>,
+) -> Option<ExtractedSprite> {
+    if visible.is_some_and(|visible| !visible.0) {
+        return None;
+    }
+    let entry = match (animator, clips) {
+        (Some(animator), Some(clips)) => animator.entry(clips).unwrap_or(sprite.entry),
+        _ => sprite.entry,
+    };
+    let layer = layer.copied().unwrap_or_default();
+    let tint = sprite.tint;
+    Some(ExtractedSprite {
+        queue: sprite.queue,
+        atlas: sprite.atlas,
+        layer: layer.layer,
+        z: layer.z,
+        material: sprite.material,
+        blend: sprite.blend,
+        pick_id: sprite.pickable.then_some(PickId::Entity(entity)),
+        shadow: sprite.shadow,
+        outline: sprite.outline,
+        kind: SubmissionKind::Entry {
+            entry,
+            transform: SpriteTransform {
+                position: transform.translation,
+                scale: transform.scale,
+                rotation: transform.rotation,
+                pivot: sprite.pivot,
+                flip_x: sprite.flip_x,
+                flip_y: sprite.flip_y,
+            },
+            tint: [tint.r, tint.g, tint.b, tint.a].map(|c| c as f32),
+        },
+    })
+}
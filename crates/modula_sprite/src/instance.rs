@@ -0,0 +1,133 @@
+use modula_texture::atlas::{AtlasGroupEntry, InstanceLayout};
+use wgpu::{BufferAddress, VertexAttribute, VertexFormat};
+
+/// The data of a sprite as laid out in the instance buffer, a pipeline drawing a [SpriteQueue](crate::SpriteQueue) reads it with [instance_layout](Self::instance_layout)
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SpriteInstance {
+    /// World position of the pivot
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+    /// Normalized position in the sprite that is placed at position and rotated around, see [SpriteTransform::pivot](crate::SpriteTransform::pivot)
+    pub pivot: [f32; 2],
+    /// Counter clockwise, in radians
+    pub rotation: f32,
+    /// [Index](AtlasGroupEntry::index) of the entry, whose atlas, layer and UVs are read from the [entry buffer](modula_texture::atlas::AtlasGroup::entry_buffer)
+    pub entry: u32,
+    /// Bit flags, see [FLIP_X](Self::FLIP_X) and [FLIP_Y](Self::FLIP_Y)
+    pub flags: u32,
+    /// The part of the image drawn on the quad, where [0, 0] is the top left of the image and [1, 1] the bottom right
+    pub region_min: [f32; 2],
+    pub region_max: [f32; 2],
+    /// Multiplied with the sampled color
+    pub tint: [f32; 4],
+    /// Depth in clip space, set from the order of the sprite when batching so later sprites are closer
+    pub depth: f32,
+}
+
+impl SpriteInstance {
+    /// Size of an instance in the buffer in bytes
+    pub const SIZE: BufferAddress = 72;
+
+    /// Mirrors the image horizontally
+    pub const FLIP_X: u32 = 1;
+    /// Mirrors the image vertically
+    pub const FLIP_Y: u32 = 2;
+
+    /// Locations 0 to 9 in the order of the fields:
+    /// ```wgsl
+    /// @location(0) position: vec2<f32>,
+    /// @location(1) size: vec2<f32>,
+    /// @location(2) pivot: vec2<f32>,
+    /// @location(3) rotation: f32,
+    /// @location(4) entry: u32,
+    /// @location(5) flags: u32,
+    /// @location(6) region_min: vec2<f32>,
+    /// @location(7) region_max: vec2<f32>,
+    /// @location(8) tint: vec4<f32>,
+    /// @location(9) depth: f32,
+    /// ```
+    pub fn instance_layout() -> InstanceLayout {
+        let formats = [
+            VertexFormat::Float32x2,
+            VertexFormat::Float32x2,
+            VertexFormat::Float32x2,
+            VertexFormat::Float32,
+            VertexFormat::Uint32,
+            VertexFormat::Uint32,
+            VertexFormat::Float32x2,
+            VertexFormat::Float32x2,
+            VertexFormat::Float32x4,
+            VertexFormat::Float32,
+        ];
+        let mut offset = 0;
+        let attributes = formats
+            .into_iter()
+            .enumerate()
+            .map(|(location, format)| {
+                let attribute = VertexAttribute {
+                    format,
+                    offset,
+                    shader_location: location as u32,
+                };
+                offset += format.size();
+                attribute
+            })
+            .collect();
+        InstanceLayout {
+            array_stride: Self::SIZE,
+            attributes,
+        }
+    }
+
+    /// An instance drawing the whole image of entry centered on position
+    pub fn from_entry(entry: AtlasGroupEntry, position: [f32; 2], size: [f32; 2]) -> Self {
+        Self {
+            position,
+            size,
+            pivot: [0.5; 2],
+            rotation: 0.0,
+            entry: entry.index() as u32,
+            flags: 0,
+            region_min: [0.0; 2],
+            region_max: [1.0; 2],
+            tint: [1.0; 4],
+            depth: 0.0,
+        }
+    }
+
+    /// Appends the instance as laid out in the buffer
+    pub(crate) fn write_gpu(&self, out: &mut Vec<u8>) {
+        for value in self.position.into_iter().chain(self.size).chain(self.pivot) {
+            out.extend(value.to_ne_bytes());
+        }
+        out.extend(self.rotation.to_ne_bytes());
+        for value in [self.entry, self.flags] {
+            out.extend(value.to_ne_bytes());
+        }
+        for value in self
+            .region_min
+            .into_iter()
+            .chain(self.region_max)
+            .chain(self.tint)
+            .chain([self.depth])
+        {
+            out.extend(value.to_ne_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_matches_size() {
+        let mut out = Vec::new();
+        SpriteInstance::default().write_gpu(&mut out);
+        assert_eq!(out.len() as BufferAddress, SpriteInstance::SIZE);
+        let layout = SpriteInstance::instance_layout();
+        let last = layout.attributes.last().unwrap();
+        assert_eq!(last.offset + last.format.size(), layout.array_stride);
+    }
+}
@@ -1,65 +1,146 @@
-/* old code todo fix
-use bevy_ecs::world::World;
-use modula_asset::{AssetId, AssetWorldExt};
-use modula_render::{Operation, RenderTarget};
-use modula_texture::atlas::Atlas;
-use wgpu::{BindGroup, Buffer, CommandEncoder, RenderPipeline};
+use bevy_ecs::{
+    schedule::IntoSystemConfigs,
+    system::{Commands, Res},
+};
+use modula_asset::init_assets;
+use modula_core::{DeviceRes, Init, ScheduleBuilder};
+use modula_render::PreDraw;
+use modula_texture::atlas::AtlasLoadSet;
+use wgpu::{BindGroup, Buffer};
 
-pub struct SpriteOperation {
-    target: AssetId<RenderTarget>,
-    queue: AssetId<SpriteQueue>,
-}
-
-impl Operation for SpriteOperation {
-    fn run(&mut self, world: &mut World, command_encoder: &mut CommandEncoder) {
-        world.asset_scope(self.target, |world, target| {
-            let mut pass = target.begin_pass(command_encoder);
-            let queue = world
-                .get_asset(self.queue)
-                .expect("no queue for sprite operation");
-            for batch in queue.batches.iter() {
-                pass.set_pipeline(
-                    &world
-                        .get_asset(batch.pipeline)
-                        .expect("no pipeline for sprite batch"),
-                );
-                pass.set_vertex_buffer(
-                    0,
-                    world
-                        .get_asset(batch.buffer)
-                        .expect("buffer was not avalibe")
-                        .slice(batch.start..(batch.start + batch.count as u64 * batch.size)),
-                );
-                let atlas = world
-                    .get_asset(batch.atlas)
-                    .expect("no atlas for sprite batch");
-                pass.set_bind_group(0, atlas.bind_group(), &[]);
-                for (i, group) in queue.bind_groups.iter().enumerate() {
-                    pass.set_bind_group(
-                        i as u32 + 1,
-                        world.get_asset(*group).expect("bind group was missing"),
-                        &[],
-                    );
-                }
-                pass.draw(0..6, 0..batch.count);
-            }
-        })
-    }
-}
+mod animation;
+mod baked;
+mod batch;
+mod camera;
+mod clip;
+mod cull;
+#[cfg(feature = "debug_draw")]
+mod debug;
+mod extract;
+mod instance;
+mod material;
+mod nine_slice;
+mod operation;
+mod outline;
+mod pick;
+mod pool;
+mod queue;
+mod shader;
+mod space;
+mod stats;
+mod style;
+#[cfg(feature = "text")]
+mod text;
 
-/// a type that holds information about what sprites to draw, and their order
-pub struct SpriteQueue {
-    // Starting at 1, as group 0 is from atlas
-    bind_groups: Vec<AssetId<BindGroup>>,
-    batches: Vec<SpriteBatch>,
-}
+pub use animation::*;
+pub use baked::BakedSprites;
+pub use batch::SpriteBatchSet;
+pub use camera::*;
+pub use cull::SpriteCulling;
+#[cfg(feature = "debug_draw")]
+pub use debug::{DebugDraw, DebugDrawSet};
+pub use extract::{Layer, Sprite, SpriteExtractSet, Transform2D, Visible};
+pub use instance::*;
+pub use material::{MaterialId, SpriteMaterial, SpriteMaterials};
+pub use nine_slice::NineSliceInsets;
+pub use operation::*;
+pub use outline::SpriteOutlineOperation;
+pub use pick::{PickHit, PickId, SpritePicker};
+pub use pool::{InstanceBufferPool, InstanceBufferPoolConfig, InstanceSlice};
+pub use queue::*;
+pub use shader::{
+    atlas_sampling_source, default_material_source, SpriteAlphaMode, SpritePipeline,
+    SpritePipelineBuilder, SPRITE_QUAD_LIBRARY,
+};
+pub use space::{Anchor, CoordinateSpace, ScreenSpace};
+pub use stats::SpriteStats;
+pub use style::*;
+#[cfg(feature = "text")]
+pub use text::{Font, TextQueue, TextSet};
 
-pub struct SpriteBatch {
-    atlas: AssetId<Atlas>,
-    pipeline: AssetId<RenderPipeline>,
-    buffer: AssetId<Buffer>,
-    start: u64,
-    size: u64,
-    count: u32,
+/// Inits [SpriteQueue], [BakedSprites] and [Camera2D] assets, along with the [SpritePipeline], [Buffer] and [BindGroup] assets used by [SpriteBatches](SpriteBatch).
+/// Submissions are batched into the [InstanceBufferPool] made during [Init], and cameras and the [ScreenSpace] are updated, during [PreDraw] in [SpriteBatchSet], where the [SpriteStats] of the last frame are published and pickable sprites are recorded by the [SpritePicker].
+/// Before that, [Sprite] entities are submitted to their queues in [SpriteExtractSet].
+/// The [CameraBindGroupLayout] and the [SpriteMaterials] are made during [Init], where the libraries of the default sprite shader are added to the [ShaderBundler](modula_render::shader::ShaderBundler) if it exists, see [SpritePipelineBuilder].
+/// Shadows and outlines of sprites are drawn by the [SpriteOperations](SpriteOperation) of the [Shadow](SpritePass::Shadow) pass and by [SpriteOutlineOperations](SpriteOutlineOperation) added to a sequence around the main one.
+/// Atlas groups are not loaded by this, so [init_atlas_loading](modula_texture::atlas::init_atlas_loading) should be used as well.
+/// With the "text" feature Font and TextQueue assets are inited too, and text is submitted in TextSet.
+/// With the "debug_draw" feature the DebugDraw resource is made during [Init], and its shapes are submitted in DebugDrawSet
+pub fn init_sprites(schedule_builder: &mut ScheduleBuilder) {
+    init_assets::<SpriteQueue>(schedule_builder);
+    init_assets::<BakedSprites>(schedule_builder);
+    init_assets::<Camera2D>(schedule_builder);
+    init_assets::<SpritePipeline>(schedule_builder);
+    init_assets::<Buffer>(schedule_builder);
+    init_assets::<BindGroup>(schedule_builder);
+    schedule_builder.add_systems(
+        Init,
+        (
+            |mut commands: Commands,
+             device: Res<DeviceRes>,
+             config: Option<Res<InstanceBufferPoolConfig>>| {
+                let config = config.map_or_else(Default::default, |config| *config);
+                commands.insert_resource(InstanceBufferPool::new(config));
+                commands.insert_resource(CameraBindGroupLayout::new(&device.0));
+                commands.insert_resource(extract::ExtractedSprites::default());
+                commands.insert_resource(SpriteStats::default());
+                commands.insert_resource(stats::PendingSpriteStats::default());
+                commands.insert_resource(SpritePicker::default());
+                commands.insert_resource(ScreenSpace::default());
+                commands.insert_resource(SpriteMaterials::default());
+                #[cfg(feature = "debug_draw")]
+                commands.insert_resource(DebugDraw::default());
+            },
+            shader::add_sprite_libraries,
+        ),
+    );
+    schedule_builder.add_systems(
+        PreDraw,
+        (
+            (
+                stats::publish_sprite_stats,
+                pick::begin_pick_frame,
+                pool::begin_instance_frame,
+            ),
+            batch::batch_sprites,
+            pool::write_instances,
+        )
+            .chain()
+            .in_set(SpriteBatchSet)
+            .after(AtlasLoadSet),
+    );
+    // before batching, as culling uses the view of the cameras and screen space sprites are resolved against the surface
+    schedule_builder.add_systems(
+        PreDraw,
+        (camera::update_cameras, space::update_screen_space)
+            .in_set(SpriteBatchSet)
+            .before(batch::batch_sprites),
+    );
+    schedule_builder.add_systems(
+        PreDraw,
+        extract::extract_sprites
+            .in_set(SpriteExtractSet)
+            .after(AnimationSet)
+            .before(SpriteBatchSet),
+    );
+    #[cfg(feature = "debug_draw")]
+    schedule_builder.add_systems(
+        PreDraw,
+        debug::submit_debug_draw
+            .in_set(DebugDrawSet)
+            .after(AtlasLoadSet)
+            .before(SpriteBatchSet),
+    );
+    #[cfg(feature = "text")]
+    {
+        init_assets::<Font>(schedule_builder);
+        init_assets::<TextQueue>(schedule_builder);
+        schedule_builder.add_systems(
+            PreDraw,
+            text::submit_text
+                .in_set(TextSet)
+                .after(AtlasLoadSet)
+                .before(SpriteBatchSet),
+        );
+    }
 }
- */
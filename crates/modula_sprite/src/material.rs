@@ -0,0 +1,70 @@
+use bevy_ecs::system::Resource;
+use modula_asset::AssetId;
+use wgpu::BindGroup;
+
+use crate::SpritePipeline;
+
+/// Identifies a [SpriteMaterial] in the [SpriteMaterials]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MaterialId(u32);
+
+impl MaterialId {
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// A pipeline with the bind groups it is drawn with, where the pipeline is usually built with a [material source](crate::SpritePipelineBuilder::with_material).
+/// The bind groups are bound after those of the [SpriteQueue](crate::SpriteQueue), so the pipeline must have the layouts of both in that order
+pub struct SpriteMaterial {
+    pub pipeline: AssetId<SpritePipeline>,
+    pub bind_groups: Vec<AssetId<BindGroup>>,
+}
+
+impl SpriteMaterial {
+    pub fn new(pipeline: AssetId<SpritePipeline>) -> Self {
+        Self {
+            pipeline,
+            bind_groups: Vec::new(),
+        }
+    }
+
+    pub fn with_bind_groups(mut self, bind_groups: Vec<AssetId<BindGroup>>) -> Self {
+        self.bind_groups = bind_groups;
+        self
+    }
+}
+
+/// Resource registering the [SpriteMaterials](SpriteMaterial) sprites can be [drawn with](crate::SpriteStyle::with_material), made during [Init](modula_core::Init).
+/// A sprite with a material is drawn with the pipeline of the material instead of the pipeline of its queue, and only batched with sprites of the same material.
+/// Sprites with a material that is not registered are skipped when batching
+#[derive(Resource, Default)]
+pub struct SpriteMaterials {
+    materials: Vec<SpriteMaterial>,
+}
+
+impl SpriteMaterials {
+    pub fn add(&mut self, material: SpriteMaterial) -> MaterialId {
+        self.materials.push(material);
+        MaterialId(self.materials.len() as u32 - 1)
+    }
+
+    #[inline]
+    pub fn get(&self, id: MaterialId) -> Option<&SpriteMaterial> {
+        self.materials.get(id.index())
+    }
+
+    /// The material can be changed, for example to swap its bind groups
+    #[inline]
+    pub fn get_mut(&mut self, id: MaterialId) -> Option<&mut SpriteMaterial> {
+        self.materials.get_mut(id.index())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (MaterialId, &SpriteMaterial)> {
+        self.materials
+            .iter()
+            .enumerate()
+            .map(|(i, material)| (MaterialId(i as u32), material))
+    }
+}
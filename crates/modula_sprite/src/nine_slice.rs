@@ -0,0 +1,236 @@
+use modula_texture::atlas::AtlasGroupEntry;
+
+use crate::{SpriteInstance, SpriteQueue, SpriteRect, SpriteStyle, SubmissionKind};
+
+/// The size of the fixed borders of a nine-slice in pixels of the source image, where top is the edge at the top of the image.
+/// Can be stored as metadata of an atlas entry, see [AtlasGroupBuilder::add_image_with_meta](modula_texture::atlas::AtlasGroupBuilder::add_image_with_meta), for [draw_panel](SpriteQueue::draw_panel)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct NineSliceInsets {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+}
+
+impl NineSliceInsets {
+    pub fn new(left: u32, right: u32, top: u32, bottom: u32) -> Self {
+        Self {
+            left,
+            right,
+            top,
+            bottom,
+        }
+    }
+
+    /// The same inset on every side
+    pub fn uniform(inset: u32) -> Self {
+        Self::new(inset, inset, inset, inset)
+    }
+}
+
+impl SpriteQueue {
+    /// Submits an entry of the current [atlas](Self::set_atlas) as nine quads covering rect, ordered like [draw](Self::draw).
+    /// The corners keep their size in pixels as world units, the edges are stretched along one axis and the center along both.
+    /// If rect is smaller than the insets the corners shrink to fit, and slices without area are skipped
+    /// ## Panics
+    /// If the atlas or pipeline is not set
+    pub fn draw_nine_slice(
+        &mut self,
+        entry: AtlasGroupEntry,
+        rect: SpriteRect,
+        insets: NineSliceInsets,
+        style: SpriteStyle,
+        z: f32,
+    ) {
+        self.submit_nine_slice(entry, rect, Some(insets), style, z);
+    }
+
+    /// Same as [draw_nine_slice](Self::draw_nine_slice), with the [NineSliceInsets] stored as metadata of the entry.
+    /// Entries without them are stretched over rect
+    /// ## Panics
+    /// If the atlas or pipeline is not set
+    pub fn draw_panel(
+        &mut self,
+        entry: AtlasGroupEntry,
+        rect: SpriteRect,
+        style: SpriteStyle,
+        z: f32,
+    ) {
+        self.submit_nine_slice(entry, rect, None, style, z);
+    }
+
+    fn submit_nine_slice(
+        &mut self,
+        entry: AtlasGroupEntry,
+        rect: SpriteRect,
+        insets: Option<NineSliceInsets>,
+        style: SpriteStyle,
+        z: f32,
+    ) {
+        let tint = style.tint;
+        self.submit(
+            style,
+            z,
+            SubmissionKind::NineSlice {
+                entry,
+                rect,
+                insets,
+                border_scale: 1.0,
+                tint: [tint.r, tint.g, tint.b, tint.a].map(|c| c as f32),
+            },
+        );
+    }
+}
+
+/// Pushes the slices with area, image_size is the size of the entry in pixels as it is not rotated.
+/// The borders are border_scale world units per pixel of the insets
+pub(crate) fn push_nine_slice(
+    entry: AtlasGroupEntry,
+    image_size: (u32, u32),
+    rect: SpriteRect,
+    (insets, border_scale): (NineSliceInsets, f32),
+    tint: [f32; 4],
+    out: &mut Vec<SpriteInstance>,
+) {
+    let (width, height) = (rect.width().max(0.0), rect.height().max(0.0));
+    // the borders shrink by the same factor when they do not fit
+    let fit = |a: u32, b: u32, size: f32| {
+        let (a, b) = (a as f32 * border_scale, b as f32 * border_scale);
+        let scale = if a + b > size { size / (a + b) } else { 1.0 };
+        (a * scale, b * scale)
+    };
+    let (left, right) = fit(insets.left, insets.right, width);
+    let (top, bottom) = fit(insets.top, insets.bottom, height);
+    // in world space where y points up, and in image space where y points down
+    let (x, y) = (rect.min[0], rect.max[1]);
+    let xs = [x, x + left, x + width - right, x + width];
+    let ys = [y, y - top, y - height + bottom, y - height];
+    let (image_width, image_height) = (image_size.0.max(1) as f32, image_size.1.max(1) as f32);
+    let us = [
+        0.0,
+        insets.left as f32 / image_width,
+        1.0 - insets.right as f32 / image_width,
+        1.0,
+    ];
+    let vs = [
+        0.0,
+        insets.top as f32 / image_height,
+        1.0 - insets.bottom as f32 / image_height,
+        1.0,
+    ];
+    for row in 0..3 {
+        for column in 0..3 {
+            let size = [xs[column + 1] - xs[column], ys[row] - ys[row + 1]];
+            if size[0] <= 0.0 || size[1] <= 0.0 {
+                continue;
+            }
+            let (u, v) = ([us[column], us[column + 1]], [vs[row], vs[row + 1]]);
+            let position = [xs[column] + size[0] / 2.0, ys[row + 1] + size[1] / 2.0];
+            let mut instance = SpriteInstance::from_entry(entry, position, size);
+            instance.region_min = [u[0], v[0]];
+            instance.region_max = [u[1], v[1]];
+            instance.tint = tint;
+            out.push(instance);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INSETS: NineSliceInsets = NineSliceInsets {
+        left: 4,
+        right: 6,
+        top: 8,
+        bottom: 2,
+    };
+
+    /// The slices of a 30 by 30 image over rect
+    fn slices(rect: SpriteRect, insets: NineSliceInsets, border_scale: f32) -> Vec<SpriteInstance> {
+        let mut out = Vec::new();
+        push_nine_slice(
+            AtlasGroupEntry::from_index(0),
+            (30, 30),
+            rect,
+            (insets, border_scale),
+            [1.0; 4],
+            &mut out,
+        );
+        out
+    }
+
+    /// The world rect covered by a slice, as (min, max)
+    fn covered(slice: &SpriteInstance) -> ([f32; 2], [f32; 2]) {
+        let [x, y] = slice.position;
+        let [width, height] = slice.size;
+        (
+            [x - width / 2.0, y - height / 2.0],
+            [x + width / 2.0, y + height / 2.0],
+        )
+    }
+
+    #[test]
+    fn corners_edges_and_center() {
+        let slices = slices(SpriteRect::new([0.0, 0.0], [100.0, 50.0]), INSETS, 1.0);
+        assert_eq!(slices.len(), 9);
+        // from the top left, row by row
+        let xs = [0.0, 4.0, 94.0, 100.0];
+        let ys = [50.0, 42.0, 2.0, 0.0];
+        let us = [0.0, 4.0 / 30.0, 24.0 / 30.0, 1.0];
+        let vs = [0.0, 8.0 / 30.0, 28.0 / 30.0, 1.0];
+        for (i, slice) in slices.iter().enumerate() {
+            let (row, column) = (i / 3, i % 3);
+            let (min, max) = covered(slice);
+            assert_eq!(min, [xs[column], ys[row + 1]], "slice {i}");
+            assert_eq!(max, [xs[column + 1], ys[row]], "slice {i}");
+            assert_eq!(slice.region_min, [us[column], vs[row]], "slice {i}");
+            assert_eq!(slice.region_max, [us[column + 1], vs[row + 1]], "slice {i}");
+        }
+    }
+
+    #[test]
+    fn border_scale_scales_the_borders() {
+        let slices = slices(SpriteRect::new([0.0, 0.0], [100.0, 50.0]), INSETS, 2.0);
+        assert_eq!(slices[0].size, [8.0, 16.0]);
+        assert_eq!(slices[4].size, [80.0, 30.0]);
+        assert_eq!(slices[8].size, [12.0, 4.0]);
+        // the image is sliced the same
+        assert_eq!(slices[0].region_max, [4.0 / 30.0, 8.0 / 30.0]);
+    }
+
+    #[test]
+    fn small_targets_shrink_the_corners() {
+        let rect = SpriteRect::new([10.0, 10.0], [15.0, 15.0]);
+        let slices = slices(rect, INSETS, 1.0);
+        // the borders are halved to fit, leaving no edges or center
+        assert_eq!(slices.len(), 4);
+        let sizes: Vec<_> = slices.iter().map(|slice| slice.size).collect();
+        assert_eq!(sizes, [[2.0, 4.0], [3.0, 4.0], [2.0, 1.0], [3.0, 1.0]]);
+        assert_eq!(covered(&slices[0]), ([10.0, 11.0], [12.0, 15.0]));
+        assert_eq!(covered(&slices[3]), ([12.0, 10.0], [15.0, 11.0]));
+        // the corners still show the whole corner of the image
+        assert_eq!(slices[3].region_min, [24.0 / 30.0, 28.0 / 30.0]);
+        assert_eq!(slices[3].region_max, [1.0, 1.0]);
+    }
+
+    #[test]
+    fn slices_without_area_are_skipped() {
+        // narrower than the borders but taller, so only the left and right columns remain
+        let narrow = slices(SpriteRect::new([0.0, 0.0], [5.0, 50.0]), INSETS, 1.0);
+        assert_eq!(narrow.len(), 6);
+        let plain = slices(
+            SpriteRect::new([0.0, 0.0], [100.0, 50.0]),
+            NineSliceInsets::default(),
+            1.0,
+        );
+        assert_eq!(plain.len(), 1);
+        assert_eq!(covered(&plain[0]), ([0.0, 0.0], [100.0, 50.0]));
+        assert_eq!(
+            (plain[0].region_min, plain[0].region_max),
+            ([0.0; 2], [1.0; 2])
+        );
+        let inverted = SpriteRect::new([10.0, 10.0], [0.0, 0.0]);
+        assert!(slices(inverted, INSETS, 1.0).is_empty());
+    }
+}
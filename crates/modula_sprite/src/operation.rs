@@ -0,0 +1,443 @@
+use std::ops::RangeInclusive;
+
+use bevy_ecs::world::{Mut, World};
+use modula_asset::{AssetId, AssetWorldExt, Assets};
+use modula_core::DeviceRes;
+use modula_render::{Operation, OperationBuilder, OperationStats, PassState, RenderTarget};
+use modula_texture::atlas::{AtlasGroup, QUAD_VERTEX_COUNT};
+use wgpu::{Buffer, CommandEncoder, Device, RenderPass};
+
+use crate::{
+    clip::scissor_rect, stats::PendingSpriteStats, BakedSprites, Camera2D, ScreenSpace,
+    SpriteBatch, SpriteBlend, SpriteMaterials, SpritePipeline, SpriteQueue,
+};
+
+/// Draws the batches of a [SpriteQueue] to a render target, in a single pass unless a [view](SpriteView) clears.
+/// Batches of atlas groups that are not built yet are skipped, and the depth of the target is cleared first if the queue or any of the [baked sprites](Self::with_baked) are [depth sorted](SpriteQueue::is_depth_sorted).
+/// With a camera its bind group is bound to group 1 and the bind groups of the queue follow it, otherwise they start at group 1.
+/// [Screen space](crate::CoordinateSpace::Screen) batches are drawn with the bind group of the [ScreenSpace] in place of the camera, or as other batches without a camera.
+/// The bind groups of the [material](crate::SpriteMaterial) of a batch follow those of the queue.
+/// The scissor rect is set from the [clip](crate::SpriteQueue::push_clip) of every batch, mapped to the target through the camera.
+/// Without views every layer is drawn with the camera of the operation.
+/// An operation of a secondary [pass](SpritePass) begins no pass when the queue has no batches for it
+pub struct SpriteOperation {
+    pub render_target: AssetId<RenderTarget>,
+    pub queue: AssetId<SpriteQueue>,
+    pub camera: Option<AssetId<Camera2D>>,
+    /// Drawn in order, layers outside every view are not drawn
+    pub views: Vec<SpriteView>,
+    /// Drawn along with the batches of the queue in the [Main](SpritePass::Main) pass, see [with_baked](Self::with_baked)
+    pub baked: Vec<AssetId<BakedSprites>>,
+    pub pass: SpritePass,
+    stats: OperationStats,
+}
+
+/// Which batches of its queue a [SpriteOperation] draws.
+/// A sequence drawing shadows adds an operation of the [Shadow](Self::Shadow) pass before the one of the main pass with the same target and camera
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SpritePass {
+    /// The sprites as they were submitted
+    #[default]
+    Main,
+    /// The [shadows](crate::SpriteShadow) of the sprites that [cast them](crate::SpriteStyle::with_shadow)
+    Shadow,
+    /// The sprites that are [outlined](crate::SpriteStyle::with_outline), drawn into the mask of a [SpriteOutlineOperation](crate::SpriteOutlineOperation)
+    Outline,
+}
+
+impl SpriteOperation {
+    pub fn new(render_target: AssetId<RenderTarget>, queue: AssetId<SpriteQueue>) -> Self {
+        Self {
+            render_target,
+            queue,
+            camera: None,
+            views: Vec::new(),
+            baked: Vec::new(),
+            pass: SpritePass::Main,
+            stats: OperationStats::default(),
+        }
+    }
+
+    /// Draws the batches of pass instead of the main batches
+    pub fn with_pass(mut self, pass: SpritePass) -> Self {
+        self.pass = pass;
+        self
+    }
+
+    pub fn with_camera(mut self, camera: AssetId<Camera2D>) -> Self {
+        self.camera = Some(camera);
+        self
+    }
+
+    /// Adds a view drawn after the views added before it
+    pub fn with_view(mut self, view: SpriteView) -> Self {
+        self.views.push(view);
+        self
+    }
+
+    /// Adds baked sprites drawn with the bind groups of the queue, before the batches of the queue at the same layer.
+    /// Baked sprites added before others are drawn first at the same layer, and missing baked sprites are skipped
+    pub fn with_baked(mut self, baked: AssetId<BakedSprites>) -> Self {
+        self.baked.push(baked);
+        self
+    }
+}
+
+/// A range of [layers](SpriteQueue::set_layer) of the queue of a [SpriteOperation] drawn with its own camera,
+/// for example to draw the world with a zoomed camera and the UI with a pixel perfect camera from the same queue.
+/// Batches never contain sprites of more than one layer, so every batch is drawn by the views including its layer
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpriteView {
+    pub layers: RangeInclusive<i32>,
+    /// The camera of the operation is used if None
+    pub camera: Option<AssetId<Camera2D>>,
+    /// Pixels of the target as x, y, width and height, where the origin is the top left.
+    /// Intersected with the clips of the batches
+    pub scissor: Option<[u32; 4]>,
+    /// Clears the color of the target with its [clear color](RenderTarget::clear_color) before drawing the view, in a new pass
+    pub clear_color: bool,
+    /// Clears the depth of the target before drawing the view, in a new pass
+    pub clear_depth: bool,
+}
+
+impl SpriteView {
+    pub fn new(layers: RangeInclusive<i32>) -> Self {
+        Self {
+            layers,
+            camera: None,
+            scissor: None,
+            clear_color: false,
+            clear_depth: false,
+        }
+    }
+
+    /// A view of a single layer
+    pub fn layer(layer: i32) -> Self {
+        Self::new(layer..=layer)
+    }
+
+    pub fn with_camera(mut self, camera: AssetId<Camera2D>) -> Self {
+        self.camera = Some(camera);
+        self
+    }
+
+    pub fn with_scissor(mut self, scissor: [u32; 4]) -> Self {
+        self.scissor = Some(scissor);
+        self
+    }
+
+    pub fn with_clear_color(mut self) -> Self {
+        self.clear_color = true;
+        self
+    }
+
+    pub fn with_clear_depth(mut self) -> Self {
+        self.clear_depth = true;
+        self
+    }
+
+    #[inline]
+    fn clears(&self) -> bool {
+        self.clear_color || self.clear_depth
+    }
+}
+
+/// What is bound in a pass, and what was done in it
+#[derive(Default)]
+pub(crate) struct PassBindings {
+    pipeline: Option<(AssetId<SpritePipeline>, SpriteBlend)>,
+    atlas: Option<(AssetId<AtlasGroup>, usize)>,
+    scissor: [u32; 4],
+    pub draw_calls: u32,
+    pub instances: u32,
+    pub pipeline_switches: usize,
+    pub bind_group_switches: usize,
+}
+
+impl PassBindings {
+    /// Adds the switches to the [SpriteStats](crate::SpriteStats) being counted, returning the stats of the operation
+    pub(crate) fn record(self, world: &mut World) -> OperationStats {
+        let mut pending = world.resource_mut::<PendingSpriteStats>();
+        pending.0.pipeline_switches += self.pipeline_switches;
+        pending.0.bind_group_switches += self.bind_group_switches;
+        OperationStats {
+            draw_calls: self.draw_calls,
+            instances: self.instances,
+        }
+    }
+}
+
+impl SpriteOperation {
+    /// Whether the queue has batches for the pass, the baked sprites count for the main pass
+    pub(crate) fn has_batches(&self, world: &World) -> bool {
+        let queue = world
+            .get_asset(self.queue)
+            .expect("no queue for sprite operation");
+        !queue.pass_batches(self.pass).is_empty()
+            || (self.pass == SpritePass::Main && !self.baked.is_empty())
+    }
+
+    /// Draws the views to target, where the first pass is begun with state
+    pub(crate) fn draw(
+        &self,
+        world: &World,
+        target: &RenderTarget,
+        command_encoder: &mut CommandEncoder,
+        state: PassState,
+        bindings: &mut PassBindings,
+    ) {
+        let batches = self.batches(world);
+        let all = [SpriteView::new(i32::MIN..=i32::MAX)];
+        let views = if self.views.is_empty() {
+            &all[..]
+        } else {
+            &self.views
+        };
+        // a pass is begun for the first view, and every view that clears
+        let mut start = 0;
+        while start < views.len() {
+            let end = views[start + 1..]
+                .iter()
+                .position(SpriteView::clears)
+                .map_or(views.len(), |i| start + 1 + i);
+            let first = start == 0;
+            let mut pass = target.begin_pass_with_state(
+                command_encoder,
+                PassState {
+                    clear_color: views[start].clear_color || (first && state.clear_color),
+                    clear_depth_stencil: views[start].clear_depth
+                        || (first && state.clear_depth_stencil),
+                    // the target is resolved once everything is drawn
+                    resolve: state.resolve && end == views.len(),
+                },
+            );
+            let (width, height) = target.size();
+            bindings.pipeline = None;
+            bindings.atlas = None;
+            bindings.scissor = [0, 0, width, height];
+            for view in &views[start..end] {
+                self.draw_view(world, target, &mut pass, view, &batches, bindings);
+            }
+            start = end;
+        }
+    }
+
+    /// The batches of the pass and of the baked sprites with their instance buffers, merged by layer with the baked batches first
+    fn batches<'a>(&self, world: &'a World) -> Vec<(&'a SpriteBatch, Option<&'a Buffer>)> {
+        let queue = world.get_asset(self.queue).unwrap();
+        let batches = queue.pass_batches(self.pass);
+        let mut baked: Vec<_> = self
+            .baked
+            .iter()
+            .filter(|_| self.pass == SpritePass::Main)
+            .filter_map(|id| world.get_asset(*id))
+            .flat_map(|baked| baked.batches().iter().map(|batch| (batch, baked.buffer())))
+            .collect();
+        // stable, so every bake keeps its order
+        baked.sort_by_key(|(batch, _)| batch.layer);
+        let mut merged = Vec::with_capacity(baked.len() + batches.len());
+        let mut baked = baked.into_iter().peekable();
+        for batch in batches {
+            while let Some(next) = baked.next_if(|(next, _)| next.layer <= batch.layer) {
+                merged.push(next);
+            }
+            merged.push((batch, world.get_asset(batch.buffer)));
+        }
+        merged.extend(baked);
+        merged
+    }
+
+    /// Draws the batches of the layers of view
+    fn draw_view(
+        &self,
+        world: &World,
+        target: &RenderTarget,
+        pass: &mut RenderPass,
+        view: &SpriteView,
+        batches: &[(&SpriteBatch, Option<&Buffer>)],
+        bindings: &mut PassBindings,
+    ) {
+        let queue = world.get_asset(self.queue).unwrap();
+        let mut first_group = 1;
+        let camera = view.camera.or(self.camera).map(|camera| {
+            world
+                .get_asset(camera)
+                .expect("no camera for sprite operation")
+        });
+        let mut camera_bind_group = None;
+        if let Some(camera) = camera {
+            // the camera is updated during the first PreDraw
+            let Some(bind_group) = camera.bind_group() else {
+                return;
+            };
+            pass.set_bind_group(1, bind_group, &[]);
+            bindings.bind_group_switches += 1;
+            camera_bind_group = Some(bind_group);
+            first_group = 2;
+        }
+        let screen = world.resource::<ScreenSpace>();
+        let mut screen_bound = false;
+        for (i, group) in queue.bind_groups().iter().enumerate() {
+            pass.set_bind_group(
+                i as u32 + first_group,
+                world.get_asset(*group).expect("bind group was missing"),
+                &[],
+            );
+            bindings.bind_group_switches += 1;
+        }
+        let first_material_group = first_group + queue.bind_groups().len() as u32;
+        let materials = world.resource::<SpriteMaterials>();
+        // the groups of the material follow the queue groups, which may start elsewhere in every view
+        let mut bound_material = None;
+        let (width, height) = target.size();
+        let view_scissor = view.scissor.unwrap_or([0, 0, width, height]);
+        for &(batch, buffer) in batches {
+            if batch.count == 0 || !view.layers.contains(&batch.layer) {
+                continue;
+            }
+            if let Some(camera_bind_group) =
+                camera_bind_group.filter(|_| batch.screen_space != screen_bound)
+            {
+                let bind_group = if batch.screen_space {
+                    screen.bind_group()
+                } else {
+                    Some(camera_bind_group)
+                };
+                // the screen space is updated during the first PreDraw
+                let Some(bind_group) = bind_group else {
+                    continue;
+                };
+                pass.set_bind_group(1, bind_group, &[]);
+                bindings.bind_group_switches += 1;
+                screen_bound = batch.screen_space;
+            }
+            let scissor = match batch.clip {
+                Some(clip) if batch.screen_space => scissor_rect(
+                    screen.clip_to_target(clip, (width, height)),
+                    None,
+                    (width, height),
+                ),
+                Some(clip) => scissor_rect(clip, camera, (width, height)),
+                None => Some([0, 0, width, height]),
+            };
+            let Some(scissor) = scissor.and_then(|scissor| intersect(scissor, view_scissor)) else {
+                continue;
+            };
+            if scissor != bindings.scissor {
+                let [x, y, width, height] = scissor;
+                pass.set_scissor_rect(x, y, width, height);
+                bindings.scissor = scissor;
+            }
+            if let Some(id) = batch.material.filter(|_| batch.material != bound_material) {
+                let material = materials.get(id).expect("no material for sprite batch");
+                for (i, group) in material.bind_groups.iter().enumerate() {
+                    pass.set_bind_group(
+                        i as u32 + first_material_group,
+                        world.get_asset(*group).expect("bind group was missing"),
+                        &[],
+                    );
+                    bindings.bind_group_switches += 1;
+                }
+                bound_material = batch.material;
+            }
+            // atlas groups loaded with AtlasGroupQueue are empty until they are built
+            let Some(atlas) = world.get_asset(batch.atlas) else {
+                continue;
+            };
+            let pipeline = world
+                .get_asset(batch.pipeline)
+                .expect("no pipeline for sprite batch")
+                .variant(batch.blend)
+                .expect("the pipeline variant of a sprite batch was not made");
+            let buffer = buffer.expect("buffer was not available");
+            // the same pipeline and atlas bind group are not set again for following batches
+            if bindings.pipeline != Some((batch.pipeline, batch.blend)) {
+                pass.set_pipeline(pipeline.get(target));
+                bindings.pipeline = Some((batch.pipeline, batch.blend));
+                bindings.pipeline_switches += 1;
+            }
+            if bindings.atlas != Some((batch.atlas, batch.bind_group_index)) {
+                pass.set_bind_group(0, &atlas.bind_groups()[batch.bind_group_index], &[]);
+                bindings.atlas = Some((batch.atlas, batch.bind_group_index));
+                bindings.bind_group_switches += 1;
+            }
+            pass.set_vertex_buffer(0, buffer.slice(batch.byte_range()));
+            pass.draw(0..QUAD_VERTEX_COUNT, 0..batch.count);
+            bindings.draw_calls += 1;
+            bindings.instances += batch.count;
+        }
+    }
+}
+
+/// The overlap of two scissor rects, None if they do not overlap
+fn intersect(a: [u32; 4], b: [u32; 4]) -> Option<[u32; 4]> {
+    let x = a[0].max(b[0]);
+    let y = a[1].max(b[1]);
+    let right = (a[0] + a[2]).min(b[0] + b[2]);
+    let bottom = (a[1] + a[3]).min(b[1] + b[3]);
+    (right > x && bottom > y).then_some([x, y, right - x, bottom - y])
+}
+
+impl Operation for SpriteOperation {
+    fn run(&mut self, world: &mut World, command_encoder: &mut CommandEncoder) {
+        // the scheduled clears are left to the operations after it
+        if self.pass != SpritePass::Main && !self.has_batches(world) {
+            self.stats = OperationStats::default();
+            return;
+        }
+        // baked sprites are written to their buffer when first drawn
+        world.resource_scope(|world, mut baked: Mut<Assets<BakedSprites>>| {
+            let device = &world.resource::<DeviceRes>().0;
+            for id in &self.baked {
+                if let Some(baked) = baked.get_mut(*id) {
+                    baked.upload(device);
+                }
+            }
+        });
+        let depth_sorted = world
+            .get_asset(self.queue)
+            .expect("no queue for sprite operation")
+            .is_depth_sorted()
+            || self
+                .baked
+                .iter()
+                .filter_map(|id| world.get_asset(*id))
+                .any(BakedSprites::is_depth_sorted);
+        // the scheduled clears are taken first, so the target can be borrowed with the other assets
+        let Some(state) = world
+            .resource_mut::<Assets<RenderTarget>>()
+            .get_mut(self.render_target)
+            .map(|target| {
+                if depth_sorted {
+                    target.schedule_clear_depth_stencil();
+                }
+                target.take_pass_state()
+            })
+        else {
+            self.stats = OperationStats::default();
+            return;
+        };
+        let mut bindings = PassBindings::default();
+        let target = world.get_asset(self.render_target).unwrap();
+        self.draw(world, target, command_encoder, state, &mut bindings);
+        self.stats = bindings.record(world);
+    }
+
+    fn stats(&self) -> OperationStats {
+        self.stats
+    }
+}
+
+impl OperationBuilder for SpriteOperation {
+    fn reading(&self) -> Vec<AssetId<RenderTarget>> {
+        Vec::new()
+    }
+
+    fn writing(&self) -> Vec<AssetId<RenderTarget>> {
+        vec![self.render_target]
+    }
+
+    fn finish(self, _device: &Device) -> impl Operation + 'static {
+        self
+    }
+}
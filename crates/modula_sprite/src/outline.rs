@@ -0,0 +1,247 @@
+use bevy_ecs::world::World;
+use modula_asset::{AssetId, AssetWorldExt, Assets};
+use modula_core::{DeviceRes, QueueRes};
+use modula_render::{
+    shader::ShaderBundler, Operation, OperationBuilder, OperationStats, PassState,
+    RenderPipelineBuilder, RenderTarget, TargetFormats, TargetPipeline,
+};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindingResource, BindingType, BlendState, Buffer, BufferUsages, Color, CommandEncoder,
+    CompareFunction, Device, PipelineLayout, PipelineLayoutDescriptor, ShaderModule,
+    ShaderModuleDescriptor, ShaderSource, TextureSampleType, TextureUsages, TextureViewDimension,
+};
+
+use crate::{operation::PassBindings, SpriteOperation, SpritePass};
+
+/// Size of the uniform of the composite shader, the color followed by the width and padding
+const OUTLINE_UNIFORM_SIZE: usize = 32;
+
+/// Draws an outline around the sprites of a queue that are [outlined](crate::SpriteStyle::with_outline), in two passes.
+/// The sprites are first drawn into a mask owned by the operation, a render target with the size and formats of the target that is made when first needed,
+/// and the mask dilated by width pixels is then blended over the target in color, leaving out the pixels covered by the sprites.
+/// Added after the [SpriteOperation] of the main pass, so the outline is drawn around the sprites and over everything drawn before it.
+/// When the queue has no outlined sprites neither pass is drawn, only the clears and resolve scheduled for the target are done
+pub struct SpriteOutlineOperation {
+    /// Draws the outlined sprites into the mask, with its target, camera and views used as they are.
+    /// The [pass](SpriteOperation::pass) is set to [Outline](SpritePass::Outline)
+    pub sprites: SpriteOperation,
+    /// With straight alpha, white by default
+    pub color: Color,
+    /// Pixels of the target the outline reaches past the sprites, 1 by default
+    pub width: u32,
+}
+
+impl SpriteOutlineOperation {
+    pub fn new(sprites: SpriteOperation) -> Self {
+        Self {
+            sprites: sprites.with_pass(SpritePass::Outline),
+            color: Color::WHITE,
+            width: 1,
+        }
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_width(mut self, width: u32) -> Self {
+        self.width = width;
+        self
+    }
+}
+
+impl OperationBuilder for SpriteOutlineOperation {
+    fn reading(&self) -> Vec<AssetId<RenderTarget>> {
+        Vec::new()
+    }
+
+    fn writing(&self) -> Vec<AssetId<RenderTarget>> {
+        vec![self.sprites.render_target]
+    }
+
+    fn finish(self, device: &Device) -> impl Operation + 'static {
+        let source = include_str!("shader/outline.wgsl");
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Sprite outline shader"),
+            source: ShaderSource::Wgsl(source.into()),
+        });
+        let mut interface = ShaderBundler::reflect(source).expect("the outline shader is valid");
+        // the mask is only loaded, so its format does not have to be filterable
+        let mask = interface
+            .binding_mut(0, 0)
+            .expect("the outline shader has a mask");
+        mask.ty = BindingType::Texture {
+            sample_type: TextureSampleType::Float { filterable: false },
+            view_dimension: TextureViewDimension::D2,
+            multisampled: false,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Sprite outline bind group layout"),
+            entries: &interface.group_entries(0),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Sprite outline pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let uniform = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Sprite outline uniform"),
+            contents: &[0; OUTLINE_UNIFORM_SIZE],
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        OutlineOperation {
+            settings: self,
+            module,
+            bind_group_layout,
+            pipeline_layout,
+            uniform,
+            pipeline: None,
+            mask: None,
+            bind_group: None,
+            stats: OperationStats::default(),
+        }
+    }
+}
+
+/// A finished [SpriteOutlineOperation]
+struct OutlineOperation {
+    settings: SpriteOutlineOperation,
+    module: ShaderModule,
+    bind_group_layout: BindGroupLayout,
+    pipeline_layout: PipelineLayout,
+    uniform: Buffer,
+    /// Made for the formats of the target when first drawn
+    pipeline: Option<TargetPipeline>,
+    mask: Option<RenderTarget>,
+    /// Made again when the textures of the mask change
+    bind_group: Option<BindGroup>,
+    stats: OperationStats,
+}
+
+impl OutlineOperation {
+    /// Makes the mask, pipeline and bind group for target if they do not match it
+    fn prepare(&mut self, device: &Device, target: &RenderTarget) {
+        let formats = TargetFormats::of(target);
+        match &mut self.mask {
+            Some(mask) if TargetFormats::of(mask) == formats => {
+                if mask.size() != target.size() {
+                    mask.resize(target.size());
+                }
+            }
+            _ => {
+                let mut config = target.current_config().clone();
+                config.transient = false;
+                if let Some(color) = &mut config.color_config {
+                    color.usages |= TextureUsages::TEXTURE_BINDING;
+                    color.clear_color = Color::TRANSPARENT;
+                }
+                self.mask = Some(RenderTarget::new(config));
+            }
+        }
+        let mask = self.mask.as_mut().unwrap();
+        if mask.scheduled_config().is_some() {
+            mask.apply(device);
+            self.bind_group = None;
+        }
+        if !self
+            .pipeline
+            .as_ref()
+            .is_some_and(|pipeline| pipeline.is_compatible(target))
+        {
+            self.pipeline = Some(
+                RenderPipelineBuilder::new(&self.module)
+                    .with_label("Sprite outline pipeline")
+                    .with_pipeline_layout(&self.pipeline_layout)
+                    .with_blend(BlendState::PREMULTIPLIED_ALPHA_BLENDING)
+                    .with_depth(false, CompareFunction::Always)
+                    .build_for_formats(device, formats),
+            );
+        }
+        if self.bind_group.is_none() {
+            let view = mask.texture_view().expect("the mask has a color texture");
+            self.bind_group = Some(device.create_bind_group(&BindGroupDescriptor {
+                label: Some("Sprite outline bind group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(view),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: self.uniform.as_entire_binding(),
+                    },
+                ],
+            }));
+        }
+    }
+
+    /// The uniform as laid out in the buffer
+    fn uniform_bytes(&self) -> [u8; OUTLINE_UNIFORM_SIZE] {
+        let Color { r, g, b, a } = self.settings.color;
+        let color = [r * a, g * a, b * a, a].map(|c| c as f32);
+        let mut bytes = [0; OUTLINE_UNIFORM_SIZE];
+        for (i, c) in color.iter().enumerate() {
+            bytes[i * 4..i * 4 + 4].copy_from_slice(&c.to_ne_bytes());
+        }
+        bytes[16..20].copy_from_slice(&(self.settings.width as i32).to_ne_bytes());
+        bytes
+    }
+}
+
+impl Operation for OutlineOperation {
+    fn run(&mut self, world: &mut World, command_encoder: &mut CommandEncoder) {
+        self.stats = OperationStats::default();
+        let sprites = &self.settings.sprites;
+        let Some(state) = world
+            .resource_mut::<Assets<RenderTarget>>()
+            .get_mut(sprites.render_target)
+            .map(RenderTarget::take_pass_state)
+        else {
+            return;
+        };
+        let target = world.get_asset(sprites.render_target).unwrap();
+        if !sprites.has_batches(world) || target.clear_color().is_none() {
+            // the clears and resolve scheduled for the target are still done
+            if state != PassState::default() {
+                target.begin_pass_with_state(command_encoder, state);
+            }
+            return;
+        }
+        self.prepare(&world.resource::<DeviceRes>().0, target);
+        world
+            .resource::<QueueRes>()
+            .0
+            .write_buffer(&self.uniform, 0, &self.uniform_bytes());
+        let mut bindings = PassBindings::default();
+        let mask = self.mask.as_ref().unwrap();
+        self.settings.sprites.draw(
+            world,
+            mask,
+            command_encoder,
+            PassState {
+                clear_color: true,
+                clear_depth_stencil: true,
+                resolve: false,
+            },
+            &mut bindings,
+        );
+        let pipeline = self.pipeline.as_ref().unwrap();
+        let mut pass = target.begin_pass_with_state(command_encoder, state);
+        pass.set_pipeline(pipeline.get(target));
+        pass.set_bind_group(0, self.bind_group.as_ref().unwrap(), &[]);
+        pass.draw(0..3, 0..1);
+        drop(pass);
+        bindings.draw_calls += 1;
+        bindings.pipeline_switches += 1;
+        bindings.bind_group_switches += 1;
+        self.stats = bindings.record(world);
+    }
+
+    fn stats(&self) -> OperationStats {
+        self.stats
+    }
+}
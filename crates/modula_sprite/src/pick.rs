@@ -0,0 +1,334 @@
+use bevy_ecs::{
+    entity::Entity,
+    system::{ResMut, Resource},
+};
+use modula_asset::{AssetId, Assets};
+use modula_texture::{
+    atlas::{AtlasGroup, AtlasGroupEntry},
+    Image,
+};
+
+use crate::{cull::SpriteQuad, Camera2D, SpriteQueue, SpriteRect};
+
+/// What a pickable sprite is found as by the [SpritePicker]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PickId {
+    /// A [pickable](crate::Sprite::with_pickable) [Sprite](crate::Sprite) entity
+    Entity(Entity),
+    /// Set with [SpriteStyle::with_pick_id](crate::SpriteStyle::with_pick_id)
+    User(u64),
+}
+
+/// A pickable sprite as it was batched
+pub(crate) struct PickRecord {
+    pub id: PickId,
+    pub layer: i32,
+    pub z: f32,
+    /// Lower is drawn later in the queue
+    pub depth: f32,
+    pub quad: SpriteQuad,
+    pub clip: Option<SpriteRect>,
+    /// The quad and clip are in pixels of the surface, see [ScreenSpace](crate::ScreenSpace)
+    pub screen_space: bool,
+    /// The atlas entry and flips, used by the alpha test
+    pub image: Option<(AssetId<AtlasGroup>, AtlasGroupEntry, [bool; 2])>,
+}
+
+/// A sprite under a point found by the [SpritePicker]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PickHit {
+    pub id: PickId,
+    pub queue: AssetId<SpriteQueue>,
+    pub layer: i32,
+    pub z: f32,
+    /// The point in the sprite, where [0, 0] is the bottom left corner and [1, 1] the top right
+    pub position: [f32; 2],
+    /// The [frame](SpritePicker::frame) the sprite was batched in
+    pub frame: u64,
+}
+
+/// Resource finding the sprites under a point on the CPU, for example for selection in editors or UI hit testing.
+/// Only pickable sprites are recorded, which are [Sprite](crate::Sprite) entities made [pickable](crate::Sprite::with_pickable)
+/// and sprites submitted with a [pick id](crate::SpriteStyle::with_pick_id).
+/// The sprites are recorded when batching in [SpriteBatchSet](crate::SpriteBatchSet), so they can be picked from the [Draw](modula_render::Draw) of the frame they are submitted in until the next batching.
+/// Culled sprites and sprites of removed entries are not recorded
+#[derive(Resource, Default)]
+pub struct SpritePicker {
+    queues: Vec<(AssetId<SpriteQueue>, Vec<PickRecord>)>,
+    frame: u64,
+    /// Of the surface when batching, to flip screen positions for screen space sprites
+    screen_height: f32,
+}
+
+impl SpritePicker {
+    /// The number of times sprites were batched, counting the current frame
+    #[inline]
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Number of recorded sprites
+    pub fn recorded(&self) -> usize {
+        self.queues.iter().map(|(_, records)| records.len()).sum()
+    }
+
+    /// The sprites under a position in the viewport of camera in pixels, where the origin is the top left and y points down.
+    /// [Screen space](crate::CoordinateSpace::Screen) sprites are found under the position as pixels of the surface instead, which is the same if the viewport of camera is the surface.
+    /// See [pick_world](Self::pick_world)
+    pub fn pick(&self, screen_position: [f32; 2], camera: &Camera2D) -> Vec<PickHit> {
+        self.hits(
+            camera.screen_to_world(screen_position),
+            Some(screen_position),
+        )
+        .into_iter()
+        .map(|(hit, _)| hit)
+        .collect()
+    }
+
+    /// The sprites with their quad over a world position and with the position inside their clip, the top-most first.
+    /// Sprites of the same queue are ordered as drawn, and sprites of different queues by layer and z.
+    /// The quads are those of the default shader, so sprites moved by a material are found where they would be without it.
+    /// Screen space sprites are not found, as they have no world position
+    pub fn pick_world(&self, world_position: [f32; 2]) -> Vec<PickHit> {
+        self.hits(world_position, None)
+            .into_iter()
+            .map(|(hit, _)| hit)
+            .collect()
+    }
+
+    /// Like [pick](Self::pick), but skips the sprites whose pixel under the position has an alpha at or below threshold.
+    /// Only entries with their source [Image] as [metadata](AtlasGroup::meta) are tested, other sprites are treated as opaque
+    pub fn pick_opaque(
+        &self,
+        screen_position: [f32; 2],
+        camera: &Camera2D,
+        atlas_groups: &Assets<AtlasGroup>,
+        threshold: f32,
+    ) -> Vec<PickHit> {
+        self.hits(
+            camera.screen_to_world(screen_position),
+            Some(screen_position),
+        )
+        .into_iter()
+        .filter(|(hit, record)| record.is_opaque(hit.position, atlas_groups, threshold))
+        .map(|(hit, _)| hit)
+        .collect()
+    }
+
+    /// The hits with their records, sorted top-most first. Screen space sprites are only tested with a screen position
+    fn hits(
+        &self,
+        world_position: [f32; 2],
+        screen_position: Option<[f32; 2]>,
+    ) -> Vec<(PickHit, &PickRecord)> {
+        // as placed by the ScreenSpace, from the bottom left corner of the surface
+        let surface_position =
+            screen_position.map(|position| [position[0], self.screen_height - position[1]]);
+        let mut hits = Vec::new();
+        for (queue, records) in &self.queues {
+            for record in records {
+                let point = if record.screen_space {
+                    let Some(point) = surface_position else {
+                        continue;
+                    };
+                    point
+                } else {
+                    world_position
+                };
+                if record.clip.is_some_and(|clip| !clip_contains(&clip, point)) {
+                    continue;
+                }
+                let position = record.quad.local(point);
+                if !position.iter().all(|p| (0.0..=1.0).contains(p)) {
+                    continue;
+                }
+                let hit = PickHit {
+                    id: record.id,
+                    queue: *queue,
+                    layer: record.layer,
+                    z: record.z,
+                    position,
+                    frame: self.frame,
+                };
+                hits.push((hit, record));
+            }
+        }
+        hits.sort_by(|(a, a_record), (b, b_record)| {
+            b.layer
+                .cmp(&a.layer)
+                .then(b.z.total_cmp(&a.z))
+                .then(a_record.depth.total_cmp(&b_record.depth))
+        });
+        hits
+    }
+
+    pub(crate) fn set_screen_height(&mut self, height: f32) {
+        self.screen_height = height;
+    }
+
+    /// Adds the recorded sprites of a queue, taking them from records
+    pub(crate) fn record(&mut self, queue: AssetId<SpriteQueue>, records: &mut Vec<PickRecord>) {
+        if records.is_empty() {
+            return;
+        }
+        self.queues.push((queue, std::mem::take(records)));
+    }
+}
+
+impl PickRecord {
+    /// Whether the pixel of the source image at position in the sprite has an alpha above threshold, true without a source image
+    fn is_opaque(
+        &self,
+        position: [f32; 2],
+        atlas_groups: &Assets<AtlasGroup>,
+        threshold: f32,
+    ) -> bool {
+        let Some((atlas, entry, flip)) = self.image else {
+            return true;
+        };
+        let Some(image) = atlas_groups
+            .get(atlas)
+            .and_then(|group| group.meta::<Image>(entry))
+        else {
+            return true;
+        };
+        let [u, v] = [0, 1].map(|i| {
+            if flip[i] {
+                1.0 - position[i]
+            } else {
+                position[i]
+            }
+        });
+        // images have their origin at the top left
+        let x = ((u * image.width as f32) as u32).min(image.width.saturating_sub(1));
+        let y = (((1.0 - v) * image.height as f32) as u32).min(image.height.saturating_sub(1));
+        image.alpha(x, y).is_none_or(|alpha| alpha > threshold)
+    }
+}
+
+/// Includes the edges, unlike [SpriteRect::intersects]
+fn clip_contains(clip: &SpriteRect, position: [f32; 2]) -> bool {
+    (0..2).all(|i| clip.min[i] <= position[i] && position[i] <= clip.max[i])
+}
+
+pub(crate) fn begin_pick_frame(mut picker: ResMut<SpritePicker>) {
+    picker.queues.clear();
+    picker.frame += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CameraViewport;
+
+    /// An unrotated pickable sprite with its bottom left corner at position
+    fn record(id: u64, (layer, z, depth): (i32, f32, f32), position: [f32; 2]) -> PickRecord {
+        PickRecord {
+            id: PickId::User(id),
+            layer,
+            z,
+            depth,
+            quad: SpriteQuad {
+                position,
+                size: [10.0, 10.0],
+                pivot: [0.0; 2],
+                rotation: 0.0,
+            },
+            clip: None,
+            screen_space: false,
+            image: None,
+        }
+    }
+
+    fn picker(mut records: Vec<PickRecord>) -> SpritePicker {
+        let mut picker = SpritePicker::default();
+        picker.record(Assets::<SpriteQueue>::new().add_empty(), &mut records);
+        picker
+    }
+
+    fn ids(hits: &[PickHit]) -> Vec<PickId> {
+        hits.iter().map(|hit| hit.id).collect()
+    }
+
+    #[test]
+    fn overlapping_sprites_are_all_hit() {
+        let picker = picker(vec![
+            record(0, (0, 0.0, 0.5), [0.0, 0.0]),
+            record(1, (0, 0.0, 0.4), [5.0, 5.0]),
+        ]);
+        assert_eq!(ids(&picker.pick_world([2.0, 2.0])), [PickId::User(0)]);
+        assert_eq!(
+            ids(&picker.pick_world([7.0, 7.0])),
+            [PickId::User(1), PickId::User(0)]
+        );
+        assert_eq!(ids(&picker.pick_world([12.0, 12.0])), [PickId::User(1)]);
+        assert!(picker.pick_world([-1.0, 5.0]).is_empty());
+        let hits = picker.pick_world([7.5, 5.0]);
+        assert_eq!(hits[0].position, [0.25, 0.0]);
+        assert_eq!(hits[1].position, [0.75, 0.5]);
+    }
+
+    #[test]
+    fn rotated_sprites_are_hit_inside_their_quad() {
+        let mut rotated = record(0, (0, 0.0, 0.5), [0.0, 0.0]);
+        rotated.quad.pivot = [0.5; 2];
+        rotated.quad.rotation = std::f32::consts::FRAC_PI_4;
+        let picker = picker(vec![rotated]);
+        // the corners of the unrotated quad are outside, the tips of the diamond inside
+        assert!(picker.pick_world([4.5, 4.5]).is_empty());
+        assert_eq!(picker.pick_world([6.5, 0.0]).len(), 1);
+        assert_eq!(picker.pick_world([0.0, 6.5]).len(), 1);
+    }
+
+    #[test]
+    fn hits_are_ordered_top_most_first() {
+        // recorded bottom-most first as batched, then by layer and z
+        let picker = picker(vec![
+            record(0, (0, 0.0, 0.8), [0.0, 0.0]),
+            record(1, (0, 0.0, 0.6), [0.0, 0.0]),
+            record(2, (0, 1.0, 0.7), [0.0, 0.0]),
+            record(3, (1, -1.0, 0.9), [0.0, 0.0]),
+        ]);
+        assert_eq!(
+            ids(&picker.pick_world([5.0, 5.0])),
+            [3, 2, 1, 0].map(PickId::User)
+        );
+    }
+
+    #[test]
+    fn queues_are_ordered_by_layer_and_z() {
+        let mut picker = picker(vec![record(0, (0, 2.0, 0.1), [0.0, 0.0])]);
+        // a later queue is not above an earlier one unless its z is higher
+        picker.record(
+            Assets::<SpriteQueue>::new().add_empty(),
+            &mut vec![
+                record(1, (0, 1.0, 0.9), [0.0, 0.0]),
+                record(2, (0, 3.0, 0.9), [0.0, 0.0]),
+            ],
+        );
+        assert_eq!(
+            ids(&picker.pick_world([5.0, 5.0])),
+            [2, 0, 1].map(PickId::User)
+        );
+    }
+
+    #[test]
+    fn clips_and_screen_space_are_respected() {
+        let mut clipped = record(0, (0, 0.0, 0.5), [0.0, 0.0]);
+        clipped.clip = Some(SpriteRect::new([0.0, 0.0], [5.0, 10.0]));
+        let mut screen = record(1, (0, 0.0, 0.4), [0.0, 0.0]);
+        screen.screen_space = true;
+        let mut picker = picker(vec![clipped, screen]);
+        picker.set_screen_height(20.0);
+        assert_eq!(ids(&picker.pick_world([2.0, 2.0])), [PickId::User(0)]);
+        assert!(picker.pick_world([7.0, 2.0]).is_empty());
+        // screen space sprites are placed from the bottom left of the surface
+        let mut camera = Camera2D::new(CameraViewport::Size(20, 20)).with_position([10.0, 10.0]);
+        camera.update_view((20, 20));
+        assert_eq!(
+            ids(&picker.pick([2.0, 18.0], &camera)),
+            [PickId::User(1), PickId::User(0)]
+        );
+        assert_eq!(ids(&picker.pick([7.0, 18.0], &camera)), [PickId::User(1)]);
+    }
+}
@@ -0,0 +1,380 @@
+use std::ops::Range;
+
+use bevy_ecs::system::{Res, ResMut, Resource};
+use modula_asset::{AssetId, Assets};
+use modula_core::{DeviceRes, QueueRes};
+use wgpu::{Buffer, BufferAddress, BufferDescriptor, BufferUsages, Device, Queue};
+
+/// Optional resource configuring the [InstanceBufferPool],
+/// read when the pool is made during [Init](modula_core::Init) so it should be inserted before. [Default] is used if it is not inserted
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InstanceBufferPoolConfig {
+    /// Number of buffers used in turn, so a buffer is not written while an earlier frame may still read it
+    pub frames_in_flight: usize,
+    /// The smallest size of a buffer in bytes
+    pub min_size: BufferAddress,
+    /// The largest size of a buffer in bytes, data pushed past this in a frame is refused
+    pub max_size: BufferAddress,
+    /// Number of consecutive frames a buffer must use at most a quarter of its size before it is halved
+    pub shrink_after: u32,
+}
+
+impl Default for InstanceBufferPoolConfig {
+    fn default() -> Self {
+        Self {
+            frames_in_flight: 2,
+            min_size: 4096,
+            max_size: 256 << 20,
+            shrink_after: 300,
+        }
+    }
+}
+
+/// Part of a buffer of the [InstanceBufferPool] holding data pushed this frame
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InstanceSlice {
+    pub buffer: AssetId<Buffer>,
+    pub range: Range<BufferAddress>,
+}
+
+/// Resource handing out [InstanceSlices](InstanceSlice) of vertex buffers for instance data, used by [SpriteQueues](crate::SpriteQueue) when batching.
+/// Data is pushed during [PreDraw](modula_render::PreDraw) in [SpriteBatchSet](crate::SpriteBatchSet), and written to the buffer of the frame in one write at the end of the set.
+/// Buffers grow to the next power of two when a frame pushes more than fits, and are halved after sustained low usage, see [InstanceBufferPoolConfig].
+/// The ids of the buffers stay the same when resized, so slices stay valid until the buffer is used again [frames_in_flight](InstanceBufferPoolConfig::frames_in_flight) frames later
+#[derive(Resource)]
+pub struct InstanceBufferPool {
+    config: InstanceBufferPoolConfig,
+    frames: Vec<PoolFrame>,
+    current: usize,
+}
+
+struct PoolFrame {
+    buffer: Option<AssetId<Buffer>>,
+    /// Size of the buffer, 0 if it is not made yet
+    capacity: BufferAddress,
+    data: Vec<u8>,
+    low_usage_frames: u32,
+}
+
+impl InstanceBufferPool {
+    /// ## Panics
+    /// If frames_in_flight is 0 or min_size is larger than max_size
+    pub fn new(config: InstanceBufferPoolConfig) -> Self {
+        assert!(
+            config.frames_in_flight > 0,
+            "an instance buffer pool needs at least one frame in flight"
+        );
+        assert!(
+            config.min_size <= config.max_size,
+            "min_size of an instance buffer pool must not be larger than max_size"
+        );
+        Self {
+            config,
+            frames: (0..config.frames_in_flight)
+                .map(|_| PoolFrame {
+                    buffer: None,
+                    capacity: 0,
+                    data: Vec::new(),
+                    low_usage_frames: 0,
+                })
+                .collect(),
+            current: 0,
+        }
+    }
+
+    #[inline]
+    pub fn config(&self) -> &InstanceBufferPoolConfig {
+        &self.config
+    }
+
+    /// Moves on to the buffer of the next frame, discarding data that was pushed but not written
+    pub fn begin_frame(&mut self, buffers: &mut Assets<Buffer>) {
+        self.current = (self.current + 1) % self.frames.len();
+        let frame = &mut self.frames[self.current];
+        frame.data.clear();
+        frame.buffer.get_or_insert_with(|| buffers.add_empty());
+    }
+
+    /// The buffer of this frame
+    /// ## Panics
+    /// If [begin_frame](Self::begin_frame) was never called
+    #[inline]
+    pub fn buffer(&self) -> AssetId<Buffer> {
+        self.frames[self.current]
+            .buffer
+            .expect("begin_frame must be called before using an instance buffer pool")
+    }
+
+    /// Copies data to the buffer of this frame, padded to a multiple of 4 bytes as required by wgpu.
+    /// None if the data of this frame would be larger than [max_size](InstanceBufferPoolConfig::max_size)
+    /// ## Panics
+    /// If [begin_frame](Self::begin_frame) was never called
+    pub fn push(&mut self, data: &[u8]) -> Option<InstanceSlice> {
+        let buffer = self.buffer();
+        let frame = &mut self.frames[self.current];
+        let start = frame.data.len() as BufferAddress;
+        let end = start + data.len() as BufferAddress;
+        if end.next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT) > self.config.max_size {
+            return None;
+        }
+        frame.data.extend_from_slice(data);
+        frame.data.resize(
+            end.next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT) as usize,
+            0,
+        );
+        Some(InstanceSlice {
+            buffer,
+            range: start..end,
+        })
+    }
+
+    /// Bytes pushed this frame, including padding
+    #[inline]
+    pub fn used(&self) -> BufferAddress {
+        self.frames[self.current].data.len() as BufferAddress
+    }
+
+    /// Size of the buffer of this frame, 0 if it is not made yet
+    #[inline]
+    pub fn capacity(&self) -> BufferAddress {
+        self.frames[self.current].capacity
+    }
+
+    /// The size the buffer of this frame should have for the data pushed, or None if it should keep its size
+    fn target_capacity(&mut self) -> Option<BufferAddress> {
+        let config = self.config;
+        let frame = &mut self.frames[self.current];
+        let used = frame.data.len() as BufferAddress;
+        if used > frame.capacity {
+            frame.low_usage_frames = 0;
+            return Some(
+                used.next_power_of_two()
+                    .clamp(config.min_size, config.max_size),
+            );
+        }
+        if used <= frame.capacity / 4 && frame.capacity > config.min_size {
+            frame.low_usage_frames += 1;
+            if frame.low_usage_frames >= config.shrink_after {
+                frame.low_usage_frames = 0;
+                return Some((frame.capacity / 2).max(config.min_size));
+            }
+        } else {
+            frame.low_usage_frames = 0;
+        }
+        None
+    }
+
+    /// Resizes the buffer of this frame if needed, and writes the data pushed this frame to it
+    pub fn write(&mut self, buffers: &mut Assets<Buffer>, device: &Device, queue: &Queue) {
+        self.write_to(&mut GpuWriter {
+            buffers,
+            device,
+            queue,
+        });
+    }
+
+    fn write_to(&mut self, writer: &mut impl InstanceWriter) {
+        if let Some(capacity) = self.target_capacity() {
+            let frame = &mut self.frames[self.current];
+            frame.capacity = capacity;
+            writer.resize(frame.buffer.unwrap(), capacity);
+        }
+        let frame = &self.frames[self.current];
+        if !frame.data.is_empty() {
+            writer.write(frame.buffer.unwrap(), &frame.data);
+        }
+    }
+}
+
+/// Where the [InstanceBufferPool] puts its data, so the pool can be used without a GPU
+trait InstanceWriter {
+    /// Replaces the buffer with one of size bytes
+    fn resize(&mut self, buffer: AssetId<Buffer>, size: BufferAddress);
+
+    /// Writes data to the start of the buffer
+    fn write(&mut self, buffer: AssetId<Buffer>, data: &[u8]);
+}
+
+struct GpuWriter<'a> {
+    buffers: &'a mut Assets<Buffer>,
+    device: &'a Device,
+    queue: &'a Queue,
+}
+
+impl InstanceWriter for GpuWriter<'_> {
+    fn resize(&mut self, buffer: AssetId<Buffer>, size: BufferAddress) {
+        self.buffers.replace(
+            buffer,
+            self.device.create_buffer(&BufferDescriptor {
+                label: Some("Instance buffer"),
+                size,
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+        );
+    }
+
+    fn write(&mut self, buffer: AssetId<Buffer>, data: &[u8]) {
+        self.queue
+            .write_buffer(self.buffers.get(buffer).unwrap(), 0, data);
+    }
+}
+
+pub(crate) fn begin_instance_frame(
+    mut pool: ResMut<InstanceBufferPool>,
+    mut buffers: ResMut<Assets<Buffer>>,
+) {
+    pool.begin_frame(&mut buffers);
+}
+
+pub(crate) fn write_instances(
+    mut pool: ResMut<InstanceBufferPool>,
+    mut buffers: ResMut<Assets<Buffer>>,
+    device: Res<DeviceRes>,
+    queue: Res<QueueRes>,
+) {
+    pool.write(&mut buffers, &device.0, &queue.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records the writes instead of making buffers
+    #[derive(Default)]
+    struct RecordingWriter {
+        resized: Vec<(AssetId<Buffer>, BufferAddress)>,
+        written: Vec<(AssetId<Buffer>, usize)>,
+    }
+
+    impl InstanceWriter for RecordingWriter {
+        fn resize(&mut self, buffer: AssetId<Buffer>, size: BufferAddress) {
+            self.resized.push((buffer, size));
+        }
+
+        fn write(&mut self, buffer: AssetId<Buffer>, data: &[u8]) {
+            self.written.push((buffer, data.len()));
+        }
+    }
+
+    fn pool(frames_in_flight: usize, max_size: BufferAddress) -> InstanceBufferPool {
+        InstanceBufferPool::new(InstanceBufferPoolConfig {
+            frames_in_flight,
+            min_size: 64,
+            max_size,
+            shrink_after: 3,
+        })
+    }
+
+    /// Pushes size bytes in a frame of its own, and writes them
+    fn frame(
+        pool: &mut InstanceBufferPool,
+        buffers: &mut Assets<Buffer>,
+        size: usize,
+    ) -> RecordingWriter {
+        pool.begin_frame(buffers);
+        pool.push(&vec![0; size]).unwrap();
+        let mut writer = RecordingWriter::default();
+        pool.write_to(&mut writer);
+        writer
+    }
+
+    #[test]
+    fn slices_are_aligned() {
+        let mut buffers = Assets::new();
+        let mut pool = pool(1, 1024);
+        pool.begin_frame(&mut buffers);
+        let buffer = pool.buffer();
+        assert_eq!(pool.push(&[1; 6]).unwrap().range, 0..6);
+        let slice = pool.push(&[2; 8]).unwrap();
+        assert_eq!(
+            slice,
+            InstanceSlice {
+                buffer,
+                range: 8..16
+            }
+        );
+        assert_eq!(pool.push(&[3; 3]).unwrap().range, 16..19);
+        assert_eq!(pool.used(), 20);
+
+        // a new frame starts at 0 again
+        pool.begin_frame(&mut buffers);
+        assert_eq!(pool.push(&[4; 4]).unwrap().range, 0..4);
+    }
+
+    #[test]
+    fn data_past_max_size_is_refused() {
+        let mut buffers = Assets::new();
+        let mut pool = pool(1, 64);
+        pool.begin_frame(&mut buffers);
+        assert_eq!(pool.push(&[0; 58]).unwrap().range, 0..58);
+        // padded to 60, so 8 more bytes do not fit
+        assert_eq!(pool.push(&[0; 8]), None);
+        assert_eq!(pool.push(&[0; 4]).unwrap().range, 60..64);
+        assert_eq!(pool.push(&[0; 1]), None);
+        assert_eq!(pool.used(), 64);
+    }
+
+    #[test]
+    fn buffers_grow_to_powers_of_two() {
+        let mut buffers = Assets::new();
+        let mut pool = pool(1, 1000);
+        let writer = frame(&mut pool, &mut buffers, 10);
+        let buffer = pool.buffer();
+        assert_eq!(writer.resized, [(buffer, 64)]);
+        assert_eq!(writer.written, [(buffer, 12)]);
+
+        // fits, so the buffer is kept
+        assert!(frame(&mut pool, &mut buffers, 64).resized.is_empty());
+        assert_eq!(frame(&mut pool, &mut buffers, 65).resized, [(buffer, 128)]);
+        assert_eq!(pool.capacity(), 128);
+        assert_eq!(frame(&mut pool, &mut buffers, 300).resized, [(buffer, 512)]);
+        // clamped to the max size
+        assert_eq!(
+            frame(&mut pool, &mut buffers, 600).resized,
+            [(buffer, 1000)]
+        );
+        assert_eq!(pool.capacity(), 1000);
+    }
+
+    #[test]
+    fn buffers_shrink_after_low_usage() {
+        let mut buffers = Assets::new();
+        let mut pool = pool(1, 1024);
+        frame(&mut pool, &mut buffers, 1000);
+        assert_eq!(pool.capacity(), 1024);
+        let buffer = pool.buffer();
+        // a quarter of the buffer is low usage, above it resets the count
+        frame(&mut pool, &mut buffers, 256);
+        frame(&mut pool, &mut buffers, 256);
+        frame(&mut pool, &mut buffers, 300);
+        frame(&mut pool, &mut buffers, 256);
+        frame(&mut pool, &mut buffers, 256);
+        assert_eq!(pool.capacity(), 1024);
+        let writer = frame(&mut pool, &mut buffers, 256);
+        assert_eq!(writer.resized, [(buffer, 512)]);
+        for _ in 0..3 {
+            frame(&mut pool, &mut buffers, 0);
+        }
+        assert_eq!(pool.capacity(), 256);
+        // never below the min size
+        for _ in 0..12 {
+            frame(&mut pool, &mut buffers, 0);
+        }
+        assert_eq!(pool.capacity(), 64);
+    }
+
+    #[test]
+    fn frames_use_their_own_buffers() {
+        let mut buffers = Assets::new();
+        let mut pool = pool(2, 1024);
+        let first = frame(&mut pool, &mut buffers, 100);
+        let second = frame(&mut pool, &mut buffers, 10);
+        let third = frame(&mut pool, &mut buffers, 10);
+        assert_ne!(first.written[0].0, second.written[0].0);
+        assert_eq!(first.written[0].0, third.written[0].0);
+        assert_eq!(second.resized, [(second.written[0].0, 64)]);
+        // the buffer of the first frame was already made large enough
+        assert!(third.resized.is_empty());
+    }
+}
@@ -0,0 +1,499 @@
+use modula_asset::AssetId;
+use modula_texture::atlas::{AtlasGroup, AtlasGroupEntry};
+use wgpu::{BindGroup, Buffer, BufferAddress};
+
+use crate::{
+    clip::ClipStack, CoordinateSpace, MaterialId, NineSliceInsets, PickId, SpriteBlend,
+    SpriteCulling, SpriteInstance, SpritePass, SpritePipeline, SpriteShadow, SpriteStyle,
+};
+
+/// A type that holds information about what sprites to draw, and their order, drawn by a [SpriteOperation](crate::SpriteOperation).
+/// Sprites are submitted with [draw](Self::draw), and turned into batches during [PreDraw](modula_render::PreDraw) in [SpriteBatchSet](crate::SpriteBatchSet).
+/// Submissions are only batched once, so a sprite must be submitted every frame it should be drawn.
+/// [Sprite](crate::Sprite) entities are submitted to their queue every frame during [SpriteExtractSet](crate::SpriteExtractSet), along with what is drawn with the queue directly.
+/// When batching, submissions are first sorted by layer, then z, then the order they were submitted, and batches only merge sprites that are adjacent after sorting.
+/// Interleaving atlases, pipelines or blend modes at the same layer and z therefore makes more batches, unless [bind group sorting](Self::set_bind_group_sorting) is set.
+/// Entries of the same atlas bind group are sampled from their binding in the shader, so they share batches whatever binding they are in
+#[derive(Default)]
+pub struct SpriteQueue {
+    // starting at group 1, as group 0 is from the atlas group
+    bind_groups: Vec<AssetId<BindGroup>>,
+    batches: Vec<SpriteBatch>,
+    shadow_batches: Vec<SpriteBatch>,
+    outline_batches: Vec<SpriteBatch>,
+    shadow: SpriteShadow,
+    /// Used by following submissions
+    atlas: Option<AssetId<AtlasGroup>>,
+    pipeline: Option<AssetId<SpritePipeline>>,
+    layer: i32,
+    space: CoordinateSpace,
+    culling: Option<SpriteCulling>,
+    bind_group_sorting: bool,
+    clips: ClipStack,
+    pub(crate) submissions: Vec<Submission>,
+    pub(crate) depth_sorted: bool,
+    /// The instances of the last batching, kept for [bake](Self::bake)
+    pub(crate) instance_data: Vec<u8>,
+    /// Where the instances of the last batching start in the buffer of the pool, None if nothing was pushed
+    pub(crate) instance_offset: Option<BufferAddress>,
+    /// Number of batches made by the last batching, which come before the pushed batches
+    pub(crate) batched: usize,
+}
+
+impl SpriteQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A queue binding bind_groups after the atlas group, in order starting at group 1
+    pub fn with_bind_groups(bind_groups: Vec<AssetId<BindGroup>>) -> Self {
+        Self {
+            bind_groups,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn bind_groups(&self) -> &[AssetId<BindGroup>] {
+        &self.bind_groups
+    }
+
+    pub fn set_bind_groups(&mut self, bind_groups: Vec<AssetId<BindGroup>>) {
+        self.bind_groups = bind_groups;
+    }
+
+    /// The atlas group of the entries submitted after this
+    pub fn set_atlas(&mut self, atlas: AssetId<AtlasGroup>) {
+        self.atlas = Some(atlas);
+    }
+
+    #[inline]
+    pub fn atlas(&self) -> Option<AssetId<AtlasGroup>> {
+        self.atlas
+    }
+
+    /// The pipeline drawing the sprites submitted after this
+    pub fn set_pipeline(&mut self, pipeline: AssetId<SpritePipeline>) {
+        self.pipeline = Some(pipeline);
+    }
+
+    #[inline]
+    pub fn pipeline(&self) -> Option<AssetId<SpritePipeline>> {
+        self.pipeline
+    }
+
+    /// The layer of the sprites submitted after this, sprites of lower layers are drawn first regardless of z. The layer is 0 by default.
+    /// Layers can be drawn with different cameras by the [views](crate::SpriteView) of a [SpriteOperation](crate::SpriteOperation)
+    pub fn set_layer(&mut self, layer: i32) {
+        self.layer = layer;
+    }
+
+    #[inline]
+    pub fn layer(&self) -> i32 {
+        self.layer
+    }
+
+    /// What the positions and sizes of the sprites and clips submitted after this are in, [World](CoordinateSpace::World) by default.
+    /// [Screen](CoordinateSpace::Screen) space sprites are never culled, and are batched separately from world space sprites at the same layer and z
+    pub fn set_coordinate_space(&mut self, space: CoordinateSpace) {
+        self.space = space;
+    }
+
+    #[inline]
+    pub fn coordinate_space(&self) -> CoordinateSpace {
+        self.space
+    }
+
+    /// Sprites entirely outside the view of the camera are skipped when batching if set, by their bounds as they are drawn by the default shader.
+    /// Culling is off by default, which suits for example UI drawn with a camera other than the one of the world.
+    /// Every layer is culled with the same camera, also when the layers are drawn by [views](crate::SpriteView) with different cameras
+    pub fn set_culling(&mut self, culling: Option<SpriteCulling>) {
+        self.culling = culling;
+    }
+
+    #[inline]
+    pub fn culling(&self) -> Option<SpriteCulling> {
+        self.culling
+    }
+
+    /// Sprites at the same layer and z are grouped by atlas bind group when batching if set, in the order the bind groups are first submitted, so they merge into fewer batches.
+    /// Sprites of different bind groups are then no longer drawn in the order they were submitted, so this should only be set when sprites at the same z do not overlap, or their order does not matter.
+    /// Off by default
+    pub fn set_bind_group_sorting(&mut self, bind_group_sorting: bool) {
+        self.bind_group_sorting = bind_group_sorting;
+    }
+
+    #[inline]
+    pub fn has_bind_group_sorting(&self) -> bool {
+        self.bind_group_sorting
+    }
+
+    /// How the sprites that [cast shadows](SpriteStyle::with_shadow) are drawn in the [Shadow](crate::SpritePass::Shadow) pass, read when batching
+    pub fn set_shadow(&mut self, shadow: SpriteShadow) {
+        self.shadow = shadow;
+    }
+
+    #[inline]
+    pub fn shadow(&self) -> SpriteShadow {
+        self.shadow
+    }
+
+    /// Clips the sprites submitted after this to rect until the matching [pop_clip](Self::pop_clip), intersected with the current clip if there is one.
+    /// The rect is in the [coordinate space](Self::set_coordinate_space) of the sprites when drawn by a [SpriteOperation](crate::SpriteOperation) with a camera,
+    /// and in pixels of the target from its bottom left corner otherwise.
+    /// Sprites are only batched with sprites of the same clip, and sprites whose clip has no pixels on the target are not drawn
+    pub fn push_clip(&mut self, rect: SpriteRect) {
+        self.clips.push(rect);
+    }
+
+    /// Removes the last pushed clip, returning it as it was intersected
+    pub fn pop_clip(&mut self) -> Option<SpriteRect> {
+        self.clips.pop()
+    }
+
+    /// The clip of the sprites submitted after this
+    #[inline]
+    pub fn clip(&self) -> Option<SpriteRect> {
+        self.clips.current()
+    }
+
+    /// Whether the last batches use a [depth sorted](SpritePipeline::is_depth_sorted) pipeline, in which case the depth of the target is cleared before drawing them
+    #[inline]
+    pub fn is_depth_sorted(&self) -> bool {
+        self.depth_sorted
+    }
+
+    /// Submits an entry of the current [atlas](Self::set_atlas), sized as the entry in pixels times the scale of the transform.
+    /// Sprites are drawn in order of [layer](Self::set_layer), then z, lowest first, and in the order they were submitted if both are equal.
+    /// Consecutive sprites with the same atlas bind group, pipeline and [blend](SpriteBlend) are drawn as one batch.
+    /// [Opaque](SpriteBlend::Opaque) sprites of a depth sorted pipeline are drawn before the rest, see [with_depth_sorting](crate::SpritePipelineBuilder::with_depth_sorting).
+    /// Entries of a group that is not built when batching are skipped
+    /// ## Panics
+    /// If the atlas or pipeline is not set
+    pub fn draw(
+        &mut self,
+        entry: AtlasGroupEntry,
+        transform: SpriteTransform,
+        style: SpriteStyle,
+        z: f32,
+    ) {
+        let tint = style.tint;
+        self.submit(
+            style,
+            z,
+            SubmissionKind::Entry {
+                entry,
+                transform,
+                tint: [tint.r, tint.g, tint.b, tint.a].map(|c| c as f32),
+            },
+        );
+    }
+
+    /// Submits an instance as is, drawn with bind group bind_group_index of the current [atlas](Self::set_atlas), ordered like [draw](Self::draw).
+    /// This should be the [bind_group_index](modula_texture::atlas::EntryUv::bind_group_index) of the [entry](SpriteInstance::entry) of the instance
+    /// ## Panics
+    /// If the atlas or pipeline is not set
+    pub fn draw_raw(
+        &mut self,
+        instance: SpriteInstance,
+        bind_group_index: usize,
+        blend: SpriteBlend,
+        z: f32,
+    ) {
+        self.submit(
+            SpriteStyle::default().with_blend(blend),
+            z,
+            SubmissionKind::Raw {
+                instance,
+                bind_group_index,
+            },
+        );
+    }
+
+    /// The tint of style is not used, as it is part of kind
+    pub(crate) fn submit(&mut self, style: SpriteStyle, z: f32, kind: SubmissionKind) {
+        let (Some(atlas), Some(pipeline)) = (self.atlas, self.pipeline) else {
+            panic!("the atlas and pipeline of a SpriteQueue must be set before drawing");
+        };
+        self.push_submission(Submission {
+            layer: self.layer,
+            z,
+            depth: 0.0,
+            atlas,
+            pipeline,
+            material: style.material,
+            blend: style.blend,
+            clip: self.clips.current(),
+            space: self.space,
+            pick_id: style.pick_id.map(PickId::User),
+            shadow: style.shadow,
+            outline: style.outline,
+            kind,
+        });
+    }
+
+    /// Submits without the current atlas, pipeline, layer, coordinate space and clip, for submissions made outside the queue
+    pub(crate) fn push_submission(&mut self, submission: Submission) {
+        self.submissions.push(submission);
+    }
+
+    /// Number of sprites submitted since the queue was last batched
+    #[inline]
+    pub fn submission_count(&self) -> usize {
+        self.submissions.len()
+    }
+
+    /// Batches are drawn in the order they are pushed.
+    /// The batches are replaced when the queue is batched, so batches pushed manually should be pushed after [SpriteBatchSet](crate::SpriteBatchSet), for example in [Draw](modula_render::Draw)
+    pub fn push_batch(&mut self, batch: SpriteBatch) {
+        self.batches.push(batch);
+    }
+
+    #[inline]
+    pub fn batches(&self) -> &[SpriteBatch] {
+        &self.batches
+    }
+
+    pub(crate) fn batches_mut(&mut self) -> &mut Vec<SpriteBatch> {
+        &mut self.batches
+    }
+
+    /// The shadows of the sprites that [cast shadows](SpriteStyle::with_shadow), made when batching and drawn in the [Shadow](crate::SpritePass::Shadow) pass
+    #[inline]
+    pub fn shadow_batches(&self) -> &[SpriteBatch] {
+        &self.shadow_batches
+    }
+
+    /// The sprites that are [outlined](SpriteStyle::with_outline), made when batching and drawn in the [Outline](crate::SpritePass::Outline) pass
+    #[inline]
+    pub fn outline_batches(&self) -> &[SpriteBatch] {
+        &self.outline_batches
+    }
+
+    /// The batches drawn in pass
+    pub(crate) fn pass_batches(&self, pass: SpritePass) -> &[SpriteBatch] {
+        match pass {
+            SpritePass::Main => &self.batches,
+            SpritePass::Shadow => &self.shadow_batches,
+            SpritePass::Outline => &self.outline_batches,
+        }
+    }
+
+    /// The batches of the secondary passes, which are replaced when batching
+    pub(crate) fn effect_batches_mut(&mut self) -> (&mut Vec<SpriteBatch>, &mut Vec<SpriteBatch>) {
+        (&mut self.shadow_batches, &mut self.outline_batches)
+    }
+
+    /// Removes all batches, submissions and clips, keeping the bind groups
+    pub fn clear(&mut self) {
+        self.batches.clear();
+        self.shadow_batches.clear();
+        self.outline_batches.clear();
+        self.submissions.clear();
+        self.clips.clear();
+        self.instance_offset = None;
+        self.batched = 0;
+    }
+}
+
+/// Where and how large a sprite is drawn, the quad is made from this in the vertex shader
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpriteTransform {
+    /// World position of the pivot
+    pub position: [f32; 2],
+    /// Multiplied with the size of the entry in pixels
+    pub scale: [f32; 2],
+    /// Counter clockwise around the pivot, in radians
+    pub rotation: f32,
+    /// Point of the sprite at the position, where [0, 0] is the bottom left corner and [1, 1] the top right.
+    /// The center by default
+    pub pivot: [f32; 2],
+    /// Mirrors the image horizontally, without moving the quad
+    pub flip_x: bool,
+    /// Mirrors the image vertically, without moving the quad
+    pub flip_y: bool,
+}
+
+impl Default for SpriteTransform {
+    fn default() -> Self {
+        Self {
+            position: [0.0; 2],
+            scale: [1.0; 2],
+            rotation: 0.0,
+            pivot: [0.5; 2],
+            flip_x: false,
+            flip_y: false,
+        }
+    }
+}
+
+impl SpriteTransform {
+    pub fn from_position(position: [f32; 2]) -> Self {
+        Self {
+            position,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_scale(mut self, scale: [f32; 2]) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_pivot(mut self, pivot: [f32; 2]) -> Self {
+        self.pivot = pivot;
+        self
+    }
+
+    pub fn with_flip(mut self, flip_x: bool, flip_y: bool) -> Self {
+        self.flip_x = flip_x;
+        self.flip_y = flip_y;
+        self
+    }
+}
+
+/// An axis aligned rect in world space, where y points up
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SpriteRect {
+    /// The bottom left corner
+    pub min: [f32; 2],
+    /// The top right corner
+    pub max: [f32; 2],
+}
+
+impl SpriteRect {
+    pub fn new(min: [f32; 2], max: [f32; 2]) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_center_size(center: [f32; 2], size: [f32; 2]) -> Self {
+        Self {
+            min: [center[0] - size[0] / 2.0, center[1] - size[1] / 2.0],
+            max: [center[0] + size[0] / 2.0, center[1] + size[1] / 2.0],
+        }
+    }
+
+    #[inline]
+    pub fn width(&self) -> f32 {
+        self.max[0] - self.min[0]
+    }
+
+    #[inline]
+    pub fn height(&self) -> f32 {
+        self.max[1] - self.min[1]
+    }
+
+    #[inline]
+    pub fn center(&self) -> [f32; 2] {
+        [
+            (self.min[0] + self.max[0]) / 2.0,
+            (self.min[1] + self.max[1]) / 2.0,
+        ]
+    }
+
+    /// Whether the rects overlap, touching edges do not count
+    pub fn intersects(&self, other: &SpriteRect) -> bool {
+        self.min[0] < other.max[0]
+            && other.min[0] < self.max[0]
+            && self.min[1] < other.max[1]
+            && other.min[1] < self.max[1]
+    }
+
+    /// The overlap of the rects, which is [empty](Self::is_empty) if they do not intersect
+    pub fn intersection(&self, other: &SpriteRect) -> SpriteRect {
+        SpriteRect {
+            min: [0, 1].map(|i| self.min[i].max(other.min[i])),
+            max: [0, 1].map(|i| self.max[i].min(other.max[i])),
+        }
+    }
+
+    /// Whether the rect has no area
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.width() <= 0.0 || self.height() <= 0.0
+    }
+}
+
+/// A sprite submitted to a [SpriteQueue], turned into an instance when batching
+pub(crate) struct Submission {
+    pub layer: i32,
+    pub z: f32,
+    /// Set from the sorted order when batching
+    pub depth: f32,
+    pub atlas: AssetId<AtlasGroup>,
+    /// Replaced by the pipeline of the material when batching
+    pub pipeline: AssetId<SpritePipeline>,
+    pub material: Option<MaterialId>,
+    pub blend: SpriteBlend,
+    pub clip: Option<SpriteRect>,
+    /// Positions are moved to pixels of the surface when batching if in screen space
+    pub space: CoordinateSpace,
+    /// Recorded by the [SpritePicker](crate::SpritePicker) if set
+    pub pick_id: Option<PickId>,
+    /// Also batched into the shadow batches of the queue
+    pub shadow: bool,
+    /// Also batched into the outline batches of the queue
+    pub outline: bool,
+    pub kind: SubmissionKind,
+}
+
+#[derive(Clone)]
+pub(crate) enum SubmissionKind {
+    Entry {
+        entry: AtlasGroupEntry,
+        transform: SpriteTransform,
+        tint: [f32; 4],
+    },
+    NineSlice {
+        entry: AtlasGroupEntry,
+        rect: SpriteRect,
+        /// Taken from the metadata of the entry if None
+        insets: Option<NineSliceInsets>,
+        /// World units per pixel of the insets
+        border_scale: f32,
+        tint: [f32; 4],
+    },
+    Raw {
+        instance: SpriteInstance,
+        bind_group_index: usize,
+    },
+}
+
+/// Instances drawn with the same atlas bind group and pipeline variant, every instance is [QUAD_VERTEX_COUNT](modula_texture::atlas::QUAD_VERTEX_COUNT) vertices
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpriteBatch {
+    /// The [layer](SpriteQueue::set_layer) of every sprite in the batch, see [SpriteView](crate::SpriteView)
+    pub layer: i32,
+    pub atlas: AssetId<AtlasGroup>,
+    /// Index in [AtlasGroup::bind_groups], bound to group 0
+    pub bind_group_index: usize,
+    pub pipeline: AssetId<SpritePipeline>,
+    /// Its bind groups are bound after those of the queue, the pipeline is the pipeline of the material
+    pub material: Option<MaterialId>,
+    /// The [variant](SpritePipeline::variant) of the pipeline, which must exist when drawing
+    pub blend: SpriteBlend,
+    /// The scissor rect is set from this when drawing, see [SpriteQueue::push_clip]
+    pub clip: Option<SpriteRect>,
+    /// Drawn with the bind group of the [ScreenSpace](crate::ScreenSpace) instead of the camera, see [CoordinateSpace]
+    pub screen_space: bool,
+    /// The instance buffer, bound to slot 0
+    pub buffer: AssetId<Buffer>,
+    /// Byte offset of the first instance in the buffer
+    pub start: BufferAddress,
+    /// Size of an instance in bytes
+    pub size: BufferAddress,
+    pub count: u32,
+}
+
+impl SpriteBatch {
+    /// The byte range of the instances in the buffer
+    #[inline]
+    pub fn byte_range(&self) -> std::ops::Range<BufferAddress> {
+        self.start..self.start + self.count as BufferAddress * self.size
+    }
+}
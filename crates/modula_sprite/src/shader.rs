@@ -0,0 +1,290 @@
+use std::fmt::Write;
+
+use bevy_ecs::system::ResMut;
+use modula_render::{
+    shader::{ShaderBundler, ShaderBundlerError, ShaderFlags, ShaderModuleSource},
+    RenderPipelineBuilder, RenderTarget, TargetFormats, TargetPipeline,
+};
+use modula_texture::atlas::AtlasGroupBindGroupLayout;
+use wgpu::{
+    BindGroupLayout, CompareFunction, Device, PipelineLayout, PipelineLayoutDescriptor,
+    ShaderModule, ShaderModuleDescriptor, VertexBufferLayout, VertexStepMode,
+};
+
+use crate::{CameraBindGroupLayout, SpriteBlend, SpriteInstance};
+
+/// Name of the library expanding [SpriteInstances](SpriteInstance) to quads, added to the [ShaderBundler] during [Init](modula_core::Init).
+/// It has 'sprite_corner' taking the vertex index and the fields used for the quad, returning the world position and UV of the corner
+pub const SPRITE_QUAD_LIBRARY: &str = "modula_sprite_quad";
+
+/// Skipped if shader bundling is not used
+pub(crate) fn add_sprite_libraries(bundler: Option<ResMut<ShaderBundler>>) {
+    let Some(mut bundler) = bundler else {
+        return;
+    };
+    bundler
+        .add_library(
+            SPRITE_QUAD_LIBRARY.into(),
+            ShaderModuleSource::new(include_str!("shader/quad.wgsl").into())
+                .with_name("modula_sprite/quad.wgsl"),
+        )
+        .expect("sprite shader libraries should only be added once");
+}
+
+/// Wgsl declaring the atlases of group 0, the sampler and the `atlas_entries` of the [entry buffer](modula_texture::atlas::AtlasGroup::entry_buffer), along with
+/// `sample_atlas(binding: u32, layer: u32, uv: vec2<f32>, ddx: vec2<f32>, ddy: vec2<f32>) -> vec4<f32>` sampling the atlas at binding.
+/// This is part of the interface of the default sprite shader, and can be used by custom ones
+pub fn atlas_sampling_source(atlas_layout: &AtlasGroupBindGroupLayout) -> ShaderModuleSource {
+    ShaderModuleSource::new(atlas_sampling_wgsl(atlas_layout))
+        .with_name("modula_sprite/atlas_sampling.wgsl")
+}
+
+/// The source of the material used by [SpritePipelineBuilder] if none is set, returning the sampled color multiplied by the tint
+pub fn default_material_source() -> ShaderModuleSource {
+    ShaderModuleSource::new(include_str!("shader/material.wgsl").into())
+        .with_name("modula_sprite/material.wgsl")
+}
+
+fn atlas_sampling_wgsl(atlas_layout: &AtlasGroupBindGroupLayout) -> String {
+    let mut source = String::new();
+    for i in 0..atlas_layout.atlas_count() {
+        writeln!(
+            source,
+            "@group(0) @binding({i})\nvar atlas_{i}: texture_2d_array<f32>;"
+        )
+        .unwrap();
+    }
+    writeln!(
+        source,
+        "@group(0) @binding({})\nvar atlas_sampler: sampler;\n",
+        atlas_layout.sampler_binding()
+    )
+    .unwrap();
+    // matches the entry buffer, see AtlasGroupBindGroupLayout
+    writeln!(
+        source,
+        "struct AtlasEntry {{\n    bind_group_index: u32,\n    binding_index: u32,\n    layer: u32,\n    rotated: u32,\n    uv_min: vec2<f32>,\n    uv_max: vec2<f32>,\n}}\n\n@group(0) @binding({})\nvar<storage, read> atlas_entries: array<AtlasEntry>;\n",
+        atlas_layout.entry_binding()
+    )
+    .unwrap();
+    // a sample with implicit derivatives would be in non-uniform control flow
+    source.push_str(
+        "fn sample_atlas(binding: u32, layer: u32, uv: vec2<f32>, ddx: vec2<f32>, ddy: vec2<f32>) -> vec4<f32> {\n    switch binding {\n",
+    );
+    for i in 1..atlas_layout.atlas_count() {
+        writeln!(
+            source,
+            "        case {i}u: {{ return textureSampleGrad(atlas_{i}, atlas_sampler, uv, layer, ddx, ddy); }}"
+        )
+        .unwrap();
+    }
+    source.push_str(
+        "        default: { return textureSampleGrad(atlas_0, atlas_sampler, uv, layer, ddx, ddy); }\n    }\n}\n",
+    );
+    source
+}
+
+/// Whether the sprite shader outputs premultiplied colors, which decides the blend states of the [SpriteBlend] modes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SpriteAlphaMode {
+    /// The shader multiplies the color by its alpha, [Alpha](SpriteBlend::Alpha) is blended with [PREMULTIPLIED_ALPHA_BLENDING](wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING)
+    #[default]
+    Premultiplied,
+    /// The color is output as is, [Alpha](SpriteBlend::Alpha) is blended with [ALPHA_BLENDING](wgpu::BlendState::ALPHA_BLENDING)
+    Straight,
+}
+
+/// The default sprite shader made by [SpritePipelineBuilder], with a [TargetPipeline] for every [SpriteBlend] made when it is first needed.
+/// The variants for blend modes used by submissions are made when batching, pipelines used by manually pushed batches should be made with [create_variant](Self::create_variant)
+pub struct SpritePipeline {
+    label: String,
+    module: ShaderModule,
+    layout: PipelineLayout,
+    alpha_mode: SpriteAlphaMode,
+    depth_sorting: bool,
+    formats: TargetFormats,
+    variants: [Option<TargetPipeline>; SpriteBlend::ALL.len()],
+}
+
+impl SpritePipeline {
+    #[inline]
+    pub fn alpha_mode(&self) -> SpriteAlphaMode {
+        self.alpha_mode
+    }
+
+    /// Whether [depth sorting](SpritePipelineBuilder::with_depth_sorting) was enabled and the target has a depth buffer
+    #[inline]
+    pub fn is_depth_sorted(&self) -> bool {
+        self.depth_sorting && self.formats.depth_stencil.is_some()
+    }
+
+    /// The formats of the target the pipeline was built for
+    #[inline]
+    pub fn formats(&self) -> TargetFormats {
+        self.formats
+    }
+
+    /// Whether the pipeline can draw to render_target, see [TargetPipeline::is_compatible]
+    pub fn is_compatible(&self, render_target: &RenderTarget) -> bool {
+        TargetFormats::of(render_target) == self.formats
+    }
+
+    /// The pipeline for blend, None if it was not made yet
+    #[inline]
+    pub fn variant(&self, blend: SpriteBlend) -> Option<&TargetPipeline> {
+        self.variants[blend.index()].as_ref()
+    }
+
+    /// The pipeline for blend, made if it does not exist
+    pub fn create_variant(&mut self, device: &Device, blend: SpriteBlend) -> &TargetPipeline {
+        let depth_sorted = self.is_depth_sorted();
+        let Self {
+            label,
+            module,
+            layout,
+            alpha_mode,
+            formats,
+            variants,
+            ..
+        } = self;
+        variants[blend.index()].get_or_insert_with(|| {
+            let instance_layout = SpriteInstance::instance_layout();
+            let fragment_entry = match blend {
+                SpriteBlend::Opaque => "fs_opaque",
+                _ => "fs_main",
+            };
+            let (depth_write, depth_compare) = match (depth_sorted, blend) {
+                (false, _) => (false, CompareFunction::Always),
+                (true, SpriteBlend::Opaque) => (true, CompareFunction::Less),
+                (true, _) => (false, CompareFunction::Less),
+            };
+            RenderPipelineBuilder::new(module)
+                .with_label(label)
+                .with_entry_points("vs_main", fragment_entry)
+                .with_pipeline_layout(layout)
+                .with_vertex_buffer(VertexBufferLayout {
+                    array_stride: instance_layout.array_stride,
+                    step_mode: VertexStepMode::Instance,
+                    attributes: &instance_layout.attributes,
+                })
+                .with_blend(blend.blend_state(*alpha_mode))
+                .with_depth(depth_write, depth_compare)
+                .build_for_formats(device, *formats)
+        })
+    }
+}
+
+/// Builds the pipeline of the default sprite shader, drawing [SpriteInstances](SpriteInstance) sampled from atlas group bind groups.
+/// Group 0 is the [AtlasGroupBindGroupLayout], group 1 the [CameraBindGroupLayout], followed by the [added](Self::with_bind_group_layout) layouts.
+/// The shader is the interface, and the color of a fragment is decided by the [material](Self::with_material) implementing it.
+/// Sprites are blended in the order they are drawn, so by default depth is tested with [Always](CompareFunction::Always) and not written, see [with_depth_sorting](Self::with_depth_sorting).
+/// The libraries of the shader are added by [init_sprites](crate::init_sprites) if [init_shader_bundling](modula_render::shader::init_shader_bundling) is used as well
+pub struct SpritePipelineBuilder<'a> {
+    label: Option<&'a str>,
+    alpha_mode: SpriteAlphaMode,
+    depth_sorting: bool,
+    material: Option<&'a ShaderModuleSource>,
+    bind_group_layouts: Vec<&'a BindGroupLayout>,
+}
+
+impl Default for SpritePipelineBuilder<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> SpritePipelineBuilder<'a> {
+    pub fn new() -> Self {
+        Self {
+            label: None,
+            alpha_mode: SpriteAlphaMode::default(),
+            depth_sorting: false,
+            material: None,
+            bind_group_layouts: Vec::new(),
+        }
+    }
+
+    pub fn with_label(mut self, label: &'a str) -> Self {
+        self.label = Some(label);
+        self
+    }
+
+    pub fn with_alpha_mode(mut self, alpha_mode: SpriteAlphaMode) -> Self {
+        self.alpha_mode = alpha_mode;
+        self
+    }
+
+    /// If the target has a depth buffer, [Opaque](SpriteBlend::Opaque) sprites write depth and are drawn first, grouped to make fewer batches,
+    /// while the other sprites are tested against them with [Less](CompareFunction::Less) in painter's order.
+    /// The depth only orders the sprites of a queue, so a [SpriteOperation](crate::SpriteOperation) drawing them clears the depth of its target first
+    pub fn with_depth_sorting(mut self, depth_sorting: bool) -> Self {
+        self.depth_sorting = depth_sorting;
+        self
+    }
+
+    /// The implementor of the shader, which must define `sprite_material(fragment: SpriteFragment) -> vec4<f32>` returning the color of a fragment with straight alpha.
+    /// SpriteFragment has the framebuffer 'position', the atlas 'uv', the 'image_uv' from [0, 0] at the top left of the image to [1, 1] at the bottom right,
+    /// the 'color' sampled from the atlas and the 'tint'. Bind groups used by the material are [added](Self::with_bind_group_layout) like others.
+    /// [default_material_source] is used if this is not set, see [SpriteMaterials](crate::SpriteMaterials) for drawing with materials
+    pub fn with_material(mut self, source: &'a ShaderModuleSource) -> Self {
+        self.material = Some(source);
+        self
+    }
+
+    /// Adds a bind group layout after the camera, the group is 2 plus the number of layouts added before.
+    /// The default shader does not use them, but the bind groups of a [SpriteQueue](crate::SpriteQueue) must match the pipeline
+    pub fn with_bind_group_layout(mut self, layout: &'a BindGroupLayout) -> Self {
+        self.bind_group_layouts.push(layout);
+        self
+    }
+
+    /// Bundles the shader for drawing to render_target, or other targets with the same formats.
+    /// The pipelines of the blend modes are made when they are first used, see [SpritePipeline]
+    pub fn build(
+        &self,
+        device: &Device,
+        bundler: &ShaderBundler,
+        atlas_layout: &AtlasGroupBindGroupLayout,
+        camera_layout: &CameraBindGroupLayout,
+        render_target: &RenderTarget,
+    ) -> Result<SpritePipeline, ShaderBundlerError> {
+        let flags = match self.alpha_mode {
+            SpriteAlphaMode::Premultiplied => ShaderFlags::new(),
+            SpriteAlphaMode::Straight => ShaderFlags::new().with("STRAIGHT_ALPHA"),
+        };
+        let interface = ShaderModuleSource::new(
+            include_str!("shader/sprite.wgsl").to_owned() + &atlas_sampling_wgsl(atlas_layout),
+        )
+        .with_name("modula_sprite/sprite.wgsl");
+        let default_material;
+        let material = match self.material {
+            Some(material) => material,
+            None => {
+                default_material = default_material_source();
+                &default_material
+            }
+        };
+        let source = bundler.bundle(&interface, material, &flags, &[])?;
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Sprite shader"),
+            source,
+        });
+        let label = self.label.unwrap_or("Sprite pipeline");
+        let mut layouts = vec![atlas_layout.layout(), camera_layout.layout()];
+        layouts.extend(&self.bind_group_layouts);
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some(label),
+            bind_group_layouts: &layouts,
+            push_constant_ranges: &[],
+        });
+        Ok(SpritePipeline {
+            label: label.into(),
+            module,
+            layout,
+            alpha_mode: self.alpha_mode,
+            depth_sorting: self.depth_sorting,
+            formats: TargetFormats::of(render_target),
+            variants: Default::default(),
+        })
+    }
+}
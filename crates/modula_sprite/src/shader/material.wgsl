@@ -0,0 +1,5 @@
+// the default sprite material, the sampled color multiplied by the tint
+
+fn sprite_material(fragment: SpriteFragment) -> vec4<f32> {
+    return fragment.color * fragment.tint;
+}
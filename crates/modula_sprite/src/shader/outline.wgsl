@@ -0,0 +1,38 @@
+// composites the outline of the sprites drawn into the mask over the target
+
+struct Outline {
+    // premultiplied
+    color: vec4<f32>,
+    width: i32,
+}
+
+@group(0) @binding(0)
+var mask: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> outline: Outline;
+
+// a triangle covering the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+// the mask dilated by a disc of the width, without the mask itself so the outline is only drawn around the sprites
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(mask));
+    let center = vec2<i32>(position.xy);
+    let width = outline.width;
+    var coverage = 0.0;
+    for (var y = -width; y <= width; y++) {
+        for (var x = -width; x <= width; x++) {
+            if x * x + y * y > width * width {
+                continue;
+            }
+            let texel = clamp(center + vec2<i32>(x, y), vec2<i32>(0), size - 1);
+            coverage = max(coverage, textureLoad(mask, texel, 0).a);
+        }
+    }
+    return outline.color * coverage * (1.0 - textureLoad(mask, center, 0).a);
+}
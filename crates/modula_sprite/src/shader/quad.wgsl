@@ -0,0 +1,63 @@
+// expands sprite instances to the corners of their quads
+
+const SPRITE_FLIP_X: u32 = 1u;
+const SPRITE_FLIP_Y: u32 = 2u;
+
+struct SpriteCorner {
+    // world position of the corner
+    position: vec2<f32>,
+    uv: vec2<f32>,
+    // the sampled point of the image, where [0, 0] is the top left and [1, 1] the bottom right
+    image_uv: vec2<f32>,
+}
+
+// the corners of the two triangles of a quad, in image space where y points down
+fn sprite_quad_corner(vertex: u32) -> vec2<f32> {
+    var corners = array<vec2<f32>, 6>(
+        vec2(0.0, 1.0), vec2(1.0, 1.0), vec2(0.0, 0.0),
+        vec2(0.0, 0.0), vec2(1.0, 1.0), vec2(1.0, 0.0),
+    );
+    return corners[vertex];
+}
+
+fn sprite_corner(
+    vertex: u32,
+    position: vec2<f32>,
+    size: vec2<f32>,
+    // where [0, 0] is the bottom left and [1, 1] the top right
+    pivot: vec2<f32>,
+    rotation: f32,
+    flags: u32,
+    // the part of the image drawn on the quad
+    region_min: vec2<f32>,
+    region_max: vec2<f32>,
+    // set if the image is stored rotated clockwise in the atlas
+    rotated: bool,
+    // the UVs of the image in the atlas
+    uv_min: vec2<f32>,
+    uv_max: vec2<f32>,
+) -> SpriteCorner {
+    var image = sprite_quad_corner(vertex);
+    // world y points up
+    let local = (vec2(image.x, 1.0 - image.y) - pivot) * size;
+    let c = cos(rotation);
+    let s = sin(rotation);
+    var out: SpriteCorner;
+    out.position = position + vec2(local.x * c - local.y * s, local.x * s + local.y * c);
+    // the quad stays in place, only the part of the image sampled at the corner changes
+    if (flags & SPRITE_FLIP_X) != 0u {
+        image.x = 1.0 - image.x;
+    }
+    if (flags & SPRITE_FLIP_Y) != 0u {
+        image.y = 1.0 - image.y;
+    }
+    image = mix(region_min, region_max, image);
+    var atlas = image;
+    if rotated {
+        // stored rotated clockwise, so the top left of the image is the top right in the atlas
+        atlas = vec2(1.0 - image.y, image.x);
+    }
+    out.uv = mix(uv_min, uv_max, atlas);
+    out.image_uv = image;
+    return out;
+}
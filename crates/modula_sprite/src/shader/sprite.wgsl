@@ -0,0 +1,87 @@
+//use modula_sprite_quad
+
+struct Camera {
+    clip_from_world: mat4x4<f32>,
+}
+
+@group(1) @binding(0)
+var<uniform> camera: Camera;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) binding: u32,
+    @location(2) @interpolate(flat) layer: u32,
+    @location(3) tint: vec4<f32>,
+    @location(4) image_uv: vec2<f32>,
+}
+
+// the input of 'sprite_material(fragment: SpriteFragment) -> vec4<f32>', which is implemented by the material and returns the color with straight alpha
+struct SpriteFragment {
+    // framebuffer position of the fragment
+    position: vec4<f32>,
+    // in the atlas
+    uv: vec2<f32>,
+    // in the image, where [0, 0] is the top left and [1, 1] the bottom right
+    image_uv: vec2<f32>,
+    // sampled from the atlas
+    color: vec4<f32>,
+    tint: vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex: u32,
+    @location(0) position: vec2<f32>,
+    @location(1) size: vec2<f32>,
+    @location(2) pivot: vec2<f32>,
+    @location(3) rotation: f32,
+    @location(4) entry_index: u32,
+    @location(5) flags: u32,
+    @location(6) region_min: vec2<f32>,
+    @location(7) region_max: vec2<f32>,
+    @location(8) tint: vec4<f32>,
+    @location(9) depth: f32,
+) -> VertexOutput {
+    let entry = atlas_entries[entry_index];
+    let corner = sprite_corner(
+        vertex, position, size, pivot, rotation, flags,
+        region_min, region_max, entry.rotated != 0u, entry.uv_min, entry.uv_max,
+    );
+    var out: VertexOutput;
+    out.position = camera.clip_from_world * vec4(corner.position, 0.0, 1.0);
+    // the projection is orthographic, so w is 1
+    out.position.z = depth;
+    out.uv = corner.uv;
+    out.binding = entry.binding_index;
+    out.layer = entry.layer;
+    out.tint = tint;
+    out.image_uv = corner.image_uv;
+    return out;
+}
+
+fn material_color(in: VertexOutput) -> vec4<f32> {
+    // the derivatives are taken before branching on the binding
+    let sampled = sample_atlas(in.binding, in.layer, in.uv, dpdx(in.uv), dpdy(in.uv));
+    return sprite_material(SpriteFragment(in.position, in.uv, in.image_uv, sampled, in.tint));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = material_color(in);
+//if(STRAIGHT_ALPHA)
+    return color;
+//else
+    return vec4(color.rgb * color.a, color.a);
+//endif
+}
+
+// used for opaque sprites, which are drawn without blending so transparent texels are discarded
+@fragment
+fn fs_opaque(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = material_color(in);
+    if color.a < 0.5 {
+        discard;
+    }
+    return vec4(color.rgb, 1.0);
+}
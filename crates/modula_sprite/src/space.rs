@@ -0,0 +1,328 @@
+use bevy_ecs::system::{Res, ResMut, Resource};
+use modula_asset::Assets;
+use modula_core::{DeviceRes, QueueRes, WindowRes};
+use modula_render::{RenderTarget, SurfaceTargetRes};
+use wgpu::{BindGroup, Buffer};
+
+use crate::{CameraBindGroupLayout, CameraUniform, SpriteRect, Submission, SubmissionKind};
+
+/// The point of the surface that [screen space](CoordinateSpace::Screen) positions are relative to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Anchor {
+    TopLeft,
+    TopCenter,
+    TopRight,
+    CenterLeft,
+    #[default]
+    Center,
+    CenterRight,
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
+}
+
+impl Anchor {
+    /// The point as a fraction of the surface, where [0, 0] is the bottom left corner and [1, 1] the top right
+    pub fn fraction(self) -> [f32; 2] {
+        match self {
+            Self::TopLeft => [0.0, 1.0],
+            Self::TopCenter => [0.5, 1.0],
+            Self::TopRight => [1.0, 1.0],
+            Self::CenterLeft => [0.0, 0.5],
+            Self::Center => [0.5, 0.5],
+            Self::CenterRight => [1.0, 0.5],
+            Self::BottomLeft => [0.0, 0.0],
+            Self::BottomCenter => [0.5, 0.0],
+            Self::BottomRight => [1.0, 0.0],
+        }
+    }
+}
+
+/// What the positions and sizes of submitted sprites are in, see [SpriteQueue::set_coordinate_space](crate::SpriteQueue::set_coordinate_space)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CoordinateSpace {
+    /// World units, drawn with the camera of the [SpriteOperation](crate::SpriteOperation)
+    #[default]
+    World,
+    /// Logical pixels from anchor where y points up, multiplied by the scale factor of the window when batching.
+    /// Drawn with the bind group of the [ScreenSpace] instead of the camera, so the sprites keep their place and size when the camera moves or zooms
+    Screen { anchor: Anchor },
+}
+
+impl CoordinateSpace {
+    pub fn screen(anchor: Anchor) -> Self {
+        Self::Screen { anchor }
+    }
+
+    #[inline]
+    pub fn is_screen(self) -> bool {
+        matches!(self, Self::Screen { .. })
+    }
+}
+
+/// Resource with the size of the surface and the scale factor of the window that [screen space](CoordinateSpace::Screen) sprites are resolved against when batching,
+/// and the camera bind group they are drawn with, which shows the surface in physical pixels from its bottom left corner.
+/// Updated during [PreDraw](modula_render::PreDraw) in [SpriteBatchSet](crate::SpriteBatchSet) before batching, so screen space sprites are anchored to the current size of the window.
+/// The scale factor is 1 without a window
+#[derive(Resource)]
+pub struct ScreenSpace {
+    size: (u32, u32),
+    scale_factor: f32,
+    buffer: Option<Buffer>,
+    bind_group: Option<BindGroup>,
+}
+
+impl Default for ScreenSpace {
+    fn default() -> Self {
+        Self {
+            size: (0, 0),
+            scale_factor: 1.0,
+            buffer: None,
+            bind_group: None,
+        }
+    }
+}
+
+impl ScreenSpace {
+    /// The size of the surface in physical pixels when last updated
+    #[inline]
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    #[inline]
+    pub fn scale_factor(&self) -> f32 {
+        self.scale_factor
+    }
+
+    /// None before the first update
+    #[inline]
+    pub fn bind_group(&self) -> Option<&BindGroup> {
+        self.bind_group.as_ref()
+    }
+
+    /// A position in logical pixels from anchor as physical pixels from the bottom left corner of the surface
+    pub fn to_surface(&self, anchor: Anchor, position: [f32; 2]) -> [f32; 2] {
+        let size = [self.size.0 as f32, self.size.1 as f32];
+        let fraction = anchor.fraction();
+        [0, 1].map(|i| size[i] * fraction[i] + position[i] * self.scale_factor)
+    }
+
+    /// A clip in physical pixels of the surface as pixels of a target of target_size, which the surface is stretched over
+    pub(crate) fn clip_to_target(&self, clip: SpriteRect, target_size: (u32, u32)) -> SpriteRect {
+        let scale = [
+            target_size.0 as f32 / self.size.0.max(1) as f32,
+            target_size.1 as f32 / self.size.1.max(1) as f32,
+        ];
+        SpriteRect::new(
+            [0, 1].map(|i| clip.min[i] * scale[i]),
+            [0, 1].map(|i| clip.max[i] * scale[i]),
+        )
+    }
+
+    /// Moves a screen space submission to physical pixels of the surface, submissions in world space are left as they are
+    pub(crate) fn resolve(&self, submission: &mut Submission) {
+        let CoordinateSpace::Screen { anchor } = submission.space else {
+            return;
+        };
+        let scale = self.scale_factor;
+        match &mut submission.kind {
+            SubmissionKind::Entry { transform, .. } => {
+                transform.position = self.to_surface(anchor, transform.position);
+                transform.scale = transform.scale.map(|s| s * scale);
+            }
+            SubmissionKind::NineSlice {
+                rect, border_scale, ..
+            } => {
+                *rect = SpriteRect::new(
+                    self.to_surface(anchor, rect.min),
+                    self.to_surface(anchor, rect.max),
+                );
+                *border_scale = scale;
+            }
+            SubmissionKind::Raw { instance, .. } => {
+                instance.position = self.to_surface(anchor, instance.position);
+                instance.size = instance.size.map(|s| s * scale);
+            }
+        }
+        if let Some(clip) = &mut submission.clip {
+            *clip = SpriteRect::new(
+                self.to_surface(anchor, clip.min),
+                self.to_surface(anchor, clip.max),
+            );
+        }
+    }
+}
+
+pub(crate) fn update_screen_space(
+    mut screen: ResMut<ScreenSpace>,
+    render_targets: Res<Assets<RenderTarget>>,
+    surface_target: Res<SurfaceTargetRes>,
+    window: Option<Res<WindowRes>>,
+    layout: Res<CameraBindGroupLayout>,
+    device: Res<DeviceRes>,
+    queue: Res<QueueRes>,
+) {
+    screen.scale_factor = window.map_or(1.0, |window| window.0.scale_factor() as f32);
+    let Some(size) = render_targets.get(surface_target.0).map(RenderTarget::size) else {
+        return;
+    };
+    if size == screen.size && screen.buffer.is_some() {
+        return;
+    }
+    screen.size = size;
+    let (width, height) = (size.0.max(1) as f32, size.1.max(1) as f32);
+    let uniform = CameraUniform::orthographic([width / 2.0, height / 2.0], [width, height]);
+    match &screen.buffer {
+        Some(buffer) => queue.0.write_buffer(buffer, 0, &uniform.to_bytes()),
+        None => {
+            let buffer = uniform.create_buffer(&device.0);
+            screen.bind_group = Some(layout.create_bind_group(&device.0, &buffer));
+            screen.buffer = Some(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use modula_texture::atlas::AtlasGroupEntry;
+
+    use super::*;
+    use crate::{SpriteBlend, SpriteTransform};
+
+    /// A 200 by 100 surface of a window with a scale factor of 2
+    fn screen() -> ScreenSpace {
+        ScreenSpace {
+            size: (200, 100),
+            scale_factor: 2.0,
+            ..Default::default()
+        }
+    }
+
+    fn submission(space: CoordinateSpace, kind: SubmissionKind) -> Submission {
+        Submission {
+            layer: 0,
+            z: 0.0,
+            depth: 0.0,
+            atlas: Assets::new().add_empty(),
+            pipeline: Assets::new().add_empty(),
+            material: None,
+            blend: SpriteBlend::Alpha,
+            clip: Some(SpriteRect::new([-5.0, -5.0], [5.0, 5.0])),
+            space,
+            pick_id: None,
+            shadow: false,
+            outline: false,
+            kind,
+        }
+    }
+
+    fn entry(position: [f32; 2]) -> SubmissionKind {
+        SubmissionKind::Entry {
+            entry: AtlasGroupEntry::from_index(0),
+            transform: SpriteTransform::from_position(position),
+            tint: [1.0; 4],
+        }
+    }
+
+    #[test]
+    fn anchors_are_points_of_the_surface() {
+        let screen = screen();
+        assert_eq!(
+            screen.to_surface(Anchor::BottomLeft, [0.0, 0.0]),
+            [0.0, 0.0]
+        );
+        assert_eq!(screen.to_surface(Anchor::Center, [0.0, 0.0]), [100.0, 50.0]);
+        assert_eq!(
+            screen.to_surface(Anchor::TopRight, [0.0, 0.0]),
+            [200.0, 100.0]
+        );
+        // logical pixels are scaled, y points up
+        assert_eq!(
+            screen.to_surface(Anchor::TopLeft, [10.0, -5.0]),
+            [20.0, 90.0]
+        );
+    }
+
+    #[test]
+    fn positions_from_any_anchor_round_trip() {
+        let screen = screen();
+        let anchors = [
+            Anchor::TopLeft,
+            Anchor::TopCenter,
+            Anchor::TopRight,
+            Anchor::CenterLeft,
+            Anchor::Center,
+            Anchor::CenterRight,
+            Anchor::BottomLeft,
+            Anchor::BottomCenter,
+            Anchor::BottomRight,
+        ];
+        let physical = [37.0, 81.0];
+        for anchor in anchors {
+            // back to logical pixels from the anchor
+            let origin = screen.to_surface(anchor, [0.0, 0.0]);
+            let logical = [0, 1].map(|i| (physical[i] - origin[i]) / screen.scale_factor());
+            assert_eq!(screen.to_surface(anchor, logical), physical, "{anchor:?}");
+        }
+    }
+
+    #[test]
+    fn screen_submissions_are_resolved() {
+        let screen = screen();
+        let mut sprite = submission(
+            CoordinateSpace::screen(Anchor::TopRight),
+            entry([-10.0, -10.0]),
+        );
+        screen.resolve(&mut sprite);
+        let SubmissionKind::Entry { transform, .. } = &sprite.kind else {
+            unreachable!()
+        };
+        assert_eq!(transform.position, [180.0, 80.0]);
+        assert_eq!(transform.scale, [2.0, 2.0]);
+        assert_eq!(
+            sprite.clip,
+            Some(SpriteRect::new([190.0, 90.0], [210.0, 110.0]))
+        );
+
+        let mut panel = submission(
+            CoordinateSpace::screen(Anchor::BottomLeft),
+            SubmissionKind::NineSlice {
+                entry: AtlasGroupEntry::from_index(0),
+                rect: SpriteRect::new([5.0, 5.0], [20.0, 10.0]),
+                insets: None,
+                border_scale: 1.0,
+                tint: [1.0; 4],
+            },
+        );
+        screen.resolve(&mut panel);
+        let SubmissionKind::NineSlice {
+            rect, border_scale, ..
+        } = panel.kind
+        else {
+            unreachable!()
+        };
+        assert_eq!(rect, SpriteRect::new([10.0, 10.0], [40.0, 20.0]));
+        assert_eq!(border_scale, 2.0);
+
+        // world space is left as it is
+        let mut world = submission(CoordinateSpace::World, entry([-10.0, -10.0]));
+        screen.resolve(&mut world);
+        let SubmissionKind::Entry { transform, .. } = &world.kind else {
+            unreachable!()
+        };
+        assert_eq!(transform.position, [-10.0, -10.0]);
+        assert_eq!(world.clip, Some(SpriteRect::new([-5.0, -5.0], [5.0, 5.0])));
+    }
+
+    #[test]
+    fn clips_are_stretched_to_the_target() {
+        let screen = screen();
+        let clip = SpriteRect::new([20.0, 10.0], [100.0, 50.0]);
+        assert_eq!(screen.clip_to_target(clip, (200, 100)), clip);
+        assert_eq!(
+            screen.clip_to_target(clip, (100, 200)),
+            SpriteRect::new([10.0, 20.0], [50.0, 100.0])
+        );
+    }
+}
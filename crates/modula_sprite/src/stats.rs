@@ -0,0 +1,34 @@
+use bevy_ecs::system::{ResMut, Resource};
+
+/// Numbers of the last frame of the sprite renderer, counted while batching in [SpriteBatchSet](crate::SpriteBatchSet) and drawing in [SpriteOperations](crate::SpriteOperation).
+/// Updated at the start of [SpriteBatchSet](crate::SpriteBatchSet), so [Draw](modula_render::Draw) systems see the previous frame
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SpriteStats {
+    /// Sprites submitted to every [SpriteQueue](crate::SpriteQueue)
+    pub submitted: usize,
+    /// Sprites skipped as they were outside the camera of a queue with [culling](crate::SpriteQueue::set_culling)
+    pub culled: usize,
+    /// Sprites put into batches, a nine-slice counts as one sprite
+    pub drawn: usize,
+    /// [SpriteBatches](crate::SpriteBatch) made when batching
+    pub batches: usize,
+    /// Bytes of instances pushed to the [InstanceBufferPool](crate::InstanceBufferPool)
+    pub instance_bytes: u64,
+    /// Bytes of the instances of every [BakedSprites](crate::BakedSprites) asset, which stay in memory while they are not drawn
+    pub baked_bytes: u64,
+    /// Times a pipeline was set while drawing
+    pub pipeline_switches: usize,
+    /// Times a bind group was set while drawing, including the camera and queue bind groups set at the start of every operation
+    pub bind_group_switches: usize,
+}
+
+/// The numbers of the frame being counted, which become the [SpriteStats] at the start of the next frame
+#[derive(Resource, Default)]
+pub(crate) struct PendingSpriteStats(pub SpriteStats);
+
+pub(crate) fn publish_sprite_stats(
+    mut stats: ResMut<SpriteStats>,
+    mut pending: ResMut<PendingSpriteStats>,
+) {
+    *stats = std::mem::take(&mut pending.0);
+}
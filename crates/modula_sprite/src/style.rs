@@ -0,0 +1,177 @@
+use wgpu::{BlendComponent, BlendFactor, BlendOperation, BlendState, Color};
+
+use crate::{MaterialId, SpriteAlphaMode};
+
+/// How a sprite is blended with what is drawn before it, every mode uses its own pipeline so sprites with different modes are never batched together
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SpriteBlend {
+    #[default]
+    Alpha,
+    /// The color is added, for example for glows
+    Additive,
+    /// The color is multiplied with the target, for example for shading.
+    /// With [Straight](SpriteAlphaMode::Straight) alpha the alpha is ignored
+    Multiply,
+    /// The color replaces the target, texels with an alpha below 0.5 are discarded.
+    /// With a [depth sorted](crate::SpritePipelineBuilder::with_depth_sorting) pipeline depth is written,
+    /// and opaque sprites are drawn before the others in any order
+    Opaque,
+}
+
+impl SpriteBlend {
+    pub const ALL: [Self; 4] = [Self::Alpha, Self::Additive, Self::Multiply, Self::Opaque];
+
+    /// Index in [ALL](Self::ALL)
+    #[inline]
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The blend state for a shader outputting colors with alpha_mode
+    pub fn blend_state(self, alpha_mode: SpriteAlphaMode) -> BlendState {
+        let component = |src_factor, dst_factor| BlendComponent {
+            src_factor,
+            dst_factor,
+            operation: BlendOperation::Add,
+        };
+        // the alpha of the target is kept when adding or multiplying
+        let keep_alpha = component(BlendFactor::Zero, BlendFactor::One);
+        match (self, alpha_mode) {
+            (Self::Opaque, _) => BlendState::REPLACE,
+            (Self::Alpha, SpriteAlphaMode::Premultiplied) => {
+                BlendState::PREMULTIPLIED_ALPHA_BLENDING
+            }
+            (Self::Alpha, SpriteAlphaMode::Straight) => BlendState::ALPHA_BLENDING,
+            (Self::Additive, SpriteAlphaMode::Premultiplied) => BlendState {
+                color: component(BlendFactor::One, BlendFactor::One),
+                alpha: keep_alpha,
+            },
+            (Self::Additive, SpriteAlphaMode::Straight) => BlendState {
+                color: component(BlendFactor::SrcAlpha, BlendFactor::One),
+                alpha: keep_alpha,
+            },
+            // target * (color * alpha + 1 - alpha), so transparent parts leave the target as is
+            (Self::Multiply, SpriteAlphaMode::Premultiplied) => BlendState {
+                color: component(BlendFactor::Dst, BlendFactor::OneMinusSrcAlpha),
+                alpha: keep_alpha,
+            },
+            (Self::Multiply, SpriteAlphaMode::Straight) => BlendState {
+                color: component(BlendFactor::Dst, BlendFactor::Zero),
+                alpha: keep_alpha,
+            },
+        }
+    }
+}
+
+/// The color, blending and material of a submitted sprite, how it is found by the [SpritePicker](crate::SpritePicker), and the secondary passes it is drawn in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpriteStyle {
+    /// Multiplied with the sampled color by the default material, the alpha also fades the sprite
+    pub tint: Color,
+    pub blend: SpriteBlend,
+    /// Drawn with the pipeline of the queue if None
+    pub material: Option<MaterialId>,
+    /// Only sprites with an id are recorded for picking
+    pub pick_id: Option<u64>,
+    /// Casts the [shadow](crate::SpriteQueue::set_shadow) of the queue, drawn by a [SpriteOperation](crate::SpriteOperation) of the [Shadow](crate::SpritePass::Shadow) pass
+    pub shadow: bool,
+    /// Outlined by a [SpriteOutlineOperation](crate::SpriteOutlineOperation)
+    pub outline: bool,
+}
+
+impl Default for SpriteStyle {
+    fn default() -> Self {
+        Self {
+            tint: Color::WHITE,
+            blend: SpriteBlend::default(),
+            material: None,
+            pick_id: None,
+            shadow: false,
+            outline: false,
+        }
+    }
+}
+
+impl SpriteStyle {
+    pub fn tinted(tint: Color) -> Self {
+        Self {
+            tint,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn with_blend(mut self, blend: SpriteBlend) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn with_material(mut self, material: MaterialId) -> Self {
+        self.material = Some(material);
+        self
+    }
+
+    /// Makes the sprite pickable, found as [PickId::User](crate::PickId::User) with id
+    pub fn with_pick_id(mut self, id: u64) -> Self {
+        self.pick_id = Some(id);
+        self
+    }
+
+    /// Sets the alpha of the tint
+    pub fn with_opacity(mut self, opacity: f64) -> Self {
+        self.tint.a = opacity;
+        self
+    }
+
+    pub fn with_shadow(mut self) -> Self {
+        self.shadow = true;
+        self
+    }
+
+    pub fn with_outline(mut self) -> Self {
+        self.outline = true;
+        self
+    }
+}
+
+/// How the sprites of a [SpriteQueue](crate::SpriteQueue) that [cast shadows](SpriteStyle::with_shadow) are drawn again in the [Shadow](crate::SpritePass::Shadow) pass.
+/// Every instance of the sprite is moved and sized by scale around the position of the sprite, which is the pivot of entries and the bottom center of nine-slices, and then moved by offset.
+/// The shadow keeps the pipeline and material of the sprite with [Alpha](SpriteBlend::Alpha) blending, and the color replaces the tint, so with the default material a black shadow is the silhouette of the sprite
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpriteShadow {
+    /// In the coordinate space of the sprite, down and to the right by default
+    pub offset: [f32; 2],
+    /// 1 by default, a smaller y flattens the shadow into a blob at the feet of a sprite with its pivot at the bottom
+    pub scale: [f32; 2],
+    /// Black at half opacity by default, the alpha is multiplied with the alpha of the tint of the sprite
+    pub color: Color,
+}
+
+impl Default for SpriteShadow {
+    fn default() -> Self {
+        Self {
+            offset: [2.0, -2.0],
+            scale: [1.0; 2],
+            color: Color {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+                a: 0.5,
+            },
+        }
+    }
+}
+
+impl SpriteShadow {
+    pub fn new(offset: [f32; 2], scale: [f32; 2], color: Color) -> Self {
+        Self {
+            offset,
+            scale,
+            color,
+        }
+    }
+}
@@ -0,0 +1,467 @@
+use ab_glyph::{Font as _, FontArc, GlyphId, InvalidFont, PxScale, ScaleFont};
+use bevy_ecs::{
+    event::EventWriter,
+    schedule::SystemSet,
+    system::{Res, ResMut, SystemParam},
+};
+use modula_asset::{AssetId, Assets};
+use modula_core::{DeviceRes, QueueRes};
+use modula_texture::{
+    atlas::{
+        AtlasGroup, AtlasGroupBindGroupLayout, AtlasGroupChanged, AtlasGroupEntry, AtlasInsertError,
+    },
+    Image,
+};
+use modula_utils::{hashbrown::hash_map::Entry, HashMap};
+use wgpu::{Color, Device, Queue};
+
+use crate::{
+    clip::ClipStack, CoordinateSpace, SpriteBlend, SpritePipeline, SpriteQueue, SpriteRect,
+    SpriteTransform, Submission, SubmissionKind,
+};
+
+/// Text drawn with [TextQueues](TextQueue) is turned into sprites during [PreDraw](modula_render::PreDraw) in this set,
+/// after [AtlasLoadSet](modula_texture::atlas::AtlasLoadSet) and before [SpriteBatchSet](crate::SpriteBatchSet)
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TextSet;
+
+/// A TrueType or OpenType font, as an asset used by [TextQueues](TextQueue)
+#[derive(Clone)]
+pub struct Font {
+    font: FontArc,
+}
+
+impl Font {
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, InvalidFont> {
+        Ok(Self {
+            font: FontArc::try_from_vec(data)?,
+        })
+    }
+
+    /// Distance between the baselines of two lines at size, in pixels
+    pub fn line_height(&self, size: f32) -> f32 {
+        let scaled = self.font.as_scaled(PxScale::from(size));
+        scaled.height() + scaled.line_gap()
+    }
+
+    /// Width of the widest line and the height of all lines of text at size, in pixels
+    pub fn measure_text(&self, text: &str, size: f32) -> (f32, f32) {
+        let mut width: f32 = 0.0;
+        let mut lines = 0;
+        self.layout(
+            text,
+            size,
+            |_, _| {},
+            |line_width| {
+                width = width.max(line_width);
+                lines += 1;
+            },
+        );
+        let scaled = self.font.as_scaled(PxScale::from(size));
+        let height = scaled.height() + (lines - 1) as f32 * self.line_height(size);
+        (width, height)
+    }
+
+    /// Calls glyph with every glyph and the position of its origin on the baseline relative to the top left of the text, where y points down.
+    /// line is called with the width of every line
+    fn layout(
+        &self,
+        text: &str,
+        size: f32,
+        mut glyph: impl FnMut(GlyphId, [f32; 2]),
+        mut line: impl FnMut(f32),
+    ) {
+        let scaled = self.font.as_scaled(PxScale::from(size));
+        let line_height = self.line_height(size);
+        for (i, text_line) in text.split('\n').enumerate() {
+            let baseline = scaled.ascent() + i as f32 * line_height;
+            let mut caret = 0.0;
+            let mut previous = None;
+            for c in text_line.chars().filter(|c| !c.is_control()) {
+                let id = scaled.glyph_id(c);
+                if let Some(previous) = previous {
+                    caret += scaled.kern(previous, id);
+                }
+                glyph(id, [caret, baseline]);
+                caret += scaled.h_advance(id);
+                previous = Some(id);
+            }
+            line(caret);
+        }
+    }
+}
+
+struct TextSubmission {
+    text: String,
+    position: [f32; 2],
+    size: f32,
+    color: Color,
+    z: f32,
+    clip: Option<SpriteRect>,
+    space: CoordinateSpace,
+}
+
+struct CachedGlyph {
+    /// None for glyphs without pixels, such as spaces
+    entry: Option<AtlasGroupEntry>,
+    /// Of the top left pixel relative to the origin, where y points down
+    offset: [f32; 2],
+    last_used: u64,
+}
+
+/// Draws text with a [Font] by submitting a sprite for every glyph to a [SpriteQueue].
+/// Glyphs are rasterized at whole pixel sizes when first drawn and inserted into an [AtlasGroup] owned by the queue,
+/// the least recently used glyphs are removed when there are more than [max_glyphs](Self::with_max_glyphs).
+/// [AtlasGroupChanged] is sent for the atlas group in frames where glyphs are inserted.
+/// Only simple left to right layout with kerning is done, lines are split at '\n'
+pub struct TextQueue {
+    font: AssetId<Font>,
+    sprite_queue: AssetId<SpriteQueue>,
+    pipeline: AssetId<SpritePipeline>,
+    atlas: Option<AssetId<AtlasGroup>>,
+    layer: i32,
+    space: CoordinateSpace,
+    clips: ClipStack,
+    max_glyphs: usize,
+    glyphs: HashMap<(GlyphId, u32), CachedGlyph>,
+    frame: u64,
+    submissions: Vec<TextSubmission>,
+}
+
+impl TextQueue {
+    /// Glyphs are drawn to sprite_queue with pipeline, which must sample atlas groups like [SpritePipelineBuilder](crate::SpritePipelineBuilder)
+    pub fn new(
+        font: AssetId<Font>,
+        sprite_queue: AssetId<SpriteQueue>,
+        pipeline: AssetId<SpritePipeline>,
+    ) -> Self {
+        Self {
+            font,
+            sprite_queue,
+            pipeline,
+            atlas: None,
+            layer: 0,
+            space: CoordinateSpace::World,
+            clips: ClipStack::default(),
+            max_glyphs: 1024,
+            glyphs: HashMap::new(),
+            frame: 0,
+            submissions: Vec::new(),
+        }
+    }
+
+    /// The number of cached glyphs before the least recently used are removed, glyphs drawn in the current frame are never removed
+    pub fn with_max_glyphs(mut self, max_glyphs: usize) -> Self {
+        self.max_glyphs = max_glyphs;
+        self
+    }
+
+    #[inline]
+    pub fn font(&self) -> AssetId<Font> {
+        self.font
+    }
+
+    /// The atlas group of the glyphs, None before text is first drawn
+    #[inline]
+    pub fn atlas(&self) -> Option<AssetId<AtlasGroup>> {
+        self.atlas
+    }
+
+    /// Number of glyphs in the atlas
+    pub fn cached_glyphs(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    /// The [layer](SpriteQueue::set_layer) of the text drawn after this
+    pub fn set_layer(&mut self, layer: i32) {
+        self.layer = layer;
+    }
+
+    /// The [coordinate space](SpriteQueue::set_coordinate_space) of the text drawn after this.
+    /// In [screen space](CoordinateSpace::Screen) the size is in logical pixels, and the glyphs are scaled by the scale factor of the window
+    pub fn set_coordinate_space(&mut self, space: CoordinateSpace) {
+        self.space = space;
+    }
+
+    /// Clips the text drawn after this like [SpriteQueue::push_clip]
+    pub fn push_clip(&mut self, rect: SpriteRect) {
+        self.clips.push(rect);
+    }
+
+    pub fn pop_clip(&mut self) -> Option<SpriteRect> {
+        self.clips.pop()
+    }
+
+    /// Draws text with its top left corner at position, with size as the height of a line in pixels without the line gap.
+    /// The glyphs are sorted with the sprites of the [SpriteQueue] at z
+    pub fn draw_text(&mut self, text: &str, position: [f32; 2], size: f32, color: Color, z: f32) {
+        self.submissions.push(TextSubmission {
+            text: text.into(),
+            position,
+            size,
+            color,
+            z,
+            clip: self.clips.current(),
+            space: self.space,
+        });
+    }
+
+    /// Rasterizes the missing glyphs of the submissions and submits them to the sprite queue
+    fn submit(&mut self, resources: &mut TextResources) {
+        self.frame += 1;
+        let Some(font) = resources.fonts.get(self.font) else {
+            self.submissions.clear();
+            return;
+        };
+        let atlas_id = *self.atlas.get_or_insert_with(|| {
+            resources.atlas_groups.add(AtlasGroup::new(
+                Vec::new(),
+                Vec::new(),
+                &resources.device.0,
+                &resources.atlas_layout,
+            ))
+        });
+        let atlas = resources.atlas_groups.get_mut(atlas_id).unwrap();
+        let sprite_queue = resources
+            .sprite_queues
+            .get_mut(self.sprite_queue)
+            .expect("no sprite queue for text queue");
+        let mut inserted = false;
+        for submission in std::mem::take(&mut self.submissions) {
+            // rasterized at whole pixel sizes and scaled to the size
+            let pixel_size = submission.size.round().max(1.0) as u32;
+            let scale = submission.size / pixel_size as f32;
+            let tint = submission.color;
+            let tint = [tint.r, tint.g, tint.b, tint.a].map(|c| c as f32);
+            font.layout(
+                &submission.text,
+                pixel_size as f32,
+                |id, origin| {
+                    let glyph = match self.glyphs.entry((id, pixel_size)) {
+                        Entry::Occupied(cached) => cached.into_mut(),
+                        Entry::Vacant(vacant) => {
+                            // not cached if it fails, so it is tried again the next time it is drawn
+                            let Ok(glyph) = rasterize(
+                                font,
+                                id,
+                                pixel_size,
+                                atlas,
+                                &resources.device.0,
+                                &resources.queue.0,
+                                &resources.atlas_layout,
+                            ) else {
+                                return;
+                            };
+                            inserted |= glyph.entry.is_some();
+                            vacant.insert(glyph)
+                        }
+                    };
+                    glyph.last_used = self.frame;
+                    let Some(entry) = glyph.entry else {
+                        return;
+                    };
+                    let top_left = [
+                        (origin[0] + glyph.offset[0]) * scale,
+                        (origin[1] + glyph.offset[1]) * scale,
+                    ];
+                    sprite_queue.push_submission(Submission {
+                        layer: self.layer,
+                        z: submission.z,
+                        depth: 0.0,
+                        atlas: atlas_id,
+                        pipeline: self.pipeline,
+                        material: None,
+                        blend: SpriteBlend::Alpha,
+                        clip: submission.clip,
+                        space: submission.space,
+                        pick_id: None,
+                        shadow: false,
+                        outline: false,
+                        kind: SubmissionKind::Entry {
+                            entry,
+                            transform: SpriteTransform::from_position([
+                                submission.position[0] + top_left[0],
+                                submission.position[1] - top_left[1],
+                            ])
+                            .with_scale([scale, scale])
+                            .with_pivot([0.0, 1.0]),
+                            tint,
+                        },
+                    });
+                },
+                |_| {},
+            );
+        }
+        self.evict(atlas);
+        if inserted {
+            // the atlases, bind groups or entry buffer may have been replaced
+            resources
+                .changed
+                .send(AtlasGroupChanged { group: atlas_id });
+        }
+    }
+
+    /// Removes the least recently used glyphs not used this frame until there are at most max_glyphs
+    fn evict(&mut self, atlas: &mut AtlasGroup) {
+        if self.glyphs.len() <= self.max_glyphs {
+            return;
+        }
+        let mut unused: Vec<_> = self
+            .glyphs
+            .iter()
+            .filter(|(_, glyph)| glyph.last_used < self.frame)
+            .map(|(key, glyph)| (glyph.last_used, *key))
+            .collect();
+        unused.sort_unstable_by_key(|(last_used, _)| *last_used);
+        let excess = self.glyphs.len() - self.max_glyphs;
+        for (_, key) in unused.into_iter().take(excess) {
+            if let Some(entry) = self.glyphs.remove(&key).and_then(|glyph| glyph.entry) {
+                atlas.remove_entry(entry);
+            }
+        }
+    }
+}
+
+/// Rasterizes a glyph as white with the coverage as alpha, and inserts it into atlas.
+/// Glyphs without pixels are not inserted and have no entry
+fn rasterize(
+    font: &Font,
+    id: GlyphId,
+    pixel_size: u32,
+    atlas: &mut AtlasGroup,
+    device: &Device,
+    queue: &Queue,
+    layout: &AtlasGroupBindGroupLayout,
+) -> Result<CachedGlyph, AtlasInsertError> {
+    let empty = CachedGlyph {
+        entry: None,
+        offset: [0.0; 2],
+        last_used: 0,
+    };
+    let Some(outline) = font
+        .font
+        .outline_glyph(id.with_scale(PxScale::from(pixel_size as f32)))
+    else {
+        return Ok(empty);
+    };
+    let bounds = outline.px_bounds();
+    let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+    if width == 0 || height == 0 {
+        return Ok(empty);
+    }
+    let mut data = vec![255; (width * height * 4) as usize];
+    outline.draw(|x, y, coverage| {
+        data[((y * width + x) * 4 + 3) as usize] = (coverage.clamp(0.0, 1.0) * 255.0).round() as u8;
+    });
+    let image = Image::from_raw_rgba8(width, height, data).expect("the glyph data has its size");
+    let entry = atlas.insert(image, device, queue, layout)?;
+    Ok(CachedGlyph {
+        entry: Some(entry),
+        offset: [bounds.min.x, bounds.min.y],
+        last_used: 0,
+    })
+}
+
+#[derive(SystemParam)]
+pub(crate) struct TextResources<'w> {
+    fonts: Res<'w, Assets<Font>>,
+    atlas_groups: ResMut<'w, Assets<AtlasGroup>>,
+    sprite_queues: ResMut<'w, Assets<SpriteQueue>>,
+    atlas_layout: Res<'w, AtlasGroupBindGroupLayout>,
+    device: Res<'w, DeviceRes>,
+    queue: Res<'w, QueueRes>,
+    changed: EventWriter<'w, AtlasGroupChanged>,
+}
+
+pub(crate) fn submit_text(
+    mut text_queues: ResMut<Assets<TextQueue>>,
+    mut resources: TextResources,
+) {
+    for (_, text_queue) in text_queues.iter_mut() {
+        text_queue.submit(&mut resources);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font() -> Font {
+        Font::from_bytes(include_bytes!("../tests/fonts/Cantarell-Regular.ttf").to_vec()).unwrap()
+    }
+
+    /// The glyph positions and line widths of text
+    fn layout(font: &Font, text: &str, size: f32) -> (Vec<[f32; 2]>, Vec<f32>) {
+        let mut glyphs = Vec::new();
+        let mut lines = Vec::new();
+        font.layout(
+            text,
+            size,
+            |_, origin| glyphs.push(origin),
+            |width| lines.push(width),
+        );
+        (glyphs, lines)
+    }
+
+    #[test]
+    fn layout_advances_along_lines() {
+        let font = font();
+        let (glyphs, lines) = layout(&font, "ab\ncd\n", 20.0);
+        // the text ends with an empty line
+        assert_eq!(glyphs.len(), 4);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[2], 0.0);
+
+        let ascent = font.font.as_scaled(PxScale::from(20.0)).ascent();
+        let line_height = font.line_height(20.0);
+        assert_eq!(glyphs[0], [0.0, ascent]);
+        assert!(glyphs[1][0] > 0.0 && glyphs[1][0] < lines[0]);
+        assert_eq!(glyphs[1][1], ascent);
+        assert_eq!(glyphs[2], [0.0, ascent + line_height]);
+        assert_eq!(glyphs[3][1], ascent + line_height);
+    }
+
+    #[test]
+    fn layout_skips_control_characters() {
+        let font = font();
+        assert_eq!(layout(&font, "a\tb\r", 16.0), layout(&font, "ab", 16.0));
+    }
+
+    #[test]
+    fn measure_text_uses_the_widest_line() {
+        let font = font();
+        let height = font.font.as_scaled(PxScale::from(24.0)).height();
+        let line_height = font.line_height(24.0);
+        let (short, _) = font.measure_text("ab", 24.0);
+        let (long, single_height) = font.measure_text("abcd", 24.0);
+        assert!(long > short);
+        assert_eq!(single_height, height);
+
+        let (_, lines) = layout(&font, "ab\nabcd\nabc", 24.0);
+        assert_eq!(
+            font.measure_text("ab\nabcd\nabc", 24.0),
+            (lines[1], height + 2.0 * line_height)
+        );
+        assert_eq!(lines[1], long);
+    }
+
+    #[test]
+    fn measure_empty_text() {
+        let font = font();
+        let height = font.font.as_scaled(PxScale::from(12.0)).height();
+        assert_eq!(font.measure_text("", 12.0), (0.0, height));
+        assert_eq!(
+            font.measure_text("\n", 12.0),
+            (0.0, height + font.line_height(12.0))
+        );
+    }
+
+    #[test]
+    fn measure_text_scales_with_size() {
+        let font = font();
+        let (width, height) = font.measure_text("Hello\nworld", 10.0);
+        let (double_width, double_height) = font.measure_text("Hello\nworld", 20.0);
+        assert!((double_width - 2.0 * width).abs() < 1e-3);
+        assert!((double_height - 2.0 * height).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,185 @@
+//! Draws sprites on a headless device and reads the result back, for tests comparing the pixels to reference images
+// every test crate compiles this module, and not every one uses all of it
+#![allow(dead_code)]
+
+use bevy_ecs::world::World;
+use modula_asset::{init_assets, AssetId, AssetWorldExt};
+use modula_core::{
+    request_headless_device, DeviceRes, Init, PreInit, QueueRes, ScheduleBuilder, WorldExt,
+};
+use modula_render::{
+    shader::{init_shader_bundling, ShaderBundler},
+    Operation, PreDraw, RenderTarget, RenderTargetColorConfig, RenderTargetConfig,
+    SurfaceTargetRes,
+};
+use modula_sprite::{
+    init_sprites, Camera2D, CameraBindGroupLayout, CameraViewport, SpriteOperation,
+    SpritePipelineBuilder, SpriteQueue, SpriteStyle, SpriteTransform,
+};
+use modula_texture::{
+    atlas::{
+        init_atlas_loading, AtlasGroup, AtlasGroupBindGroupLayout, AtlasGroupBuilder,
+        AtlasGroupEntry, AtlasGroupQueue,
+    },
+    init_texture_loading, read_texture, AlphaMode, Image, PixelFormat,
+};
+use wgpu::{CommandEncoderDescriptor, TextureUsages};
+
+/// A world with the sprite systems, drawing one queue with a pixel perfect camera at the origin to an offscreen target
+pub struct Scene {
+    pub world: World,
+    pub queue: AssetId<SpriteQueue>,
+    pub camera: AssetId<Camera2D>,
+    target: AssetId<RenderTarget>,
+}
+
+impl Scene {
+    pub fn new(size: (u32, u32)) -> Self {
+        let (device, queue) =
+            request_headless_device().expect("no adapter found, not even the fallback adapter");
+        let mut schedule_builder = ScheduleBuilder::new();
+        init_assets::<RenderTarget>(&mut schedule_builder);
+        init_shader_bundling(&mut schedule_builder);
+        init_texture_loading(&mut schedule_builder);
+        init_atlas_loading(&mut schedule_builder);
+        init_sprites(&mut schedule_builder);
+        let mut world = schedule_builder.finish();
+        world.try_add_schedule(PreInit);
+        world.run_and_apply_deferred(PreInit);
+        world.insert_resource(DeviceRes(device));
+        world.insert_resource(QueueRes(queue));
+        world.run_and_apply_deferred(Init);
+
+        let mut render_target = RenderTarget::new(RenderTargetConfig {
+            size,
+            depth_stencil_config: None,
+            color_config: Some(RenderTargetColorConfig {
+                usages: TextureUsages::COPY_SRC,
+                ..Default::default()
+            }),
+            transient: false,
+        });
+        render_target.apply(&world.resource::<DeviceRes>().0);
+        let pipeline = SpritePipelineBuilder::new()
+            .build(
+                &world.resource::<DeviceRes>().0,
+                world.resource::<ShaderBundler>(),
+                world.resource::<AtlasGroupBindGroupLayout>(),
+                world.resource::<CameraBindGroupLayout>(),
+                &render_target,
+            )
+            .expect("the sprite shader should bundle");
+        let target = world.add_asset(render_target);
+        // cameras following the surface follow the target instead
+        world.insert_resource(SurfaceTargetRes(target));
+        let pipeline = world.add_asset(pipeline);
+        let camera = world.add_asset(Camera2D::new(CameraViewport::Target(target)));
+        let mut sprite_queue = SpriteQueue::new();
+        sprite_queue.set_pipeline(pipeline);
+        let queue = world.add_asset(sprite_queue);
+        Self {
+            world,
+            queue,
+            camera,
+            target,
+        }
+    }
+
+    /// Queues the group and makes it the atlas of the queue, it is built when [render](Self::render) is called
+    pub fn add_group(&mut self, builder: AtlasGroupBuilder) -> AssetId<AtlasGroup> {
+        let group = self.world.add_empty_asset();
+        self.world
+            .resource_mut::<AtlasGroupQueue>()
+            .init_group(group, builder);
+        self.world
+            .with_asset(self.queue, |queue: &mut SpriteQueue| queue.set_atlas(group));
+        group
+    }
+
+    /// Submits the entry to the queue, see [SpriteQueue::draw]
+    pub fn draw(&mut self, entry: AtlasGroupEntry, transform: SpriteTransform, style: SpriteStyle) {
+        self.world
+            .with_asset(self.queue, |queue: &mut SpriteQueue| {
+                queue.draw(entry, transform, style, 0.0)
+            });
+    }
+
+    /// Runs [PreDraw] and draws the queue, returning the RGBA8 sRGB pixels of the target row by row
+    pub fn render(&mut self) -> Vec<u8> {
+        self.world.run_and_apply_deferred(PreDraw);
+        self.world
+            .with_asset(self.target, RenderTarget::schedule_clear_color);
+        let device = &self.world.resource::<DeviceRes>().0;
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor { label: None });
+        SpriteOperation::new(self.target, self.queue)
+            .with_camera(self.camera)
+            .run(&mut self.world, &mut encoder);
+        let queue = &self.world.resource::<QueueRes>().0;
+        queue.submit([encoder.finish()]);
+        let target = self.world.get_asset(self.target).unwrap();
+        read_texture(
+            &self.world.resource::<DeviceRes>().0,
+            queue,
+            target.texture().unwrap(),
+            0,
+        )
+    }
+}
+
+/// An opaque image where every pixel is different, so wrong rotations and flips can not match
+pub fn pattern(width: u32, height: u32) -> Image {
+    Image {
+        data: (0..width * height)
+            .flat_map(|i| {
+                [
+                    (i * 40 % 256) as u8,
+                    (255 - i * 12 % 256) as u8,
+                    (i * 7) as u8,
+                    255,
+                ]
+            })
+            .collect(),
+        width,
+        height,
+        format: PixelFormat::Rgba8,
+        color_space: None,
+        alpha_mode: AlphaMode::Straight,
+    }
+}
+
+/// The pixels of a width by height region of an RGBA8 image row by row, with the top left at (x, y)
+pub fn crop(
+    pixels: &[u8],
+    image_width: u32,
+    (x, y): (u32, u32),
+    (width, height): (u32, u32),
+) -> Vec<u8> {
+    (y..y + height)
+        .flat_map(|row| {
+            let start = ((row * image_width + x) * 4) as usize;
+            &pixels[start..start + width as usize * 4]
+        })
+        .copied()
+        .collect()
+}
+
+/// Panics if any channel differs by more than tolerance, naming the first pixel that does
+pub fn assert_similar(actual: &[u8], expected: &[u8], width: u32, tolerance: u8) {
+    assert_eq!(
+        actual.len(),
+        expected.len(),
+        "the images have different sizes"
+    );
+    let differing = actual
+        .chunks(4)
+        .zip(expected.chunks(4))
+        .position(|(a, e)| a.iter().zip(e).any(|(a, e)| a.abs_diff(*e) > tolerance));
+    if let Some(i) = differing {
+        let (x, y) = (i as u32 % width, i as u32 / width);
+        panic!(
+            "pixel ({x}, {y}) is {:?}, expected {:?}",
+            &actual[i * 4..i * 4 + 4],
+            &expected[i * 4..i * 4 + 4]
+        );
+    }
+}
@@ -0,0 +1,75 @@
+//! Sprites of a queue with culling are skipped when their bounds are outside the view of the camera
+
+mod common;
+
+use common::{pattern, Scene};
+use modula_asset::AssetWorldExt;
+use modula_core::WorldExt;
+use modula_render::PreDraw;
+use modula_sprite::{SpriteCulling, SpriteQueue, SpriteStats, SpriteStyle, SpriteTransform};
+use modula_texture::atlas::AtlasGroupBuilder;
+
+/// Draws a 4 by 4 sprite at each position with a 16 by 16 view around the origin, returning the stats of the frame
+fn cull(culling: impl Fn(&Scene) -> SpriteCulling, positions: &[[f32; 2]]) -> SpriteStats {
+    let mut scene = Scene::new((16, 16));
+    let culling = culling(&scene);
+    scene
+        .world
+        .with_asset(scene.queue, |queue: &mut SpriteQueue| {
+            queue.set_culling(Some(culling))
+        });
+    let mut builder = AtlasGroupBuilder::new(1);
+    let entry = builder.add_image(pattern(4, 4));
+    scene.add_group(builder);
+    // the camera is updated and the atlas built by the first frame
+    scene.world.run_and_apply_deferred(PreDraw);
+    for &position in positions {
+        scene.draw(
+            entry,
+            SpriteTransform::from_position(position),
+            SpriteStyle::default(),
+        );
+    }
+    scene.world.run_and_apply_deferred(PreDraw);
+    // the stats of a frame are published when the next one is batched
+    scene.world.run_and_apply_deferred(PreDraw);
+    *scene.world.resource::<SpriteStats>()
+}
+
+#[test]
+fn sprites_inside_and_straddling_the_view_are_kept() {
+    let stats = cull(
+        |scene| SpriteCulling::new(scene.camera),
+        &[[0.0, 0.0], [-7.0, 6.0], [9.0, 0.0], [0.0, -9.5]],
+    );
+    assert_eq!(stats.submitted, 4);
+    assert_eq!(stats.culled, 0);
+    assert_eq!(stats.drawn, 4);
+}
+
+#[test]
+fn sprites_outside_the_view_are_culled() {
+    let stats = cull(
+        |scene| SpriteCulling::new(scene.camera),
+        &[
+            [0.0, 0.0],
+            [10.5, 0.0],
+            [-40.0, 0.0],
+            [0.0, 100.0],
+            [12.0, 12.0],
+        ],
+    );
+    assert_eq!(stats.submitted, 5);
+    assert_eq!(stats.culled, 4);
+    assert_eq!(stats.drawn, 1);
+}
+
+#[test]
+fn the_margin_keeps_sprites_near_the_view() {
+    let stats = cull(
+        |scene| SpriteCulling::new(scene.camera).with_margin(4.0),
+        &[[12.0, 0.0], [0.0, -13.0], [20.0, 0.0]],
+    );
+    assert_eq!(stats.culled, 1);
+    assert_eq!(stats.drawn, 2);
+}
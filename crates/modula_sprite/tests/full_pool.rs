@@ -0,0 +1,58 @@
+//! Sprites refused by a full instance buffer pool are not drawn, counted or picked
+
+mod common;
+
+use common::{pattern, Scene};
+use modula_core::WorldExt;
+use modula_render::PreDraw;
+use modula_sprite::{
+    InstanceBufferPool, InstanceBufferPoolConfig, SpritePicker, SpriteStats, SpriteStyle,
+    SpriteTransform,
+};
+use modula_texture::atlas::AtlasGroupBuilder;
+
+/// Batches two pickable sprites with a pool of max_size bytes, returning the stats and the number of picked sprites
+fn batch(max_size: u64) -> (SpriteStats, usize) {
+    let mut scene = Scene::new((16, 16));
+    scene
+        .world
+        .insert_resource(InstanceBufferPool::new(InstanceBufferPoolConfig {
+            min_size: 4,
+            max_size,
+            ..Default::default()
+        }));
+    let mut builder = AtlasGroupBuilder::new(1);
+    let entry = builder.add_image(pattern(4, 4));
+    scene.add_group(builder);
+    for id in 0..2 {
+        scene.draw(
+            entry,
+            SpriteTransform::default(),
+            SpriteStyle::default().with_pick_id(id),
+        );
+    }
+    scene.world.run_and_apply_deferred(PreDraw);
+    let picked = scene.world.resource::<SpritePicker>().recorded();
+    // the stats of a frame are published when the next one is batched
+    scene.world.run_and_apply_deferred(PreDraw);
+    (*scene.world.resource::<SpriteStats>(), picked)
+}
+
+#[test]
+fn accepted_sprites_are_counted() {
+    let (stats, picked) = batch(InstanceBufferPoolConfig::default().max_size);
+    assert_eq!(stats.submitted, 2);
+    assert_eq!(stats.drawn, 2);
+    assert!(stats.instance_bytes > 0);
+    assert_eq!(picked, 2);
+}
+
+#[test]
+fn refused_sprites_are_not_counted() {
+    let (stats, picked) = batch(4);
+    assert_eq!(stats.submitted, 2);
+    assert_eq!(stats.drawn, 0);
+    assert_eq!(stats.batches, 0);
+    assert_eq!(stats.instance_bytes, 0);
+    assert_eq!(picked, 0);
+}
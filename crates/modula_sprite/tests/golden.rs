@@ -0,0 +1,80 @@
+//! Renders a known sprite layout and compares it to a reference image, covering batching, the camera and atlas UVs.
+//! Run with MODULA_UPDATE_REFERENCES set to write the reference instead, after checking the new output is right
+
+mod common;
+
+use std::f32::consts::FRAC_PI_2;
+
+use common::{assert_similar, pattern, Scene};
+use modula_asset::AssetWorldExt;
+use modula_sprite::{Camera2D, SpriteBlend, SpriteStyle, SpriteTransform};
+use modula_texture::atlas::AtlasGroupBuilder;
+use wgpu::Color;
+
+const SIZE: (u32, u32) = (32, 32);
+const REFERENCE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/reference/sprites.png");
+
+/// Sprites are placed on whole pixels with even sizes, so no pixel center is on an edge
+fn render_layout(scene: &mut Scene) -> Vec<u8> {
+    let mut builder = AtlasGroupBuilder::new(1);
+    let wide = builder.add_image(pattern(6, 4));
+    let square = builder.add_image(pattern(4, 4));
+    let tall = builder.add_image(pattern(2, 6));
+    scene.add_group(builder);
+    scene
+        .world
+        .with_asset(scene.camera, |camera: &mut Camera2D| {
+            camera.position = [2.0, -1.0];
+        });
+
+    scene.draw(
+        wide,
+        SpriteTransform::from_position([-6.0, 8.0]),
+        SpriteStyle::default(),
+    );
+    scene.draw(
+        square,
+        SpriteTransform::from_position([8.0, 6.0]).with_scale([2.0, 2.0]),
+        SpriteStyle::tinted(Color {
+            r: 1.0,
+            g: 0.5,
+            b: 0.25,
+            a: 1.0,
+        }),
+    );
+    scene.draw(
+        tall,
+        SpriteTransform::from_position([-8.0, -6.0])
+            .with_rotation(FRAC_PI_2)
+            .with_flip(true, false),
+        SpriteStyle::default(),
+    );
+    // overlaps the first sprite, blended over it
+    scene.draw(
+        square,
+        SpriteTransform::from_position([-4.0, 6.0]),
+        SpriteStyle::default().with_opacity(0.5),
+    );
+    scene.draw(
+        wide,
+        SpriteTransform::from_position([6.0, -8.0]).with_pivot([0.0, 0.0]),
+        SpriteStyle::default().with_blend(SpriteBlend::Additive),
+    );
+    scene.render()
+}
+
+#[test]
+fn sprites_match_reference() {
+    let mut scene = Scene::new(SIZE);
+    let pixels = render_layout(&mut scene);
+    if std::env::var_os("MODULA_UPDATE_REFERENCES").is_some() {
+        image::save_buffer(REFERENCE, &pixels, SIZE.0, SIZE.1, image::ColorType::Rgba8)
+            .expect("the reference could not be written");
+        return;
+    }
+    let reference = image::open(REFERENCE)
+        .expect("the reference could not be read")
+        .into_rgba8();
+    assert_eq!(reference.dimensions(), SIZE);
+    assert_similar(&pixels, reference.as_raw(), SIZE.0, 2);
+}
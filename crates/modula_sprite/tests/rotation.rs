@@ -0,0 +1,64 @@
+//! Sprites rotated or flipped in the shader are compared to the same images rotated or flipped on the CPU
+
+mod common;
+
+use std::f32::consts::FRAC_PI_2;
+
+use common::{assert_similar, crop, pattern, Scene};
+use modula_sprite::{SpriteStyle, SpriteTransform};
+use modula_texture::{atlas::AtlasGroupBuilder, Image};
+
+const SIZE: (u32, u32) = (16, 16);
+
+/// Draws the image at the center of the target with the transform
+fn render(image: Image, transform: SpriteTransform) -> Vec<u8> {
+    let mut scene = Scene::new(SIZE);
+    let mut builder = AtlasGroupBuilder::new(1);
+    let entry = builder.add_image(image);
+    scene.add_group(builder);
+    scene.draw(entry, transform, SpriteStyle::default());
+    scene.render()
+}
+
+/// The image with its pixels moved by f, which maps a pixel of the result to a pixel of the image
+fn remap(image: &Image, width: u32, height: u32, f: impl Fn(u32, u32) -> (u32, u32)) -> Image {
+    let data = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .flat_map(|(x, y)| {
+            let (from_x, from_y) = f(x, y);
+            let start = ((from_y * image.width + from_x) * 4) as usize;
+            image.data[start..start + 4].to_vec()
+        })
+        .collect();
+    Image {
+        data,
+        width,
+        height,
+        ..image.clone()
+    }
+}
+
+/// The pixels of a centered sprite of the given size
+fn sprite_pixels(pixels: &[u8], (width, height): (u32, u32)) -> Vec<u8> {
+    let origin = ((SIZE.0 - width) / 2, (SIZE.1 - height) / 2);
+    crop(pixels, SIZE.0, origin, (width, height))
+}
+
+#[test]
+fn rotated_sprite_matches_rotated_image() {
+    let image = pattern(6, 4);
+    // rotating counter clockwise moves the top row of the image to the left column, from the bottom up
+    let rotated = remap(&image, 4, 6, |x, y| (5 - y, x));
+    let drawn = render(image, SpriteTransform::default().with_rotation(FRAC_PI_2));
+    let reference = render(rotated.clone(), SpriteTransform::default());
+    assert_similar(&drawn, &reference, SIZE.0, 1);
+    assert_similar(&sprite_pixels(&drawn, (4, 6)), &rotated.data, 4, 1);
+}
+
+#[test]
+fn flipped_sprite_matches_flipped_image() {
+    let image = pattern(6, 4);
+    let flipped = remap(&image, 6, 4, |x, y| (5 - x, 3 - y));
+    let drawn = render(image, SpriteTransform::default().with_flip(true, true));
+    assert_similar(&sprite_pixels(&drawn, (6, 4)), &flipped.data, 6, 1);
+}
@@ -0,0 +1,43 @@
+//! Compares the atlas layouters on 5000 mixed size rects, run with `cargo bench -p modula_texture`
+
+use std::time::{Duration, Instant};
+
+use modula_texture::atlas::{
+    AtlasLayouter, AtlasLayouterOutput, DefaultLayouter, MaxAtlasSize, ShelfLayouter,
+};
+
+const RECTS: usize = 5000;
+
+fn main() {
+    // a fixed LCG, so every run packs the same rects
+    let mut state = 0x2545f491u32;
+    let mut next = |max: u32| {
+        state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+        (state >> 16) % max + 4
+    };
+    let sizes: Vec<_> = (0..RECTS).map(|_| (next(60), next(60))).collect();
+
+    let (time, output) = measure::<DefaultLayouter>(sizes.clone());
+    report("DefaultLayouter", time, &output);
+    let (time, output) = measure::<ShelfLayouter>(sizes);
+    report("ShelfLayouter", time, &output);
+}
+
+fn measure<L: AtlasLayouter>(sizes: Vec<(u32, u32)>) -> (Duration, AtlasLayouterOutput) {
+    let start = Instant::now();
+    let output =
+        L::layout(sizes, MaxAtlasSize::new(8192, 256)).expect("rects fit in the max atlas size");
+    (start.elapsed(), output)
+}
+
+fn report(name: &str, time: Duration, output: &AtlasLayouterOutput) {
+    let area: u64 = output
+        .atlases
+        .iter()
+        .map(|((width, height, layers), _)| *width as u64 * *height as u64 * *layers as u64)
+        .sum();
+    println!(
+        "{name}: {time:?}, {} atlases with {area} pixels",
+        output.atlases.len()
+    );
+}
@@ -0,0 +1,234 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+};
+
+use bevy_ecs::{prelude::*, system::SystemParam};
+use modula_asset::{AssetId, Assets};
+use wgpu::Texture;
+
+use crate::{
+    ColorSpace, DefaultTextures, Image, ImageLoadError, MipGeneration, MipMapImage,
+    TextureLoadOptions, TextureQueue,
+};
+
+/// Max number of threads decoding images, fewer are used if the system has fewer cores
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_DECODE_THREADS: usize = 4;
+
+/// Sent during [PreDraw](modula_render::PreDraw) when a file loaded with [AsyncTextureLoader] could not be read or decoded.
+/// The asset stays empty unless [error texture fallback](AsyncTextureQueue::set_error_fallback) is enabled, events can be read until the end of the next frame
+#[derive(Event, Debug)]
+pub struct TextureLoadFailed {
+    pub asset_id: AssetId<Texture>,
+    pub path: PathBuf,
+    pub error: ImageLoadError,
+}
+
+struct LoadJob {
+    asset_id: AssetId<Texture>,
+    path: PathBuf,
+    color_space: ColorSpace,
+    mip_generation: MipGeneration,
+}
+
+type LoadResult = (LoadJob, Result<MipMapImage, ImageLoadError>);
+
+/// Decodes image files on background threads, the decoded images are put in [TextureQueue] during [TextureLoadSet](crate::TextureLoadSet).
+/// If the goal is to just load a texture consider [AsyncTextureLoader]
+#[derive(Resource)]
+pub struct AsyncTextureQueue {
+    /// None until the first load, so the threads are only started if needed
+    #[cfg(not(target_arch = "wasm32"))]
+    jobs: Option<Sender<LoadJob>>,
+    done_sender: Sender<LoadResult>,
+    done: Mutex<Receiver<LoadResult>>,
+    pending: usize,
+    error_fallback: bool,
+}
+
+impl AsyncTextureQueue {
+    pub(crate) fn new() -> Self {
+        let (done_sender, done) = mpsc::channel();
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            jobs: None,
+            done_sender,
+            done: Mutex::new(done),
+            pending: 0,
+            error_fallback: false,
+        }
+    }
+
+    /// Reads and decodes the file in the background, and loads it to the given asset like [load_texture_with_mips](crate::TextureLoader::load_texture_with_mips).
+    /// The texture is labeled with the file name, the current texture is destroyed if it already exists
+    pub fn load(
+        &mut self,
+        asset_id: AssetId<Texture>,
+        path: impl AsRef<Path>,
+        color_space: ColorSpace,
+        mip_generation: MipGeneration,
+    ) {
+        let job = LoadJob {
+            asset_id,
+            path: path.as_ref().to_path_buf(),
+            color_space,
+            mip_generation,
+        };
+        self.pending += 1;
+        self.spawn(job);
+    }
+
+    /// Number of files that are loaded but not yet put in [TextureQueue]
+    #[inline]
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// If enabled, files that fail to load are replaced by [DefaultTextures::error_image], so the asset is not left empty.
+    /// Disabled by default
+    pub fn set_error_fallback(&mut self, enabled: bool) {
+        self.error_fallback = enabled;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn spawn(&mut self, job: LoadJob) {
+        let done_sender = &self.done_sender;
+        let jobs = self.jobs.get_or_insert_with(|| start_threads(done_sender));
+        jobs.send(job).expect("decode threads do not stop");
+    }
+
+    /// There are no threads on the web, so the file is decoded immediately
+    #[cfg(all(target_arch = "wasm32", not(feature = "web-decode")))]
+    fn spawn(&mut self, job: LoadJob) {
+        let result = decode(&job.path);
+        // the receiver is owned by self, so this can not fail
+        let _ = self.done_sender.send((job, result));
+    }
+
+    /// The file is fetched and decoded by the browser without blocking
+    #[cfg(all(target_arch = "wasm32", feature = "web-decode"))]
+    fn spawn(&mut self, job: LoadJob) {
+        let done_sender = self.done_sender.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let result = match crate::web_decode::fetch(&job.path).await {
+                Ok(data) => Image::decode_web(&data).await.map(MipMapImage::from),
+                Err(error) => Err(error),
+            };
+            let _ = done_sender.send((job, result));
+        });
+    }
+}
+
+/// Starts the decode threads, they stop when the returned sender is dropped
+#[cfg(not(target_arch = "wasm32"))]
+fn start_threads(done_sender: &Sender<LoadResult>) -> Sender<LoadJob> {
+    use std::sync::Arc;
+
+    let (jobs, receiver) = mpsc::channel::<LoadJob>();
+    let receiver = Arc::new(Mutex::new(receiver));
+    let count = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(MAX_DECODE_THREADS);
+    for i in 0..count {
+        let receiver = receiver.clone();
+        let done_sender = done_sender.clone();
+        std::thread::Builder::new()
+            .name(format!("texture decode {i}"))
+            .spawn(move || loop {
+                // the lock is released before decoding, so the other threads can take jobs
+                let job = match receiver.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    Err(_) => return,
+                };
+                let result = decode(&job.path);
+                if done_sender.send((job, result)).is_err() {
+                    return;
+                }
+            })
+            .expect("failed to start texture decode thread");
+    }
+    jobs
+}
+
+#[cfg(not(all(target_arch = "wasm32", feature = "web-decode")))]
+fn decode(path: &Path) -> Result<MipMapImage, ImageLoadError> {
+    Image::load_from_path(path).map(MipMapImage::from)
+}
+
+#[derive(SystemParam)]
+pub struct AsyncTextureLoader<'w> {
+    async_queue: ResMut<'w, AsyncTextureQueue>,
+    texture_assets: ResMut<'w, Assets<Texture>>,
+}
+
+impl AsyncTextureLoader<'_> {
+    /// Loads a texture from a file without blocking, like [load_texture](crate::TextureLoader::load_texture).
+    /// The asset is empty until the file is decoded, if that fails [TextureLoadFailed] is sent
+    #[inline]
+    pub fn load_from_path(&mut self, path: impl AsRef<Path>) -> AssetId<Texture> {
+        self.load_from_path_with_mips(path, ColorSpace::Srgb, MipGeneration::default())
+    }
+
+    /// Same as [load_from_path](Self::load_from_path), but with control over the color space and how mip levels are generated
+    pub fn load_from_path_with_mips(
+        &mut self,
+        path: impl AsRef<Path>,
+        color_space: ColorSpace,
+        mip_generation: MipGeneration,
+    ) -> AssetId<Texture> {
+        let asset_id = self.texture_assets.add_empty();
+        self.async_queue
+            .load(asset_id, path, color_space, mip_generation);
+        asset_id
+    }
+}
+
+/// Moves decoded images to [TextureQueue], runs before the queue is drained
+pub(crate) fn receive_decoded_textures(
+    mut async_queue: ResMut<AsyncTextureQueue>,
+    mut texture_queue: ResMut<TextureQueue>,
+    mut load_failed: EventWriter<TextureLoadFailed>,
+) {
+    let async_queue = &mut *async_queue;
+    for (job, result) in async_queue.done.get_mut().unwrap().try_iter() {
+        async_queue.pending -= 1;
+        // textures are labeled with their file name, so they can be found in debugging tools
+        let label = job
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        match result {
+            Ok(image) => {
+                let options = TextureLoadOptions {
+                    format: Some(image.levels()[0].format.texture_format(job.color_space)),
+                    mip_generation: job.mip_generation,
+                    label,
+                    ..Default::default()
+                };
+                texture_queue.load(job.asset_id, image, options);
+            }
+            Err(error) => {
+                if async_queue.error_fallback {
+                    let options = TextureLoadOptions {
+                        label,
+                        ..Default::default()
+                    };
+                    texture_queue.load(
+                        job.asset_id,
+                        DefaultTextures::error_image().into(),
+                        options,
+                    );
+                }
+                load_failed.send(TextureLoadFailed {
+                    asset_id: job.asset_id,
+                    path: job.path,
+                    error,
+                });
+            }
+        }
+    }
+}
@@ -1,28 +1,113 @@
 use core::fmt::Debug;
-use std::{cmp::min, usize};
+use std::{
+    cmp::min,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    mem,
+};
 
-use bevy_ecs::system::{Res, ResMut, Resource};
+use bevy_ecs::{prelude::*, system::SystemParam};
 use modula_asset::{AssetId, Assets};
-use modula_core::{DeviceRes, QueueRes, ScheduleBuilder};
+use modula_core::{DeviceRes, Init, PreInit, QueueRes, ScheduleBuilder};
 use modula_render::PreDraw;
+use modula_utils::{HashMap, HashSet};
 use wgpu::{
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, Device, Extent3d, Origin3d, Queue, ShaderStages, Texture, TextureAspect,
-    TextureDescriptor, TextureFormat, TextureUsages, TextureViewDescriptor,
+    BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType, BufferDescriptor, BufferSize,
+    BufferUsages, Device, Extent3d, Origin3d, Queue, Sampler, SamplerBindingType,
+    SamplerDescriptor, ShaderStages, Texture, TextureAspect, TextureDescriptor, TextureFormat,
+    TextureUsages, TextureViewDescriptor, TextureViewDimension,
+};
+
+use crate::{
+    check_writable, mipmap, AlphaMode, MipFilter, MipGeneration, MipMapImage, SamplerConfig,
+    TextureLoadSet, TextureMemoryStats, TrimmedImage, DEFAULT_TEXTURE_FORMAT,
 };
 
-use crate::MipMapImage;
+/// The label of atlas textures, also used in [TextureMemoryStats]
+const ATLAS_TEXTURE_LABEL: &str = "Atlas Texture";
+
+/// The default of [AtlasGroupBuilder::set_padding] and [AtlasGroupBuilder::set_extrude]
+const DEFAULT_PADDING: u32 = 1;
+
+/// Usages of atlases added by [AtlasGroup::insert] to a group without atlases
+const DEFAULT_ATLAS_USAGES: TextureUsages =
+    TextureUsages::TEXTURE_BINDING.union(TextureUsages::COPY_DST);
+
+/// Smallest width and height of atlases added by [AtlasGroup::insert]
+const MIN_INSERTED_ATLAS_SIZE: u32 = 256;
+
+/// Number of atlases in a bind group when [MaxBoundAtlases] is not inserted, the default limit of wgpu
+const DEFAULT_MAX_BOUND_ATLASES: u32 = 16;
 
+/// Size of an [EntryUv] in the [entry buffer](AtlasGroup::entry_buffer)
+const ENTRY_BUFFER_STRIDE: u64 = 32;
+
+mod debug;
 mod default_layouter;
+mod defragment;
+mod loader;
+mod meta;
+mod paths;
 mod render;
+#[cfg(feature = "serde")]
+mod saved;
+mod shelf;
+mod shelf_layouter;
 
 pub use default_layouter::*;
+pub use loader::*;
+use meta::EntryMeta;
+pub use render::*;
+#[cfg(feature = "serde")]
+pub use saved::*;
+use shelf::ShelfAllocator;
+pub use shelf_layouter::*;
+
+type LayoutFn<E> = fn(Vec<(u32, u32)>, MaxAtlasSize) -> Result<AtlasLayouterOutput, E>;
 
-/// Inits atlas loading using a custom atlas loader, for most cases you can just use [init_atlas_loading]
+/// Systems that build and change groups queued in [AtlasGroupQueue] during [PreDraw], anything that runs in [PreDraw] and needs the groups should run after this
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AtlasLoadSet;
+
+/// Inits atlas loading using a custom atlas loader, for most cases you can just use [init_atlas_loading].  
+/// Groups queued in [AtlasGroupQueue] are built during [PreDraw] in [AtlasLoadSet], after [TextureLoadSet]
 pub fn init_custom_atlas_loading<L: AtlasLayouter + 'static>(
     schedule_builder: &mut ScheduleBuilder,
 ) {
-    schedule_builder.add_systems(PreDraw, handle_atlas_group_queue::<L>)
+    modula_asset::init_assets::<AtlasGroup>(schedule_builder);
+    schedule_builder.add_systems(PreInit, |mut commands: Commands| {
+        commands.insert_resource(AtlasGroupQueue::new());
+        commands.insert_resource(Events::<AtlasGroupChanged>::default());
+        commands.insert_resource(Events::<AtlasBuildFailed>::default());
+    });
+    schedule_builder.add_systems(
+        Init,
+        |mut commands: Commands,
+         device: Res<DeviceRes>,
+         max_bound: Option<Res<MaxBoundAtlases>>| {
+            let max_atlases = max_bound.map_or(DEFAULT_MAX_BOUND_ATLASES, |max| max.0);
+            commands.insert_resource(AtlasGroupBindGroupLayout::with_max_atlases(
+                &device.0,
+                max_atlases,
+            ));
+        },
+    );
+    schedule_builder.add_systems(
+        PreDraw,
+        (
+            |mut changed: ResMut<Events<AtlasGroupChanged>>,
+             mut failed: ResMut<Events<AtlasBuildFailed>>| {
+                changed.update();
+                failed.update();
+            },
+            release_old_bind_groups,
+            handle_atlas_group_queue::<L>,
+        )
+            .chain()
+            .in_set(AtlasLoadSet)
+            .after(TextureLoadSet),
+    )
 }
 
 /// Inits atlas loading using [DefaultLayouter], use [init_custom_atlas_loading] to use a different [AtlasLayouter]
@@ -52,12 +137,112 @@ impl Atlas {
     pub fn layout(&self) -> &AtlasLayout {
         &self.layout
     }
+
+    /// Width and height of the texture
+    #[inline]
+    pub fn size(&self) -> (u32, u32) {
+        (self.texture.width(), self.texture.height())
+    }
+
+    #[inline]
+    pub fn layer_count(&self) -> u32 {
+        self.texture.depth_or_array_layers()
+    }
 }
 
 /// Layout of the atlas
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AtlasLayout(pub Vec<SubTexture>);
 
+impl AtlasLayout {
+    /// Layout of a sprite sheet with equal sized tiles on layer 0, in row-major order so tile (column, row) is at `row * columns + column`.  
+    /// Margin is the space before the first tile, and spacing is the space between tiles
+    pub fn grid(
+        tile_width: u32,
+        tile_height: u32,
+        columns: u32,
+        rows: u32,
+        spacing: u32,
+        margin: u32,
+    ) -> Self {
+        let tiles = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(|(column, row)| SubTexture {
+                layer: 0,
+                x: margin + column * (tile_width + spacing),
+                y: margin + row * (tile_height + spacing),
+                width: tile_width,
+                height: tile_height,
+                trim: None,
+                rotated: false,
+            })
+            .collect();
+        Self(tiles)
+    }
+}
+
+/// The grid of a sprite sheet, see [AtlasLayout::grid]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct GridParams {
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub columns: u32,
+    pub rows: u32,
+    /// Space between tiles
+    pub spacing: u32,
+    /// Space before the first tile
+    pub margin: u32,
+}
+
+impl GridParams {
+    /// A grid without spacing or margin
+    pub fn new(tile_width: u32, tile_height: u32, columns: u32, rows: u32) -> Self {
+        Self {
+            tile_width,
+            tile_height,
+            columns,
+            rows,
+            spacing: 0,
+            margin: 0,
+        }
+    }
+
+    pub fn with_spacing(mut self, spacing: u32) -> Self {
+        self.spacing = spacing;
+        self
+    }
+
+    pub fn with_margin(mut self, margin: u32) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// The size the grid needs, without margin after the last tile
+    pub fn size(&self) -> (u32, u32) {
+        let size = |tile: u32, count: u32| {
+            self.margin + count * tile + count.saturating_sub(1) * self.spacing
+        };
+        (
+            size(self.tile_width, self.columns),
+            size(self.tile_height, self.rows),
+        )
+    }
+
+    #[inline]
+    pub fn layout(&self) -> AtlasLayout {
+        AtlasLayout::grid(
+            self.tile_width,
+            self.tile_height,
+            self.columns,
+            self.rows,
+            self.spacing,
+            self.margin,
+        )
+    }
+}
+
 /// A subsection of a texture atlas.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Clone, Copy)]
 pub struct SubTexture {
     pub layer: u32,
@@ -65,6 +250,47 @@ pub struct SubTexture {
     pub y: u32,
     pub width: u32,
     pub height: u32,
+    /// Set by [AtlasGroupBuilder] for images added with [add_trimmed_image](AtlasGroupBuilder::add_trimmed_image), layouters should leave this None
+    pub trim: Option<Trim>,
+    /// The image is stored rotated 90 degrees clockwise, so width and height are swapped compared to the image.
+    /// Only set by layouters when rotation is allowed, see [AtlasLayouter::layout_rotating]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub rotated: bool,
+}
+
+impl SubTexture {
+    /// The corners of the sub texture in UV coordinates, atlas_size is the width and height of the atlas
+    pub fn uv_min_max(&self, atlas_size: (u32, u32)) -> ([f32; 2], [f32; 2]) {
+        let (width, height) = (atlas_size.0 as f32, atlas_size.1 as f32);
+        (
+            [self.x as f32 / width, self.y as f32 / height],
+            [
+                (self.x + self.width) as f32 / width,
+                (self.y + self.height) as f32 / height,
+            ],
+        )
+    }
+
+    /// The UVs of the top left, top right, bottom right and bottom left corners of the image, which are not the same corners in the atlas if it is [rotated](Self::rotated)
+    pub fn uv_corners(&self, atlas_size: (u32, u32)) -> [[f32; 2]; 4] {
+        let (min, max) = self.uv_min_max(atlas_size);
+        let corners = [min, [max[0], min[1]], max, [min[0], max[1]]];
+        if self.rotated {
+            // rotating clockwise moves the top left of the image to the top right of the atlas
+            [corners[1], corners[2], corners[3], corners[0]]
+        } else {
+            corners
+        }
+    }
+}
+
+/// Where a trimmed [SubTexture] was in its original image, used to place it as if it was not trimmed
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Trim {
+    /// Position of the trimmed part in the original image
+    pub offset: (u32, u32),
+    pub original_size: (u32, u32),
 }
 
 /// A group of [Atlases](Atlas), this is useful to 'pretend' that multiple atlases are the same, as a single atlas may not be big enough for all [SubTextures](SubTexture)
@@ -72,59 +298,98 @@ pub struct AtlasGroup {
     atlases: Vec<Atlas>,
     entry_map: Vec<(usize, usize)>,
     bind_groups: Vec<BindGroup>,
+    /// Bind groups replaced by [rebuild_bind_groups](AtlasGroup::rebuild_bind_groups) this frame, kept as passes may still use them
+    old_bind_groups: Vec<BindGroup>,
+    /// Number of atlases in every bind group
+    slots: usize,
+    sampler: Sampler,
+    names: HashMap<String, AtlasGroupEntry>,
+    meta: EntryMeta,
+    /// Indices of entries removed by [remove_entry](AtlasGroup::remove_entry)
+    removed: HashSet<usize>,
+    /// Used by [insert](AtlasGroup::insert), set by [AtlasGroupBuilder]
+    padding: u32,
+    extrude: u32,
+    /// Max width and height of atlases added by [insert](AtlasGroup::insert), set by [AtlasGroupBuilder]
+    max_width_hight: u32,
+    /// Free space of the atlases, made when the first entry is inserted as the padding is not known before
+    allocators: Vec<ShelfAllocator>,
+    /// Storage buffer with the [EntryUv] of every entry, bound to the bind groups
+    entry_buffer: Buffer,
+}
+
+/// Everything a shader needs to sample an [AtlasGroupEntry], see [AtlasGroup::entry_uvs]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EntryUv {
+    /// Index in [AtlasGroup::bind_groups]
+    pub bind_group_index: u32,
+    /// Binding of the atlas in the bind group
+    pub binding_index: u32,
+    /// Array layer of the atlas
+    pub layer: u32,
+    /// The entry is stored rotated 90 degrees clockwise, see [SubTexture::uv_corners]
+    pub rotated: bool,
+    pub uv_min: [f32; 2],
+    pub uv_max: [f32; 2],
+}
+
+impl EntryUv {
+    /// Appends the entry as laid out in the [entry buffer](AtlasGroup::entry_buffer)
+    fn write_gpu(&self, out: &mut Vec<u8>) {
+        let rotated = self.rotated as u32;
+        for value in [
+            self.bind_group_index,
+            self.binding_index,
+            self.layer,
+            rotated,
+        ] {
+            out.extend(value.to_ne_bytes());
+        }
+        for value in self.uv_min.into_iter().chain(self.uv_max) {
+            out.extend(value.to_ne_bytes());
+        }
+    }
 }
 
 impl AtlasGroup {
-    /// Creates an [AtlasGroup] from a vec of [Atlases](Atlas), needs Device and layout to create [BindGroup]
+    /// Creates an [AtlasGroup] from a vec of [Atlases](Atlas), needs Device and layout to create [BindGroup].  
+    /// Slots of the last bind group after the last atlas are bound to a transparent 1x1 texture.  
+    /// A linear sampler clamped to edge and the [entry buffer](Self::entry_buffer) are created and bound to every bind group
     pub fn new(
         atlases: Vec<Atlas>,
         entry_map: Vec<(usize, usize)>,
         device: &Device,
         layout: &AtlasGroupBindGroupLayout,
     ) -> Self {
-        let view_desc = TextureViewDescriptor {
-            label: Some("AtlasGroup TextureView"),
-            format: None,
-            dimension: None,
-            aspect: TextureAspect::All,
-            base_mip_level: 0,
-            mip_level_count: None,
-            base_array_layer: 0,
-            array_layer_count: None,
+        // non filtering layouts need a nearest sampler
+        let sampler_config = if layout.is_filterable() {
+            SamplerConfig::linear_clamp()
+        } else {
+            SamplerConfig::nearest()
         };
-
-        let views: Vec<_> = atlases
-            .iter()
-            .map(|tex| tex.texture.create_view(&view_desc))
-            .collect();
-
-        // Iterate where i = (0..atlas count/atlases per BG)
-        // and binding = (0..atlases per BG)
-        // then choose the right view
-        // BIG MESS
-        let bind_groups = (0..atlases.len().div_ceil(layout.atlas_count()))
-            .map(|i| {
-                let entries = (0..layout.atlas_count())
-                    .map(|binding| {
-                        let view_idx = min(binding + i * layout.atlas_count(), atlases.len());
-                        BindGroupEntry {
-                            binding: binding as u32,
-                            resource: wgpu::BindingResource::TextureView(&views[view_idx]),
-                        }
-                    })
-                    .collect::<Vec<_>>();
-
-                device.create_bind_group(&BindGroupDescriptor {
-                    label: Some("AtlasGroup BindGroup"),
-                    layout: layout.layout(),
-                    entries: &entries,
-                })
-            })
-            .collect();
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("AtlasGroup Sampler"),
+            ..sampler_config.into()
+        });
+        let slots = layout.atlas_count();
+        let entry_buffer = create_entry_storage(&atlases, &entry_map, slots, device);
+        let bind_groups = create_bind_groups(&atlases, device, layout, &sampler, &entry_buffer);
         AtlasGroup {
             atlases,
             entry_map,
             bind_groups,
+            old_bind_groups: Vec::new(),
+            slots,
+            sampler,
+            names: HashMap::new(),
+            meta: EntryMeta::default(),
+            removed: HashSet::new(),
+            padding: DEFAULT_PADDING,
+            extrude: DEFAULT_PADDING,
+            max_width_hight: device.limits().max_texture_dimension_2d,
+            allocators: Vec::new(),
+            entry_buffer,
         }
     }
 
@@ -144,15 +409,288 @@ impl AtlasGroup {
         &self.entry_map
     }
 
+    /// The sampler bound to the bind groups
+    #[inline]
+    pub fn sampler(&self) -> &Sampler {
+        &self.sampler
+    }
+
     /// Bind groups with atlases
     #[inline]
     pub fn bind_groups(&self) -> &[BindGroup] {
         &self.bind_groups
     }
+
+    /// Creates new texture views, bind groups and the [entry buffer](Self::entry_buffer) from the current atlases, with the atlas count of layout.
+    /// Use this after changing the texture of an atlas, [insert](Self::insert) already does it when an atlas is added.  
+    /// The old bind groups are kept until [release_old_bind_groups](Self::release_old_bind_groups), as passes recorded this frame may still use them.
+    /// Groups in [Assets] are released at the start of [PreDraw], other groups must be released by the user once the frame is submitted
+    pub fn rebuild_bind_groups(&mut self, device: &Device, layout: &AtlasGroupBindGroupLayout) {
+        self.slots = layout.atlas_count();
+        self.entry_buffer =
+            create_entry_storage(&self.atlases, &self.entry_map, self.slots, device);
+        let new = create_bind_groups(
+            &self.atlases,
+            device,
+            layout,
+            &self.sampler,
+            &self.entry_buffer,
+        );
+        self.old_bind_groups
+            .append(&mut mem::replace(&mut self.bind_groups, new));
+    }
+
+    /// Drops the bind groups replaced by [rebuild_bind_groups](Self::rebuild_bind_groups)
+    pub fn release_old_bind_groups(&mut self) {
+        self.old_bind_groups.clear();
+    }
+
+    #[inline]
+    pub fn has_old_bind_groups(&self) -> bool {
+        !self.old_bind_groups.is_empty()
+    }
+
+    /// Adds an image to the group after it is built, the entry works like the entries added with [AtlasGroupBuilder::add_image].  
+    /// The image is placed in free space below the built entries, if no atlas has room a new atlas is added and the bind groups are made again.  
+    /// New atlases have the size, format, mip levels and usages of the first atlas, or are larger if the image does not fit.
+    /// The padding and extrusion of the [AtlasGroupBuilder] is used, or the default if the group was made with [new](Self::new).  
+    /// The index of an entry removed with [remove_entry](Self::remove_entry) is reused if there is one
+    pub fn insert(
+        &mut self,
+        img: impl Into<MipMapImage>,
+        device: &Device,
+        queue: &Queue,
+        layout: &AtlasGroupBindGroupLayout,
+    ) -> Result<AtlasGroupEntry, AtlasInsertError> {
+        self.insert_with(img.into(), true, device, queue, layout)
+    }
+
+    /// [insert](Self::insert), the image is always given a new index if reuse is false, as [AtlasGroupQueue] hands out indices before inserting
+    fn insert_with(
+        &mut self,
+        img: MipMapImage,
+        reuse: bool,
+        device: &Device,
+        queue: &Queue,
+        layout: &AtlasGroupBindGroupLayout,
+    ) -> Result<AtlasGroupEntry, AtlasInsertError> {
+        let size = img.sizes()[0];
+        let (format, mip_levels, usages) = self.atlas_options();
+        let alignment = self.alignment();
+        let lead = alignment.lead_padding(self.padding);
+        let padded =
+            alignment.aligned_size((lead + size.0 + self.padding, lead + size.1 + self.padding));
+        for level in img.levels() {
+            check_writable(level, format).map_err(AtlasInsertError::Format)?;
+        }
+        if !mip_levels_fit(&img, mip_levels) {
+            return Err(AtlasInsertError::MipLevels {
+                size,
+                levels: img.level_count(),
+                mip_levels,
+            });
+        }
+        self.create_allocators();
+        let found = self
+            .allocators
+            .iter_mut()
+            .enumerate()
+            .find_map(|(i, allocator)| Some((i, allocator.allocate(padded.0, padded.1)?)));
+        let (atlas_idx, (layer, x, y)) = match found {
+            Some(found) => found,
+            None => {
+                let max = alignment.aligned_max();
+                let atlas_size = self.atlases.first().map_or(0, |atlas| atlas.size().0);
+                let atlas_size = atlas_size
+                    .max(padded.0.max(padded.1).next_power_of_two())
+                    .max(MIN_INSERTED_ATLAS_SIZE)
+                    .min(max);
+                if padded.0 > atlas_size || padded.1 > atlas_size {
+                    return Err(AtlasInsertError::TooLarge { size, max });
+                }
+                let atlas_size = (atlas_size, atlas_size, 1);
+                let texture = create_atlas_texture(device, atlas_size, format, mip_levels, usages);
+                self.atlases
+                    .push(Atlas::new(texture, AtlasLayout(Vec::new())));
+                let mut allocator = ShelfAllocator::new(
+                    atlas_size,
+                    &AtlasLayout(Vec::new()),
+                    self.padding,
+                    alignment.rect_alignment(),
+                );
+                let placed = allocator
+                    .allocate(padded.0, padded.1)
+                    .expect("the new atlas fits the image");
+                self.allocators.push(allocator);
+                self.rebuild_bind_groups(device, layout);
+                (self.atlases.len() - 1, placed)
+            }
+        };
+        let subtex = SubTexture {
+            layer,
+            x: x + lead,
+            y: y + lead,
+            width: size.0,
+            height: size.1,
+            trim: None,
+            rotated: false,
+        };
+        let extrude = self.extrude.min(self.padding);
+        write_entry(
+            &img,
+            &subtex,
+            mip_levels,
+            extrude,
+            queue,
+            &self.atlases[atlas_idx].texture,
+        );
+        let reused = match reuse {
+            true => free_index(&self.removed, &self.entry_map, atlas_idx),
+            false => None,
+        };
+        let entry = match reused {
+            Some(index) => {
+                self.removed.remove(&index);
+                let old = self.entry_map[index];
+                let shared = self
+                    .entry_map
+                    .iter()
+                    .enumerate()
+                    .any(|(i, mapped)| i != index && *mapped == old);
+                let atlas_layout = &mut self.atlases[atlas_idx].layout.0;
+                // the sub texture of the removed entry is overwritten, unless another entry points to it
+                if old.0 == atlas_idx && !shared {
+                    atlas_layout[old.1] = subtex;
+                } else {
+                    atlas_layout.push(subtex);
+                    self.entry_map[index] = (atlas_idx, atlas_layout.len() - 1);
+                }
+                AtlasGroupEntry::from_index(index)
+            }
+            None => {
+                let atlas_layout = &mut self.atlases[atlas_idx].layout.0;
+                atlas_layout.push(subtex);
+                self.entry_map.push((atlas_idx, atlas_layout.len() - 1));
+                AtlasGroupEntry::from_index(self.entry_map.len() - 1)
+            }
+        };
+        if self.entry_buffer.size() < self.entry_map.len() as u64 * ENTRY_BUFFER_STRIDE {
+            // the bind groups are made again with a larger buffer
+            self.rebuild_bind_groups(device, layout);
+        } else {
+            let mut data = Vec::with_capacity(ENTRY_BUFFER_STRIDE as usize);
+            self.entry_uvs(entry).write_gpu(&mut data);
+            let offset = entry.index() as u64 * ENTRY_BUFFER_STRIDE;
+            queue.write_buffer(&self.entry_buffer, offset, &data);
+        }
+        Ok(entry)
+    }
+
+    /// Format, mip levels and usages of new atlases, from the first atlas
+    fn atlas_options(&self) -> (TextureFormat, u32, TextureUsages) {
+        self.atlases
+            .first()
+            .map_or((DEFAULT_TEXTURE_FORMAT, 1, DEFAULT_ATLAS_USAGES), |atlas| {
+                let texture = &atlas.texture;
+                (texture.format(), texture.mip_level_count(), texture.usage())
+            })
+    }
+
+    /// The max size and alignment of new entries, from the format and mip levels of the atlases
+    fn alignment(&self) -> MaxAtlasSize {
+        let (format, mip_levels, _) = self.atlas_options();
+        let (origin_alignment, size_alignment) = layout_alignment(format, mip_levels);
+        MaxAtlasSize {
+            origin_alignment,
+            size_alignment,
+            ..MaxAtlasSize::new(self.max_width_hight, 1)
+        }
+    }
+
+    /// Creates the allocators of the atlases that have none
+    fn create_allocators(&mut self) {
+        let alignment = self.alignment().rect_alignment();
+        for atlas in &self.atlases[self.allocators.len()..] {
+            let size = (atlas.size().0, atlas.size().1, atlas.layer_count());
+            let allocator = ShelfAllocator::new(size, &atlas.layout, self.padding, alignment);
+            self.allocators.push(allocator);
+        }
+    }
+
+    /// Storage buffer with the [EntryUv] of every entry, so shaders can look entries up by [index](AtlasGroupEntry::index).
+    /// It is bound to every bind group at the [entry binding](AtlasGroupBindGroupLayout::entry_binding), and is made again along with the bind groups
+    /// when [insert](Self::insert) fills it, in that case [AtlasGroupChanged] is sent by [AtlasGroupQueue]
+    #[inline]
+    pub fn entry_buffer(&self) -> &Buffer {
+        &self.entry_buffer
+    }
+
+    /// The entry added with [add_image_named](AtlasGroupBuilder::add_image_named)
+    #[inline]
+    pub fn entry(&self, name: &str) -> Option<AtlasGroupEntry> {
+        self.names.get(name).copied()
+    }
+
+    /// Names of the named entries, in no particular order
+    pub fn entry_names(&self) -> impl Iterator<Item = &str> {
+        self.names.keys().map(String::as_str)
+    }
+
+    /// The bind group, binding, layer and UVs of an entry
+    /// ## Panics
+    /// If the entry is not in the group
+    pub fn entry_uvs(&self, entry: AtlasGroupEntry) -> EntryUv {
+        entry_uv(&self.atlases, &self.entry_map, self.slots, entry.index())
+    }
+}
+
+/// The [EntryUv] of entry index, with slots atlases in every bind group
+fn entry_uv(
+    atlases: &[Atlas],
+    entry_map: &[(usize, usize)],
+    slots: usize,
+    index: usize,
+) -> EntryUv {
+    let (atlas_idx, subtex_idx) = entry_map[index];
+    let atlas = &atlases[atlas_idx];
+    let subtex = &atlas.layout.0[subtex_idx];
+    let (uv_min, uv_max) = subtex.uv_min_max(atlas.size());
+    let (bind_group_index, binding_index) = atlas_slot(atlas_idx, slots);
+    EntryUv {
+        bind_group_index: bind_group_index as u32,
+        binding_index: binding_index as u32,
+        layer: subtex.layer,
+        rotated: subtex.rotated,
+        uv_min,
+        uv_max,
+    }
+}
+
+/// The [entry buffer](AtlasGroup::entry_buffer) of the entries, room is left for inserted entries as the buffer is made again when it is full
+fn create_entry_storage(
+    atlases: &[Atlas],
+    entry_map: &[(usize, usize)],
+    slots: usize,
+    device: &Device,
+) -> Buffer {
+    let capacity = entry_map.len().max(1).next_power_of_two() as u64;
+    let buffer = device.create_buffer(&BufferDescriptor {
+        label: Some("AtlasGroup Entry Buffer"),
+        size: capacity * ENTRY_BUFFER_STRIDE,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        mapped_at_creation: true,
+    });
+    let mut data = Vec::with_capacity(entry_map.len() * ENTRY_BUFFER_STRIDE as usize);
+    for i in 0..entry_map.len() {
+        entry_uv(atlases, entry_map, slots, i).write_gpu(&mut data);
+    }
+    buffer.slice(..).get_mapped_range_mut()[..data.len()].copy_from_slice(&data);
+    buffer.unmap();
+    buffer
 }
 
 /// An entry into an [AtlasGroup]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct AtlasGroupEntry(usize);
 
 impl AtlasGroupEntry {
@@ -167,27 +705,93 @@ impl AtlasGroupEntry {
     }
 }
 
-/// Used as a singleton for the layout of an [AtlasGroup]'s bind group
+/// Optional resource, the max number of atlases in a bind group of an [AtlasGroup],
+/// read when [AtlasGroupBindGroupLayout] is made during [Init] so it should be inserted before. 16 if it is not inserted
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MaxBoundAtlases(pub u32);
+
+/// Used as a singleton for the layout of an [AtlasGroup]'s bind group.  
+/// Bindings 0 to [atlas_count](Self::atlas_count) - 1 are the atlases as float [D2Array](TextureViewDimension::D2Array) textures,
+/// and binding [atlas_count](Self::atlas_count) is a sampler, all visible in the fragment stage.  
+/// The textures and sampler are filtering, unless the layout is made [with_format](Self::with_format) with a format that can not be filtered.  
+/// The [entry binding](Self::entry_binding) after the sampler is the [entry buffer](AtlasGroup::entry_buffer), visible in the vertex and fragment stages, matching this WGSL:
+/// ```wgsl
+/// struct AtlasEntry {
+///     bind_group_index: u32,
+///     binding_index: u32,
+///     layer: u32,
+///     // 1 if the entry is rotated 90 degrees clockwise
+///     rotated: u32,
+///     uv_min: vec2<f32>,
+///     uv_max: vec2<f32>,
+/// }
+///
+/// @group(0) @binding(ENTRY_BINDING) var<storage, read> atlas_entries: array<AtlasEntry>;
+/// ```
 #[derive(Resource)]
 pub struct AtlasGroupBindGroupLayout {
     layout: BindGroupLayout,
     atlas_count: usize,
+    filterable: bool,
 }
 
 impl AtlasGroupBindGroupLayout {
+    /// Has up to 16 atlases, see [with_max_atlases](Self::with_max_atlases)
+    #[inline]
     pub fn new(device: &Device) -> Self {
-        let atlas_count = device.limits().max_sampled_textures_per_shader_stage as usize;
+        Self::with_max_atlases(device, DEFAULT_MAX_BOUND_ATLASES)
+    }
+
+    /// Has max_atlases atlases, or fewer if the device can not sample that many textures in a shader stage.
+    /// Some drivers report thousands, which would make very large bind groups
+    #[inline]
+    pub fn with_max_atlases(device: &Device, max_atlases: u32) -> Self {
+        Self::create(device, max_atlases, true)
+    }
+
+    /// Like [with_max_atlases](Self::with_max_atlases), for groups with the format.
+    /// If the format can not be filtered on the device, such as [Rgba32Float](TextureFormat::Rgba32Float) without
+    /// [FLOAT32_FILTERABLE](wgpu::Features::FLOAT32_FILTERABLE), the textures are non filterable and the sampler is non filtering
+    pub fn with_format(device: &Device, max_atlases: u32, format: TextureFormat) -> Self {
+        Self::create(device, max_atlases, is_filterable(format, device))
+    }
+
+    fn create(device: &Device, max_atlases: u32, filterable: bool) -> Self {
+        let atlas_count = device
+            .limits()
+            .max_sampled_textures_per_shader_stage
+            .min(max_atlases) as usize;
         let entries = (0..atlas_count)
-            .map(|_| BindGroupLayoutEntry {
-                binding: 0,
+            .map(|binding| BindGroupLayoutEntry {
+                binding: binding as u32,
                 visibility: ShaderStages::FRAGMENT,
                 ty: wgpu::BindingType::Texture {
-                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    sample_type: wgpu::TextureSampleType::Float { filterable },
                     view_dimension: wgpu::TextureViewDimension::D2Array,
                     multisampled: false,
                 },
                 count: None,
             })
+            .chain([BindGroupLayoutEntry {
+                binding: atlas_count as u32,
+                visibility: ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(if filterable {
+                    SamplerBindingType::Filtering
+                } else {
+                    SamplerBindingType::NonFiltering
+                }),
+                count: None,
+            }])
+            .chain([BindGroupLayoutEntry {
+                binding: atlas_count as u32 + 1,
+                visibility: ShaderStages::VERTEX_FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: BufferSize::new(ENTRY_BUFFER_STRIDE),
+                },
+                count: None,
+            }])
             .collect::<Vec<_>>();
         let desc = BindGroupLayoutDescriptor {
             label: Some("AtlasGroupBindGroupLayout"),
@@ -196,6 +800,7 @@ impl AtlasGroupBindGroupLayout {
         Self {
             layout: device.create_bind_group_layout(&desc),
             atlas_count,
+            filterable,
         }
     }
 
@@ -206,13 +811,42 @@ impl AtlasGroupBindGroupLayout {
     pub fn atlas_count(&self) -> usize {
         self.atlas_count
     }
+
+    /// False if the layout was made [with_format](Self::with_format) with a format that can not be filtered
+    #[inline]
+    pub fn is_filterable(&self) -> bool {
+        self.filterable
+    }
+
+    /// The binding of the sampler, after the atlases
+    #[inline]
+    pub fn sampler_binding(&self) -> u32 {
+        self.atlas_count as u32
+    }
+
+    /// The binding of the [entry buffer](AtlasGroup::entry_buffer), after the sampler
+    #[inline]
+    pub fn entry_binding(&self) -> u32 {
+        self.atlas_count as u32 + 1
+    }
 }
 
-/// Can be used to create an [AtlasGroup]
+/// Can be used to create an [AtlasGroup].  
+/// Atlases are [DEFAULT_TEXTURE_FORMAT] unless [set_format](Self::set_format) is used,
+/// so images that should be [Linear](crate::ColorSpace::Linear), like normal maps, need a linear format such as [Rgba8Unorm](TextureFormat::Rgba8Unorm)
 pub struct AtlasGroupBuilder {
     images: Vec<MipMapImage>,
+    trims: Vec<Option<Trim>>,
+    names: Vec<(String, AtlasGroupEntry)>,
+    meta: EntryMeta,
     mip_levels: u32,
+    padding: u32,
+    extrude: u32,
     usages: TextureUsages,
+    alpha_mode: Option<AlphaMode>,
+    max_size: Option<(u32, u32)>,
+    format: TextureFormat,
+    allow_rotation: bool,
 }
 
 impl AtlasGroupBuilder {
@@ -224,24 +858,131 @@ impl AtlasGroupBuilder {
     pub fn with_usages(usages: TextureUsages, mip_levels: u32) -> Self {
         Self {
             images: Vec::new(),
+            trims: Vec::new(),
+            names: Vec::new(),
+            meta: EntryMeta::default(),
             mip_levels,
+            padding: DEFAULT_PADDING,
+            extrude: DEFAULT_PADDING,
             usages: usages | TextureUsages::COPY_DST,
+            alpha_mode: None,
+            max_size: None,
+            format: DEFAULT_TEXTURE_FORMAT,
+            allow_rotation: false,
         }
     }
 
-    /// If image has 1 mipmap level, it will be drawn to the first mip level.  
-    /// Otherwise it should match the mip levels of the [AtlasGroupBuilder]
+    /// Levels the image is missing are generated from its last level, so every mip level of the atlas is written.  
+    /// The image must be large enough for the mip levels of the [AtlasGroupBuilder] and not have more, otherwise [build](Self::build) returns an error.  
+    /// The pixels of the image must have the size of a texel of the [format](Self::set_format) and the [PixelFormat](crate::PixelFormat) of the images added before,
+    /// otherwise [build](Self::build) returns [Format](AtlasBuildError::Format)
+    /// ## Panics
+    /// If the [AlphaMode] of the image is not the same as the images added before
     pub fn add_image(&mut self, img: impl Into<MipMapImage>) -> AtlasGroupEntry {
-        self.images.push(img.into());
+        let img = img.into();
+        let alpha_mode = img.levels()[0].alpha_mode;
+        let expected = *self.alpha_mode.get_or_insert(alpha_mode);
+        assert_eq!(
+            alpha_mode, expected,
+            "all images in an atlas group must have the same alpha mode"
+        );
+        self.images.push(img);
+        self.trims.push(None);
         AtlasGroupEntry::from_index(self.images.len() - 1)
     }
 
+    /// Same as [add_image](Self::add_image), but the entry can be found with [AtlasGroup::entry].  
+    /// Names must be unique, [build](Self::build) returns an error otherwise
+    pub fn add_image_named(
+        &mut self,
+        name: impl Into<String>,
+        img: impl Into<MipMapImage>,
+    ) -> AtlasGroupEntry {
+        let entry = self.add_image(img);
+        self.names.push((name.into(), entry));
+        entry
+    }
+
+    /// Same as [add_image](Self::add_image), but the [Trim] is stored in the [SubTexture] of the entry.  
+    /// The trimmed image has a single mip level, see [Image::trimmed](crate::Image::trimmed)
+    pub fn add_trimmed_image(&mut self, img: TrimmedImage) -> AtlasGroupEntry {
+        let trim = img.trim();
+        let entry = self.add_image(img.image);
+        self.trims[entry.index()] = Some(trim);
+        entry
+    }
+
     #[inline]
     pub fn mip_levels(&self) -> u32 {
         self.mip_levels
     }
 
-    /// Returns the sizes of the elements, useful for layouting
+    /// The format of the atlases, [DEFAULT_TEXTURE_FORMAT] by default.
+    /// Smaller formats like [R8Unorm](TextureFormat::R8Unorm) save memory for masks, and linear formats are needed for data like normal maps.  
+    /// Groups with formats that can not be filtered need a layout made with [AtlasGroupBindGroupLayout::with_format]
+    /// ## Panics
+    /// If images were already added
+    pub fn set_format(&mut self, format: TextureFormat) {
+        assert!(
+            self.images.is_empty(),
+            "the format of an atlas group must be set before images are added"
+        );
+        self.format = format;
+    }
+
+    #[inline]
+    pub fn format(&self) -> TextureFormat {
+        self.format
+    }
+
+    /// Space around every image, so filtering does not sample the images next to it. 1 by default.  
+    /// The space is halved for every mip level, so levels where it is below 1 can still bleed
+    pub fn set_padding(&mut self, padding: u32) {
+        self.padding = padding;
+    }
+
+    /// Number of pixels of the padding that are filled with the edge pixels of the image, at most the padding. 1 by default.  
+    /// This stops the transparent padding from bleeding into the edges, and is halved for every mip level like the padding
+    pub fn set_extrude(&mut self, extrude: u32) {
+        self.extrude = extrude;
+    }
+
+    /// Limits the width and height and layers of the atlases, the device limits are used if they are lower.
+    /// Smaller atlases can be useful for streaming, or when a device reports limits it can not handle
+    pub fn set_max_size(&mut self, width_height: u32, layers: u32) {
+        self.max_size = Some((width_height, layers));
+    }
+
+    /// Lets the layouter rotate images 90 degrees, which can pack tall and thin images better. False by default.  
+    /// Only layouters that implement [layout_rotating](AtlasLayouter::layout_rotating), like [ShelfLayouter], rotate images.
+    /// Shaders must use [SubTexture::uv_corners] or [EntryUv::rotated] to sample rotated entries
+    pub fn set_allow_rotation(&mut self, allow_rotation: bool) {
+        self.allow_rotation = allow_rotation;
+    }
+
+    #[inline]
+    pub fn allow_rotation(&self) -> bool {
+        self.allow_rotation
+    }
+
+    #[inline]
+    pub fn padding(&self) -> u32 {
+        self.padding
+    }
+
+    #[inline]
+    pub fn extrude(&self) -> u32 {
+        self.extrude
+    }
+
+    /// The [AlphaMode] of the added images, None if no images are added
+    #[inline]
+    pub fn alpha_mode(&self) -> Option<AlphaMode> {
+        self.alpha_mode
+    }
+
+    /// Returns the sizes of the elements, useful for layouting.  
+    /// The padding is not included, the layouter is given sizes with padding on every side
     pub fn sizes(&self) -> Vec<(u32, u32)> {
         self.images.iter().map(|img| img.sizes()[0]).collect()
     }
@@ -252,62 +993,469 @@ impl AtlasGroupBuilder {
         device: &Device,
         queue: &Queue,
         bind_layout: &AtlasGroupBindGroupLayout,
-    ) -> Result<AtlasGroup, L::Error> {
-        let lim = device.limits();
-        let output = L::layout(
-            self.sizes(),
-            MaxAtlasSize {
-                max_width_hight: lim.max_texture_dimension_2d,
-                max_layers: lim.max_texture_array_layers,
+    ) -> Result<AtlasGroup, AtlasBuildError<L::Error>> {
+        self.build_with(self.layout_fn::<L>(), device, queue, bind_layout)
+    }
+
+    /// Builds an atlas with a layouter picked at runtime, see [ErasedLayouter]
+    pub fn build_with_layouter(
+        &self,
+        layouter: &dyn ErasedLayouter,
+        device: &Device,
+        queue: &Queue,
+        bind_layout: &AtlasGroupBindGroupLayout,
+    ) -> Result<AtlasGroup, AtlasBuildError<String>> {
+        self.build_with(
+            |sizes, max_atlas_size| {
+                if self.allow_rotation {
+                    layouter.layout_rotating_erased(sizes, max_atlas_size)
+                } else {
+                    layouter.layout_erased(sizes, max_atlas_size)
+                }
             },
-        )?;
+            device,
+            queue,
+            bind_layout,
+        )
+    }
+
+    fn build_with<E>(
+        &self,
+        layout: impl FnOnce(Vec<(u32, u32)>, MaxAtlasSize) -> Result<AtlasLayouterOutput, E>,
+        device: &Device,
+        queue: &Queue,
+        bind_layout: &AtlasGroupBindGroupLayout,
+    ) -> Result<AtlasGroup, AtlasBuildError<E>> {
+        let names = self.validate()?;
+        if bind_layout.is_filterable() && !is_filterable(self.format, device) {
+            return Err(AtlasBuildError::UnfilterableFormat(self.format));
+        }
+        let output = self.pack(layout, self.max_atlas_size(device))?;
+        Ok(self.create_group(output, names, device, queue, bind_layout))
+    }
+
+    /// The layout function of L, rotating if it is allowed
+    fn layout_fn<L: AtlasLayouter>(&self) -> LayoutFn<L::Error> {
+        if self.allow_rotation {
+            L::layout_rotating
+        } else {
+            L::layout
+        }
+    }
+
+    /// Lays out the images with padding, and shrinks the sub textures back to the images
+    fn pack<E>(
+        &self,
+        layout: impl FnOnce(Vec<(u32, u32)>, MaxAtlasSize) -> Result<AtlasLayouterOutput, E>,
+        max_atlas_size: MaxAtlasSize,
+    ) -> Result<AtlasLayouterOutput, AtlasBuildError<E>> {
+        self.validate_sizes(&max_atlas_size)?;
+        // the padding before the image is larger when aligned, so the image is aligned when the rect is
+        let lead = max_atlas_size.lead_padding(self.padding);
+        let padded_sizes = self
+            .sizes()
+            .into_iter()
+            .map(|(width, height)| (lead + width + self.padding, lead + height + self.padding))
+            .collect();
+        let mut output = layout(padded_sizes, max_atlas_size).map_err(AtlasBuildError::Layout)?;
+        for (trim, (atlas_idx, el_idx)) in self.trims.iter().zip(&output.entry_map) {
+            let subtex = &mut output.atlases[*atlas_idx].1 .0[*el_idx];
+            // the sub texture is the image inside the padding
+            subtex.x += lead;
+            subtex.y += lead;
+            subtex.width -= lead + self.padding;
+            subtex.height -= lead + self.padding;
+            subtex.trim = *trim;
+        }
+        Ok(output)
+    }
+
+    /// The device limits, lowered by [set_max_size](Self::set_max_size)
+    fn max_atlas_size(&self, device: &Device) -> MaxAtlasSize {
+        let lim = device.limits();
+        self.clamp_max_size(MaxAtlasSize::new(
+            lim.max_texture_dimension_2d,
+            lim.max_texture_array_layers,
+        ))
+    }
+
+    /// Lowers the size by [set_max_size](Self::set_max_size), and adds the alignment needed by the format and mip levels
+    fn clamp_max_size(&self, max_atlas_size: MaxAtlasSize) -> MaxAtlasSize {
+        let (max_width_hight, max_layers) = self.max_size.unwrap_or((u32::MAX, u32::MAX));
+        let (origin_alignment, size_alignment) = layout_alignment(self.format, self.mip_levels);
+        MaxAtlasSize {
+            max_width_hight: max_atlas_size.max_width_hight.min(max_width_hight),
+            max_layers: max_atlas_size.max_layers.min(max_layers),
+            origin_alignment: lcm(max_atlas_size.origin_alignment, origin_alignment),
+            size_alignment: lcm(max_atlas_size.size_alignment, size_alignment),
+        }
+    }
+
+    /// Checks the names and mip levels of the images, returning the names of the group
+    fn validate<E>(&self) -> Result<HashMap<String, AtlasGroupEntry>, AtlasBuildError<E>> {
+        let mut names = HashMap::new();
+        for (name, entry) in &self.names {
+            if names.insert(name.clone(), *entry).is_some() {
+                return Err(AtlasBuildError::DuplicateName(name.clone()));
+            }
+        }
+        let pixel_format = self.images.first().map(|img| img.levels()[0].format);
+        for (entry_index, img) in self.images.iter().enumerate() {
+            let format_error = img.levels().iter().find_map(|level| {
+                if Some(level.format) != pixel_format {
+                    return Some(format!(
+                        "{:?} image does not have the pixel format {:?} of the first image",
+                        level.format,
+                        pixel_format.expect("there is a first image")
+                    ));
+                }
+                check_writable(level, self.format).err()
+            });
+            if let Some(error) = format_error {
+                return Err(AtlasBuildError::Format { entry_index, error });
+            }
+            if !mip_levels_fit(img, self.mip_levels) {
+                let size = img.sizes()[0];
+                return Err(AtlasBuildError::MipLevels {
+                    entry_index,
+                    size,
+                    levels: img.level_count(),
+                    mip_levels: self.mip_levels,
+                });
+            }
+        }
+        Ok(names)
+    }
+
+    /// Checks that every image with its padding and alignment fits in an atlas, so the error can name the image instead of failing in the layouter
+    fn validate_sizes<E>(&self, max_atlas_size: &MaxAtlasSize) -> Result<(), AtlasBuildError<E>> {
+        let max_width_hight = max_atlas_size.max_width_hight;
+        let lead = max_atlas_size.lead_padding(self.padding);
+        for (entry_index, (width, height)) in self.sizes().into_iter().enumerate() {
+            let padded = (lead + width + self.padding, lead + height + self.padding);
+            let padded = max_atlas_size.aligned_size(padded);
+            if padded.0.max(padded.1) > max_atlas_size.aligned_max() {
+                let name = self
+                    .names
+                    .iter()
+                    .find(|(_, entry)| entry.index() == entry_index)
+                    .map(|(name, _)| name.clone());
+                return Err(AtlasBuildError::TooLarge {
+                    entry_index,
+                    name,
+                    size: (width, height),
+                    max: max_width_hight,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates the atlases of the layout and writes the images to them, the layout must already account for padding and trims
+    fn create_group(
+        &self,
+        output: AtlasLayouterOutput,
+        names: HashMap<String, AtlasGroupEntry>,
+        device: &Device,
+        queue: &Queue,
+        bind_layout: &AtlasGroupBindGroupLayout,
+    ) -> AtlasGroup {
         let mut atlases = Vec::with_capacity(output.atlases.len());
         for layout in output.atlases {
-            let tex = create_atlas_texture(&device, &layout, self);
+            let tex =
+                create_atlas_texture(device, layout.0, self.format, self.mip_levels, self.usages);
             atlases.push(Atlas::new(tex, layout.1));
         }
         for (img_idx, (atlas_idx, el_idx)) in output.entry_map.iter().enumerate() {
             let atlas = &atlases[*atlas_idx];
             let subtex = atlas.layout.0[*el_idx];
-            let img = &self.images[img_idx];
-            img.write_to_texture(
+            let extrude = self.extrude.min(self.padding);
+            write_entry(
+                &self.images[img_idx],
+                &subtex,
+                self.mip_levels,
+                extrude,
                 queue,
-                Origin3d {
-                    x: subtex.x,
-                    y: subtex.y,
-                    z: subtex.layer,
-                },
                 atlas.texture(),
-            )
+            );
         }
-        Ok(AtlasGroup::new(
-            atlases,
-            output.entry_map,
-            device,
-            bind_layout,
-        ))
+        let mut group = AtlasGroup::new(atlases, output.entry_map, device, bind_layout);
+        group.names = names;
+        // entries keep their index in the group, only where entry_map places them changes
+        group.meta = self.meta.clone();
+        group.padding = self.padding;
+        group.extrude = self.extrude;
+        group.max_width_hight = self.max_atlas_size(device).max_width_hight;
+        group
     }
 }
 
-/// Used to layout and create [AtlasGroup]s, to manually layout groups you can directly create [AtlasGroup]s
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AtlasBuildError<E> {
+    /// The [AtlasLayouter] failed
+    Layout(E),
+    /// More than one image was added with the name
+    DuplicateName(String),
+    /// An image has more mip levels than the atlas, or is too small to have them
+    MipLevels {
+        entry_index: usize,
+        size: (u32, u32),
+        levels: usize,
+        mip_levels: u32,
+    },
+    /// An image with its padding is larger than the max texture size
+    TooLarge {
+        entry_index: usize,
+        name: Option<String>,
+        size: (u32, u32),
+        max: u32,
+    },
+    /// The format can not be filtered, but the bind group layout is filtering, see [AtlasGroupBindGroupLayout::with_format]
+    UnfilterableFormat(TextureFormat),
+    /// An image can not be written to the format of the atlases, or has another pixel format than the first image
+    Format { entry_index: usize, error: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AtlasInsertError {
+    /// The image has more mip levels than the atlases, or is too small to have them
+    MipLevels {
+        size: (u32, u32),
+        levels: usize,
+        mip_levels: u32,
+    },
+    /// The image with its padding is larger than the max texture size
+    TooLarge { size: (u32, u32), max: u32 },
+    /// The image can not be written to the format of the atlases
+    Format(String),
+}
+
+impl Error for AtlasInsertError {}
+
+impl Display for AtlasInsertError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MipLevels {
+                size,
+                levels,
+                mip_levels,
+            } => write!(
+                f,
+                "{}x{} image with {levels} mip levels does not fit the {mip_levels} levels of the atlas",
+                size.0, size.1
+            ),
+            Self::TooLarge { size, max } => write!(
+                f,
+                "{}x{} image with padding is larger than the max atlas size {max}",
+                size.0, size.1
+            ),
+            Self::Format(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl<E: Debug> Error for AtlasBuildError<E> {}
+
+impl<E: Debug> Display for AtlasBuildError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Layout(e) => write!(f, "atlas layout failed: {:?}", e),
+            Self::DuplicateName(name) => {
+                write!(f, "more than one atlas image is named \"{name}\"")
+            }
+            Self::MipLevels {
+                entry_index,
+                size,
+                levels,
+                mip_levels,
+            } => write!(
+                f,
+                "atlas entry {entry_index} is {}x{} with {levels} mip levels, which does not fit the {mip_levels} levels of the atlas",
+                size.0, size.1
+            ),
+            Self::TooLarge {
+                entry_index,
+                name,
+                size,
+                max,
+            } => {
+                write!(f, "atlas entry {entry_index}")?;
+                if let Some(name) = name {
+                    write!(f, " (\"{name}\")")?;
+                }
+                write!(
+                    f,
+                    " is {}x{}, which with padding is larger than the max atlas size {max}",
+                    size.0, size.1
+                )
+            }
+            Self::UnfilterableFormat(format) => write!(
+                f,
+                "{format:?} atlases can not be filtered, the bind group layout must be made with the format"
+            ),
+            Self::Format { entry_index, error } => write!(
+                f,
+                "atlas entry {entry_index} can not be added: {error}, the format of the group can be changed with set_format"
+            ),
+        }
+    }
+}
+
+enum AtlasGroupOperation {
+    Init(
+        AssetId<AtlasGroup>,
+        AtlasGroupBuilder,
+        Option<Box<dyn ErasedLayouter>>,
+    ),
+    /// With the entry returned by [AtlasGroupQueue::add_to_group]
+    Insert(AssetId<AtlasGroup>, MipMapImage, AtlasGroupEntry),
+    RebuildBindGroups(AssetId<AtlasGroup>),
+    Defragment(AssetId<AtlasGroup>),
+}
+
+/// Used to layout and create [AtlasGroup]s, to manually layout groups you can directly create [AtlasGroup]s.  
+/// Operations are done in the order they are queued, during [PreDraw]
 #[derive(Resource)]
-pub struct AtlasGroupQueue(Vec<(AssetId<AtlasGroup>, AtlasGroupBuilder)>);
+pub struct AtlasGroupQueue {
+    queue: Vec<AtlasGroupOperation>,
+    /// Number of entries every group will have after the queue is done, used to return entries before they are added.
+    /// Set to the actual number of entries when an operation on the group fails
+    entry_counts: HashMap<AssetId<AtlasGroup>, usize>,
+}
 
 impl AtlasGroupQueue {
+    pub(crate) fn new() -> Self {
+        Self {
+            queue: Vec::new(),
+            entry_counts: HashMap::new(),
+        }
+    }
+
+    /// Builds the group during [PreDraw], if building fails [AtlasBuildFailed] is sent and the group is not added
     pub fn init_group(&mut self, group: AssetId<AtlasGroup>, descriptor: AtlasGroupBuilder) {
-        self.0.push((group, descriptor));
+        self.entry_counts.insert(group, descriptor.images.len());
+        self.queue
+            .push(AtlasGroupOperation::Init(group, descriptor, None));
+    }
+
+    /// Like [init_group](Self::init_group), but the group is laid out with layouter instead of the layouter of [init_custom_atlas_loading]
+    pub fn init_group_with_layouter(
+        &mut self,
+        group: AssetId<AtlasGroup>,
+        descriptor: AtlasGroupBuilder,
+        layouter: Box<dyn ErasedLayouter>,
+    ) {
+        self.entry_counts.insert(group, descriptor.images.len());
+        self.queue
+            .push(AtlasGroupOperation::Init(group, descriptor, Some(layouter)));
+    }
+
+    /// Adds an image to a group with [AtlasGroup::insert], the entry can be used once the image is added during [PreDraw].  
+    /// [AtlasGroupChanged] is sent once the image is added, and [AtlasBuildFailed] if the image could not be inserted or the group failed to build.
+    /// Images added after one that fails in the same frame also fail, as they would not get the returned entries, and can be added again
+    /// ## Panics
+    /// If the group was not made with [init_group](Self::init_group)
+    pub fn add_to_group(
+        &mut self,
+        group: AssetId<AtlasGroup>,
+        img: impl Into<MipMapImage>,
+    ) -> AtlasGroupEntry {
+        let count = self
+            .entry_counts
+            .get_mut(&group)
+            .expect("images can only be added to groups made with init_group");
+        let entry = AtlasGroupEntry::from_index(*count);
+        *count += 1;
+        self.queue
+            .push(AtlasGroupOperation::Insert(group, img.into(), entry));
+        entry
+    }
+
+    /// Calls [AtlasGroup::rebuild_bind_groups] during [PreDraw] and sends [AtlasGroupChanged],
+    /// useful after the texture of an atlas was changed, for example when its images are reloaded
+    pub fn rebuild_bind_groups(&mut self, group: AssetId<AtlasGroup>) {
+        self.queue
+            .push(AtlasGroupOperation::RebuildBindGroups(group));
+    }
+
+    /// Calls [AtlasGroup::defragment] during [PreDraw] and sends [AtlasGroupChanged].
+    /// [AtlasBuildFailed] is sent instead if the atlases do not have [COPY_SRC](TextureUsages::COPY_SRC) usage
+    pub fn defragment(&mut self, group: AssetId<AtlasGroup>) {
+        self.queue.push(AtlasGroupOperation::Defragment(group));
     }
 }
 
+/// Sent during [PreDraw] whenever [AtlasGroupQueue] changes a group: when it is built or rebuilt, an image is added, or its bind groups are made again.
+/// Bind groups, atlases and the [entry buffer](AtlasGroup::entry_buffer) may have been replaced, so systems holding them or indices into them should fetch them again.
+/// Events can be read until the end of the next frame
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasGroupChanged {
+    pub group: AssetId<AtlasGroup>,
+}
+
+/// Sent during [PreDraw] when a group queued in [AtlasGroupQueue] could not be built, or an image could not be added to it.
+/// A group that failed to build is not added to [Assets], so it stays empty
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct AtlasBuildFailed {
+    pub group: AssetId<AtlasGroup>,
+    pub error: String,
+}
+
 pub trait AtlasLayouter {
     type Error: Debug + Sized;
-    /// Layouts an [AtlasGroup] by taking a vec of image sizes and returning the sizes and layouts of atlases in a group
+    /// Layouts an [AtlasGroup] by taking a vec of image sizes and returning the sizes and layouts of atlases in a group.  
+    /// Sub textures must be placed at multiples of [origin_alignment](MaxAtlasSize::origin_alignment) and atlas sizes must be multiples of [size_alignment](MaxAtlasSize::size_alignment),
+    /// packing the [aligned sizes](MaxAtlasSize::aligned_size) does both when rects are placed next to each other
     fn layout(
         sizes: Vec<(u32, u32)>,
         max_atlas_size: MaxAtlasSize,
     ) -> Result<AtlasLayouterOutput, Self::Error>;
+
+    /// Like [layout](Self::layout), but sub textures may be [rotated](SubTexture::rotated), used when [AtlasGroupBuilder::set_allow_rotation] is set.
+    /// Layouters that do not rotate can keep the default, which calls [layout](Self::layout)
+    fn layout_rotating(
+        sizes: Vec<(u32, u32)>,
+        max_atlas_size: MaxAtlasSize,
+    ) -> Result<AtlasLayouterOutput, Self::Error> {
+        Self::layout(sizes, max_atlas_size)
+    }
+}
+
+/// Object safe [AtlasLayouter], so the layouter of a group can be picked at runtime with [AtlasGroupQueue::init_group_with_layouter].
+/// Implemented for every [AtlasLayouter], errors are formatted with Debug
+pub trait ErasedLayouter: Send + Sync {
+    fn layout_erased(
+        &self,
+        sizes: Vec<(u32, u32)>,
+        max_atlas_size: MaxAtlasSize,
+    ) -> Result<AtlasLayouterOutput, String>;
+
+    fn layout_rotating_erased(
+        &self,
+        sizes: Vec<(u32, u32)>,
+        max_atlas_size: MaxAtlasSize,
+    ) -> Result<AtlasLayouterOutput, String>;
+}
+
+impl<L: AtlasLayouter + Send + Sync> ErasedLayouter for L {
+    fn layout_erased(
+        &self,
+        sizes: Vec<(u32, u32)>,
+        max_atlas_size: MaxAtlasSize,
+    ) -> Result<AtlasLayouterOutput, String> {
+        L::layout(sizes, max_atlas_size).map_err(|e| format!("{e:?}"))
+    }
+
+    fn layout_rotating_erased(
+        &self,
+        sizes: Vec<(u32, u32)>,
+        max_atlas_size: MaxAtlasSize,
+    ) -> Result<AtlasLayouterOutput, String> {
+        L::layout_rotating(sizes, max_atlas_size).map_err(|e| format!("{e:?}"))
+    }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AtlasLayouterOutput {
     /// This should map texture indices to (atlas_idx, subtex_idx)
     pub entry_map: Vec<(usize, usize)>,
@@ -319,44 +1467,886 @@ pub struct MaxAtlasSize {
     /// Maximum width and height
     pub max_width_hight: u32,
     pub max_layers: u32,
+    /// Sub textures must be placed at multiples of this, so every mip level and compressed block of them starts at a whole texel
+    pub origin_alignment: u32,
+    /// The width and height of atlases must be multiples of this, the block size of compressed formats
+    pub size_alignment: u32,
+}
+
+impl MaxAtlasSize {
+    /// Without alignment, the alignment of the format and mip levels is added when a [AtlasGroupBuilder] is built
+    pub fn new(max_width_hight: u32, max_layers: u32) -> Self {
+        Self {
+            max_width_hight,
+            max_layers,
+            origin_alignment: 1,
+            size_alignment: 1,
+        }
+    }
+
+    /// Multiple of both alignments, rects of sizes that are multiples of it keep each other aligned when placed next to each other
+    pub fn rect_alignment(&self) -> u32 {
+        lcm(self.origin_alignment, self.size_alignment)
+    }
+
+    /// The size rounded up to the [rect alignment](Self::rect_alignment), layouters can place rects of this size next to each other
+    pub fn aligned_size(&self, size: (u32, u32)) -> (u32, u32) {
+        let alignment = self.rect_alignment();
+        (
+            size.0.next_multiple_of(alignment),
+            size.1.next_multiple_of(alignment),
+        )
+    }
+
+    /// The max width and height rounded down to the [rect alignment](Self::rect_alignment)
+    pub fn aligned_max(&self) -> u32 {
+        let alignment = self.rect_alignment();
+        self.max_width_hight / alignment * alignment
+    }
+
+    /// Padding before an image, rounded up so the image is aligned in an aligned rect
+    fn lead_padding(&self, padding: u32) -> u32 {
+        padding.next_multiple_of(self.origin_alignment)
+    }
+}
+
+/// The (origin, size) alignment of sub textures in atlases with the format and mip levels, see [MaxAtlasSize]
+fn layout_alignment(format: TextureFormat, mip_levels: u32) -> (u32, u32) {
+    let (block_width, block_height) = format.block_dimensions();
+    let size_alignment = lcm(block_width, block_height);
+    // the origin of every level must be a whole block
+    (
+        size_alignment << mip_levels.saturating_sub(1),
+        size_alignment,
+    )
+}
+
+fn lcm(a: u32, b: u32) -> u32 {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    a / x * b
+}
+
+/// The previous frame is submitted by now, so bind groups replaced during it are no longer used
+fn release_old_bind_groups(mut atlas_groups: ResMut<Assets<AtlasGroup>>) {
+    // only borrowed mutably when needed, to not trigger change detection every frame
+    if !atlas_groups
+        .iter()
+        .any(|(_, group)| group.has_old_bind_groups())
+    {
+        return;
+    }
+    for (_, group) in atlas_groups.iter_mut() {
+        group.release_old_bind_groups();
+    }
+}
+
+/// What building and changing atlas groups needs on the GPU
+#[derive(SystemParam)]
+struct AtlasGpu<'w> {
+    device: Res<'w, DeviceRes>,
+    queue: Res<'w, QueueRes>,
+    bind_layout: Res<'w, AtlasGroupBindGroupLayout>,
 }
 
 fn handle_atlas_group_queue<L: AtlasLayouter>(
     mut in_queue: ResMut<AtlasGroupQueue>,
     mut atlas_groups: ResMut<Assets<AtlasGroup>>,
-    bind_layout: Res<AtlasGroupBindGroupLayout>,
-    device: Res<DeviceRes>,
-    queue: Res<QueueRes>,
+    // optional, as atlases can be used without texture loading
+    mut memory_stats: Option<ResMut<TextureMemoryStats>>,
+    mut changed: EventWriter<AtlasGroupChanged>,
+    mut failed: EventWriter<AtlasBuildFailed>,
+    gpu: AtlasGpu,
 ) {
-    for (group, builder) in in_queue.0.drain(..) {
-        atlas_groups.replace(
-            group,
-            builder
-                .build::<L>(&device.0, &queue.0, &bind_layout)
-                .expect("error during atlas layout"),
+    let (device, queue, bind_layout) = (&gpu.device.0, &gpu.queue.0, &*gpu.bind_layout);
+    let in_queue = &mut *in_queue;
+    // the entry counts of these are set to the actual count once the queue is done
+    let mut failed_groups = HashSet::new();
+    for op in in_queue.queue.drain(..) {
+        match op {
+            AtlasGroupOperation::Init(group, builder, layouter) => {
+                let new = match layouter {
+                    Some(layouter) => builder
+                        .build_with_layouter(&*layouter, device, queue, bind_layout)
+                        .map_err(|e| e.to_string()),
+                    None => builder
+                        .build::<L>(device, queue, bind_layout)
+                        .map_err(|e| e.to_string()),
+                };
+                let new = match new {
+                    Ok(new) => new,
+                    Err(e) => {
+                        log::error!("failed to build atlas group: {e}");
+                        failed.send(AtlasBuildFailed { group, error: e });
+                        failed_groups.insert(group);
+                        continue;
+                    }
+                };
+                if let Some(stats) = memory_stats.as_mut() {
+                    for atlas in new.atlases() {
+                        stats.add(atlas.texture(), Some(ATLAS_TEXTURE_LABEL));
+                    }
+                }
+                let old = atlas_groups.replace(group, new);
+                if let (Some(stats), Some(old)) = (memory_stats.as_mut(), old) {
+                    for atlas in old.atlases() {
+                        stats.remove(atlas.texture());
+                    }
+                }
+                changed.send(AtlasGroupChanged { group });
+            }
+            AtlasGroupOperation::Insert(group, img, entry) => {
+                let Some(atlas_group) = atlas_groups.get_mut(group) else {
+                    // the group failed to build or was removed
+                    failed.send(AtlasBuildFailed {
+                        group,
+                        error: "image was added to an atlas group that does not exist".into(),
+                    });
+                    continue;
+                };
+                if atlas_group.entry_map().len() != entry.index() {
+                    // an operation queued before failed, so the image would get another entry
+                    failed.send(AtlasBuildFailed {
+                        group,
+                        error: format!(
+                            "entry {} was not added, as an operation on the group queued before it failed",
+                            entry.index()
+                        ),
+                    });
+                    failed_groups.insert(group);
+                    continue;
+                }
+                let atlas_count = atlas_group.atlas_count();
+                let inserted = atlas_group.insert_with(img, false, device, queue, bind_layout);
+                if let Err(e) = inserted {
+                    log::error!("failed to add image to atlas group: {e}");
+                    failed.send(AtlasBuildFailed {
+                        group,
+                        error: e.to_string(),
+                    });
+                    failed_groups.insert(group);
+                    continue;
+                }
+                if let Some(stats) = memory_stats.as_mut() {
+                    for atlas in &atlas_group.atlases()[atlas_count..] {
+                        stats.add(atlas.texture(), Some(ATLAS_TEXTURE_LABEL));
+                    }
+                }
+                changed.send(AtlasGroupChanged { group });
+            }
+            AtlasGroupOperation::RebuildBindGroups(group) => {
+                let Some(atlas_group) = atlas_groups.get_mut(group) else {
+                    log::warn!("bind groups were rebuilt for an atlas group that does not exist");
+                    continue;
+                };
+                atlas_group.rebuild_bind_groups(device, bind_layout);
+                changed.send(AtlasGroupChanged { group });
+            }
+            AtlasGroupOperation::Defragment(group) => {
+                let Some(atlas_group) = atlas_groups.get_mut(group) else {
+                    log::warn!("an atlas group that does not exist was defragmented");
+                    continue;
+                };
+                if !atlas_group.can_defragment() {
+                    failed.send(AtlasBuildFailed {
+                        group,
+                        error: "atlases must have COPY_SRC usage to be defragmented".into(),
+                    });
+                    continue;
+                }
+                if let Some(stats) = memory_stats.as_mut() {
+                    for atlas in atlas_group.atlases() {
+                        stats.remove(atlas.texture());
+                    }
+                }
+                // the atlases are only replaced if it succeeds, so they are counted again either way
+                let defragmented = atlas_group.defragment(device, queue, bind_layout);
+                if let Some(stats) = memory_stats.as_mut() {
+                    for atlas in atlas_group.atlases() {
+                        stats.add(atlas.texture(), Some(ATLAS_TEXTURE_LABEL));
+                    }
+                }
+                if let Err(e) = defragmented {
+                    log::error!("failed to defragment atlas group: {e}");
+                    failed.send(AtlasBuildFailed {
+                        group,
+                        error: e.to_string(),
+                    });
+                    continue;
+                }
+                changed.send(AtlasGroupChanged { group });
+            }
+        }
+    }
+    for group in failed_groups {
+        // groups that do not exist keep their count, as adding to them fails anyway
+        if let Some(atlas_group) = atlas_groups.get(group) {
+            in_queue
+                .entry_counts
+                .insert(group, atlas_group.entry_map().len());
+        }
+    }
+}
+
+/// The removed index an inserted entry reuses, the lowest index placed in the same atlas is preferred as its sub texture can be overwritten
+fn free_index(
+    removed: &HashSet<usize>,
+    entry_map: &[(usize, usize)],
+    atlas_idx: usize,
+) -> Option<usize> {
+    let same_atlas = removed
+        .iter()
+        .filter(|i| entry_map[**i].0 == atlas_idx)
+        .min();
+    same_atlas.or_else(|| removed.iter().min()).copied()
+}
+
+/// True if textures of the format can be sampled with a filtering sampler on the device
+fn is_filterable(format: TextureFormat, device: &Device) -> bool {
+    matches!(
+        format.sample_type(None, Some(device.features())),
+        Some(wgpu::TextureSampleType::Float { filterable: true })
+    )
+}
+
+/// True if the image does not have more than mip_levels levels, and is large enough to have them
+fn mip_levels_fit(img: &MipMapImage, mip_levels: u32) -> bool {
+    let size = img.sizes()[0];
+    let max_levels = 32 - size.0.max(size.1).leading_zeros();
+    img.level_count() as u32 <= mip_levels && mip_levels <= max_levels
+}
+
+/// Writes mip_levels levels of the image with extrude edge pixels around it, halving extrude for every level.
+/// Missing levels are generated from the last level of the image
+fn write_entry(
+    img: &MipMapImage,
+    subtex: &SubTexture,
+    mip_levels: u32,
+    extrude: u32,
+    queue: &Queue,
+    texture: &Texture,
+) {
+    let mut levels = img
+        .clone()
+        .generate_levels(MipFilter::default())
+        .to_levels();
+    let missing = mip_levels as usize - levels.len();
+    let generated =
+        mipmap::generate_levels(levels.last().unwrap(), missing + 1, MipFilter::default());
+    levels.extend(generated);
+    for (level, image) in levels.iter().enumerate() {
+        let level_extrude = extrude >> level;
+        let origin = Origin3d {
+            x: (subtex.x >> level) - level_extrude,
+            y: (subtex.y >> level) - level_extrude,
+            z: subtex.layer,
+        };
+        let image = if subtex.rotated {
+            image.rotated_90().extruded(level_extrude)
+        } else {
+            image.extruded(level_extrude)
+        };
+        MipMapImage::from(image).write_levels(
+            queue,
+            origin,
+            texture,
+            MipGeneration::default(),
+            level as u32,
         );
     }
 }
 
+/// Creates the bind groups of the atlases, see [AtlasGroup::new]
+fn create_bind_groups(
+    atlases: &[Atlas],
+    device: &Device,
+    layout: &AtlasGroupBindGroupLayout,
+    sampler: &Sampler,
+    entry_buffer: &Buffer,
+) -> Vec<BindGroup> {
+    let view_desc = TextureViewDescriptor {
+        label: Some("AtlasGroup TextureView"),
+        format: None,
+        // the layout uses arrays, atlases with a single layer would otherwise get a D2 view
+        dimension: Some(TextureViewDimension::D2Array),
+        aspect: TextureAspect::All,
+        base_mip_level: 0,
+        mip_level_count: None,
+        base_array_layer: 0,
+        array_layer_count: None,
+    };
+
+    let mut views: Vec<_> = atlases
+        .iter()
+        .map(|tex| tex.texture.create_view(&view_desc))
+        .collect();
+    let bind_group_views = bind_group_views(atlases.len(), layout.atlas_count());
+    // slots after the last atlas are bound to an empty texture, so shaders can not sample a real atlas out of range
+    if bind_group_views
+        .iter()
+        .flatten()
+        .any(|&i| i == atlases.len())
+    {
+        views.push(create_padding_texture(device).create_view(&view_desc));
+    }
+
+    bind_group_views
+        .iter()
+        .map(|view_indices| {
+            let entries = view_indices
+                .iter()
+                .enumerate()
+                .map(|(binding, &view_idx)| BindGroupEntry {
+                    binding: binding as u32,
+                    resource: wgpu::BindingResource::TextureView(&views[view_idx]),
+                })
+                .chain([
+                    BindGroupEntry {
+                        binding: layout.sampler_binding(),
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                    BindGroupEntry {
+                        binding: layout.entry_binding(),
+                        resource: entry_buffer.as_entire_binding(),
+                    },
+                ])
+                .collect::<Vec<_>>();
+
+            device.create_bind_group(&BindGroupDescriptor {
+                label: Some("AtlasGroup BindGroup"),
+                layout: layout.layout(),
+                entries: &entries,
+            })
+        })
+        .collect()
+}
+
+/// The bind group and binding of an atlas, with slots atlases in a bind group, see [bind_group_views]
+fn atlas_slot(atlas_idx: usize, slots: usize) -> (usize, usize) {
+    (atlas_idx / slots, atlas_idx % slots)
+}
+
+/// The index of the atlas bound to every binding of every bind group, with slots atlases in a bind group.
+/// Bindings after the last atlas get atlas_count, the index of the padding view after the atlas views
+fn bind_group_views(atlas_count: usize, slots: usize) -> Vec<Vec<usize>> {
+    (0..atlas_count.div_ceil(slots))
+        .map(|i| {
+            (0..slots)
+                .map(|binding| min(binding + i * slots, atlas_count))
+                .collect()
+        })
+        .collect()
+}
+
+/// A transparent 1x1 texture, bound to the unused slots of [AtlasGroup] bind groups
+fn create_padding_texture(device: &Device) -> Texture {
+    // new textures are zeroed, so nothing has to be written
+    device.create_texture(&TextureDescriptor {
+        label: Some("AtlasGroup Padding Texture"),
+        size: Extent3d {
+            width: 1,
+            height: 1,
+            // two layers like atlases, see create_atlas_texture
+            depth_or_array_layers: 2,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: TextureFormat::Rgba8UnormSrgb,
+        usage: TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    })
+}
+
 fn create_atlas_texture(
     device: &Device,
-    layout: &((u32, u32, u32), AtlasLayout),
-    descriptor: &AtlasGroupBuilder,
+    size: (u32, u32, u32),
+    format: TextureFormat,
+    mip_levels: u32,
+    usages: TextureUsages,
 ) -> Texture {
     let size = Extent3d {
-        width: layout.0 .0,
-        height: layout.0 .1,
-        depth_or_array_layers: layout.0 .2,
+        width: size.0,
+        height: size.1,
+        // GL backends bind textures with a single layer as 2D textures, which can not be sampled through the array views
+        depth_or_array_layers: size.2.max(2),
     };
-    let tex = device.create_texture(&TextureDescriptor {
-        label: Some("Atlas Texture"),
+
+    device.create_texture(&TextureDescriptor {
+        label: Some(ATLAS_TEXTURE_LABEL),
         size,
-        mip_level_count: descriptor.mip_levels,
+        mip_level_count: mip_levels,
         sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
-        format: TextureFormat::Rgba8UnormSrgb,
-        usage: descriptor.usages,
+        format,
+        usage: usages,
         view_formats: &[],
-    });
-    tex
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::system::RunSystemOnce;
+    use modula_core::request_headless_device;
+
+    use super::*;
+    use crate::{read_texture, test_utils::image, Image, PixelFormat};
+
+    /// A world with what [handle_atlas_group_queue] needs
+    fn atlas_world() -> World {
+        let (device, queue) =
+            request_headless_device().expect("no adapter found, not even the fallback adapter");
+        let mut world = World::new();
+        world.insert_resource(AtlasGroupBindGroupLayout::new(&device));
+        world.insert_resource(DeviceRes(device));
+        world.insert_resource(QueueRes(queue));
+        world.insert_resource(AtlasGroupQueue::new());
+        world.insert_resource(Assets::<AtlasGroup>::new());
+        world.insert_resource(Events::<AtlasGroupChanged>::default());
+        world.insert_resource(Events::<AtlasBuildFailed>::default());
+        world
+    }
+
+    fn handle_queue(world: &mut World) -> Vec<AtlasBuildFailed> {
+        handle_queue_with::<DefaultLayouter>(world)
+    }
+
+    fn handle_queue_with<L: AtlasLayouter + 'static>(world: &mut World) -> Vec<AtlasBuildFailed> {
+        world.run_system_once(handle_atlas_group_queue::<L>);
+        let mut failed = world.resource_mut::<Events<AtlasBuildFailed>>();
+        failed.drain().collect()
+    }
+
+    fn entry_size(world: &World, group: AssetId<AtlasGroup>, entry: AtlasGroupEntry) -> (u32, u32) {
+        let group = world.resource::<Assets<AtlasGroup>>().get(group).unwrap();
+        let (atlas, subtex) = group.entry_map()[entry.index()];
+        let subtex = &group.atlases()[atlas].layout().0[subtex];
+        (subtex.width, subtex.height)
+    }
+
+    /// The sub texture of the entry, and its texels in the first level of the atlas
+    fn read_entry(
+        world: &World,
+        group: AssetId<AtlasGroup>,
+        entry: AtlasGroupEntry,
+    ) -> (SubTexture, Vec<u8>) {
+        let group = world.resource::<Assets<AtlasGroup>>().get(group).unwrap();
+        let (atlas, subtex) = group.entry_map()[entry.index()];
+        let atlas = &group.atlases()[atlas];
+        let subtex = atlas.layout().0[subtex];
+        let texture = atlas.texture();
+        let data = read_texture(
+            &world.resource::<DeviceRes>().0,
+            &world.resource::<QueueRes>().0,
+            texture,
+            0,
+        );
+        let pixel_size = texture.format().block_copy_size(None).unwrap() as usize;
+        let width = texture.width() as usize;
+        let layer_size = width * texture.height() as usize * pixel_size;
+        let layer = &data[subtex.layer as usize * layer_size..];
+        let texels = (subtex.y..subtex.y + subtex.height)
+            .flat_map(|y| {
+                let start = (y as usize * width + subtex.x as usize) * pixel_size;
+                &layer[start..start + subtex.width as usize * pixel_size]
+            })
+            .copied()
+            .collect();
+        (subtex, texels)
+    }
+
+    #[test]
+    fn failed_insert_followed_by_successful_insert() {
+        let mut world = atlas_world();
+        let group = world.resource_mut::<Assets<AtlasGroup>>().add_empty();
+        let mut builder = AtlasGroupBuilder::new(1);
+        builder.add_image(image(4, 4, PixelFormat::Rgba8));
+        world
+            .resource_mut::<AtlasGroupQueue>()
+            .init_group(group, builder);
+        assert!(handle_queue(&mut world).is_empty());
+
+        // an R8 image can not be written to the sRGB atlas
+        let mut atlas_queue = world.resource_mut::<AtlasGroupQueue>();
+        let failing = atlas_queue.add_to_group(group, image(8, 8, PixelFormat::R8));
+        let after_failing = atlas_queue.add_to_group(group, image(6, 6, PixelFormat::Rgba8));
+        assert_eq!((failing.index(), after_failing.index()), (1, 2));
+        // the image queued after the failed one would have gotten entry 1, so it fails too
+        assert_eq!(handle_queue(&mut world).len(), 2);
+        let atlas_groups = world.resource::<Assets<AtlasGroup>>();
+        assert_eq!(atlas_groups.get(group).unwrap().entry_map().len(), 1);
+
+        let entry = world
+            .resource_mut::<AtlasGroupQueue>()
+            .add_to_group(group, image(10, 10, PixelFormat::Rgba8));
+        assert_eq!(entry.index(), 1);
+        assert!(handle_queue(&mut world).is_empty());
+        assert_eq!(entry_size(&world, group, entry), (10, 10));
+
+        let entry = world
+            .resource_mut::<AtlasGroupQueue>()
+            .add_to_group(group, image(12, 12, PixelFormat::Rgba8));
+        assert_eq!(entry.index(), 2);
+        assert!(handle_queue(&mut world).is_empty());
+        assert_eq!(entry_size(&world, group, entry), (12, 12));
+    }
+
+    #[test]
+    fn failed_rebuild_keeps_entry_count() {
+        let mut world = atlas_world();
+        let group = world.resource_mut::<Assets<AtlasGroup>>().add_empty();
+        let mut builder = AtlasGroupBuilder::new(1);
+        builder.add_image(image(4, 4, PixelFormat::Rgba8));
+        world
+            .resource_mut::<AtlasGroupQueue>()
+            .init_group(group, builder);
+        assert!(handle_queue(&mut world).is_empty());
+
+        // too many mip levels for the images, so the old group is kept
+        let mut builder = AtlasGroupBuilder::new(8);
+        builder.add_image(image(4, 4, PixelFormat::Rgba8));
+        builder.add_image(image(4, 4, PixelFormat::Rgba8));
+        world
+            .resource_mut::<AtlasGroupQueue>()
+            .init_group(group, builder);
+        assert_eq!(handle_queue(&mut world).len(), 1);
+
+        let entry = world
+            .resource_mut::<AtlasGroupQueue>()
+            .add_to_group(group, image(6, 6, PixelFormat::Rgba8));
+        assert_eq!(entry.index(), 1);
+        assert!(handle_queue(&mut world).is_empty());
+        assert_eq!(entry_size(&world, group, entry), (6, 6));
+    }
+
+    #[test]
+    fn single_channel_entry_round_trip() {
+        let mut world = atlas_world();
+        let group = world.resource_mut::<Assets<AtlasGroup>>().add_empty();
+        let mut builder = AtlasGroupBuilder::with_usages(
+            TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_SRC,
+            1,
+        );
+        builder.set_format(TextureFormat::R8Unorm);
+        let mut mask = image(5, 3, PixelFormat::R8);
+        mask.data = (0..15).map(|i| i * 17).collect();
+        let data = mask.data.clone();
+        let entry = builder.add_image(mask);
+        world
+            .resource_mut::<AtlasGroupQueue>()
+            .init_group(group, builder);
+        assert!(handle_queue(&mut world).is_empty());
+        assert_eq!(read_entry(&world, group, entry).1, data);
+    }
+
+    #[test]
+    fn rotated_entry_matches_image() {
+        let mut world = atlas_world();
+        let group = world.resource_mut::<Assets<AtlasGroup>>().add_empty();
+        let mut builder = AtlasGroupBuilder::with_usages(
+            TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_SRC,
+            1,
+        );
+        builder.set_allow_rotation(true);
+        // every texel is different, so a wrong rotation or flip can not match
+        let (width, height) = (3, 5);
+        let mut tall = image(width, height, PixelFormat::Rgba8);
+        tall.data = (0..width * height)
+            .flat_map(|i| [i as u8 * 10, 255 - i as u8, 0, 255])
+            .collect();
+        let texel = |x: u32, y: u32| {
+            let start = (y * width + x) as usize * 4;
+            tall.data[start..start + 4].to_vec()
+        };
+        // rotated clockwise, the left column of the image becomes the top row
+        let expected: Vec<u8> = (0..width)
+            .flat_map(|x| (0..height).rev().flat_map(move |y| texel(x, y)))
+            .collect();
+        let entry = builder.add_image(tall.clone());
+        world
+            .resource_mut::<AtlasGroupQueue>()
+            .init_group(group, builder);
+        assert!(handle_queue_with::<ShelfLayouter>(&mut world).is_empty());
+
+        let (subtex, texels) = read_entry(&world, group, entry);
+        assert!(subtex.rotated);
+        assert_eq!((subtex.width, subtex.height), (height, width));
+        assert_eq!(texels, expected);
+
+        // the UV corners must point at the texels of the image corners
+        let atlas_groups = world.resource::<Assets<AtlasGroup>>();
+        let texture = atlas_groups.get(group).unwrap().atlases()[0].texture();
+        let atlas_size = (texture.width(), texture.height());
+        let corners = subtex.uv_corners(atlas_size);
+        let image_corners = [
+            (0, 0),
+            (width - 1, 0),
+            (width - 1, height - 1),
+            (0, height - 1),
+        ];
+        for (uv, (x, y)) in corners.into_iter().zip(image_corners) {
+            // the corner texel is the one inside the sub texture next to the UV
+            let u = (uv[0] * atlas_size.0 as f32).round() as u32;
+            let v = (uv[1] * atlas_size.1 as f32).round() as u32;
+            let local_x = (u - subtex.x).min(subtex.width - 1);
+            let local_y = (v - subtex.y).min(subtex.height - 1);
+            let start = (local_y * subtex.width + local_x) as usize * 4;
+            assert_eq!(texels[start..start + 4], texel(x, y));
+        }
+    }
+
+    #[test]
+    fn format_mismatch_is_a_build_error() {
+        let validate = |builder: &AtlasGroupBuilder| builder.validate::<()>().map(|_| ());
+        let mut builder = AtlasGroupBuilder::new(1);
+        builder.add_image(image(4, 4, PixelFormat::Rgba8));
+        builder.add_image(image(4, 4, PixelFormat::Rgba8));
+        assert_eq!(validate(&builder), Ok(()));
+
+        // an R8 image with a pixel format other than the first image
+        builder.add_image(image(4, 4, PixelFormat::R8));
+        assert!(matches!(
+            validate(&builder),
+            Err(AtlasBuildError::Format { entry_index: 2, .. })
+        ));
+
+        // an R8 image can not be written to the sRGB atlas
+        let mut builder = AtlasGroupBuilder::new(1);
+        builder.add_image(image(4, 4, PixelFormat::R8));
+        assert!(matches!(
+            validate(&builder),
+            Err(AtlasBuildError::Format { entry_index: 0, .. })
+        ));
+        let mut builder = AtlasGroupBuilder::new(1);
+        builder.set_format(TextureFormat::R8Unorm);
+        builder.add_image(image(4, 4, PixelFormat::R8));
+        assert_eq!(validate(&builder), Ok(()));
+    }
+
+    #[test]
+    fn alignment_of_formats() {
+        let bc1 = TextureFormat::Bc1RgbaUnormSrgb;
+        assert_eq!(layout_alignment(bc1, 1), (4, 4));
+        // every level of a block must start at a whole block
+        assert_eq!(layout_alignment(bc1, 3), (16, 4));
+        assert_eq!(layout_alignment(TextureFormat::Bc7RgbaUnorm, 5), (64, 4));
+        assert_eq!(layout_alignment(TextureFormat::Rgba8UnormSrgb, 1), (1, 1));
+        assert_eq!(layout_alignment(TextureFormat::Rgba8UnormSrgb, 4), (8, 1));
+        // no mip levels is treated as one
+        assert_eq!(layout_alignment(bc1, 0), (4, 4));
+    }
+
+    #[test]
+    fn aligned_sizes_and_padding() {
+        let (origin_alignment, size_alignment) =
+            layout_alignment(TextureFormat::Bc1RgbaUnormSrgb, 3);
+        let max = MaxAtlasSize {
+            origin_alignment,
+            size_alignment,
+            ..MaxAtlasSize::new(1000, 1)
+        };
+        assert_eq!(max.rect_alignment(), 16);
+        assert_eq!(max.aligned_size((5, 17)), (16, 32));
+        assert_eq!(max.aligned_size((16, 32)), (16, 32));
+        assert_eq!(max.aligned_size((0, 1)), (0, 16));
+        assert_eq!(max.aligned_max(), 992);
+        assert_eq!(max.lead_padding(0), 0);
+        assert_eq!(max.lead_padding(1), 16);
+        assert_eq!(max.lead_padding(17), 32);
+
+        let bc1 = layout_alignment(TextureFormat::Bc1RgbaUnormSrgb, 1);
+        let max = MaxAtlasSize {
+            origin_alignment: bc1.0,
+            size_alignment: bc1.1,
+            ..MaxAtlasSize::new(1000, 1)
+        };
+        assert_eq!(max.aligned_size((1, 6)), (4, 8));
+        assert_eq!(max.aligned_max(), 1000);
+        assert_eq!(max.lead_padding(1), 4);
+
+        let unaligned = MaxAtlasSize::new(1000, 1);
+        assert_eq!(unaligned.aligned_size((5, 17)), (5, 17));
+        assert_eq!(unaligned.lead_padding(3), 3);
+    }
+
+    #[test]
+    fn lcm_edge_values() {
+        assert_eq!(lcm(1, 1), 1);
+        assert_eq!(lcm(4, 4), 4);
+        assert_eq!(lcm(1, 7), 7);
+        assert_eq!(lcm(7, 1), 7);
+        assert_eq!(lcm(8, 4), 8);
+        assert_eq!(lcm(4, 6), 12);
+        assert_eq!(lcm(5, 3), 15);
+        // divided before multiplying, so large equal values do not overflow
+        assert_eq!(lcm(1 << 31, 1 << 31), 1 << 31);
+        assert_eq!(lcm(1 << 16, 3), 3 << 16);
+    }
+
+    #[test]
+    fn layouters_keep_alignment() {
+        let sizes = vec![(5, 5), (3, 7), (13, 2), (1, 1), (30, 9)];
+        let (origin_alignment, size_alignment) =
+            layout_alignment(TextureFormat::Bc1RgbaUnormSrgb, 2);
+        let max = || MaxAtlasSize {
+            origin_alignment,
+            size_alignment,
+            ..MaxAtlasSize::new(256, 4)
+        };
+        for output in [
+            DefaultLayouter::layout(sizes.clone(), max()).unwrap(),
+            ShelfLayouter::layout(sizes.clone(), max()).unwrap(),
+        ] {
+            for ((width, height, _), _) in &output.atlases {
+                assert_eq!((width % size_alignment, height % size_alignment), (0, 0));
+            }
+            for (&(atlas_idx, subtex_idx), size) in output.entry_map.iter().zip(&sizes) {
+                let subtex = &output.atlases[atlas_idx].1 .0[subtex_idx];
+                let origin = (subtex.x % origin_alignment, subtex.y % origin_alignment);
+                assert_eq!(origin, (0, 0));
+                assert_eq!((subtex.width, subtex.height), *size);
+            }
+        }
+    }
+
+    #[test]
+    fn free_index_prefers_the_same_atlas() {
+        let entry_map = [(0, 0), (1, 0), (0, 1), (1, 1)];
+        assert_eq!(free_index(&HashSet::new(), &entry_map, 0), None);
+        let removed = HashSet::from_iter([3, 1, 2]);
+        assert_eq!(free_index(&removed, &entry_map, 0), Some(2));
+        assert_eq!(free_index(&removed, &entry_map, 1), Some(1));
+        // no removed entry is in atlas 2
+        assert_eq!(free_index(&removed, &entry_map, 2), Some(1));
+    }
+
+    /// A built group with COPY_SRC usage and an entry for every value, the texels of an entry are its value
+    fn defragmentable_group(world: &mut World, values: &[u8]) -> AssetId<AtlasGroup> {
+        let group = world.resource_mut::<Assets<AtlasGroup>>().add_empty();
+        let usages = DEFAULT_ATLAS_USAGES | TextureUsages::COPY_SRC;
+        let mut builder = AtlasGroupBuilder::with_usages(usages, 1);
+        for value in values {
+            builder.add_image(filled(8, 8, *value));
+        }
+        world
+            .resource_mut::<AtlasGroupQueue>()
+            .init_group(group, builder);
+        assert!(handle_queue(world).is_empty());
+        group
+    }
+
+    fn filled(width: u32, height: u32, value: u8) -> Image {
+        Image {
+            data: vec![value; width as usize * height as usize * 4],
+            ..image(width, height, PixelFormat::Rgba8)
+        }
+    }
+
+    fn insert_direct(world: &mut World, group: AssetId<AtlasGroup>, img: Image) -> AtlasGroupEntry {
+        world.resource_scope(|world, mut atlas_groups: Mut<Assets<AtlasGroup>>| {
+            atlas_groups
+                .get_mut(group)
+                .unwrap()
+                .insert(
+                    img,
+                    &world.resource::<DeviceRes>().0,
+                    &world.resource::<QueueRes>().0,
+                    world.resource::<AtlasGroupBindGroupLayout>(),
+                )
+                .unwrap()
+        })
+    }
+
+    fn remove_direct(world: &mut World, group: AssetId<AtlasGroup>, entry: usize) {
+        let mut atlas_groups = world.resource_mut::<Assets<AtlasGroup>>();
+        let atlas_group = atlas_groups.get_mut(group).unwrap();
+        atlas_group.remove_entry(AtlasGroupEntry::from_index(entry));
+    }
+
+    #[test]
+    fn insert_reuses_removed_entries() {
+        let mut world = atlas_world();
+        let group = defragmentable_group(&mut world, &[10, 20, 30]);
+        let occupancy = |world: &World| {
+            let atlas_groups = world.resource::<Assets<AtlasGroup>>();
+            atlas_groups.get(group).unwrap().occupancy(0)
+        };
+        let full = occupancy(&world);
+        remove_direct(&mut world, group, 1);
+        assert_eq!(occupancy(&world), full * 2.0 / 3.0);
+
+        let entry = insert_direct(&mut world, group, filled(8, 8, 40));
+        assert_eq!(entry.index(), 1);
+        assert_eq!(occupancy(&world), full);
+        let atlas_groups = world.resource::<Assets<AtlasGroup>>();
+        let atlas_group = atlas_groups.get(group).unwrap();
+        assert_eq!(atlas_group.entry_map().len(), 3);
+        assert!(!atlas_group.is_removed(entry));
+        // the sub texture of the removed entry was overwritten
+        assert_eq!(atlas_group.atlases()[0].layout().0.len(), 3);
+        for (index, value) in [(0, 10), (1, 40), (2, 30)] {
+            let (_, texels) = read_entry(&world, group, AtlasGroupEntry::from_index(index));
+            assert!(texels.iter().all(|texel| *texel == value));
+        }
+
+        // nothing is removed, so a new index is used
+        let entry = insert_direct(&mut world, group, filled(8, 8, 50));
+        assert_eq!(entry.index(), 3);
+    }
+
+    #[test]
+    fn removed_entries_are_reused_after_defragment() {
+        let mut world = atlas_world();
+        let group = defragmentable_group(&mut world, &[10, 20, 30, 40]);
+        remove_direct(&mut world, group, 0);
+        remove_direct(&mut world, group, 2);
+        world.resource_mut::<AtlasGroupQueue>().defragment(group);
+        assert!(handle_queue(&mut world).is_empty());
+        {
+            let atlas_groups = world.resource::<Assets<AtlasGroup>>();
+            let atlas_group = atlas_groups.get(group).unwrap();
+            assert_eq!(atlas_group.entry_map().len(), 4);
+            assert!(atlas_group.is_removed(AtlasGroupEntry::from_index(0)));
+            assert!(atlas_group.is_removed(AtlasGroupEntry::from_index(2)));
+        }
+        for (index, value) in [(1, 20), (3, 40)] {
+            let (_, texels) = read_entry(&world, group, AtlasGroupEntry::from_index(index));
+            assert!(texels.iter().all(|texel| *texel == value));
+        }
+
+        let first = insert_direct(&mut world, group, filled(8, 8, 50));
+        let second = insert_direct(&mut world, group, filled(4, 4, 60));
+        let third = insert_direct(&mut world, group, filled(4, 4, 70));
+        assert_eq!((first.index(), second.index(), third.index()), (0, 2, 4));
+        for (index, value) in [(0, 50), (1, 20), (2, 60), (3, 40), (4, 70)] {
+            let (_, texels) = read_entry(&world, group, AtlasGroupEntry::from_index(index));
+            assert!(texels.iter().all(|texel| *texel == value));
+        }
+    }
+
+    #[test]
+    fn bind_group_views_pad_the_last_group() {
+        // the padding view is index 3
+        assert_eq!(bind_group_views(3, 4), [[0, 1, 2, 3]]);
+        assert_eq!(bind_group_views(4, 4), [[0, 1, 2, 3]]);
+        assert_eq!(
+            bind_group_views(10, 4),
+            [[0, 1, 2, 3], [4, 5, 6, 7], [8, 9, 10, 10]]
+        );
+        assert_eq!(bind_group_views(3, 1), [[0], [1], [2]]);
+        assert!(bind_group_views(0, 4).is_empty());
+    }
+
+    #[test]
+    fn atlas_slots_match_bind_group_views() {
+        for (atlas_count, slots) in [(3, 4), (10, 4), (8, 4), (5, 1)] {
+            let views = bind_group_views(atlas_count, slots);
+            for atlas_idx in 0..atlas_count {
+                let (bind_group, binding) = atlas_slot(atlas_idx, slots);
+                assert_eq!(views[bind_group][binding], atlas_idx);
+            }
+        }
+    }
 }
@@ -0,0 +1,105 @@
+use super::{
+    AtlasBuildError, AtlasGroup, AtlasGroupBuilder, AtlasLayout, AtlasLayouter, MaxAtlasSize,
+};
+use crate::Image;
+
+impl AtlasGroup {
+    /// Draws the sub textures of an atlas as rects with a darker border, colored by their entry, to see how it is packed.
+    /// The layers are drawn below each other, separated by a gray line
+    /// ## Panics
+    /// If the atlas is not in the group
+    pub fn debug_image(&self, atlas_index: usize) -> Image {
+        let atlas = &self.atlases[atlas_index];
+        let (width, height) = atlas.size();
+        draw_layout(
+            (width, height, atlas.layer_count()),
+            &atlas.layout,
+            &subtex_entries(&self.entry_map, atlas_index, atlas.layout.0.len()),
+        )
+    }
+}
+
+impl AtlasGroupBuilder {
+    /// Lays out the images with L without creating any GPU resources, and draws every atlas like [AtlasGroup::debug_image].  
+    /// max_atlas_size is used instead of the device limits, lowered by [set_max_size](Self::set_max_size)
+    pub fn debug_pack_preview<L: AtlasLayouter>(
+        &self,
+        max_atlas_size: MaxAtlasSize,
+    ) -> Result<Vec<Image>, AtlasBuildError<L::Error>> {
+        let output = self.pack(self.layout_fn::<L>(), self.clamp_max_size(max_atlas_size))?;
+        Ok(output
+            .atlases
+            .iter()
+            .enumerate()
+            .map(|(atlas_index, (size, layout))| {
+                let entries = subtex_entries(&output.entry_map, atlas_index, layout.0.len());
+                draw_layout(*size, layout, &entries)
+            })
+            .collect())
+    }
+}
+
+/// The entry of every sub texture in an atlas, sub textures without an entry get their own index
+fn subtex_entries(
+    entry_map: &[(usize, usize)],
+    atlas_index: usize,
+    subtex_count: usize,
+) -> Vec<usize> {
+    let mut entries: Vec<_> = (0..subtex_count).collect();
+    for (entry, (atlas_idx, subtex_idx)) in entry_map.iter().enumerate() {
+        if *atlas_idx == atlas_index {
+            entries[*subtex_idx] = entry;
+        }
+    }
+    entries
+}
+
+fn draw_layout(size: (u32, u32, u32), layout: &AtlasLayout, entries: &[usize]) -> Image {
+    let (width, height, layers) = size;
+    let image_height = height * layers + layers.saturating_sub(1);
+    let mut data = vec![0; width as usize * image_height as usize * 4];
+    let mut fill = |x: u32, y: u32, w: u32, h: u32, color: [u8; 4]| {
+        for row in y..y + h {
+            let start = (row as usize * width as usize + x as usize) * 4;
+            for pixel in data[start..start + w as usize * 4].chunks_exact_mut(4) {
+                pixel.copy_from_slice(&color);
+            }
+        }
+    };
+    for layer in 1..layers {
+        fill(0, layer * (height + 1) - 1, width, 1, [128, 128, 128, 255]);
+    }
+    for (subtex, entry) in layout.0.iter().zip(entries) {
+        let color = entry_color(*entry);
+        let border = color.map(|c| c / 2);
+        let y = subtex.y + subtex.layer * (height + 1);
+        fill(subtex.x, y, subtex.width, subtex.height, border);
+        if subtex.width > 2 && subtex.height > 2 {
+            fill(
+                subtex.x + 1,
+                y + 1,
+                subtex.width - 2,
+                subtex.height - 2,
+                color,
+            );
+        }
+    }
+    Image::from_raw_rgba8(width, image_height, data).expect("data has the size of the image")
+}
+
+/// A bright color that is always the same for an entry
+fn entry_color(entry: usize) -> [u8; 4] {
+    // multiplicative hashing spreads close indices over the hue
+    let hue = (entry as u32).wrapping_mul(2654435769) as f32 / u32::MAX as f32 * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let (r, g, b) = match hue as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    let channel = |c: f32| (c * 191.0) as u8 + 64;
+    [channel(r), channel(g), channel(b), 255]
+}
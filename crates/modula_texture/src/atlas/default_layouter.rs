@@ -16,21 +16,26 @@ impl AtlasLayouter for DefaultLayouter {
         sizes: Vec<(u32, u32)>,
         max_atlas_size: MaxAtlasSize,
     ) -> Result<AtlasLayouterOutput, RectanglePackError> {
+        // rects are placed next to each other, so aligned sizes give aligned origins
         let mut rects = GroupedRectsToPlace::new();
         for (i, s) in sizes.iter().enumerate() {
+            let s = max_atlas_size.aligned_size(*s);
             rects.push_rect(i, None, RectToInsert::new(s.0, s.1, 1));
         }
+        // atlas sizes are searched in steps of the alignment
+        let alignment = max_atlas_size.rect_alignment();
+        let max_steps = (max_atlas_size.max_width_hight / alignment).max(1);
         let res = modula_utils::binsearch(
-            |i| attempt(i as u32, 1, 1, &rects),
-            1..max_atlas_size.max_width_hight as i32 + 1,
+            |i| attempt(i as u32 * alignment, 1, 1, &rects),
+            1..max_steps as i32 + 1,
         );
         if res.is_ok() {
-            return res;
+            return res.map(|output| with_sizes(output, &sizes));
         }
         modula_utils::binsearch_upwards(
             |i| {
                 attempt(
-                    max_atlas_size.max_width_hight,
+                    max_steps * alignment,
                     max_atlas_size.max_layers,
                     i as u32,
                     &rects,
@@ -38,9 +43,19 @@ impl AtlasLayouter for DefaultLayouter {
             },
             1,
         )
+        .map(|output| with_sizes(output, &sizes))
     }
 }
 
+/// Sets the sizes of the sub textures back to the requested sizes, from the aligned sizes they were packed with
+fn with_sizes(mut output: AtlasLayouterOutput, sizes: &[(u32, u32)]) -> AtlasLayouterOutput {
+    for ((atlas_idx, subtex_idx), size) in output.entry_map.iter().zip(sizes) {
+        let subtex = &mut output.atlases[*atlas_idx].1 .0[*subtex_idx];
+        (subtex.width, subtex.height) = *size;
+    }
+    output
+}
+
 fn attempt(
     wh: u32,
     max_depth: u32,
@@ -53,7 +68,7 @@ fn attempt(
         bins.insert(i, TargetBin::new(wh, wh, 1));
     }
     let packing =
-        rectangle_pack::pack_rects(&rects, &mut bins, &volume_heuristic, &contains_smallest_box)?;
+        rectangle_pack::pack_rects(rects, &mut bins, &volume_heuristic, &contains_smallest_box)?;
     let res = packing.packed_locations();
 
     let mut layout = vec![
@@ -62,7 +77,9 @@ fn attempt(
             x: 0,
             y: 0,
             width: 0,
-            height: 0
+            height: 0,
+            trim: None,
+            rotated: false,
         };
         res.len()
     ];
@@ -73,6 +90,8 @@ fn attempt(
             y: location.y(),
             width: location.width(),
             height: location.height(),
+            trim: None,
+            rotated: false,
         };
     }
 
@@ -80,15 +99,8 @@ fn attempt(
     let mut atlases = Vec::with_capacity(atlas_count as usize);
     for i in 0..atlas_count {
         atlases.push((
-            (
-                wh,
-                wh,
-                if i == atlas_count - 1 {
-                    layers % max_depth
-                } else {
-                    max_depth
-                },
-            ),
+            // the last atlas gets the remaining layers, which is max_depth if layers is a multiple of it
+            (wh, wh, max_depth.min(layers - i * max_depth)),
             AtlasLayout(Vec::new()),
         ));
     }
@@ -103,3 +115,56 @@ fn attempt(
 
     Ok(AtlasLayouterOutput { entry_map, atlases })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lays out layers images filling a layer each, with max_layers layers in an atlas
+    fn layout_layers(layers: usize, max_layers: u32) -> AtlasLayouterOutput {
+        let output =
+            DefaultLayouter::layout(vec![(16, 16); layers], MaxAtlasSize::new(16, max_layers))
+                .unwrap();
+        assert_eq!(output.entry_map.len(), layers);
+        let mut used = Vec::new();
+        for &(atlas_idx, subtex_idx) in &output.entry_map {
+            let ((_, _, depth), layout) = &output.atlases[atlas_idx];
+            let subtex = &layout.0[subtex_idx];
+            assert!(subtex.layer < *depth);
+            assert_eq!((subtex.width, subtex.height), (16, 16));
+            used.push((atlas_idx, subtex.layer));
+        }
+        // every image has a layer of its own
+        used.sort();
+        used.dedup();
+        assert_eq!(used.len(), layers);
+        for ((_, _, depth), layout) in &output.atlases {
+            assert!((1..=max_layers).contains(depth));
+            assert_eq!(layout.0.len(), *depth as usize);
+        }
+        output
+    }
+
+    fn depths(output: &AtlasLayouterOutput) -> Vec<u32> {
+        output.atlases.iter().map(|(size, _)| size.2).collect()
+    }
+
+    #[test]
+    fn layers_equal_to_max_layers() {
+        assert_eq!(depths(&layout_layers(4, 4)), [4]);
+        assert_eq!(depths(&layout_layers(1, 1)), [1]);
+    }
+
+    #[test]
+    fn layers_multiple_of_max_layers() {
+        assert_eq!(depths(&layout_layers(8, 4)), [4, 4]);
+        assert_eq!(depths(&layout_layers(3, 1)), [1, 1, 1]);
+    }
+
+    #[test]
+    fn layers_not_multiple_of_max_layers() {
+        assert_eq!(depths(&layout_layers(5, 4)), [4, 1]);
+        assert_eq!(depths(&layout_layers(7, 3)), [3, 3, 1]);
+        assert_eq!(depths(&layout_layers(2, 3)), [2]);
+    }
+}
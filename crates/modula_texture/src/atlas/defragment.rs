@@ -0,0 +1,275 @@
+use modula_utils::HashSet;
+use wgpu::{
+    CommandEncoderDescriptor, Device, Extent3d, ImageCopyTexture, Origin3d, Queue, TextureAspect,
+    TextureUsages,
+};
+
+use super::{
+    create_atlas_texture, Atlas, AtlasGroup, AtlasGroupBindGroupLayout, AtlasGroupEntry,
+    AtlasLayout, AtlasLayouter, MaxAtlasSize, ShelfLayoutError, ShelfLayouter, SubTexture,
+};
+
+impl AtlasGroup {
+    /// Removes an entry, so its index and space can be used by entries added with [insert](Self::insert) and it is left out by [defragment](Self::defragment).
+    /// Its name and metadata are removed. The entry must not be used after,
+    /// as its [EntryUv](super::EntryUv) may point to the space of an entry inserted later. Removing an entry again does nothing
+    /// ## Panics
+    /// If the entry is not in the group
+    pub fn remove_entry(&mut self, entry: AtlasGroupEntry) {
+        assert!(
+            entry.index() < self.entry_map.len(),
+            "only entries in the group can be removed"
+        );
+        if !self.removed.insert(entry.index()) {
+            return;
+        }
+        self.names.retain(|_, named| named.index() != entry.index());
+        self.meta.remove(entry);
+        self.create_allocators();
+        let (atlas_idx, subtex_idx) = self.entry_map[entry.index()];
+        let subtex = self.atlases[atlas_idx].layout.0[subtex_idx];
+        let (x, y, width, height) = self.padded_rect(&subtex, &self.alignment());
+        self.allocators[atlas_idx].deallocate(subtex.layer, x, y, width, height);
+    }
+
+    #[inline]
+    pub fn is_removed(&self, entry: AtlasGroupEntry) -> bool {
+        self.removed.contains(&entry.index())
+    }
+
+    /// Fraction of the texels of an atlas used by entries that are not removed, without padding.
+    /// A low occupancy after removing entries means [defragment](Self::defragment) could use fewer atlases
+    /// ## Panics
+    /// If the atlas is not in the group
+    pub fn occupancy(&self, atlas_index: usize) -> f32 {
+        let atlas = &self.atlases[atlas_index];
+        let used = used_texels(&self.entry_map, &self.removed, atlas_index, &atlas.layout);
+        let (width, height) = atlas.size();
+        used as f64 as f32 / (width as u64 * height as u64 * atlas.layer_count() as u64) as f32
+    }
+
+    /// True if the atlases have [COPY_SRC](TextureUsages::COPY_SRC) usage, which [defragment](Self::defragment) needs
+    pub fn can_defragment(&self) -> bool {
+        self.atlases
+            .iter()
+            .all(|atlas| atlas.texture.usage().contains(TextureUsages::COPY_SRC))
+    }
+
+    /// Packs the entries that are not removed into new atlases with [ShelfLayouter], copying them on the GPU, usually giving fewer atlases.
+    /// Entries keep their index, but their [EntryUv](super::EntryUv) and the bind groups change, so [AtlasGroupChanged](super::AtlasGroupChanged) should be sent,
+    /// this is done by [AtlasGroupQueue::defragment](super::AtlasGroupQueue::defragment).
+    /// The [entry buffer](Self::entry_buffer) is made again along with the bind groups.
+    /// Removed entries stay removed, so their index can still be reused by [insert](Self::insert).
+    /// If the entries can not be packed the group is left as it was and the error is returned
+    /// ## Panics
+    /// If the atlases do not have [COPY_SRC](TextureUsages::COPY_SRC) usage, see [can_defragment](Self::can_defragment)
+    pub fn defragment(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        layout: &AtlasGroupBindGroupLayout,
+    ) -> Result<(), ShelfLayoutError> {
+        assert!(
+            self.can_defragment(),
+            "atlases must have COPY_SRC usage to be defragmented"
+        );
+        let (format, mip_levels, usages) = self.atlas_options();
+        let alignment = self.alignment();
+        // (entry index, atlas index, sub texture, padded rect) of the entries that are kept
+        let kept: Vec<_> = (0..self.entry_map.len())
+            .filter(|i| !self.removed.contains(i))
+            .map(|i| {
+                let (atlas_idx, subtex_idx) = self.entry_map[i];
+                let subtex = self.atlases[atlas_idx].layout.0[subtex_idx];
+                (i, atlas_idx, subtex, self.padded_rect(&subtex, &alignment))
+            })
+            .collect();
+        let max_layers = self
+            .atlases
+            .iter()
+            .map(Atlas::layer_count)
+            .max()
+            .unwrap_or(1);
+        let output = ShelfLayouter::layout(
+            kept.iter().map(|(.., rect)| (rect.2, rect.3)).collect(),
+            MaxAtlasSize {
+                max_layers,
+                ..alignment
+            },
+        )?;
+
+        let mut atlases: Vec<_> = output
+            .atlases
+            .iter()
+            .map(|(size, _)| {
+                let texture = create_atlas_texture(device, *size, format, mip_levels, usages);
+                Atlas::new(texture, AtlasLayout(Vec::new()))
+            })
+            .collect();
+        // removed entries point to an empty sub texture, so their UVs are still valid
+        let mut entry_map = vec![(0, 0); self.entry_map.len()];
+        if !self.removed.is_empty() {
+            atlases[0].layout.0.push(SubTexture {
+                layer: 0,
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+                trim: None,
+                rotated: false,
+            });
+        }
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("AtlasGroup Defragment"),
+        });
+        for ((entry_idx, old_atlas, subtex, rect), (atlas_idx, placed_idx)) in
+            kept.into_iter().zip(&output.entry_map)
+        {
+            let placed = output.atlases[*atlas_idx].1 .0[*placed_idx];
+            let new_atlas = &mut atlases[*atlas_idx];
+            for level in 0..mip_levels {
+                copy_rect(
+                    &mut encoder,
+                    (&self.atlases[old_atlas], (rect.0, rect.1, subtex.layer)),
+                    (new_atlas, (placed.x, placed.y, placed.layer)),
+                    (rect.2, rect.3),
+                    level,
+                );
+            }
+            // the image keeps its offset in the padded rect
+            new_atlas.layout.0.push(SubTexture {
+                layer: placed.layer,
+                x: placed.x + subtex.x - rect.0,
+                y: placed.y + subtex.y - rect.1,
+                ..subtex
+            });
+            entry_map[entry_idx] = (*atlas_idx, new_atlas.layout.0.len() - 1);
+        }
+        queue.submit([encoder.finish()]);
+
+        self.atlases = atlases;
+        self.entry_map = entry_map;
+        self.allocators.clear();
+        self.rebuild_bind_groups(device, layout);
+        Ok(())
+    }
+
+    /// The rect of a sub texture with its padding, (x, y, width, height), as it was allocated by the layouter or [insert](Self::insert)
+    fn padded_rect(&self, subtex: &SubTexture, alignment: &MaxAtlasSize) -> (u32, u32, u32, u32) {
+        let lead = alignment.lead_padding(self.padding);
+        let size = alignment.aligned_size((
+            lead + subtex.width + self.padding,
+            lead + subtex.height + self.padding,
+        ));
+        // saturating, as groups made with AtlasGroup::new may not have padding around every entry
+        (
+            subtex.x.saturating_sub(lead),
+            subtex.y.saturating_sub(lead),
+            size.0,
+            size.1,
+        )
+    }
+}
+
+/// Texels of the sub textures in an atlas used by entries that are not removed
+fn used_texels(
+    entry_map: &[(usize, usize)],
+    removed: &HashSet<usize>,
+    atlas_index: usize,
+    layout: &AtlasLayout,
+) -> u64 {
+    entry_map
+        .iter()
+        .enumerate()
+        .filter(|(i, (atlas_idx, _))| *atlas_idx == atlas_index && !removed.contains(i))
+        .map(|(_, (_, subtex_idx))| {
+            let subtex = &layout.0[*subtex_idx];
+            subtex.width as u64 * subtex.height as u64
+        })
+        .sum()
+}
+
+/// Copies a level of a rect from one atlas to another, (atlas, (x, y, layer)) at level 0, the rect is clipped to the level size
+fn copy_rect(
+    encoder: &mut wgpu::CommandEncoder,
+    (src, src_origin): (&Atlas, (u32, u32, u32)),
+    (dst, dst_origin): (&Atlas, (u32, u32, u32)),
+    size: (u32, u32),
+    level: u32,
+) {
+    let level_size = |atlas: &Atlas| {
+        let (width, height) = atlas.size();
+        ((width >> level).max(1), (height >> level).max(1))
+    };
+    let (src_width, src_height) = level_size(src);
+    let (dst_width, dst_height) = level_size(dst);
+    let (src_x, src_y) = (src_origin.0 >> level, src_origin.1 >> level);
+    let (dst_x, dst_y) = (dst_origin.0 >> level, dst_origin.1 >> level);
+    let width = (size.0 >> level)
+        .min(src_width.saturating_sub(src_x))
+        .min(dst_width.saturating_sub(dst_x));
+    let height = (size.1 >> level)
+        .min(src_height.saturating_sub(src_y))
+        .min(dst_height.saturating_sub(dst_y));
+    if width == 0 || height == 0 {
+        return;
+    }
+    encoder.copy_texture_to_texture(
+        ImageCopyTexture {
+            texture: src.texture(),
+            mip_level: level,
+            origin: Origin3d {
+                x: src_x,
+                y: src_y,
+                z: src_origin.2,
+            },
+            aspect: TextureAspect::All,
+        },
+        ImageCopyTexture {
+            texture: dst.texture(),
+            mip_level: level,
+            origin: Origin3d {
+                x: dst_x,
+                y: dst_y,
+                z: dst_origin.2,
+            },
+            aspect: TextureAspect::All,
+        },
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subtex(x: u32, width: u32, height: u32) -> SubTexture {
+        SubTexture {
+            layer: 0,
+            x,
+            y: 0,
+            width,
+            height,
+            trim: None,
+            rotated: false,
+        }
+    }
+
+    #[test]
+    fn used_texels_skip_removed_and_other_atlases() {
+        let layout = AtlasLayout(vec![subtex(0, 4, 4), subtex(4, 2, 3), subtex(8, 5, 1)]);
+        let entry_map = [(0, 0), (0, 1), (1, 0), (0, 2)];
+        assert_eq!(
+            used_texels(&entry_map, &HashSet::new(), 0, &layout),
+            16 + 6 + 5
+        );
+        let removed = HashSet::from_iter([1]);
+        assert_eq!(used_texels(&entry_map, &removed, 0, &layout), 16 + 5);
+        let removed = HashSet::from_iter([0, 1, 3]);
+        assert_eq!(used_texels(&entry_map, &removed, 0, &layout), 0);
+    }
+}
@@ -0,0 +1,124 @@
+use bevy_ecs::{prelude::*, system::SystemParam};
+use modula_asset::{AssetId, Assets};
+use wgpu::{TextureFormat, TextureUsages};
+
+use super::{AtlasGroup, AtlasGroupBuilder, AtlasGroupEntry, AtlasGroupQueue, DEFAULT_PADDING};
+use crate::{MipMapImage, DEFAULT_TEXTURE_FORMAT};
+
+/// Options for [AtlasLoader::load_atlas], each option is set on the [AtlasGroupBuilder] of the group
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AtlasLoadOptions {
+    pub mip_levels: u32,
+    /// Extra usages, [TEXTURE_BINDING](TextureUsages::TEXTURE_BINDING) and [COPY_DST](TextureUsages::COPY_DST) are always used
+    pub usages: TextureUsages,
+    /// See [AtlasGroupBuilder::set_padding]
+    pub padding: u32,
+    /// See [AtlasGroupBuilder::set_extrude]
+    pub extrude: u32,
+    /// See [AtlasGroupBuilder::set_format]
+    pub format: TextureFormat,
+    /// Max (width and height, layers), see [AtlasGroupBuilder::set_max_size]
+    pub max_size: Option<(u32, u32)>,
+    /// See [AtlasGroupBuilder::set_allow_rotation]
+    pub allow_rotation: bool,
+}
+
+impl Default for AtlasLoadOptions {
+    fn default() -> Self {
+        Self {
+            mip_levels: 1,
+            usages: TextureUsages::empty(),
+            padding: DEFAULT_PADDING,
+            extrude: DEFAULT_PADDING,
+            format: DEFAULT_TEXTURE_FORMAT,
+            max_size: None,
+            allow_rotation: false,
+        }
+    }
+}
+
+impl AtlasLoadOptions {
+    pub fn with_mip_levels(mut self, mip_levels: u32) -> Self {
+        self.mip_levels = mip_levels;
+        self
+    }
+
+    pub fn with_usages(mut self, usages: TextureUsages) -> Self {
+        self.usages = usages;
+        self
+    }
+
+    pub fn with_padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn with_extrude(mut self, extrude: u32) -> Self {
+        self.extrude = extrude;
+        self
+    }
+
+    pub fn with_format(mut self, format: TextureFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_max_size(mut self, width_height: u32, layers: u32) -> Self {
+        self.max_size = Some((width_height, layers));
+        self
+    }
+
+    pub fn with_allow_rotation(mut self, allow_rotation: bool) -> Self {
+        self.allow_rotation = allow_rotation;
+        self
+    }
+
+    /// An empty builder with the options
+    pub fn builder(&self) -> AtlasGroupBuilder {
+        let mut builder = AtlasGroupBuilder::with_usages(
+            self.usages | TextureUsages::TEXTURE_BINDING,
+            self.mip_levels,
+        );
+        builder.set_padding(self.padding);
+        builder.set_extrude(self.extrude);
+        builder.set_format(self.format);
+        if let Some((width_height, layers)) = self.max_size {
+            builder.set_max_size(width_height, layers);
+        }
+        builder.set_allow_rotation(self.allow_rotation);
+        builder
+    }
+}
+
+#[derive(SystemParam)]
+pub struct AtlasLoader<'w> {
+    atlas_queue: ResMut<'w, AtlasGroupQueue>,
+    atlas_groups: ResMut<'w, Assets<AtlasGroup>>,
+}
+
+impl AtlasLoader<'_> {
+    /// Makes a group of the named images, see [AtlasGroupBuilder::add_image_named].
+    /// The entries are returned in the order of the images, and can be used once the group is built during [PreDraw](modula_render::PreDraw).
+    /// If building fails [AtlasBuildFailed](super::AtlasBuildFailed) is sent and the group stays empty
+    /// ## Panics
+    /// Like [AtlasGroupBuilder::add_image], if the images do not have the same alpha mode
+    pub fn load_atlas<N: Into<String>, I: Into<MipMapImage>>(
+        &mut self,
+        images: impl IntoIterator<Item = (N, I)>,
+        options: AtlasLoadOptions,
+    ) -> (AssetId<AtlasGroup>, Vec<AtlasGroupEntry>) {
+        let mut builder = options.builder();
+        let entries = images
+            .into_iter()
+            .map(|(name, img)| builder.add_image_named(name, img))
+            .collect();
+        (self.load_builder(builder), entries)
+    }
+
+    /// Makes a group from a builder, like [load_atlas](Self::load_atlas)
+    pub fn load_builder(&mut self, builder: AtlasGroupBuilder) -> AssetId<AtlasGroup> {
+        let group = self.atlas_groups.add_empty();
+        self.atlas_queue.init_group(group, builder);
+        group
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    any::{Any, TypeId},
+    sync::Arc,
+};
+
+use modula_utils::HashMap;
+
+use super::{AtlasGroup, AtlasGroupBuilder, AtlasGroupEntry, MipMapImage};
+
+/// Metadata of entries by type, indexed by [AtlasGroupEntry::index].
+/// Values are behind an [Arc], so the builder can be built more than once without metadata being [Clone]
+#[derive(Default)]
+pub(super) struct EntryMeta(HashMap<TypeId, Box<dyn MetaVec>>);
+
+/// A Vec<Option<Arc<M>>>, erased so metadata of different types can be stored together
+trait MetaVec: Send + Sync {
+    fn clone_box(&self) -> Box<dyn MetaVec>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn remove(&mut self, index: usize);
+}
+
+impl<M: Send + Sync + 'static> MetaVec for Vec<Option<Arc<M>>> {
+    fn clone_box(&self) -> Box<dyn MetaVec> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn remove(&mut self, index: usize) {
+        if let Some(value) = self.get_mut(index) {
+            *value = None;
+        }
+    }
+}
+
+impl Clone for EntryMeta {
+    fn clone(&self) -> Self {
+        Self(
+            self.0
+                .iter()
+                .map(|(type_id, values)| (*type_id, values.clone_box()))
+                .collect(),
+        )
+    }
+}
+
+impl EntryMeta {
+    fn get<M: Send + Sync + 'static>(&self, entry: AtlasGroupEntry) -> Option<&M> {
+        let values = self
+            .0
+            .get(&TypeId::of::<M>())?
+            .as_any()
+            .downcast_ref::<Vec<Option<Arc<M>>>>()
+            .expect("metadata is stored by its TypeId");
+        values.get(entry.index())?.as_deref()
+    }
+
+    /// Removes the metadata of every type of an entry
+    pub(super) fn remove(&mut self, entry: AtlasGroupEntry) {
+        for values in self.0.values_mut() {
+            values.remove(entry.index());
+        }
+    }
+
+    fn set<M: Send + Sync + 'static>(&mut self, entry: AtlasGroupEntry, meta: M) {
+        let values = self
+            .0
+            .entry(TypeId::of::<M>())
+            .or_insert_with(|| Box::new(Vec::<Option<Arc<M>>>::new()))
+            .as_any_mut()
+            .downcast_mut::<Vec<Option<Arc<M>>>>()
+            .expect("metadata is stored by its TypeId");
+        if values.len() <= entry.index() {
+            values.resize(entry.index() + 1, None);
+        }
+        values[entry.index()] = Some(Arc::new(meta));
+    }
+}
+
+impl AtlasGroupBuilder {
+    /// Same as [add_image](Self::add_image), but meta can be found with [AtlasGroup::meta], useful for data like pivot points or collision boxes.
+    /// Entries can have metadata of any number of types, but only one value of every type.
+    /// Metadata is not part of layouts saved with the `serde` feature, a group loaded from one gets the metadata of its builder
+    pub fn add_image_with_meta<M: Send + Sync + 'static>(
+        &mut self,
+        img: impl Into<MipMapImage>,
+        meta: M,
+    ) -> AtlasGroupEntry {
+        let entry = self.add_image(img);
+        self.set_meta(entry, meta);
+        entry
+    }
+
+    /// Sets the metadata of type M of an entry, replacing the metadata of that type it had
+    /// ## Panics
+    /// If the entry is not in the builder
+    pub fn set_meta<M: Send + Sync + 'static>(&mut self, entry: AtlasGroupEntry, meta: M) {
+        assert!(
+            entry.index() < self.images.len(),
+            "metadata can only be set for entries in the builder"
+        );
+        self.meta.set(entry, meta);
+    }
+}
+
+impl AtlasGroup {
+    /// The metadata of type M of an entry, added with [AtlasGroupBuilder::add_image_with_meta] or [set_meta](Self::set_meta)
+    #[inline]
+    pub fn meta<M: Send + Sync + 'static>(&self, entry: AtlasGroupEntry) -> Option<&M> {
+        self.meta.get(entry)
+    }
+
+    /// Sets the metadata of type M of an entry, such as entries added by [insert](Self::insert)
+    /// ## Panics
+    /// If the entry is not in the group
+    pub fn set_meta<M: Send + Sync + 'static>(&mut self, entry: AtlasGroupEntry, meta: M) {
+        assert!(
+            entry.index() < self.entry_map.len(),
+            "metadata can only be set for entries in the group"
+        );
+        self.meta.set(entry, meta);
+    }
+}
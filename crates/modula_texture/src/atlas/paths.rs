@@ -0,0 +1,81 @@
+use std::path::{Path, PathBuf};
+
+use super::{AtlasGroupBuilder, AtlasGroupEntry};
+use crate::{Image, ImageLoadError};
+
+/// Max number of threads used by [AtlasGroupBuilder::add_images_from_paths], fewer are used if the system has fewer cores
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_DECODE_THREADS: usize = 8;
+
+type DecodeResult = Result<Image, ImageLoadError>;
+
+impl AtlasGroupBuilder {
+    /// Decodes the files on multiple threads and adds them with [add_image_named](Self::add_image_named), named by their file stem.
+    /// The entries are in the order of the paths, file stems must be unique as [build](Self::build) returns an error otherwise.
+    /// If any file fails to load no images are added, and the error of every file that failed is returned
+    /// ## Panics
+    /// Like [add_image](Self::add_image), if a decoded image does not have the same format or alpha mode as the other images
+    pub fn add_images_from_paths(
+        &mut self,
+        paths: impl IntoIterator<Item = impl AsRef<Path>>,
+    ) -> Result<Vec<AtlasGroupEntry>, Vec<(PathBuf, ImageLoadError)>> {
+        let paths: Vec<_> = paths
+            .into_iter()
+            .map(|path| path.as_ref().to_path_buf())
+            .collect();
+        // everything is decoded before the builder is changed, so it is left as it was on failure
+        let results = decode_all(&paths);
+        let mut images = Vec::with_capacity(paths.len());
+        let mut failed = Vec::new();
+        for (path, result) in paths.into_iter().zip(results) {
+            match result {
+                Ok(image) => images.push((path, image)),
+                Err(error) => failed.push((path, error)),
+            }
+        }
+        if !failed.is_empty() {
+            return Err(failed);
+        }
+        Ok(images
+            .into_iter()
+            .map(|(path, image)| {
+                let name = path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                self.add_image_named(name, image)
+            })
+            .collect())
+    }
+}
+
+/// Decodes the files in order, split in chunks over scoped threads
+#[cfg(not(target_arch = "wasm32"))]
+fn decode_all(paths: &[PathBuf]) -> Vec<DecodeResult> {
+    let count = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(MAX_DECODE_THREADS)
+        .min(paths.len());
+    if count <= 1 {
+        return paths.iter().map(Image::load_from_path).collect();
+    }
+    let chunk_size = paths.len().div_ceil(count);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(|| chunk.iter().map(Image::load_from_path).collect::<Vec<_>>())
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("texture decode thread panicked"))
+            .collect()
+    })
+}
+
+/// There are no threads on the web, so the files are decoded one by one
+#[cfg(target_arch = "wasm32")]
+fn decode_all(paths: &[PathBuf]) -> Vec<DecodeResult> {
+    paths.iter().map(Image::load_from_path).collect()
+}
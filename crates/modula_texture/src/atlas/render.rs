@@ -1,6 +1,16 @@
-use std::marker::PhantomData;
+use std::{marker::PhantomData, ops::Range};
 
-use wgpu::{BindGroup, BindGroupLayout, ShaderSource};
+use modula_render::{RenderPipelineBuilder, RenderTarget, TargetPipeline};
+use wgpu::{
+    BindGroup, BindGroupLayout, BlendState, BufferAddress, BufferSlice, CompareFunction, Device,
+    RenderPass, ShaderModule, ShaderModuleDescriptor, ShaderSource, VertexAttribute,
+    VertexBufferLayout, VertexStepMode,
+};
+
+use super::AtlasGroupBindGroupLayout;
+
+/// Number of vertices drawn for every instance, two triangles without an index buffer
+pub const QUAD_VERTEX_COUNT: u32 = 6;
 
 /// Provides bind group layouts to [AtlasShaders](AtlasShader), this exists to make bind groups more abstract
 pub trait BindGroupLayoutProvider {
@@ -12,11 +22,266 @@ pub trait BindGroupProvider {
     fn bind_groups(&self) -> &[&BindGroup];
 }
 
+/// No bind groups besides the atlases
+impl BindGroupLayoutProvider for () {
+    fn layouts(&self) -> &[&BindGroupLayout] {
+        &[]
+    }
+}
+
+impl BindGroupProvider for () {
+    fn bind_groups(&self) -> &[&BindGroup] {
+        &[]
+    }
+}
+
+impl BindGroupLayoutProvider for Vec<&BindGroupLayout> {
+    fn layouts(&self) -> &[&BindGroupLayout] {
+        self
+    }
+}
+
+impl BindGroupProvider for Vec<&BindGroup> {
+    fn bind_groups(&self) -> &[&BindGroup] {
+        self
+    }
+}
+
+impl<const N: usize> BindGroupLayoutProvider for [&BindGroupLayout; N] {
+    fn layouts(&self) -> &[&BindGroupLayout] {
+        self
+    }
+}
+
+impl<const N: usize> BindGroupProvider for [&BindGroup; N] {
+    fn bind_groups(&self) -> &[&BindGroup] {
+        self
+    }
+}
+
+/// The per instance vertex buffer of an [AtlasShader], bound to slot 0
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InstanceLayout {
+    pub array_stride: BufferAddress,
+    /// If empty the shader has no vertex buffer, and instance data must come from a bind group
+    pub attributes: Vec<VertexAttribute>,
+}
+
+/// A pipeline drawing instanced quads with atlases, using the entry points 'vs_main' and 'fs_main'.
+/// The [AtlasGroup](super::AtlasGroup) bind group is group 0, followed by the bind groups of Layout.
+/// Every instance is [QUAD_VERTEX_COUNT] vertices, so the vertex shader should make the quad from its vertex and instance index.
+/// Sprites are alpha blended in the order they are drawn, so depth is tested with [Always](CompareFunction::Always) and not written
 pub struct AtlasShader<Layout: BindGroupLayoutProvider> {
     _layout: PhantomData<Layout>,
-    layouts: Vec<BindGroupLayout>,
+    module: ShaderModule,
+    instance_layout: InstanceLayout,
+    layout_count: usize,
+    pipeline: TargetPipeline,
 }
 
 impl<Layout: BindGroupLayoutProvider> AtlasShader<Layout> {
-    pub fn new(source: ShaderSource) {}
+    /// The source can be made by the [ShaderBundler](modula_render::shader::ShaderBundler),
+    /// the pipeline matches the formats and sample count of render_target
+    pub fn new(
+        device: &Device,
+        source: ShaderSource,
+        atlas_layout: &AtlasGroupBindGroupLayout,
+        layout: &Layout,
+        instance_layout: InstanceLayout,
+        render_target: &RenderTarget,
+    ) -> Self {
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("AtlasShader"),
+            source,
+        });
+        let pipeline = build_pipeline(
+            device,
+            &module,
+            atlas_layout,
+            layout.layouts(),
+            &instance_layout,
+            render_target,
+        );
+        Self {
+            _layout: PhantomData,
+            module,
+            instance_layout,
+            layout_count: layout.layouts().len(),
+            pipeline,
+        }
+    }
+
+    /// Creates the pipeline again for render_target, for example when its formats changed, see [TargetPipeline::is_compatible]
+    pub fn rebuild(
+        &mut self,
+        device: &Device,
+        atlas_layout: &AtlasGroupBindGroupLayout,
+        layout: &Layout,
+        render_target: &RenderTarget,
+    ) {
+        self.pipeline = build_pipeline(
+            device,
+            &self.module,
+            atlas_layout,
+            layout.layouts(),
+            &self.instance_layout,
+            render_target,
+        );
+        self.layout_count = layout.layouts().len();
+    }
+
+    #[inline]
+    pub fn pipeline(&self) -> &TargetPipeline {
+        &self.pipeline
+    }
+
+    #[inline]
+    pub fn instance_layout(&self) -> &InstanceLayout {
+        &self.instance_layout
+    }
+}
+
+fn build_pipeline(
+    device: &Device,
+    module: &ShaderModule,
+    atlas_layout: &AtlasGroupBindGroupLayout,
+    layouts: &[&BindGroupLayout],
+    instance_layout: &InstanceLayout,
+    render_target: &RenderTarget,
+) -> TargetPipeline {
+    let mut builder = RenderPipelineBuilder::new(module)
+        .with_label("AtlasShader Pipeline")
+        .with_bind_group_layout(atlas_layout.layout())
+        .with_blend(BlendState::ALPHA_BLENDING)
+        .with_depth(false, CompareFunction::Always);
+    for layout in layouts {
+        builder = builder.with_bind_group_layout(layout);
+    }
+    if !instance_layout.attributes.is_empty() {
+        builder = builder.with_vertex_buffer(VertexBufferLayout {
+            array_stride: instance_layout.array_stride,
+            step_mode: VertexStepMode::Instance,
+            attributes: &instance_layout.attributes,
+        });
+    }
+    builder.build(device, render_target)
+}
+
+/// Draws instances with an [AtlasShader], binding the bind groups of a [BindGroupProvider] matching the Layout of the shader
+pub struct AtlasRenderer<Layout: BindGroupLayoutProvider> {
+    shader: AtlasShader<Layout>,
+}
+
+impl<Layout: BindGroupLayoutProvider> AtlasRenderer<Layout> {
+    pub fn new(shader: AtlasShader<Layout>) -> Self {
+        Self { shader }
+    }
+
+    #[inline]
+    pub fn shader(&self) -> &AtlasShader<Layout> {
+        &self.shader
+    }
+
+    #[inline]
+    pub fn shader_mut(&mut self) -> &mut AtlasShader<Layout> {
+        &mut self.shader
+    }
+
+    /// Draws the instances in range, with atlas_bind_group from [AtlasGroup::bind_groups](super::AtlasGroup::bind_groups).
+    /// instances is the instance buffer, ignored if the [InstanceLayout] has no attributes
+    /// ## Panics
+    /// If bind_groups does not have a bind group for every layout of the shader
+    pub fn draw(
+        &self,
+        pass: &mut RenderPass,
+        render_target: &RenderTarget,
+        atlas_bind_group: &BindGroup,
+        bind_groups: &impl BindGroupProvider,
+        instances: Option<BufferSlice>,
+        range: Range<u32>,
+    ) {
+        let bind_groups = bind_groups.bind_groups();
+        assert_eq!(
+            bind_groups.len(),
+            self.shader.layout_count,
+            "the BindGroupProvider must have a bind group for every layout of the AtlasShader"
+        );
+        pass.set_pipeline(self.shader.pipeline.get(render_target));
+        pass.set_bind_group(0, atlas_bind_group, &[]);
+        for (i, bind_group) in bind_groups.iter().enumerate() {
+            pass.set_bind_group(i as u32 + 1, bind_group, &[]);
+        }
+        let has_buffer = !self.shader.instance_layout.attributes.is_empty();
+        if let Some(instances) = instances.filter(|_| has_buffer) {
+            pass.set_vertex_buffer(0, instances);
+        }
+        pass.draw(0..QUAD_VERTEX_COUNT, range);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use modula_core::request_headless_device;
+    use modula_render::RenderTargetConfig;
+    use wgpu::{
+        BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, BufferBindingType,
+        ErrorFilter, ShaderStages, VertexFormat,
+    };
+
+    use super::*;
+
+    const SHADER: &str = "
+@group(1) @binding(0) var<uniform> color: vec4<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32, @location(0) offset: vec2<f32>) -> @builtin(position) vec4<f32> {
+    let corner = vec2<f32>(f32(vertex & 1u), f32((vertex >> 1u) & 1u));
+    return vec4<f32>(corner + offset, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return color;
+}
+";
+
+    #[test]
+    fn pipeline_with_default_target_config() {
+        let (device, _queue) =
+            request_headless_device().expect("no adapter found, not even the fallback adapter");
+        let color_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let render_target = RenderTarget::new(RenderTargetConfig::default());
+        device.push_error_scope(ErrorFilter::Validation);
+        let shader = AtlasShader::new(
+            &device,
+            ShaderSource::Wgsl(SHADER.into()),
+            &AtlasGroupBindGroupLayout::new(&device),
+            &[&color_layout],
+            InstanceLayout {
+                array_stride: 8,
+                attributes: vec![VertexAttribute {
+                    format: VertexFormat::Float32x2,
+                    offset: 0,
+                    shader_location: 0,
+                }],
+            },
+            &render_target,
+        );
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            panic!("{error}");
+        }
+        assert!(shader.pipeline().is_compatible(&render_target));
+    }
 }
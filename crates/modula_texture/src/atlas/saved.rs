@@ -0,0 +1,197 @@
+use core::fmt::Debug;
+use std::{
+    convert::Infallible,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    fs, io,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use wgpu::{Device, Queue};
+
+use super::{
+    AtlasBuildError, AtlasGroup, AtlasGroupBindGroupLayout, AtlasGroupBuilder, AtlasLayout,
+    AtlasLayouter, AtlasLayouterOutput,
+};
+
+/// The layout of an [AtlasGroup] as saved by [AtlasGroupBuilder::save_layout]
+#[derive(Serialize, Deserialize)]
+struct SavedLayout {
+    /// Hash of everything in the builder that affects the layout, see [AtlasGroupBuilder::layout_hash]
+    source_hash: u64,
+    /// Not needed to load the layout, as the names come from the builder, but useful when reading the file
+    names: Vec<(String, usize)>,
+    output: AtlasLayouterOutput,
+}
+
+#[derive(Debug)]
+pub enum SavedLayoutError<E = Infallible> {
+    IOError(io::Error),
+    /// The layout could not be written or parsed as RON
+    Ron(String),
+    /// The layout did not match the images, and repacking them failed
+    Build(AtlasBuildError<E>),
+}
+
+impl<E: Debug> Error for SavedLayoutError<E> {}
+
+impl<E: Debug> Display for SavedLayoutError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IOError(e) => write!(f, "saved atlas layout IOError: {}", e),
+            Self::Ron(e) => write!(f, "saved atlas layout RON error: {}", e),
+            Self::Build(e) => write!(f, "failed to repack saved atlas layout: {}", e),
+        }
+    }
+}
+
+impl<E> From<io::Error> for SavedLayoutError<E> {
+    fn from(value: io::Error) -> Self {
+        Self::IOError(value)
+    }
+}
+
+impl AtlasGroupBuilder {
+    /// Saves the layout of a group built from this builder as RON, so it can be loaded with [AtlasGroup::from_saved_layout] without packing the images again.
+    /// Entries [inserted](AtlasGroup::insert) after the group was built are not part of the builder,
+    /// so a layout with them will not match the images when loaded
+    pub fn save_layout(
+        &self,
+        group: &AtlasGroup,
+        path: impl AsRef<Path>,
+    ) -> Result<(), SavedLayoutError> {
+        let mut names: Vec<_> = self
+            .names
+            .iter()
+            .map(|(name, entry)| (name.clone(), entry.index()))
+            .collect();
+        names.sort_by_key(|(_, index)| *index);
+        let saved = SavedLayout {
+            source_hash: self.layout_hash(),
+            names,
+            output: AtlasLayouterOutput {
+                entry_map: group.entry_map.clone(),
+                atlases: group
+                    .atlases
+                    .iter()
+                    .map(|atlas| {
+                        let (width, height) = atlas.size();
+                        (
+                            (width, height, atlas.layer_count()),
+                            AtlasLayout(atlas.layout.0.clone()),
+                        )
+                    })
+                    .collect(),
+            },
+        };
+        let ron = ron::ser::to_string_pretty(&saved, ron::ser::PrettyConfig::default())
+            .map_err(|e| SavedLayoutError::Ron(e.to_string()))?;
+        fs::write(path, ron)?;
+        Ok(())
+    }
+
+    /// FNV-1a hash of the images, names and options, the std hashers are not used as they may change between Rust versions
+    fn layout_hash(&self) -> u64 {
+        let mut hash = Fnv1a::new();
+        hash.write_u32(self.mip_levels);
+        hash.write_u32(self.padding);
+        hash.write_u32(self.extrude);
+        hash.write_u32(self.allow_rotation as u32);
+        hash.write_u32(self.images.len() as u32);
+        for (img, trim) in self.images.iter().zip(&self.trims) {
+            hash.write_u32(img.level_count() as u32);
+            for level in img.levels() {
+                hash.write_u32(level.width);
+                hash.write_u32(level.height);
+                hash.write(&level.data);
+            }
+            if let Some(trim) = trim {
+                hash.write_u32(trim.offset.0);
+                hash.write_u32(trim.offset.1);
+                hash.write_u32(trim.original_size.0);
+                hash.write_u32(trim.original_size.1);
+            }
+        }
+        for (name, entry) in &self.names {
+            hash.write(name.as_bytes());
+            hash.write_u32(entry.index() as u32);
+        }
+        hash.0
+    }
+
+    /// True if the saved layout places every image of the builder in an atlas of the saved size
+    fn fits_saved(&self, output: &AtlasLayouterOutput) -> bool {
+        output.entry_map.len() == self.images.len()
+            && self
+                .sizes()
+                .iter()
+                .zip(&output.entry_map)
+                .all(|(size, (atlas_idx, el_idx))| {
+                    output
+                        .atlases
+                        .get(*atlas_idx)
+                        .and_then(|(atlas_size, layout)| {
+                            let subtex = layout.0.get(*el_idx)?;
+                            let stored_size = if subtex.rotated {
+                                (size.1, size.0)
+                            } else {
+                                *size
+                            };
+                            Some(
+                                (subtex.width, subtex.height) == stored_size
+                                    && subtex.layer < atlas_size.2
+                                    && subtex.x + subtex.width <= atlas_size.0
+                                    && subtex.y + subtex.height <= atlas_size.1,
+                            )
+                        })
+                        .unwrap_or(false)
+                })
+    }
+}
+
+impl AtlasGroup {
+    /// Creates a group from a layout saved with [AtlasGroupBuilder::save_layout] and the builder with the same images.
+    /// If the images or options of the builder changed since the layout was saved, they are packed again with L and a warning is logged
+    pub fn from_saved_layout<L: AtlasLayouter>(
+        path: impl AsRef<Path>,
+        images: &AtlasGroupBuilder,
+        device: &Device,
+        queue: &Queue,
+        bind_layout: &AtlasGroupBindGroupLayout,
+    ) -> Result<AtlasGroup, SavedLayoutError<L::Error>> {
+        let path = path.as_ref();
+        let saved: SavedLayout = ron::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| SavedLayoutError::Ron(e.to_string()))?;
+        if saved.source_hash != images.layout_hash() || !images.fits_saved(&saved.output) {
+            log::warn!(
+                "saved atlas layout {} does not match its images, they are packed again",
+                path.display()
+            );
+            return images
+                .build::<L>(device, queue, bind_layout)
+                .map_err(SavedLayoutError::Build);
+        }
+        let names = images.validate().map_err(SavedLayoutError::Build)?;
+        Ok(images.create_group(saved.output, names, device, queue, bind_layout))
+    }
+}
+
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.write(&value.to_le_bytes());
+    }
+}
@@ -0,0 +1,109 @@
+use super::AtlasLayout;
+
+/// Free space of an atlas for entries added after it is built, rects are placed left to right in rows (shelves)
+pub(super) struct ShelfAllocator {
+    width: u32,
+    height: u32,
+    layers: Vec<LayerShelves>,
+}
+
+struct LayerShelves {
+    shelves: Vec<Shelf>,
+    /// Top of the space below the shelves
+    free_y: u32,
+    /// Space of removed rects, (x, y, width, height)
+    free_rects: Vec<(u32, u32, u32, u32)>,
+}
+
+struct Shelf {
+    y: u32,
+    height: u32,
+    /// Left of the space after the rects in the shelf
+    free_x: u32,
+}
+
+impl ShelfAllocator {
+    /// Only the space below every entry of a layer and the space of [deallocated](Self::deallocate) rects is used, as the space between packed entries is not tracked.
+    /// Rects are placed at multiples of alignment if their sizes are multiples of it
+    pub(super) fn new(
+        size: (u32, u32, u32),
+        layout: &AtlasLayout,
+        padding: u32,
+        alignment: u32,
+    ) -> Self {
+        let mut layers: Vec<_> = (0..size.2)
+            .map(|_| LayerShelves {
+                shelves: Vec::new(),
+                free_y: 0,
+                free_rects: Vec::new(),
+            })
+            .collect();
+        for subtex in &layout.0 {
+            let layer = &mut layers[subtex.layer as usize];
+            layer.free_y = layer.free_y.max(subtex.y + subtex.height + padding);
+        }
+        for layer in &mut layers {
+            layer.free_y = layer.free_y.next_multiple_of(alignment);
+        }
+        Self {
+            width: size.0,
+            height: size.1,
+            layers,
+        }
+    }
+
+    /// Finds space for a width x height rect, returning the layer and position
+    pub(super) fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32, u32)> {
+        if width > self.width {
+            return None;
+        }
+        for (i, layer) in self.layers.iter_mut().enumerate() {
+            // removed rects are filled first, the rest of the rect is split to the right and below
+            let free = layer
+                .free_rects
+                .iter()
+                .position(|rect| rect.2 >= width && rect.3 >= height);
+            if let Some(free) = free {
+                let (x, y, free_width, free_height) = layer.free_rects.swap_remove(free);
+                if free_width > width {
+                    layer
+                        .free_rects
+                        .push((x + width, y, free_width - width, height));
+                }
+                if free_height > height {
+                    layer
+                        .free_rects
+                        .push((x, y + height, free_width, free_height - height));
+                }
+                return Some((i as u32, x, y));
+            }
+            let shelf = layer
+                .shelves
+                .iter_mut()
+                .find(|shelf| shelf.height >= height && shelf.free_x + width <= self.width);
+            if let Some(shelf) = shelf {
+                let x = shelf.free_x;
+                shelf.free_x += width;
+                return Some((i as u32, x, shelf.y));
+            }
+            if layer.free_y + height <= self.height {
+                let y = layer.free_y;
+                layer.shelves.push(Shelf {
+                    y,
+                    height,
+                    free_x: width,
+                });
+                layer.free_y += height;
+                return Some((i as u32, 0, y));
+            }
+        }
+        None
+    }
+
+    /// Makes the space of a rect free, so it can be allocated again
+    pub(super) fn deallocate(&mut self, layer: u32, x: u32, y: u32, width: u32, height: u32) {
+        self.layers[layer as usize]
+            .free_rects
+            .push((x, y, width, height));
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    cmp::Reverse,
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+use super::{
+    shelf::ShelfAllocator, AtlasLayout, AtlasLayouter, AtlasLayouterOutput, MaxAtlasSize,
+    SubTexture,
+};
+
+/// [AtlasLayouter] that places rects left to right in rows (shelves), tallest first.
+/// Much faster than [DefaultLayouter](super::DefaultLayouter) for many rects, but wastes more space when their heights vary a lot.  
+/// When rotating, rects taller than they are wide are rotated so the shelves are lower
+pub struct ShelfLayouter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShelfLayoutError {
+    /// A rect is larger than the max atlas size
+    TooLarge {
+        index: usize,
+        size: (u32, u32),
+        max: u32,
+    },
+}
+
+impl Error for ShelfLayoutError {}
+
+impl Display for ShelfLayoutError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooLarge { index, size, max } => write!(
+                f,
+                "rect {index} is {}x{}, which is larger than the max atlas size {max}",
+                size.0, size.1
+            ),
+        }
+    }
+}
+
+impl AtlasLayouter for ShelfLayouter {
+    type Error = ShelfLayoutError;
+
+    fn layout(
+        sizes: Vec<(u32, u32)>,
+        max_atlas_size: MaxAtlasSize,
+    ) -> Result<AtlasLayouterOutput, ShelfLayoutError> {
+        layout(sizes, max_atlas_size, false)
+    }
+
+    fn layout_rotating(
+        sizes: Vec<(u32, u32)>,
+        max_atlas_size: MaxAtlasSize,
+    ) -> Result<AtlasLayouterOutput, ShelfLayoutError> {
+        layout(sizes, max_atlas_size, true)
+    }
+}
+
+fn layout(
+    sizes: Vec<(u32, u32)>,
+    max_atlas_size: MaxAtlasSize,
+    rotate: bool,
+) -> Result<AtlasLayouterOutput, ShelfLayoutError> {
+    let max = max_atlas_size.aligned_max();
+    if let Some((index, size)) = sizes.iter().enumerate().find(|(_, size)| {
+        let size = max_atlas_size.aligned_size(**size);
+        size.0.max(size.1) > max
+    }) {
+        return Err(ShelfLayoutError::TooLarge {
+            index,
+            size: *size,
+            max,
+        });
+    }
+    let rotated: Vec<_> = sizes.iter().map(|size| rotate && size.1 > size.0).collect();
+    let sizes: Vec<_> = sizes
+        .into_iter()
+        .zip(&rotated)
+        .map(|(size, rotated)| if *rotated { (size.1, size.0) } else { size })
+        .collect();
+    // rects are placed next to each other, so aligned sizes give aligned origins
+    let aligned: Vec<_> = sizes
+        .iter()
+        .map(|size| max_atlas_size.aligned_size(*size))
+        .collect();
+    let alignment = max_atlas_size.rect_alignment();
+    let mut order: Vec<_> = (0..aligned.len()).collect();
+    order.sort_by_key(|i| Reverse(aligned[*i].1));
+    // start at the smallest size that could fit everything, and grow it by an eighth until it does
+    let area: u64 = aligned.iter().map(|s| s.0 as u64 * s.1 as u64).sum();
+    let largest = aligned.iter().map(|s| s.0.max(s.1)).max().unwrap_or(1);
+    let mut wh = ((area as f64).sqrt().ceil() as u32)
+        .max(largest)
+        .next_multiple_of(alignment)
+        .min(max);
+    loop {
+        if let Some(output) = pack(&aligned, &order, (wh, 1), false) {
+            return Ok(with_rotation(output, &sizes, &rotated));
+        }
+        if wh == max {
+            break;
+        }
+        wh = (wh + wh.div_ceil(8)).next_multiple_of(alignment).min(max);
+    }
+    let output = pack(&aligned, &order, (max, max_atlas_size.max_layers), true)
+        .expect("every rect fits in an empty atlas");
+    Ok(with_rotation(output, &sizes, &rotated))
+}
+
+/// Marks the sub textures of rotated rects, and sets their sizes back from the aligned sizes they were packed with
+fn with_rotation(
+    mut output: AtlasLayouterOutput,
+    sizes: &[(u32, u32)],
+    rotated: &[bool],
+) -> AtlasLayouterOutput {
+    for (((atlas_idx, subtex_idx), size), rotated) in
+        output.entry_map.iter().zip(sizes).zip(rotated)
+    {
+        let subtex = &mut output.atlases[*atlas_idx].1 .0[*subtex_idx];
+        (subtex.width, subtex.height) = *size;
+        subtex.rotated = *rotated;
+    }
+    output
+}
+
+/// Packs the rects in the order into wh x wh atlases with up to max_layers layers, only using one atlas unless multiple_atlases is set
+fn pack(
+    sizes: &[(u32, u32)],
+    order: &[usize],
+    (wh, max_layers): (u32, u32),
+    multiple_atlases: bool,
+) -> Option<AtlasLayouterOutput> {
+    let new_allocator =
+        || ShelfAllocator::new((wh, wh, max_layers), &AtlasLayout(Vec::new()), 0, 1);
+    let mut allocators = vec![new_allocator()];
+    // (atlas_idx, layer, x, y) of every rect
+    let mut placements = vec![(0, 0, 0, 0); sizes.len()];
+    for &i in order {
+        let (width, height) = sizes[i];
+        let placed = allocators
+            .iter_mut()
+            .enumerate()
+            .find_map(|(atlas_idx, allocator)| {
+                let (layer, x, y) = allocator.allocate(width, height)?;
+                Some((atlas_idx, layer, x, y))
+            });
+        placements[i] = match placed {
+            Some(placement) => placement,
+            None if multiple_atlases => {
+                let mut allocator = new_allocator();
+                let (layer, x, y) = allocator.allocate(width, height)?;
+                allocators.push(allocator);
+                (allocators.len() - 1, layer, x, y)
+            }
+            None => return None,
+        };
+    }
+    // atlases only get the layers that are used
+    let mut layer_counts = vec![1; allocators.len()];
+    for (atlas_idx, layer, _, _) in &placements {
+        layer_counts[*atlas_idx] = layer_counts[*atlas_idx].max(layer + 1);
+    }
+    let mut atlases: Vec<_> = layer_counts
+        .into_iter()
+        .map(|layers| ((wh, wh, layers), AtlasLayout(Vec::new())))
+        .collect();
+    let mut entry_map = Vec::with_capacity(sizes.len());
+    for (&(width, height), (atlas_idx, layer, x, y)) in sizes.iter().zip(placements) {
+        let layout = &mut atlases[atlas_idx].1 .0;
+        layout.push(SubTexture {
+            layer,
+            x,
+            y,
+            width,
+            height,
+            trim: None,
+            rotated: false,
+        });
+        entry_map.push((atlas_idx, layout.len() - 1));
+    }
+    Some(AtlasLayouterOutput { entry_map, atlases })
+}
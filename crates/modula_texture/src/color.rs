@@ -0,0 +1,151 @@
+use std::sync::OnceLock;
+
+use wgpu::TextureFormat;
+
+use crate::{ColorSpace, Image, MipMapImage, PixelFormat};
+
+/// Converts an sRGB encoded value to linear, values are normally 0-1 but larger values are also converted
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts a linear value to sRGB encoding, values are normally 0-1 but larger values are also converted
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Same as [srgb_to_linear], but for 8 bit values using a lookup table
+pub fn srgb_to_linear_u8(value: u8) -> u8 {
+    static TABLE: OnceLock<[u8; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| lookup_table(srgb_to_linear))[value as usize]
+}
+
+/// Same as [linear_to_srgb], but for 8 bit values using a lookup table
+pub fn linear_to_srgb_u8(value: u8) -> u8 {
+    static TABLE: OnceLock<[u8; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| lookup_table(linear_to_srgb))[value as usize]
+}
+
+fn lookup_table(f: fn(f32) -> f32) -> [u8; 256] {
+    std::array::from_fn(|i| (f(i as f32 / 255.0) * 255.0).round() as u8)
+}
+
+/// What [TextureQueue](crate::TextureQueue) does when the [color_space](Image::color_space) of an image does not match the texture it is written to,
+/// such as an sRGB image written to [Rgba8Unorm](TextureFormat::Rgba8Unorm).
+/// Only formats with an sRGB variant are checked, and images without a color space are never converted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ColorSpaceMismatch {
+    /// Writes the image as is
+    Ignore,
+    /// Writes the image as is and logs a warning
+    #[default]
+    Warn,
+    /// Converts the image to the color space of the texture
+    Convert,
+}
+
+impl ColorSpace {
+    /// The color space of a texture format, None for formats without an sRGB variant such as float formats
+    pub fn of_format(format: TextureFormat) -> Option<ColorSpace> {
+        if format.is_srgb() {
+            Some(ColorSpace::Srgb)
+        } else if format.add_srgb_suffix() != format {
+            Some(ColorSpace::Linear)
+        } else {
+            None
+        }
+    }
+}
+
+impl Image {
+    /// Converts sRGB encoded color channels to linear, alpha is not converted.
+    /// Does nothing if the image is already [Linear](ColorSpace::Linear)
+    pub fn srgb_to_linear(&mut self) {
+        if self.color_space != Some(ColorSpace::Linear) {
+            self.map_color_channels(srgb_to_linear_u8, srgb_to_linear);
+            self.color_space = Some(ColorSpace::Linear);
+        }
+    }
+
+    /// Converts linear color channels to sRGB encoding, alpha is not converted.
+    /// Does nothing if the image is already [Srgb](ColorSpace::Srgb)
+    pub fn linear_to_srgb(&mut self) {
+        if self.color_space != Some(ColorSpace::Srgb) {
+            self.map_color_channels(linear_to_srgb_u8, linear_to_srgb);
+            self.color_space = Some(ColorSpace::Srgb);
+        }
+    }
+
+    /// Maps every channel except alpha, 8 bit channels with map_u8 and others with map normalized to 0-1
+    fn map_color_channels(&mut self, map_u8: fn(u8) -> u8, map: fn(f32) -> f32) {
+        let channels = self.format.channels();
+        // formats with fewer than 4 channels have no alpha
+        let is_color = |sample: usize| channels != 4 || sample % 4 != 3;
+        if self.format.bytes_per_channel() == 1 {
+            for (i, value) in self.data.iter_mut().enumerate() {
+                if is_color(i) {
+                    *value = map_u8(*value);
+                }
+            }
+            return;
+        }
+        let max = match self.format {
+            PixelFormat::Rgba16Float | PixelFormat::Rgba32Float => 1.0,
+            _ => u16::MAX as f32,
+        };
+        let mut data = Vec::with_capacity(self.data.len());
+        for i in 0..self.data.len() / self.format.bytes_per_channel() {
+            let value = self.sample(i);
+            let value = if is_color(i) {
+                map(value / max) * max
+            } else {
+                value
+            };
+            self.push_sample(&mut data, value);
+        }
+        self.data = data;
+    }
+}
+
+/// Warns about or converts levels with a color space that does not match the format
+pub(crate) fn resolve_color_space(
+    image: MipMapImage,
+    format: TextureFormat,
+    mismatch: ColorSpaceMismatch,
+) -> MipMapImage {
+    let Some(target) = ColorSpace::of_format(format) else {
+        return image;
+    };
+    let matches = |img: &Image| img.color_space.is_none_or(|cs| cs == target);
+    if mismatch == ColorSpaceMismatch::Ignore || image.levels().iter().all(matches) {
+        return image;
+    }
+    if mismatch == ColorSpaceMismatch::Warn {
+        let source = image.levels().iter().find_map(|img| img.color_space);
+        log::warn!("{source:?} image is written to {format:?} texture, colors will be wrong");
+        return image;
+    }
+    let convert = |mut img: Image| {
+        if !matches(&img) {
+            match target {
+                ColorSpace::Srgb => img.linear_to_srgb(),
+                ColorSpace::Linear => img.srgb_to_linear(),
+            }
+        }
+        img
+    };
+    match image {
+        MipMapImage::FromLevel(base, count) => MipMapImage::FromLevel(convert(base), count),
+        MipMapImage::WithImages(levels) => {
+            MipMapImage::WithImages(levels.into_iter().map(convert).collect())
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+use wgpu::{
+    Extent3d, Features, ImageCopyTexture, ImageDataLayout, Origin3d, Queue, Texture, TextureAspect,
+    TextureFormat,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompressedImageError {
+    /// The format is not a block compressed format
+    NotCompressed(TextureFormat),
+    /// The width or height of the first level is not a multiple of the block size of the format
+    UnalignedSize {
+        size: (u32, u32),
+        block_size: (u32, u32),
+    },
+    /// More mip levels than the size allows, or 0
+    InvalidMipLevels(u32),
+    /// The length of the data does not match the size, format and mip levels
+    DataLength { expected: usize, actual: usize },
+    /// The device does not have the features needed for the format, such as [TEXTURE_COMPRESSION_BC](Features::TEXTURE_COMPRESSION_BC)
+    MissingFeatures {
+        format: TextureFormat,
+        missing: Features,
+    },
+}
+
+impl Error for CompressedImageError {}
+
+impl Display for CompressedImageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotCompressed(format) => write!(f, "{format:?} is not a compressed format"),
+            Self::UnalignedSize { size, block_size } => write!(
+                f,
+                "size {}x{} is not a multiple of the block size {}x{}",
+                size.0, size.1, block_size.0, block_size.1
+            ),
+            Self::InvalidMipLevels(levels) => write!(f, "invalid number of mip levels {levels}"),
+            Self::DataLength { expected, actual } => {
+                write!(f, "expected {expected} bytes of data, got {actual}")
+            }
+            Self::MissingFeatures { format, missing } => {
+                write!(
+                    f,
+                    "{format:?} needs the missing device features {missing:?}"
+                )
+            }
+        }
+    }
+}
+
+/// Pre-compressed image data, such as BC1, BC3 or BC7, written to textures as is.
+/// Unlike [Image](crate::Image) all mip levels must be provided, as compressed levels can not be generated
+#[derive(Clone)]
+pub struct CompressedImage {
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    /// All levels, starting with the first
+    data: Vec<u8>,
+    mip_levels: u32,
+}
+
+impl CompressedImage {
+    /// Makes a new compressed image, data must contain the mip levels in order, each stored as rows of blocks.
+    /// Width and height must be multiples of the block size, levels smaller than a block are stored as a single block
+    pub fn new(
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+        data: Vec<u8>,
+        mip_levels: u32,
+    ) -> Result<Self, CompressedImageError> {
+        if !format.is_compressed() {
+            return Err(CompressedImageError::NotCompressed(format));
+        }
+        let block_size = format.block_dimensions();
+        if width == 0
+            || height == 0
+            || !width.is_multiple_of(block_size.0)
+            || !height.is_multiple_of(block_size.1)
+        {
+            return Err(CompressedImageError::UnalignedSize {
+                size: (width, height),
+                block_size,
+            });
+        }
+        if mip_levels == 0 || mip_levels > 32 - width.max(height).leading_zeros() {
+            return Err(CompressedImageError::InvalidMipLevels(mip_levels));
+        }
+        let image = Self {
+            format,
+            width,
+            height,
+            data,
+            mip_levels,
+        };
+        let expected = (0..mip_levels).map(|l| image.level_len(l)).sum();
+        if image.data.len() != expected {
+            return Err(CompressedImageError::DataLength {
+                expected,
+                actual: image.data.len(),
+            });
+        }
+        Ok(image)
+    }
+
+    #[inline]
+    pub fn format(&self) -> TextureFormat {
+        self.format
+    }
+
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    #[inline]
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The size of a mip level in pixels, this may be smaller than a block
+    pub fn level_size(&self, level: u32) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+
+    /// The number of blocks per row and rows of blocks in a mip level
+    fn level_blocks(&self, level: u32) -> (u32, u32) {
+        let (width, height) = self.level_size(level);
+        let (block_width, block_height) = self.format.block_dimensions();
+        (width.div_ceil(block_width), height.div_ceil(block_height))
+    }
+
+    fn block_bytes(&self) -> u32 {
+        self.format
+            .block_copy_size(None)
+            .expect("compressed formats have a single aspect")
+    }
+
+    /// The layout of a mip level, rows of blocks are tightly packed
+    pub fn level_layout(&self, level: u32) -> ImageDataLayout {
+        let (columns, rows) = self.level_blocks(level);
+        ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(columns * self.block_bytes()),
+            rows_per_image: Some(rows),
+        }
+    }
+
+    fn level_len(&self, level: u32) -> usize {
+        let (columns, rows) = self.level_blocks(level);
+        (columns * rows * self.block_bytes()) as usize
+    }
+
+    /// Checks that a device with the features supports the format
+    pub fn check_features(
+        format: TextureFormat,
+        features: Features,
+    ) -> Result<(), CompressedImageError> {
+        let missing = format.required_features() - features;
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(CompressedImageError::MissingFeatures { format, missing })
+        }
+    }
+
+    /// Directly writes all levels to a texture, for most cases [TextureLoader](crate::TextureLoader) or [TextureQueue](crate::TextureQueue) should be sufficient.
+    /// origin is the origin in the first level and is scaled down for the others
+    /// ## Panics
+    /// If the texture does not have the same format, or origin is not aligned to blocks in every level
+    pub fn write_to_texture(&self, queue: &Queue, origin: Origin3d, texture: &Texture) {
+        if let Err(err) = self.check_writable(origin, texture.format()) {
+            panic!("{err}");
+        }
+        let (block_width, block_height) = self.format.block_dimensions();
+        let mut offset = 0;
+        for level in 0..self.mip_levels {
+            let level_origin = Origin3d {
+                x: origin.x >> level,
+                y: origin.y >> level,
+                z: origin.z,
+            };
+            let (columns, rows) = self.level_blocks(level);
+            let len = self.level_len(level);
+            let layout = self.level_layout(level);
+            debug_assert!(
+                offset + len <= self.data.len(),
+                "data of mip level {level} is shorter than its layout"
+            );
+            queue.write_texture(
+                ImageCopyTexture {
+                    texture,
+                    origin: level_origin,
+                    mip_level: level,
+                    aspect: TextureAspect::All,
+                },
+                &self.data[offset..offset + len],
+                layout,
+                // levels smaller than a block are copied as a whole block
+                Extent3d {
+                    width: columns * block_width,
+                    height: rows * block_height,
+                    depth_or_array_layers: 1,
+                },
+            );
+            offset += len;
+        }
+    }
+
+    /// Checks the conditions of [write_to_texture](Self::write_to_texture), returning the reason it would panic
+    pub(crate) fn check_writable(
+        &self,
+        origin: Origin3d,
+        format: TextureFormat,
+    ) -> Result<(), String> {
+        if format != self.format {
+            return Err(format!(
+                "{:?} image can not be written to {format:?} texture",
+                self.format
+            ));
+        }
+        let (block_width, block_height) = self.format.block_dimensions();
+        for level in 0..self.mip_levels {
+            if !(origin.x >> level).is_multiple_of(block_width)
+                || !(origin.y >> level).is_multiple_of(block_height)
+            {
+                return Err(format!(
+                    "origin of mip level {level} is not aligned to blocks"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    error::Error,
+    f32::consts::PI,
+    fmt::{self, Display, Formatter},
+};
+
+use bevy_ecs::prelude::*;
+use modula_asset::{AssetId, Assets};
+use wgpu::{Texture, TextureView, TextureViewDescriptor, TextureViewDimension};
+
+use crate::{Image, TextureQueue};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CubemapError {
+    /// A face is not square
+    NotSquare { face: usize, size: (u32, u32) },
+    /// A face does not have the same size or [PixelFormat](crate::PixelFormat) as the first face
+    FaceMismatch(usize),
+}
+
+impl Error for CubemapError {}
+
+impl Display for CubemapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotSquare { face, size } => {
+                write!(
+                    f,
+                    "face {face} is {}x{}, faces must be square",
+                    size.0, size.1
+                )
+            }
+            Self::FaceMismatch(face) => {
+                write!(f, "face {face} does not have the size and format of face 0")
+            }
+        }
+    }
+}
+
+/// A 6 layer texture with a [Cube](TextureViewDimension::Cube) view, for skyboxes and environment maps.
+/// The asset is created during [TextureLoadSet](crate::TextureLoadSet), after its texture
+pub struct CubeTexture {
+    texture: AssetId<Texture>,
+    view: TextureView,
+}
+
+impl CubeTexture {
+    /// The texture asset, its layers are the faces
+    #[inline]
+    pub fn texture(&self) -> AssetId<Texture> {
+        self.texture
+    }
+
+    #[inline]
+    pub fn view(&self) -> &TextureView {
+        &self.view
+    }
+}
+
+/// Checks that all faces are square and have the same size and format
+pub(crate) fn validate_faces(faces: &[Image; 6]) -> Result<(), CubemapError> {
+    let first = &faces[0];
+    for (face, image) in faces.iter().enumerate() {
+        if image.width != image.height {
+            return Err(CubemapError::NotSquare {
+                face,
+                size: (image.width, image.height),
+            });
+        }
+        if image.width != first.width || image.format != first.format {
+            return Err(CubemapError::FaceMismatch(face));
+        }
+    }
+    Ok(())
+}
+
+/// Creates the views of cube textures queued by [TextureLoader::load_cubemap](crate::TextureLoader::load_cubemap), runs after the textures are created
+pub(crate) fn create_cube_views(
+    mut texture_queue: ResMut<TextureQueue>,
+    texture_assets: Res<Assets<Texture>>,
+    mut cube_assets: ResMut<Assets<CubeTexture>>,
+) {
+    for (texture_id, cube_id) in texture_queue.cube_views.drain(..) {
+        // only missing if the texture was removed in the same frame
+        let Some(texture) = texture_assets.get(texture_id) else {
+            continue;
+        };
+        let view = texture.create_view(&TextureViewDescriptor {
+            label: Some("CubeTexture TextureView"),
+            dimension: Some(TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        cube_assets.replace(
+            cube_id,
+            CubeTexture {
+                texture: texture_id,
+                view,
+            },
+        );
+    }
+}
+
+impl Image {
+    /// Converts an equirectangular (latitude-longitude) panorama to the 6 faces of a cubemap, in the order +X, -X, +Y, -Y, +Z, -Z.
+    /// The center of the image is +Z and the top is +Y, the image is sampled linearly and wraps horizontally
+    /// ## Panics
+    /// If face_size is 0, or the format has fewer than 4 channels
+    pub fn equirectangular_to_cube_faces(&self, face_size: u32) -> [Image; 6] {
+        assert!(face_size > 0, "can not make faces of 0 pixels");
+        assert_eq!(
+            self.format.channels(),
+            4,
+            "{:?} is not a 4 channel format",
+            self.format
+        );
+        std::array::from_fn(|face| {
+            let mut data = Vec::with_capacity(
+                face_size as usize * face_size as usize * self.format.bytes_per_pixel(),
+            );
+            for y in 0..face_size {
+                for x in 0..face_size {
+                    // -1 to 1 across the face, v goes down
+                    let u = (x as f32 + 0.5) / face_size as f32 * 2.0 - 1.0;
+                    let v = (y as f32 + 0.5) / face_size as f32 * 2.0 - 1.0;
+                    let dir = face_direction(face, u, v);
+                    for value in self.sample_direction(dir) {
+                        self.push_sample(&mut data, value);
+                    }
+                }
+            }
+            Image {
+                data,
+                width: face_size,
+                height: face_size,
+                format: self.format,
+                color_space: self.color_space,
+                alpha_mode: self.alpha_mode,
+            }
+        })
+    }
+
+    /// Bilinearly samples the pixel of an equirectangular image in a direction
+    fn sample_direction(&self, [x, y, z]: [f32; 3]) -> [f32; 4] {
+        let len = (x * x + y * y + z * z).sqrt();
+        let px = (0.5 + x.atan2(z) / (2.0 * PI)) * self.width as f32 - 0.5;
+        let py = (y / len).clamp(-1.0, 1.0).acos() / PI * self.height as f32 - 0.5;
+        let (x0, y0) = (px.floor(), py.floor());
+        let (fx, fy) = (px - x0, py - y0);
+        let column = |c: f32| (c as i64).rem_euclid(self.width as i64) as usize;
+        let row = |r: f32| (r as i64).clamp(0, self.height as i64 - 1) as usize;
+        let mut res = [0.0; 4];
+        for (sx, sy, weight) in [
+            (column(x0), row(y0), (1.0 - fx) * (1.0 - fy)),
+            (column(x0 + 1.0), row(y0), fx * (1.0 - fy)),
+            (column(x0), row(y0 + 1.0), (1.0 - fx) * fy),
+            (column(x0 + 1.0), row(y0 + 1.0), fx * fy),
+        ] {
+            let pixel = (sy * self.width as usize + sx) * 4;
+            for (channel, value) in res.iter_mut().enumerate() {
+                *value += self.sample(pixel + channel) * weight;
+            }
+        }
+        res
+    }
+}
+
+/// The direction of a point on a cube face, u goes right and v goes down, both from -1 to 1
+fn face_direction(face: usize, u: f32, v: f32) -> [f32; 3] {
+    match face {
+        0 => [1.0, -v, -u],
+        1 => [-1.0, -v, u],
+        2 => [u, 1.0, v],
+        3 => [u, -1.0, -v],
+        4 => [u, -v, 1.0],
+        _ => [-u, -v, -1.0],
+    }
+}
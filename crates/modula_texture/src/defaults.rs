@@ -0,0 +1,77 @@
+use bevy_ecs::prelude::*;
+use modula_asset::{AssetId, Assets};
+use wgpu::{Texture, TextureFormat};
+
+use crate::{Image, TextureLoadOptions, TextureQueue, DEFAULT_TEXTURE_FORMAT};
+
+/// Size of [DefaultTextures::error_image]
+const ERROR_SIZE: u32 = 16;
+/// Size of the squares in [DefaultTextures::error_image]
+const ERROR_SQUARE_SIZE: u32 = 4;
+
+/// Fallback textures for unbound slots and failed loads, inserted during [Init](modula_core::Init).
+/// They are written through [TextureQueue], so they exist after the first [TextureLoadSet](crate::TextureLoadSet)
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct DefaultTextures {
+    /// 1x1 white
+    pub white: AssetId<Texture>,
+    /// 1x1 black
+    pub black: AssetId<Texture>,
+    /// 1x1 (128, 128, 255) in a linear format, a normal pointing straight out
+    pub flat_normal: AssetId<Texture>,
+    /// 16x16 magenta and black checkerboard, see [error_image](Self::error_image)
+    pub error: AssetId<Texture>,
+}
+
+impl DefaultTextures {
+    /// The image of [error](Self::error), can be used to replace an image that failed to load
+    pub fn error_image() -> Image {
+        let mut data = Vec::with_capacity((ERROR_SIZE * ERROR_SIZE * 4) as usize);
+        for y in 0..ERROR_SIZE {
+            for x in 0..ERROR_SIZE {
+                let magenta = (x / ERROR_SQUARE_SIZE + y / ERROR_SQUARE_SIZE).is_multiple_of(2);
+                data.extend_from_slice(if magenta {
+                    &[255, 0, 255, 255]
+                } else {
+                    &[0, 0, 0, 255]
+                });
+            }
+        }
+        Image::from_raw_rgba8(ERROR_SIZE, ERROR_SIZE, data).unwrap()
+    }
+
+    /// Gets the texture, or [error](Self::error) if it does not exist, such as while loading or after a failed load.
+    /// Returns None if the error texture is not created yet
+    pub fn texture_or_error<'a>(
+        &self,
+        texture_assets: &'a Assets<Texture>,
+        asset_id: AssetId<Texture>,
+    ) -> Option<&'a Texture> {
+        texture_assets
+            .get(asset_id)
+            .or_else(|| texture_assets.get(self.error))
+    }
+}
+
+pub(crate) fn create_default_textures(
+    mut commands: Commands,
+    mut texture_queue: ResMut<TextureQueue>,
+    mut texture_assets: ResMut<Assets<Texture>>,
+) {
+    let mut load = |image: Image, format: TextureFormat| {
+        let asset_id = texture_assets.add_empty();
+        texture_queue.load(
+            asset_id,
+            image.into(),
+            TextureLoadOptions::default().with_format(format),
+        );
+        asset_id
+    };
+    let pixel = |color: [u8; 4]| Image::from_raw_rgba8(1, 1, color.to_vec()).unwrap();
+    commands.insert_resource(DefaultTextures {
+        white: load(pixel([255; 4]), DEFAULT_TEXTURE_FORMAT),
+        black: load(pixel([0, 0, 0, 255]), DEFAULT_TEXTURE_FORMAT),
+        flat_normal: load(pixel([128, 128, 255, 255]), TextureFormat::Rgba8Unorm),
+        error: load(DefaultTextures::error_image(), DEFAULT_TEXTURE_FORMAT),
+    });
+}
@@ -0,0 +1,194 @@
+use bevy_ecs::prelude::*;
+use modula_asset::{AssetId, Assets};
+use wgpu::{Origin3d, Texture};
+
+use crate::{Image, TextureQueue};
+
+/// The default of [DynamicTexture::set_full_upload_threshold]
+const DEFAULT_FULL_UPLOAD_THRESHOLD: f32 = 0.5;
+
+/// A rectangle of pixels
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PixelRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl PixelRect {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[inline]
+    pub fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    /// The smallest rect containing both rects
+    pub fn union(&self, other: &PixelRect) -> PixelRect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let end_x = (self.x + self.width).max(other.x + other.width);
+        let end_y = (self.y + self.height).max(other.y + other.height);
+        PixelRect::new(x, y, end_x - x, end_y - y)
+    }
+
+    /// True if the rects overlap or share an edge
+    pub fn touches(&self, other: &PixelRect) -> bool {
+        self.x <= other.x + other.width
+            && other.x <= self.x + self.width
+            && self.y <= other.y + other.height
+            && other.y <= self.y + self.height
+    }
+
+    /// The part of the rect inside a width x height image, None if it is empty
+    fn clamped(&self, width: u32, height: u32) -> Option<PixelRect> {
+        let end_x = self.x.saturating_add(self.width).min(width);
+        let end_y = self.y.saturating_add(self.height).min(height);
+        (self.x < end_x && self.y < end_y)
+            .then(|| PixelRect::new(self.x, self.y, end_x - self.x, end_y - self.y))
+    }
+}
+
+/// A texture with a CPU side [Image] that is changed often, such as a UI or video frame.
+/// Changed parts are marked dirty and only those are uploaded during [TextureLoadSet](crate::TextureLoadSet).
+/// The dirty parts are copied when they are queued, so the image can be changed right after without affecting the upload.
+/// Created with [TextureLoader::load_dynamic_texture](crate::TextureLoader::load_dynamic_texture)
+pub struct DynamicTexture {
+    texture: AssetId<Texture>,
+    image: Image,
+    dirty: Vec<PixelRect>,
+    full_upload_threshold: f32,
+}
+
+impl DynamicTexture {
+    /// The whole image is dirty, so it is uploaded during the next [TextureLoadSet](crate::TextureLoadSet)
+    pub(crate) fn new(texture: AssetId<Texture>, image: Image) -> Self {
+        let dirty = vec![PixelRect::new(0, 0, image.width, image.height)];
+        Self {
+            texture,
+            image,
+            dirty,
+            full_upload_threshold: DEFAULT_FULL_UPLOAD_THRESHOLD,
+        }
+    }
+
+    #[inline]
+    pub fn texture(&self) -> AssetId<Texture> {
+        self.texture
+    }
+
+    #[inline]
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    /// Mutably gets the image, changed parts must be marked with [mark_dirty](Self::mark_dirty).
+    /// The size and format of the image should not be changed
+    #[inline]
+    pub fn image_mut(&mut self) -> &mut Image {
+        &mut self.image
+    }
+
+    /// Marks a part of the image to be uploaded, the part outside the image is ignored
+    pub fn mark_dirty(&mut self, rect: PixelRect) {
+        if let Some(rect) = rect.clamped(self.image.width, self.image.height) {
+            self.dirty.push(rect);
+        }
+    }
+
+    /// Marks the whole image to be uploaded
+    pub fn mark_all_dirty(&mut self) {
+        self.mark_dirty(PixelRect::new(0, 0, self.image.width, self.image.height));
+    }
+
+    /// Copies tightly packed pixels in the format of the image to rect, and marks it dirty
+    /// ## Panics
+    /// If rect is not inside the image, or data does not have the size of rect
+    pub fn write_pixels(&mut self, rect: PixelRect, data: &[u8]) {
+        assert!(
+            rect.clamped(self.image.width, self.image.height) == Some(rect),
+            "{rect:?} is not inside the {}x{} image",
+            self.image.width,
+            self.image.height
+        );
+        let pixel_size = self.image.format.bytes_per_pixel();
+        let row_len = rect.width as usize * pixel_size;
+        assert_eq!(
+            data.len(),
+            row_len * rect.height as usize,
+            "data does not match the size of {rect:?}"
+        );
+        for (row, src) in data.chunks_exact(row_len).enumerate() {
+            let start = ((rect.y as usize + row) * self.image.width as usize + rect.x as usize)
+                * pixel_size;
+            self.image.data[start..start + row_len].copy_from_slice(src);
+        }
+        self.dirty.push(rect);
+    }
+
+    /// If the dirty area is more than this fraction of the image after merging, the whole image is uploaded at once.
+    /// 0.5 by default
+    pub fn set_full_upload_threshold(&mut self, threshold: f32) {
+        self.full_upload_threshold = threshold;
+    }
+
+    /// Merges the dirty rects and queues the writes
+    fn upload(&mut self, texture_queue: &mut TextureQueue) {
+        let rects = coalesce(std::mem::take(&mut self.dirty));
+        let dirty_area: u64 = rects.iter().map(PixelRect::area).sum();
+        let full_area = self.image.width as u64 * self.image.height as u64;
+        if dirty_area as f64 > full_area as f64 * self.full_upload_threshold as f64 {
+            texture_queue.write(self.image.clone(), self.texture, Origin3d::ZERO);
+            return;
+        }
+        for rect in rects {
+            let region = self
+                .image
+                .cropped(rect.x, rect.y, rect.width, rect.height)
+                .expect("dirty rects are clamped to the image");
+            let origin = Origin3d {
+                x: rect.x,
+                y: rect.y,
+                z: 0,
+            };
+            texture_queue.write_region(self.texture, 0, origin, region);
+        }
+    }
+}
+
+/// Merges rects that touch until none do
+fn coalesce(mut rects: Vec<PixelRect>) -> Vec<PixelRect> {
+    let mut i = 0;
+    while i < rects.len() {
+        match (i + 1..rects.len()).find(|&j| rects[i].touches(&rects[j])) {
+            Some(j) => {
+                rects[i] = rects[i].union(&rects[j]);
+                rects.swap_remove(j);
+                // the larger rect may touch rects that were already checked
+                i = 0;
+            }
+            None => i += 1,
+        }
+    }
+    rects
+}
+
+/// Queues the dirty parts of all dynamic textures, runs before the queue is drained
+pub(crate) fn upload_dynamic_textures(
+    mut dynamic_assets: ResMut<Assets<DynamicTexture>>,
+    mut texture_queue: ResMut<TextureQueue>,
+) {
+    for (_, dynamic) in dynamic_assets.iter_mut() {
+        if !dynamic.dirty.is_empty() {
+            dynamic.upload(&mut texture_queue);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use wgpu::TextureFormat;
+
+/// How the color values of a texture are stored
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    /// Values are sRGB encoded and converted to linear when sampled, used for colors
+    #[default]
+    Srgb,
+    /// Values are sampled as stored, used for data such as normal maps, roughness maps and lookup tables
+    Linear,
+}
+
+impl ColorSpace {
+    /// The 8 bit RGBA format of the color space
+    pub fn format(self) -> TextureFormat {
+        match self {
+            ColorSpace::Srgb => TextureFormat::Rgba8UnormSrgb,
+            ColorSpace::Linear => TextureFormat::Rgba8Unorm,
+        }
+    }
+}
+
+/// How the pixels of an [Image](crate::Image) are stored, integer channels are unsigned and normalized.  
+/// Channels larger than a byte are stored in native endianness
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PixelFormat {
+    R8,
+    Rg8,
+    #[default]
+    Rgba8,
+    R16,
+    Rgba16,
+    /// Half precision floats, for HDR data
+    Rgba16Float,
+    /// Single precision floats, for HDR data
+    Rgba32Float,
+}
+
+impl PixelFormat {
+    pub fn channels(self) -> usize {
+        match self {
+            PixelFormat::R8 | PixelFormat::R16 => 1,
+            PixelFormat::Rg8 => 2,
+            PixelFormat::Rgba8
+            | PixelFormat::Rgba16
+            | PixelFormat::Rgba16Float
+            | PixelFormat::Rgba32Float => 4,
+        }
+    }
+
+    pub fn bytes_per_channel(self) -> usize {
+        match self {
+            PixelFormat::R8 | PixelFormat::Rg8 | PixelFormat::Rgba8 => 1,
+            PixelFormat::R16 | PixelFormat::Rgba16 | PixelFormat::Rgba16Float => 2,
+            PixelFormat::Rgba32Float => 4,
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, PixelFormat::Rgba16Float | PixelFormat::Rgba32Float)
+    }
+
+    #[inline]
+    pub fn bytes_per_pixel(self) -> usize {
+        self.channels() * self.bytes_per_channel()
+    }
+
+    /// The texture format matching the pixel format, color_space is only used for [Rgba8](PixelFormat::Rgba8),
+    /// as the other formats have no sRGB variant.  
+    /// 16 bit integer formats need [TEXTURE_FORMAT_16BIT_NORM](wgpu::Features::TEXTURE_FORMAT_16BIT_NORM),
+    /// and [Rgba32Float](PixelFormat::Rgba32Float) needs [FLOAT32_FILTERABLE](wgpu::Features::FLOAT32_FILTERABLE) to be filtered
+    pub fn texture_format(self, color_space: ColorSpace) -> TextureFormat {
+        match self {
+            PixelFormat::R8 => TextureFormat::R8Unorm,
+            PixelFormat::Rg8 => TextureFormat::Rg8Unorm,
+            PixelFormat::Rgba8 => color_space.format(),
+            PixelFormat::R16 => TextureFormat::R16Unorm,
+            PixelFormat::Rgba16 => TextureFormat::Rgba16Unorm,
+            PixelFormat::Rgba16Float => TextureFormat::Rgba16Float,
+            PixelFormat::Rgba32Float => TextureFormat::Rgba32Float,
+        }
+    }
+}
+
+/// What happens to float values outside of 0 to 1 when loading HDR images
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FloatRange {
+    /// Values are kept, except values too large for [Rgba16Float](PixelFormat::Rgba16Float) which become its largest value
+    #[default]
+    Preserve,
+    /// Values are clamped to 0 to 1
+    Clamp,
+}
+
+/// How the alpha of an [Image](crate::Image) is applied to its color channels
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum AlphaMode {
+    /// Color channels are stored as is
+    #[default]
+    Straight,
+    /// Color channels are multiplied by alpha, which blends correctly when filtered
+    Premultiplied,
+}
@@ -0,0 +1,240 @@
+use std::{fs, io::Read, path::Path};
+
+use ktx2::{Format, Reader, SupercompressionScheme};
+use wgpu::{AstcBlock, AstcChannel, TextureFormat};
+
+use crate::{
+    AlphaMode, ColorSpace, CompressedImage, Image, ImageLoadError, MipMapImage, PixelFormat,
+};
+
+/// The contents of a KTX2 file, uncompressed formats that match a [PixelFormat] are loaded as [Images](Image),
+/// while BCn and ASTC data is loaded as a [CompressedImage].
+/// Only single 2d images are supported, so arrays, cube maps and 3d textures return [Unsupported](ImageLoadError::Unsupported),
+/// as do BasisLZ and zlib supercompression
+pub enum Ktx2Texture {
+    Image(MipMapImage),
+    Compressed(CompressedImage),
+}
+
+impl Ktx2Texture {
+    /// Load from file data
+    pub fn load_from_data(data: &[u8]) -> Result<Self, ImageLoadError> {
+        let reader =
+            Reader::new(data).map_err(|e| ImageLoadError::ContainerParse(e.to_string()))?;
+        let header = reader.header();
+        if header.layer_count > 1 || header.face_count != 1 || header.pixel_depth > 1 {
+            return Err(ImageLoadError::Unsupported(
+                "KTX2 arrays, cube maps and 3d textures".into(),
+            ));
+        }
+        let format = header.format.ok_or_else(|| {
+            ImageLoadError::Unsupported("KTX2 without a format, such as Basis Universal".into())
+        })?;
+        let mut levels = Vec::with_capacity(reader.levels().len());
+        for level in reader.levels() {
+            levels.push(match header.supercompression_scheme {
+                None => level.data.to_vec(),
+                Some(SupercompressionScheme::Zstandard) => {
+                    decompress_zstd(level.data, level.uncompressed_byte_length)?
+                }
+                Some(scheme) => {
+                    return Err(ImageLoadError::Unsupported(format!(
+                        "KTX2 supercompression {scheme:?}"
+                    )))
+                }
+            });
+        }
+        let (width, height) = (header.pixel_width, header.pixel_height.max(1));
+        if let Some((pixel_format, color_space)) = pixel_format(format) {
+            let images = levels
+                .into_iter()
+                .enumerate()
+                .map(|(level, data)| {
+                    let image = Image {
+                        data,
+                        width: (width >> level).max(1),
+                        height: (height >> level).max(1),
+                        format: pixel_format,
+                        color_space: Some(color_space),
+                        alpha_mode: AlphaMode::Straight,
+                    };
+                    let expected = image.width as usize
+                        * image.height as usize
+                        * pixel_format.bytes_per_pixel();
+                    if image.data.len() != expected {
+                        return Err(ImageLoadError::ContainerParse(format!(
+                            "KTX2 level {level} has {} bytes, expected {expected}",
+                            image.data.len()
+                        )));
+                    }
+                    Ok(image)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let image = MipMapImage::with_images(images)
+                .map_err(|e| ImageLoadError::ContainerParse(e.to_string()))?;
+            Ok(Self::Image(image))
+        } else if let Some(texture_format) = compressed_format(format) {
+            let mip_levels = levels.len() as u32;
+            let image =
+                CompressedImage::new(texture_format, width, height, levels.concat(), mip_levels)?;
+            Ok(Self::Compressed(image))
+        } else {
+            Err(ImageLoadError::Unsupported(format!(
+                "KTX2 format {format:?}"
+            )))
+        }
+    }
+
+    /// Load from file
+    pub fn load_from_path(path: impl AsRef<Path>) -> Result<Self, ImageLoadError> {
+        Self::load_from_data(&fs::read(path)?)
+    }
+
+    /// The texture format the file is stored in
+    pub fn format(&self) -> TextureFormat {
+        match self {
+            Ktx2Texture::Image(image) => {
+                let first = &image.levels()[0];
+                first
+                    .format
+                    .texture_format(first.color_space.unwrap_or_default())
+            }
+            Ktx2Texture::Compressed(image) => image.format(),
+        }
+    }
+}
+
+fn decompress_zstd(data: &[u8], uncompressed_len: u64) -> Result<Vec<u8>, ImageLoadError> {
+    let parse_error = |e: &dyn std::error::Error| ImageLoadError::ContainerParse(e.to_string());
+    let mut decoder = ruzstd::decoding::StreamingDecoder::new(data).map_err(|e| parse_error(&e))?;
+    let mut res = Vec::with_capacity(uncompressed_len as usize);
+    decoder.read_to_end(&mut res)?;
+    if res.len() as u64 != uncompressed_len {
+        return Err(ImageLoadError::ContainerParse(format!(
+            "zstd level decompressed to {} bytes, expected {uncompressed_len}",
+            res.len()
+        )));
+    }
+    Ok(res)
+}
+
+fn pixel_format(format: Format) -> Option<(PixelFormat, ColorSpace)> {
+    Some(match format {
+        Format::R8_UNORM => (PixelFormat::R8, ColorSpace::Linear),
+        Format::R8G8_UNORM => (PixelFormat::Rg8, ColorSpace::Linear),
+        Format::R8G8B8A8_UNORM => (PixelFormat::Rgba8, ColorSpace::Linear),
+        Format::R8G8B8A8_SRGB => (PixelFormat::Rgba8, ColorSpace::Srgb),
+        Format::R16_UNORM => (PixelFormat::R16, ColorSpace::Linear),
+        Format::R16G16B16A16_UNORM => (PixelFormat::Rgba16, ColorSpace::Linear),
+        Format::R16G16B16A16_SFLOAT => (PixelFormat::Rgba16Float, ColorSpace::Linear),
+        Format::R32G32B32A32_SFLOAT => (PixelFormat::Rgba32Float, ColorSpace::Linear),
+        _ => return None,
+    })
+}
+
+fn compressed_format(format: Format) -> Option<TextureFormat> {
+    Some(match format {
+        Format::BC1_RGBA_UNORM_BLOCK => TextureFormat::Bc1RgbaUnorm,
+        Format::BC1_RGBA_SRGB_BLOCK => TextureFormat::Bc1RgbaUnormSrgb,
+        Format::BC2_UNORM_BLOCK => TextureFormat::Bc2RgbaUnorm,
+        Format::BC2_SRGB_BLOCK => TextureFormat::Bc2RgbaUnormSrgb,
+        Format::BC3_UNORM_BLOCK => TextureFormat::Bc3RgbaUnorm,
+        Format::BC3_SRGB_BLOCK => TextureFormat::Bc3RgbaUnormSrgb,
+        Format::BC4_UNORM_BLOCK => TextureFormat::Bc4RUnorm,
+        Format::BC4_SNORM_BLOCK => TextureFormat::Bc4RSnorm,
+        Format::BC5_UNORM_BLOCK => TextureFormat::Bc5RgUnorm,
+        Format::BC5_SNORM_BLOCK => TextureFormat::Bc5RgSnorm,
+        Format::BC6H_UFLOAT_BLOCK => TextureFormat::Bc6hRgbUfloat,
+        Format::BC6H_SFLOAT_BLOCK => TextureFormat::Bc6hRgbFloat,
+        Format::BC7_UNORM_BLOCK => TextureFormat::Bc7RgbaUnorm,
+        Format::BC7_SRGB_BLOCK => TextureFormat::Bc7RgbaUnormSrgb,
+        _ => return astc_format(format.value()),
+    })
+}
+
+/// ASTC formats are numbered by block size, with unorm and sRGB alternating and float formats after
+fn astc_format(value: u32) -> Option<TextureFormat> {
+    const BLOCKS: [AstcBlock; 14] = [
+        AstcBlock::B4x4,
+        AstcBlock::B5x4,
+        AstcBlock::B5x5,
+        AstcBlock::B6x5,
+        AstcBlock::B6x6,
+        AstcBlock::B8x5,
+        AstcBlock::B8x6,
+        AstcBlock::B8x8,
+        AstcBlock::B10x5,
+        AstcBlock::B10x6,
+        AstcBlock::B10x8,
+        AstcBlock::B10x10,
+        AstcBlock::B12x10,
+        AstcBlock::B12x12,
+    ];
+    let ldr_start = Format::ASTC_4x4_UNORM_BLOCK.value();
+    let hdr_start = Format::ASTC_4x4_SFLOAT_BLOCK.value();
+    let (idx, channel) = if (ldr_start..ldr_start + 28).contains(&value) {
+        let offset = value - ldr_start;
+        let channel = match offset % 2 {
+            0 => AstcChannel::Unorm,
+            _ => AstcChannel::UnormSrgb,
+        };
+        (offset / 2, channel)
+    } else if (hdr_start..hdr_start + 14).contains(&value) {
+        (value - hdr_start, AstcChannel::Hdr)
+    } else {
+        return None;
+    };
+    Some(TextureFormat::Astc {
+        block: BLOCKS[idx as usize],
+        channel,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 4x2 R8G8B8A8_SRGB image with its full chain of 3 levels
+    const MIPS: &[u8] = include_bytes!("../tests/fixtures/rgba8_srgb_mips.ktx2");
+    /// The same levels, each in a zstd frame of raw blocks
+    const MIPS_ZSTD: &[u8] = include_bytes!("../tests/fixtures/rgba8_srgb_mips_zstd.ktx2");
+
+    fn assert_mips(data: &[u8]) {
+        let texture = Ktx2Texture::load_from_data(data).unwrap();
+        assert_eq!(texture.format(), TextureFormat::Rgba8UnormSrgb);
+        let Ktx2Texture::Image(image) = texture else {
+            panic!("uncompressed data should load as an image");
+        };
+        assert_eq!(image.sizes(), [(4, 2), (2, 1), (1, 1)]);
+        let levels = image.levels();
+        assert!(levels.iter().all(|level| level.format == PixelFormat::Rgba8
+            && level.color_space == Some(ColorSpace::Srgb)));
+        assert_eq!(
+            levels[0].data,
+            [
+                0, 7, 13, 255, 30, 57, 83, 254, 60, 107, 153, 253, 90, 157, 223, 252, 120, 207, 37,
+                251, 150, 1, 107, 250, 180, 51, 177, 249, 210, 101, 247, 248
+            ]
+        );
+        assert_eq!(levels[1].data, [100, 107, 113, 255, 130, 157, 183, 254]);
+        assert_eq!(levels[2].data, [200, 207, 213, 255]);
+    }
+
+    #[test]
+    fn load_levels() {
+        assert_mips(MIPS);
+    }
+
+    #[test]
+    fn load_zstd_levels() {
+        assert_mips(MIPS_ZSTD);
+    }
+
+    #[test]
+    fn truncated_file_is_parse_error() {
+        assert!(matches!(
+            Ktx2Texture::load_from_data(&MIPS[..40]),
+            Err(ImageLoadError::ContainerParse(_))
+        ));
+    }
+}
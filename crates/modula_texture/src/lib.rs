@@ -1,24 +1,65 @@
-// TODO Handle mipmapping
-
 use std::{
     error::Error,
     fmt::{self, Display, Formatter},
-    io,
+    io::{self, BufRead, Cursor, Seek},
     path::Path,
     slice,
 };
 
+use atlas::{AtlasLayout, GridParams};
 use bevy_ecs::{prelude::*, system::SystemParam};
-use image::{DynamicImage, ImageError, ImageReader};
+use half::f16;
+use image::{DynamicImage, ImageDecoder, ImageError, ImageReader};
 use modula_asset::{AssetId, Assets};
-use modula_core::{DeviceRes, PreInit, QueueRes, ScheduleBuilder};
+use modula_core::{DeviceRes, Init, PreInit, QueueRes, ScheduleBuilder};
 use modula_render::PreDraw;
+use modula_utils::HashSet;
 use wgpu::{
-    Device, Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, Queue, Texture, TextureAspect,
-    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+    CommandEncoderDescriptor, Device, Extent3d, Features, ImageCopyTexture, ImageDataLayout,
+    Origin3d, Queue, Texture, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages, TextureView,
 };
 
+mod async_loader;
 pub mod atlas;
+mod color;
+mod compressed;
+mod cubemap;
+mod defaults;
+mod dynamic;
+mod format;
+#[cfg(feature = "ktx2")]
+mod ktx;
+mod memory;
+mod mipmap;
+mod readback;
+mod sampler;
+#[cfg(test)]
+mod test_utils;
+mod transform;
+mod view;
+#[cfg(all(target_arch = "wasm32", feature = "web-decode"))]
+mod web_decode;
+
+pub use async_loader::*;
+pub use color::*;
+pub use compressed::*;
+pub use cubemap::*;
+pub use defaults::*;
+pub use dynamic::*;
+pub use format::*;
+#[cfg(feature = "ktx2")]
+pub use ktx::*;
+pub use memory::*;
+pub use mipmap::*;
+pub use readback::*;
+pub use sampler::*;
+pub use transform::*;
+pub use view::*;
+
+/// The format used by [TextureQueue::init_default] and [TextureLoader::load_texture]
+/// This is the format of [ColorSpace::Srgb]
+pub const DEFAULT_TEXTURE_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
 
 /// Systems that load textures during [PreDraw], anything that runs in [PreDraw] and needs textures should run after this
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
@@ -26,17 +67,99 @@ pub struct TextureLoadSet;
 
 pub fn init_texture_loading(schedule_builder: &mut ScheduleBuilder) {
     modula_asset::init_assets::<Texture>(schedule_builder);
+    modula_asset::init_assets::<CubeTexture>(schedule_builder);
+    modula_asset::init_assets::<TextureView>(schedule_builder);
+    modula_asset::init_assets::<DynamicTexture>(schedule_builder);
+    schedule_builder.add_systems(PreInit, |mut commands: Commands| {
+        commands.insert_resource(TextureQueue::new());
+    });
+    schedule_builder.add_systems(
+        Init,
+        |mut commands: Commands,
+         mut texture_queue: ResMut<TextureQueue>,
+         device: Res<DeviceRes>| {
+            commands.insert_resource(MipmapGenerator::new(&device.0));
+            texture_queue.features = Some(device.0.features());
+            texture_queue.max_texture_size = Some(device.0.limits().max_texture_dimension_2d);
+        },
+    );
+    schedule_builder.add_systems(Init, defaults::create_default_textures);
     schedule_builder.add_systems(PreInit, |mut commands: Commands| {
-        commands.insert_resource(TextureQueue { queue: Vec::new() });
+        commands.insert_resource(Events::<TextureWriteFailed>::default());
+        commands.insert_resource(Events::<TextureLoadFailed>::default());
+        commands.insert_resource(AsyncTextureQueue::new());
+        commands.insert_resource(TextureMemoryStats::default());
+        commands.insert_resource(Events::<TextureBudgetExceeded>::default());
     });
     // doing in PreDraw because draw will need the textures, but PreDraw should only sync data
-    schedule_builder.add_systems(PreDraw, load_textures.in_set(TextureLoadSet));
+    schedule_builder.add_systems(
+        PreDraw,
+        (
+            |mut write_failed: ResMut<Events<TextureWriteFailed>>,
+             mut load_failed: ResMut<Events<TextureLoadFailed>>,
+             mut budget_exceeded: ResMut<Events<TextureBudgetExceeded>>| {
+                write_failed.update();
+                load_failed.update();
+                budget_exceeded.update();
+            },
+            async_loader::receive_decoded_textures,
+            dynamic::upload_dynamic_textures,
+            load_textures,
+            view::update_texture_views,
+            cubemap::create_cube_views,
+            memory::check_texture_budget,
+        )
+            .chain()
+            .in_set(TextureLoadSet),
+    );
+}
+
+/// Sent during [PreDraw] when a write or init queued in [TextureQueue] could not be done, the operation is dropped.  
+/// Events can be read until the end of the next frame
+#[derive(Event, Debug, Clone)]
+pub struct TextureWriteFailed {
+    pub asset_id: AssetId<Texture>,
+    /// The label the texture was created with, None if it has no label or does not exist
+    pub label: Option<String>,
+    pub reason: TextureWriteError,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextureWriteError {
+    /// The texture was never initialized, or was removed.
+    /// Inits are done before writes queued in the same frame, so the order they are queued in does not matter
+    MissingTexture,
+    /// The image does not match the format of the texture, or its own size
+    InvalidImage(String),
+    /// The image does not fit in the texture at the origin, or the mip level or layer does not exist
+    OutOfBounds(String),
+    /// The texture could not be initialized, as the device does not have the features its format needs
+    UnsupportedFormat(String),
+}
+
+impl Display for TextureWriteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureWriteError::MissingTexture => write!(f, "texture does not exist"),
+            TextureWriteError::InvalidImage(e) => write!(f, "invalid image: {}", e),
+            TextureWriteError::OutOfBounds(e) => write!(f, "out of bounds: {}", e),
+            TextureWriteError::UnsupportedFormat(e) => write!(f, "unsupported format: {}", e),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum ImageLoadError {
     IOError(io::Error),
     ImageError(ImageError),
+    /// A texture container such as KTX2 could not be parsed
+    ContainerParse(String),
+    /// A texture container with content that is not supported
+    Unsupported(String),
+    /// A texture container with compressed data that does not match its header
+    CompressedImage(CompressedImageError),
+    /// The browser could not fetch or decode the image, only with the `web-decode` feature on wasm32
+    WebDecode(String),
 }
 
 impl Error for ImageLoadError {}
@@ -46,19 +169,31 @@ impl Display for ImageLoadError {
         match self {
             ImageLoadError::IOError(e) => write!(f, "Texture load IOError: {}", e),
             ImageLoadError::ImageError(e) => write!(f, "Texture load ImageError: {}", e),
+            ImageLoadError::ContainerParse(e) => write!(f, "Texture container parse error: {}", e),
+            ImageLoadError::Unsupported(e) => write!(f, "Texture load unsupported: {}", e),
+            ImageLoadError::CompressedImage(e) => {
+                write!(f, "Texture load CompressedImageError: {}", e)
+            }
+            ImageLoadError::WebDecode(e) => write!(f, "Texture web decode error: {}", e),
         }
     }
 }
 
 impl From<io::Error> for ImageLoadError {
     fn from(value: io::Error) -> Self {
-        return Self::IOError(value);
+        Self::IOError(value)
     }
 }
 
 impl From<ImageError> for ImageLoadError {
     fn from(value: ImageError) -> Self {
-        return Self::ImageError(value);
+        Self::ImageError(value)
+    }
+}
+
+impl From<CompressedImageError> for ImageLoadError {
+    fn from(value: CompressedImageError) -> Self {
+        Self::CompressedImage(value)
     }
 }
 
@@ -69,33 +204,267 @@ pub struct Image {
     pub data: Vec<u8>,
     pub width: u32,
     pub height: u32,
+    pub format: PixelFormat,
+    /// The color space declared by the source, None if it is unknown.  
+    /// Only embedded ICC profiles are read when loading, so most files will have None
+    pub color_space: Option<ColorSpace>,
+    /// Images are loaded as [Straight](AlphaMode::Straight), use [premultiply_alpha](Image::premultiply_alpha) to change it
+    pub alpha_mode: AlphaMode,
+}
+
+/// Returned when the data of an image does not match its size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageSizeMismatch {
+    pub expected: usize,
+    pub actual: usize,
+}
+
+impl Error for ImageSizeMismatch {}
+
+impl Display for ImageSizeMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "image data has {} bytes, expected {}",
+            self.actual, self.expected
+        )
+    }
 }
 
 impl Image {
-    /// Load from file data
+    /// Makes an [Rgba8](PixelFormat::Rgba8) image from raw pixels, data must have 4 bytes for every pixel
+    pub fn from_raw_rgba8(
+        width: u32,
+        height: u32,
+        data: Vec<u8>,
+    ) -> Result<Self, ImageSizeMismatch> {
+        let expected = width as usize * height as usize * PixelFormat::Rgba8.bytes_per_pixel();
+        if data.len() != expected {
+            return Err(ImageSizeMismatch {
+                expected,
+                actual: data.len(),
+            });
+        }
+        Ok(Self {
+            data,
+            width,
+            height,
+            format: PixelFormat::Rgba8,
+            color_space: None,
+            alpha_mode: AlphaMode::Straight,
+        })
+    }
+
+    /// Multiplies the color channels by alpha, does nothing if already [Premultiplied](AlphaMode::Premultiplied).  
+    /// Only RGBA formats have alpha, other formats are only marked as premultiplied
+    pub fn premultiply_alpha(&mut self) {
+        if self.alpha_mode == AlphaMode::Straight {
+            self.map_color(|color, alpha| color * alpha);
+            self.alpha_mode = AlphaMode::Premultiplied;
+        }
+    }
+
+    /// Divides the color channels by alpha, does nothing if already [Straight](AlphaMode::Straight).  
+    /// Pixels with 0 alpha become black, and precision is lost for low alpha values
+    pub fn unpremultiply_alpha(&mut self) {
+        if self.alpha_mode == AlphaMode::Premultiplied {
+            self.map_color(|color, alpha| if alpha == 0.0 { 0.0 } else { color / alpha });
+            self.alpha_mode = AlphaMode::Straight;
+        }
+    }
+
+    /// The alpha of the pixel at x, y from the top left, normalized to 0-1.
+    /// None if the pixel is outside the image or the format has no alpha
+    pub fn alpha(&self, x: u32, y: u32) -> Option<f32> {
+        if self.format.channels() != 4 || x >= self.width || y >= self.height {
+            return None;
+        }
+        let max = if self.format.is_float() {
+            1.0
+        } else if self.format.bytes_per_channel() == 2 {
+            u16::MAX as f32
+        } else {
+            u8::MAX as f32
+        };
+        let pixel = y as usize * self.width as usize + x as usize;
+        Some(self.sample(pixel * 4 + 3) / max)
+    }
+
+    /// Maps the color channels of RGBA formats with the alpha, using values normalized to 0-1
+    fn map_color(&mut self, f: impl Fn(f32, f32) -> f32) {
+        if self.format.channels() != 4 {
+            return;
+        }
+        let max = if self.format.is_float() {
+            1.0
+        } else if self.format.bytes_per_channel() == 2 {
+            u16::MAX as f32
+        } else {
+            u8::MAX as f32
+        };
+        let mut data = Vec::with_capacity(self.data.len());
+        for pixel in 0..self.data.len() / self.format.bytes_per_pixel() {
+            let alpha = self.sample(pixel * 4 + 3) / max;
+            for channel in 0..3 {
+                let color = self.sample(pixel * 4 + channel) / max;
+                self.push_sample(&mut data, f(color, alpha) * max);
+            }
+            self.push_sample(&mut data, alpha * max);
+        }
+        self.data = data;
+    }
+
+    /// Load from file data, using the image crate on all targets.  
+    /// With the `web-decode` feature on wasm32, `Image::decode_web` uses the browser instead
     pub fn load_from_data(data: &[u8]) -> Result<Self, ImageLoadError> {
-        Ok(image::load_from_memory(data)?.into())
+        Self::decode(ImageReader::new(Cursor::new(data)).with_guessed_format()?)
     }
     /// Load from file
     pub fn load_from_path(path: impl AsRef<Path>) -> Result<Self, ImageLoadError> {
-        Ok(ImageReader::open(path)?.decode()?.into())
+        Self::decode(ImageReader::open(path)?)
+    }
+
+    fn decode(reader: ImageReader<impl BufRead + Seek>) -> Result<Self, ImageLoadError> {
+        let mut decoder = reader.into_decoder()?;
+        // the description of sRGB profiles names the color space
+        let srgb_profile = decoder
+            .icc_profile()?
+            .is_some_and(|profile| profile.windows(4).any(|w| w == b"sRGB"));
+        let mut image: Image = DynamicImage::from_decoder(decoder)?.into();
+        if srgb_profile {
+            image.color_space = Some(ColorSpace::Srgb);
+        }
+        Ok(image)
     }
 
     pub fn to_mipmap(self, level_count: usize) -> MipMapImage {
         MipMapImage::from_level(self, level_count)
     }
-}
 
-// FIXME maybe don't use image lib publicly, as web should maybe use a different implementation
-// or maybe make another method for web...
-impl From<DynamicImage> for Image {
-    fn from(value: DynamicImage) -> Self {
+    /// The layout of the data when written to a texture, rows are tightly packed with the pixel size of the format
+    pub fn data_layout(&self) -> ImageDataLayout {
+        ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(self.format.bytes_per_pixel() as u32 * self.width),
+            rows_per_image: Some(self.height),
+        }
+    }
+
+    /// Load an HDR image such as '.hdr' or '.exr' from file data, format must be a [float](PixelFormat::is_float) format
+    pub fn load_hdr_from_data(
+        data: &[u8],
+        format: PixelFormat,
+        range: FloatRange,
+    ) -> Result<Self, ImageLoadError> {
+        let image = image::load_from_memory(data)?;
+        Ok(Self::from_dynamic_hdr(image, format, range))
+    }
+
+    /// Load an HDR image such as '.hdr' or '.exr' from file, format must be a [float](PixelFormat::is_float) format
+    pub fn load_hdr_from_path(
+        path: impl AsRef<Path>,
+        format: PixelFormat,
+        range: FloatRange,
+    ) -> Result<Self, ImageLoadError> {
+        let image = ImageReader::open(path)?.decode()?;
+        Ok(Self::from_dynamic_hdr(image, format, range))
+    }
+
+    /// Converts to a [float](PixelFormat::is_float) format
+    /// ## Panics
+    /// If format is not a float format
+    pub fn from_dynamic_hdr(value: DynamicImage, format: PixelFormat, range: FloatRange) -> Self {
+        assert!(format.is_float(), "{format:?} is not a float format");
+        let mut data = value.to_rgba32f().into_vec();
+        if range == FloatRange::Clamp {
+            data.iter_mut().for_each(|v| *v = v.clamp(0.0, 1.0));
+        }
+        Self {
+            data: float_bytes(data, format),
+            width: value.width(),
+            height: value.height(),
+            format,
+            color_space: dynamic_color_space(&value),
+            alpha_mode: AlphaMode::Straight,
+        }
+    }
+
+    /// Converts to the given format, [R8](PixelFormat::R8) and [R16](PixelFormat::R16) store the luminance,
+    /// and [Rg8](PixelFormat::Rg8) stores the luminance and alpha.  
+    /// Float values are [preserved](FloatRange::Preserve)
+    pub fn from_dynamic(value: DynamicImage, format: PixelFormat) -> Self {
+        let data = match format {
+            PixelFormat::R8 => value.to_luma8().into_vec(),
+            PixelFormat::Rg8 => value.to_luma_alpha8().into_vec(),
+            PixelFormat::Rgba8 => value.to_rgba8().into_vec(),
+            PixelFormat::R16 => u16_bytes(value.to_luma16().into_vec()),
+            PixelFormat::Rgba16 => u16_bytes(value.to_rgba16().into_vec()),
+            PixelFormat::Rgba16Float | PixelFormat::Rgba32Float => {
+                float_bytes(value.to_rgba32f().into_vec(), format)
+            }
+        };
         Self {
-            data: value.to_rgba8().into_vec(),
+            data,
             width: value.width(),
             height: value.height(),
+            format,
+            color_space: dynamic_color_space(&value),
+            alpha_mode: AlphaMode::Straight,
         }
     }
+
+    /// Converts to the format closest to the source, keeping the channel count where possible and the precision of 16 bit and float images
+    pub fn from_dynamic_preserving(value: DynamicImage) -> Self {
+        let format = match value {
+            DynamicImage::ImageLuma8(_) => PixelFormat::R8,
+            DynamicImage::ImageLumaA8(_) => PixelFormat::Rg8,
+            DynamicImage::ImageLuma16(_) => PixelFormat::R16,
+            DynamicImage::ImageLumaA16(_)
+            | DynamicImage::ImageRgb16(_)
+            | DynamicImage::ImageRgba16(_) => PixelFormat::Rgba16,
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+                PixelFormat::Rgba32Float
+            }
+            _ => PixelFormat::Rgba8,
+        };
+        Self::from_dynamic(value, format)
+    }
+}
+
+/// Float images are not sRGB encoded by convention, the color space of other images is not known
+fn dynamic_color_space(value: &DynamicImage) -> Option<ColorSpace> {
+    match value {
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => Some(ColorSpace::Linear),
+        _ => None,
+    }
+}
+
+fn u16_bytes(data: Vec<u16>) -> Vec<u8> {
+    data.into_iter().flat_map(u16::to_ne_bytes).collect()
+}
+
+/// Half floats are clamped to the largest finite value instead of becoming infinite
+fn float_bytes(data: Vec<f32>, format: PixelFormat) -> Vec<u8> {
+    match format {
+        PixelFormat::Rgba16Float => data
+            .into_iter()
+            .flat_map(|v| {
+                f16::from_f32(v.clamp(-f16::MAX.to_f32(), f16::MAX.to_f32())).to_ne_bytes()
+            })
+            .collect(),
+        _ => data.into_iter().flat_map(f32::to_ne_bytes).collect(),
+    }
+}
+
+// FIXME maybe don't use image lib publicly, web can decode with the browser using the web-decode feature,
+// but the image lib is still compiled to wasm because of this and the sync loading methods
+/// Converts to [Rgba8](PixelFormat::Rgba8), use [Image::from_dynamic_preserving] to keep the format of the source.  
+/// Float images are recorded as [Linear](ColorSpace::Linear), as they are not sRGB encoded by convention,
+/// the color space of other images is not known
+impl From<DynamicImage> for Image {
+    fn from(value: DynamicImage) -> Self {
+        Self::from_dynamic(value, PixelFormat::Rgba8)
+    }
 }
 
 /// A collection of [Images](Image) to be used for mipmap layers
@@ -109,14 +478,48 @@ pub enum MipMapImage {
 
 impl MipMapImage {
     /// Makes a new [MipMapImage] from its layers
-    /// This means that all levels are provided, use [from_level](MipMapImage::from_level) to have the engine automatically generate levels
-    /// ## Panics
-    /// If levels is empty
-    pub fn with_images(levels: Vec<Image>) -> Self {
-        if levels.is_empty() {
-            panic!("levels may not be empty");
+    /// This means that all levels are provided, use [from_level](MipMapImage::from_level) to have the engine automatically generate levels.  
+    /// Returns an error if the levels are not a valid mip chain, see [validate](Self::validate)
+    pub fn with_images(levels: Vec<Image>) -> Result<Self, MipChainError> {
+        let image = Self::WithImages(levels);
+        image.validate()?;
+        Ok(image)
+    }
+
+    /// Checks that there is at least one level, that every level is half the size of the previous rounded down to at least 1,
+    /// that all levels have the same [PixelFormat], and that there are not more levels than the size allows
+    pub fn validate(&self) -> Result<(), MipChainError> {
+        let levels = self.levels();
+        let Some(first) = levels.first() else {
+            return Err(MipChainError::Empty);
+        };
+        let max_levels = 32 - first.width.max(first.height).leading_zeros() as usize;
+        if self.level_count() > max_levels {
+            return Err(MipChainError::TooManyLevels {
+                count: self.level_count(),
+                max: max_levels,
+            });
+        }
+        for (level, pair) in levels.windows(2).enumerate() {
+            let level = level + 1;
+            let (prev, image) = (&pair[0], &pair[1]);
+            let expected = ((prev.width / 2).max(1), (prev.height / 2).max(1));
+            if (image.width, image.height) != expected {
+                return Err(MipChainError::LevelSize {
+                    level,
+                    expected,
+                    actual: (image.width, image.height),
+                });
+            }
+            if image.format != first.format {
+                return Err(MipChainError::LevelFormat {
+                    level,
+                    expected: first.format,
+                    actual: image.format,
+                });
+            }
         }
-        Self::WithImages(levels)
+        Ok(())
     }
 
     pub fn from_level(base: Image, level: usize) -> Self {
@@ -159,22 +562,85 @@ impl MipMapImage {
             .collect()
     }
 
-    /// Directly writes to a texture, for most cases [TextureLoader] or [TextureQueue] should be sufficient
+    /// Directly writes to a texture, for most cases [TextureLoader] or [TextureQueue] should be sufficient.  
+    /// The missing levels of [FromLevel](MipMapImage::FromLevel) are generated using [MipFilter::Box], origin is the origin in the first level and is scaled down for the others.  
+    /// The [PixelFormat] of the images must have the same size as the format of the texture
+    /// ## Panics
+    /// If the images do not match the format of the texture, or do not fit in it at the origin
+    #[inline]
     pub fn write_to_texture(&self, queue: &Queue, origin: Origin3d, texture: &Texture) {
-        for (mip_level, image) in self.levels().into_iter().enumerate() {
+        self.write_to_texture_with(queue, origin, texture, MipGeneration::default());
+    }
+
+    /// Same as [write_to_texture](Self::write_to_texture), but with [MipGeneration::Gpu] only the first level of [FromLevel](MipMapImage::FromLevel) is written,
+    /// and [MipmapGenerator::generate_mipmaps] must be used after
+    pub fn write_to_texture_with(
+        &self,
+        queue: &Queue,
+        origin: Origin3d,
+        texture: &Texture,
+        mip_generation: MipGeneration,
+    ) {
+        if let Err(err) = self.check_write(origin, texture, 0, mip_generation) {
+            panic!("{err}");
+        }
+        self.write_levels(queue, origin, texture, mip_generation, 0);
+    }
+
+    /// Checks that the images match the format of the texture and that every level fits, see [write_levels](Self::write_levels)
+    fn check_write(
+        &self,
+        origin: Origin3d,
+        texture: &Texture,
+        base_level: u32,
+        mip_generation: MipGeneration,
+    ) -> Result<(), TextureWriteError> {
+        for image in self.levels() {
+            check_writable(image, texture.format()).map_err(TextureWriteError::InvalidImage)?;
+        }
+        self.check_bounds(origin, texture, base_level, mip_generation)
+            .map_err(TextureWriteError::OutOfBounds)
+    }
+
+    /// Writes the levels starting at base_level, origin is the origin in base_level.
+    /// The images must match the format of the texture and fit in it, see [check_write](Self::check_write)
+    fn write_levels(
+        &self,
+        queue: &Queue,
+        origin: Origin3d,
+        texture: &Texture,
+        mip_generation: MipGeneration,
+        base_level: u32,
+    ) {
+        let generated = match (self, mip_generation) {
+            (MipMapImage::FromLevel(base, count), MipGeneration::Cpu(filter)) => {
+                mipmap::generate_levels(base, *count, filter)
+            }
+            _ => Vec::new(),
+        };
+        for (level, image) in self.levels().iter().chain(&generated).enumerate() {
+            let layout = image.data_layout();
+            debug_assert_eq!(
+                image.data.len(),
+                layout.bytes_per_row.unwrap() as usize * image.height as usize,
+                "data of mip level {level} does not match its {}x{} {:?} layout",
+                image.width,
+                image.height,
+                image.format
+            );
             queue.write_texture(
                 ImageCopyTexture {
                     texture,
-                    origin,
-                    mip_level: mip_level as u32,
+                    origin: Origin3d {
+                        x: origin.x >> level,
+                        y: origin.y >> level,
+                        z: origin.z,
+                    },
+                    mip_level: base_level + level as u32,
                     aspect: TextureAspect::All,
                 },
                 &image.data,
-                ImageDataLayout {
-                    offset: 0,
-                    bytes_per_row: Some(4 * image.width),
-                    rows_per_image: Some(image.height),
-                },
+                layout,
                 Extent3d {
                     width: image.width,
                     height: image.height,
@@ -183,6 +649,68 @@ impl MipMapImage {
             );
         }
     }
+
+    /// Checks that every level that would be written fits in the texture, with the first level written to base_level
+    fn check_bounds(
+        &self,
+        origin: Origin3d,
+        texture: &Texture,
+        base_level: u32,
+        mip_generation: MipGeneration,
+    ) -> Result<(), String> {
+        let mut sizes = self.sizes();
+        if let (MipMapImage::FromLevel(base, count), MipGeneration::Cpu(_)) = (self, mip_generation)
+        {
+            sizes
+                .extend((1..*count).map(|i| ((base.width >> i).max(1), (base.height >> i).max(1))));
+        }
+        if origin.z >= texture.depth_or_array_layers() {
+            return Err(format!(
+                "layer {} does not exist, the texture has {} layers",
+                origin.z,
+                texture.depth_or_array_layers()
+            ));
+        }
+        for (i, (width, height)) in sizes.into_iter().enumerate() {
+            let level = base_level + i as u32;
+            if level >= texture.mip_level_count() {
+                return Err(format!(
+                    "mip level {level} does not exist, the texture has {} levels",
+                    texture.mip_level_count()
+                ));
+            }
+            let (x, y) = (origin.x >> i, origin.y >> i);
+            let level_width = (texture.width() >> level).max(1);
+            let level_height = (texture.height() >> level).max(1);
+            if x as u64 + width as u64 > level_width as u64
+                || y as u64 + height as u64 > level_height as u64
+            {
+                return Err(format!(
+                    "{width}x{height} image at ({x}, {y}) does not fit in mip level {level}, which is {level_width}x{level_height}"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_writable(image: &Image, format: TextureFormat) -> Result<(), String> {
+    let bytes_per_pixel = image.format.bytes_per_pixel();
+    if format.block_dimensions() != (1, 1)
+        || format.block_copy_size(None) != Some(bytes_per_pixel as u32)
+    {
+        return Err(format!(
+            "{:?} image can not be written to {format:?} texture",
+            image.format
+        ));
+    }
+    if image.data.len() != image.width as usize * image.height as usize * bytes_per_pixel {
+        return Err(format!(
+            "{}x{} {:?} image has wrong data length",
+            image.width, image.height, image.format
+        ));
+    }
+    Ok(())
 }
 
 impl From<Image> for MipMapImage {
@@ -191,22 +719,216 @@ impl From<Image> for MipMapImage {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MipChainError {
+    /// There are no levels
+    Empty,
+    /// There are more levels than a mip chain of the size of the first level has
+    TooManyLevels { count: usize, max: usize },
+    /// A level is not half the size of the previous level, rounded down to at least 1
+    LevelSize {
+        level: usize,
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    /// A level does not have the [PixelFormat] of the first level
+    LevelFormat {
+        level: usize,
+        expected: PixelFormat,
+        actual: PixelFormat,
+    },
+}
+
+impl Error for MipChainError {}
+
+impl Display for MipChainError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "mip chain has no levels"),
+            Self::TooManyLevels { count, max } => {
+                write!(f, "mip chain has {count} levels, the size allows {max}")
+            }
+            Self::LevelSize {
+                level,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "mip level {level} is {}x{}, expected {}x{}",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            Self::LevelFormat {
+                level,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "mip level {level} has format {actual:?}, expected {expected:?}"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LayeredTextureError {
     /// Returned if a layered image was attempted, but there are no layers
     NoLayers,
-    /// Returned if not all layers share the same size for every mipmap level
-    InvalidLayer,
+    /// A layer does not have the same mip level sizes as the first layer, the sizes include generated levels
+    InvalidLayer {
+        layer_index: usize,
+        expected_sizes: Vec<(u32, u32)>,
+        actual_sizes: Vec<(u32, u32)>,
+    },
+    /// A layer does not have the [PixelFormat] of the first layer
+    LayerFormat {
+        layer_index: usize,
+        expected: PixelFormat,
+        actual: PixelFormat,
+    },
+    /// The mip levels of a layer are not a valid chain
+    InvalidMipChain {
+        layer_index: usize,
+        error: MipChainError,
+    },
+}
+
+impl Error for LayeredTextureError {}
+
+impl Display for LayeredTextureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoLayers => write!(f, "layered texture has no layers"),
+            Self::InvalidLayer {
+                layer_index,
+                expected_sizes,
+                actual_sizes,
+            } => write!(
+                f,
+                "layer {layer_index} has mip sizes {actual_sizes:?}, expected {expected_sizes:?} like layer 0"
+            ),
+            Self::LayerFormat {
+                layer_index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "layer {layer_index} has format {actual:?}, expected {expected:?} like layer 0"
+            ),
+            Self::InvalidMipChain { layer_index, error } => {
+                write!(f, "layer {layer_index} has invalid mip levels: {error}")
+            }
+        }
+    }
 }
 
 /// used to put textures in assets, if the goal is to just load a texture consider [TextureLoader]
 #[derive(Resource)]
 pub struct TextureQueue {
     queue: Vec<TextureOperation>,
+    /// Cube textures to create views for once their texture exists
+    cube_views: Vec<(AssetId<Texture>, AssetId<CubeTexture>)>,
+    /// All views made by [create_view](Self::create_view), they are checked for replaced textures every [PreDraw]
+    views: Vec<view::ViewInfo>,
+    removed_views: Vec<AssetId<TextureView>>,
+    color_space_mismatch: ColorSpaceMismatch,
+    /// The features of the device, None before [Init]
+    features: Option<Features>,
+    /// The max_texture_dimension_2d limit of the device, None before [Init]
+    max_texture_size: Option<u32>,
 }
 
 impl TextureQueue {
-    /// inits a texture on the given asset, discards the current texture if it already exists
-    pub fn init(
+    fn new() -> Self {
+        Self {
+            queue: Vec::new(),
+            cube_views: Vec::new(),
+            views: Vec::new(),
+            removed_views: Vec::new(),
+            color_space_mismatch: ColorSpaceMismatch::default(),
+            features: None,
+            max_texture_size: None,
+        }
+    }
+
+    /// inits a texture on the given asset, the current texture is destroyed if it already exists.  
+    /// If the device does not have the features the format needs, the texture is not made and [TextureWriteFailed] is sent
+    pub fn init(&mut self, asset_id: AssetId<Texture>, info: TextureInitInfo) {
+        self.queue
+            .push(TextureOperation::InitTexture(asset_id, info));
+    }
+
+    /// Inits and writes a texture sized after the image, downscaling it if it is larger than the max texture size
+    pub(crate) fn load(
+        &mut self,
+        asset_id: AssetId<Texture>,
+        image: MipMapImage,
+        options: TextureLoadOptions,
+    ) {
+        let image = if options.generate_mips {
+            full_mip_chain(image)
+        } else {
+            image
+        };
+        let max_size = self.max_texture_size;
+        let image = match max_size.and_then(|max| transform::fit_to_size(&image, max)) {
+            Some(fit) => {
+                let (width, height) = image.sizes()[0];
+                log::warn!(
+                    "{width}x{height} texture is larger than the max texture size {}, it was downscaled",
+                    max_size.unwrap()
+                );
+                fit
+            }
+            None => image,
+        };
+        let format = options
+            .format
+            .unwrap_or_else(|| image.levels()[0].format.texture_format(ColorSpace::Srgb));
+        self.queue.push(TextureOperation::InitTexture(
+            asset_id,
+            TextureInitInfo {
+                size: image.sizes()[0],
+                usage: options.texture_usages(),
+                mip_count: image.level_count() as u32,
+                layers: None,
+                format,
+                label: options.label,
+            },
+        ));
+        self.write_with_mips(image, asset_id, Origin3d::ZERO, options.mip_generation);
+    }
+
+    /// Creates a view of the texture on the given asset, after the inits queued before the next [PreDraw].  
+    /// The view is made again whenever the texture asset is replaced, and removed if the texture is removed
+    pub fn create_view(
+        &mut self,
+        asset_id: AssetId<TextureView>,
+        texture_id: AssetId<Texture>,
+        config: TextureViewConfig,
+    ) {
+        self.views.retain(|info| info.asset_id != asset_id);
+        self.views.push(view::ViewInfo {
+            asset_id,
+            texture_id,
+            config,
+            created_from: None,
+        });
+    }
+
+    /// Stops updating a view made by [create_view](Self::create_view), and removes the asset during the next [PreDraw]
+    pub fn remove_view(&mut self, asset_id: AssetId<TextureView>) {
+        self.views.retain(|info| info.asset_id != asset_id);
+        self.removed_views.push(asset_id);
+    }
+
+    /// Sets what happens when an image is written to a texture of another color space, [Warn](ColorSpaceMismatch::Warn) by default
+    pub fn set_color_space_mismatch(&mut self, mismatch: ColorSpaceMismatch) {
+        self.color_space_mismatch = mismatch;
+    }
+
+    /// Same as [init](Self::init), using [DEFAULT_TEXTURE_FORMAT]
+    #[inline]
+    pub fn init_default(
         &mut self,
         asset_id: AssetId<Texture>,
         size: (u32, u32),
@@ -214,73 +936,385 @@ impl TextureQueue {
         mip_count: u32,
         layers: Option<u32>,
     ) {
-        self.queue
-            .push(TextureOperation::InitTexture(TextureInitInfo {
-                asset_id,
-                size,
-                usage,
+        self.init(
+            asset_id,
+            TextureInitInfo {
                 mip_count,
                 layers,
+                ..TextureInitInfo::new(size, usage, DEFAULT_TEXTURE_FORMAT)
+            },
+        );
+    }
+
+    /// Same as [init](Self::init), but for a compressed format.  
+    /// Returns an error if the format is not compressed, or the device does not support it.
+    /// Before [Init] the device is not known, so a missing feature will instead panic when the texture is created
+    pub fn init_compressed(
+        &mut self,
+        asset_id: AssetId<Texture>,
+        info: TextureInitInfo,
+    ) -> Result<(), CompressedImageError> {
+        if !info.format.is_compressed() {
+            return Err(CompressedImageError::NotCompressed(info.format));
+        }
+        if let Some(features) = self.features {
+            CompressedImage::check_features(info.format, features)?;
+        }
+        self.init(asset_id, info);
+        Ok(())
+    }
+
+    /// writes a compressed image to the texture at the given asset, the texture must have the format of the image.  
+    /// If the write can not be done it is dropped and [TextureWriteFailed] is sent
+    pub fn write_compressed(
+        &mut self,
+        image: CompressedImage,
+        asset_id: AssetId<Texture>,
+        origin: Origin3d,
+    ) {
+        self.queue
+            .push(TextureOperation::WriteCompressed(CompressedWriteInfo {
+                image,
+                asset_id,
+                origin,
             }));
     }
 
-    /// writes a 2d image to the texture at the given asset, if it does not exist a panic will occur
+    /// writes a 2d image to the texture at the given asset.  
+    /// If the texture does not exist, or the image does not match its format or does not fit, the write is dropped and [TextureWriteFailed] is sent
     pub fn write(
         &mut self,
         image: impl Into<MipMapImage>,
         asset_id: AssetId<Texture>,
         origin: Origin3d,
+    ) {
+        self.write_with_mips(image, asset_id, origin, MipGeneration::default());
+    }
+
+    /// Same as [write](Self::write), but with control over how missing mip levels are generated.  
+    /// With [MipGeneration::Gpu] the levels are generated for every layer of the texture, after all writes queued before the next [PreDraw]
+    pub fn write_with_mips(
+        &mut self,
+        image: impl Into<MipMapImage>,
+        asset_id: AssetId<Texture>,
+        origin: Origin3d,
+        mip_generation: MipGeneration,
     ) {
         self.queue
             .push(TextureOperation::WriteTexture(TextureWriteInfo {
                 image: image.into(),
                 asset_id,
                 origin,
+                mip_level: 0,
+                mip_generation,
             }));
     }
+
+    /// Writes an image to a region of a single mip level, origin is the origin in that level.  
+    /// If the region does not fit in the level, the write is dropped and [TextureWriteFailed] is sent
+    pub fn write_region(
+        &mut self,
+        asset_id: AssetId<Texture>,
+        mip_level: u32,
+        origin: Origin3d,
+        image: Image,
+    ) {
+        self.queue
+            .push(TextureOperation::WriteTexture(TextureWriteInfo {
+                image: image.into(),
+                asset_id,
+                origin,
+                mip_level,
+                mip_generation: MipGeneration::default(),
+            }));
+    }
+}
+
+/// Options for [TextureLoader::load_texture_with] and [TextureLoader::load_layered_texture_with]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextureLoadOptions {
+    /// Extra usages, [TEXTURE_BINDING](TextureUsages::TEXTURE_BINDING) and [COPY_DST](TextureUsages::COPY_DST) are always used,
+    /// and [RENDER_ATTACHMENT](TextureUsages::RENDER_ATTACHMENT) is added for [MipGeneration::Gpu]
+    pub usages: TextureUsages,
+    /// The format of the texture, if None it is picked like in [load_texture](TextureLoader::load_texture)
+    pub format: Option<TextureFormat>,
+    /// Makes a full mip chain for images with a single level, images with more levels are loaded as they are
+    pub generate_mips: bool,
+    /// How missing mip levels are generated
+    pub mip_generation: MipGeneration,
+    pub label: Option<String>,
+}
+
+impl Default for TextureLoadOptions {
+    fn default() -> Self {
+        Self {
+            usages: TextureUsages::empty(),
+            format: None,
+            generate_mips: false,
+            mip_generation: MipGeneration::default(),
+            label: None,
+        }
+    }
+}
+
+impl TextureLoadOptions {
+    pub fn with_usages(mut self, usages: TextureUsages) -> Self {
+        self.usages = usages;
+        self
+    }
+
+    pub fn with_format(mut self, format: TextureFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Makes a full mip chain using mip_generation, see [generate_mips](Self::generate_mips)
+    pub fn with_generated_mips(mut self, mip_generation: MipGeneration) -> Self {
+        self.generate_mips = true;
+        self.mip_generation = mip_generation;
+        self
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// The usages the texture is created with
+    pub fn texture_usages(&self) -> TextureUsages {
+        let mut usages = self.usages | TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST;
+        if self.mip_generation == MipGeneration::Gpu {
+            usages |= TextureUsages::RENDER_ATTACHMENT;
+        }
+        usages
+    }
+}
+
+/// Makes images with a single level into a full mip chain
+fn full_mip_chain(image: MipMapImage) -> MipMapImage {
+    let base = match image {
+        MipMapImage::FromLevel(base, _) => base,
+        MipMapImage::WithImages(mut levels) if levels.len() == 1 => levels.pop().unwrap(),
+        with_images => return with_images,
+    };
+    let level_count = 32 - base.width.max(base.height).leading_zeros() as usize;
+    MipMapImage::from_level(base, level_count)
 }
 
 #[derive(SystemParam)]
 pub struct TextureLoader<'w> {
     texture_queue: ResMut<'w, TextureQueue>,
     texture_assets: ResMut<'w, Assets<Texture>>,
+    cube_assets: ResMut<'w, Assets<CubeTexture>>,
+    view_assets: ResMut<'w, Assets<TextureView>>,
+    dynamic_assets: ResMut<'w, Assets<DynamicTexture>>,
 }
 
 impl TextureLoader<'_> {
-    /// loads a texture
+    /// loads a texture, with a mip level for every level of the image.  
+    /// Images larger than the max texture size of the device are downscaled with a warning, by removing levels or resizing.  
+    /// The format is picked from the [PixelFormat] of the image, [Rgba8](PixelFormat::Rgba8) images use [DEFAULT_TEXTURE_FORMAT]
+    #[inline]
     pub fn load_texture(&mut self, image: impl Into<MipMapImage>) -> AssetId<Texture> {
+        self.load_texture_with_mips(image, ColorSpace::Srgb, MipGeneration::default())
+    }
+
+    /// Same as [load_texture](Self::load_texture), but the texture is labeled for debugging tools, [TextureMemoryStats] and [TextureWriteFailed]
+    #[inline]
+    pub fn load_texture_labeled(
+        &mut self,
+        image: impl Into<MipMapImage>,
+        label: impl Into<String>,
+    ) -> AssetId<Texture> {
+        self.load_texture_with(image, TextureLoadOptions::default().with_label(label))
+    }
+
+    /// Loads an HDR texture, for images with a [float](PixelFormat::is_float) format such as the ones from [Image::load_hdr_from_path]
+    /// ## Panics
+    /// If the image does not have a float format
+    pub fn load_hdr_texture(&mut self, image: impl Into<MipMapImage>) -> AssetId<Texture> {
         let image = image.into();
+        let format = image.levels()[0].format;
+        assert!(format.is_float(), "{format:?} is not a float format");
+        self.load_texture_with_mips(image, ColorSpace::Linear, MipGeneration::default())
+    }
+
+    /// Same as [load_texture](Self::load_texture), but the texture is [Linear](ColorSpace::Linear), for data such as normal maps.  
+    /// Linear images can also be added to [atlases](atlas) with a linear format, see [AtlasGroupBuilder::set_format](atlas::AtlasGroupBuilder::set_format)
+    #[inline]
+    pub fn load_texture_linear(&mut self, image: impl Into<MipMapImage>) -> AssetId<Texture> {
+        self.load_texture_with_mips(image, ColorSpace::Linear, MipGeneration::default())
+    }
+
+    /// Same as [load_texture](Self::load_texture), but with the given format, the pixel size of the image must match the format.  
+    /// Linear formats such as [Rgba8Unorm](TextureFormat::Rgba8Unorm) should be used for data that is not color, like normal maps
+    pub fn load_texture_with_format(
+        &mut self,
+        image: impl Into<MipMapImage>,
+        format: TextureFormat,
+    ) -> AssetId<Texture> {
+        self.load_texture_with(image, TextureLoadOptions::default().with_format(format))
+    }
+
+    /// Same as [load_texture](Self::load_texture), but with control over the color space and how missing mip levels are generated.  
+    /// [RENDER_ATTACHMENT](TextureUsages::RENDER_ATTACHMENT) usage is added for [MipGeneration::Gpu].  
+    /// Mip levels generated on the CPU are filtered as stored, so sRGB levels are slightly darker than when filtered on the GPU
+    pub fn load_texture_with_mips(
+        &mut self,
+        image: impl Into<MipMapImage>,
+        color_space: ColorSpace,
+        mip_generation: MipGeneration,
+    ) -> AssetId<Texture> {
+        let image = image.into();
+        let format = image.levels()[0].format.texture_format(color_space);
+        let options = TextureLoadOptions {
+            format: Some(format),
+            mip_generation,
+            ..Default::default()
+        };
+        self.load_texture_with(image, options)
+    }
+
+    /// Same as [load_texture](Self::load_texture), but with [TextureLoadOptions] for the usages, format, label and mip levels
+    pub fn load_texture_with(
+        &mut self,
+        image: impl Into<MipMapImage>,
+        options: TextureLoadOptions,
+    ) -> AssetId<Texture> {
         let asset_id = self.texture_assets.add_empty();
-        self.texture_queue.init(
-            asset_id,
-            image.sizes()[0],
-            TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-            1,
-            None,
+        self.texture_queue.load(asset_id, image.into(), options);
+        asset_id
+    }
+
+    /// Loads a sprite sheet as is like [load_texture](Self::load_texture), with the layout of its tiles in row-major order, see [AtlasLayout::grid]
+    /// ## Panics
+    /// If the grid is larger than the image
+    pub fn load_grid_atlas(
+        &mut self,
+        image: Image,
+        grid: GridParams,
+    ) -> (AssetId<Texture>, AtlasLayout) {
+        let (width, height) = grid.size();
+        assert!(
+            width <= image.width && height <= image.height,
+            "{width}x{height} grid does not fit in {}x{} image",
+            image.width,
+            image.height
         );
-        self.texture_queue.write(image, asset_id, Origin3d::ZERO);
+        (self.load_texture(image), grid.layout())
+    }
+
+    /// Loads a view of a texture, see [TextureQueue::create_view]
+    pub fn load_view(
+        &mut self,
+        texture_id: AssetId<Texture>,
+        config: TextureViewConfig,
+    ) -> AssetId<TextureView> {
+        let asset_id = self.view_assets.add_empty();
+        self.texture_queue.create_view(asset_id, texture_id, config);
         asset_id
     }
 
-    /// loads a layered image, all layers must be same size
+    /// Loads a texture with a single level that is updated from a CPU side image, see [DynamicTexture].  
+    /// The format is picked from the image, using its color space or sRGB if it is unknown
+    pub fn load_dynamic_texture(&mut self, image: Image) -> AssetId<DynamicTexture> {
+        let texture_id = self.texture_assets.add_empty();
+        let format = image
+            .format
+            .texture_format(image.color_space.unwrap_or_default());
+        self.texture_queue.init(
+            texture_id,
+            TextureInitInfo::new(
+                (image.width, image.height),
+                TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                format,
+            )
+            .with_label("Dynamic Texture"),
+        );
+        self.dynamic_assets
+            .add(DynamicTexture::new(texture_id, image))
+    }
+
+    /// loads a compressed texture, with a mip level for every level of the image
+    pub fn load_compressed_texture(
+        &mut self,
+        image: CompressedImage,
+    ) -> Result<AssetId<Texture>, CompressedImageError> {
+        // checking before allocating the asset
+        if let Some(features) = self.texture_queue.features {
+            CompressedImage::check_features(image.format(), features)?;
+        }
+        let asset_id = self.texture_assets.add_empty();
+        self.texture_queue.init_compressed(
+            asset_id,
+            TextureInitInfo::new(
+                (image.width(), image.height()),
+                TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                image.format(),
+            )
+            .with_mip_count(image.mip_levels()),
+        )?;
+        self.texture_queue
+            .write_compressed(image, asset_id, Origin3d::ZERO);
+        Ok(asset_id)
+    }
+
+    /// loads the contents of a KTX2 file, with a mip level for every level in the file
+    #[cfg(feature = "ktx2")]
+    pub fn load_ktx2_texture(
+        &mut self,
+        texture: Ktx2Texture,
+    ) -> Result<AssetId<Texture>, CompressedImageError> {
+        let format = texture.format();
+        match texture {
+            Ktx2Texture::Image(image) => Ok(self.load_texture_with_format(image, format)),
+            Ktx2Texture::Compressed(image) => self.load_compressed_texture(image),
+        }
+    }
+
+    /// loads a layered image, all layers must be same size and format and have the same number of levels.  
+    /// The format is picked like in [load_texture](Self::load_texture)
+    #[inline]
     pub fn load_layered_texture(
         &mut self,
         layers: Vec<MipMapImage>,
     ) -> Result<AssetId<Texture>, LayeredTextureError> {
+        self.load_layered_texture_with(layers, TextureLoadOptions::default())
+    }
+
+    /// Same as [load_layered_texture](Self::load_layered_texture), but with [TextureLoadOptions].  
+    /// Unlike [load_texture](Self::load_texture), layers larger than the max texture size are not downscaled
+    pub fn load_layered_texture_with(
+        &mut self,
+        layers: Vec<MipMapImage>,
+        options: TextureLoadOptions,
+    ) -> Result<AssetId<Texture>, LayeredTextureError> {
+        let layers: Vec<MipMapImage> = if options.generate_mips {
+            layers.into_iter().map(full_mip_chain).collect()
+        } else {
+            layers
+        };
         // checking to not allocate asset in case of error
-        if let Some(err) = validate_layers(&layers) {
-            return Err(err);
-        }
+        validate_layers(&layers)?;
         let asset_id = self.texture_assets.add_empty();
-        self.texture_queue.init(
+        let format = options.format.unwrap_or_else(|| {
+            layers[0].levels()[0]
+                .format
+                .texture_format(ColorSpace::Srgb)
+        });
+        self.texture_queue.queue.push(TextureOperation::InitTexture(
             asset_id,
-            layers[0].sizes()[0],
-            TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-            layers.len() as u32,
-            Some(layers.len() as u32),
-        );
+            TextureInitInfo {
+                size: layers[0].sizes()[0],
+                usage: options.texture_usages(),
+                mip_count: layers[0].level_count() as u32,
+                layers: Some(layers.len() as u32),
+                format,
+                label: options.label,
+            },
+        ));
         for (layer, mip_image) in layers.into_iter().enumerate() {
-            self.texture_queue.write(
+            self.texture_queue.write_with_mips(
                 mip_image,
                 asset_id,
                 Origin3d {
@@ -288,81 +1322,671 @@ impl TextureLoader<'_> {
                     y: 0,
                     z: layer as u32,
                 },
+                options.mip_generation,
             );
         }
         Ok(asset_id)
     }
+
+    /// Loads a cubemap from faces in the order +X, -X, +Y, -Y, +Z, -Z, see [Image::equirectangular_to_cube_faces] for panoramas.
+    /// The format is picked like in [load_texture](Self::load_texture), the texture and its cube view are created during the next [PreDraw]
+    pub fn load_cubemap(
+        &mut self,
+        faces: [Image; 6],
+    ) -> Result<AssetId<CubeTexture>, CubemapError> {
+        // checking to not allocate asset in case of error
+        cubemap::validate_faces(&faces)?;
+        let texture_id = self.texture_assets.add_empty();
+        self.texture_queue.init(
+            texture_id,
+            TextureInitInfo::new(
+                (faces[0].width, faces[0].height),
+                TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                faces[0].format.texture_format(ColorSpace::Srgb),
+            )
+            .with_layers(6)
+            .with_label("Cube Texture"),
+        );
+        for (layer, face) in faces.into_iter().enumerate() {
+            self.texture_queue.write(
+                face,
+                texture_id,
+                Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: layer as u32,
+                },
+            );
+        }
+        let cube_id = self.cube_assets.add_empty();
+        self.texture_queue.cube_views.push((texture_id, cube_id));
+        Ok(cube_id)
+    }
 }
 
-fn validate_layers(images: &Vec<MipMapImage>) -> Option<LayeredTextureError> {
+fn validate_layers(images: &[MipMapImage]) -> Result<(), LayeredTextureError> {
     if images.is_empty() {
-        return Some(LayeredTextureError::NoLayers);
+        return Err(LayeredTextureError::NoLayers);
+    }
+    for (layer_index, image) in images.iter().enumerate() {
+        image
+            .validate()
+            .map_err(|error| LayeredTextureError::InvalidMipChain { layer_index, error })?;
     }
-    let first_size = images[0].sizes();
-    if images[1..].iter().all(|img| img.sizes() == first_size) {
-        None
-    } else {
-        Some(LayeredTextureError::InvalidLayer)
+    let expected_sizes = chain_sizes(&images[0]);
+    let expected_format = images[0].levels()[0].format;
+    for (layer_index, image) in images.iter().enumerate().skip(1) {
+        let actual_sizes = chain_sizes(image);
+        if actual_sizes != expected_sizes {
+            return Err(LayeredTextureError::InvalidLayer {
+                layer_index,
+                expected_sizes,
+                actual_sizes,
+            });
+        }
+        let actual = image.levels()[0].format;
+        if actual != expected_format {
+            return Err(LayeredTextureError::LayerFormat {
+                layer_index,
+                expected: expected_format,
+                actual,
+            });
+        }
     }
+    Ok(())
+}
+
+/// The size of every level of a valid chain, including the levels [FromLevel](MipMapImage::FromLevel) generates
+fn chain_sizes(image: &MipMapImage) -> Vec<(u32, u32)> {
+    let base = &image.levels()[0];
+    (0..image.level_count() as u32)
+        .map(|level| ((base.width >> level).max(1), (base.height >> level).max(1)))
+        .collect()
 }
 
 enum TextureOperation {
     WriteTexture(TextureWriteInfo),
-    InitTexture(TextureInitInfo),
+    WriteCompressed(CompressedWriteInfo),
+    InitTexture(AssetId<Texture>, TextureInitInfo),
+}
+
+impl TextureOperation {
+    fn asset_id(&self) -> AssetId<Texture> {
+        match self {
+            TextureOperation::WriteTexture(info) => info.asset_id,
+            TextureOperation::WriteCompressed(info) => info.asset_id,
+            TextureOperation::InitTexture(asset_id, _) => *asset_id,
+        }
+    }
+}
+
+/// The operations of one frame, see [partition_operations]
+struct PartitionedOperations {
+    inits: Vec<(AssetId<Texture>, TextureInitInfo)>,
+    /// Only writes, in the order they were queued
+    writes: Vec<TextureOperation>,
+    /// Targets of writes to textures that do not exist and are not initialized in the same frame
+    missing: Vec<AssetId<Texture>>,
+}
+
+/// Splits the operations into inits and writes, inits are done first so writes queued before the init in the same frame can not miss the texture.  
+/// exists tells if a texture exists before the inits are done
+fn partition_operations(
+    operations: impl IntoIterator<Item = TextureOperation>,
+    exists: impl Fn(AssetId<Texture>) -> bool,
+) -> PartitionedOperations {
+    let (inits, writes): (Vec<_>, Vec<_>) = operations
+        .into_iter()
+        .partition(|op| matches!(op, TextureOperation::InitTexture(..)));
+    let inits: Vec<_> = inits
+        .into_iter()
+        .map(|op| match op {
+            TextureOperation::InitTexture(asset_id, info) => (asset_id, info),
+            _ => unreachable!("partitioned above"),
+        })
+        .collect();
+    let initialized: HashSet<_> = inits.iter().map(|(asset_id, _)| *asset_id).collect();
+    let (writes, missing): (Vec<_>, Vec<_>) = writes
+        .into_iter()
+        .partition(|op| initialized.contains(&op.asset_id()) || exists(op.asset_id()));
+    PartitionedOperations {
+        inits,
+        writes,
+        missing: missing.iter().map(TextureOperation::asset_id).collect(),
+    }
 }
 
 struct TextureWriteInfo {
     image: MipMapImage,
     asset_id: AssetId<Texture>,
     origin: Origin3d,
+    /// The level the first level of the image is written to
+    mip_level: u32,
+    mip_generation: MipGeneration,
 }
 
-struct TextureInitInfo {
+struct CompressedWriteInfo {
+    image: CompressedImage,
     asset_id: AssetId<Texture>,
-    size: (u32, u32),
-    usage: TextureUsages,
-    mip_count: u32,
-    layers: Option<u32>,
+    origin: Origin3d,
+}
+
+/// The texture [TextureQueue::init] makes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextureInitInfo {
+    pub size: (u32, u32),
+    pub usage: TextureUsages,
+    pub mip_count: u32,
+    /// The number of layers of a 2d array texture, None for a plain 2d texture
+    pub layers: Option<u32>,
+    pub format: TextureFormat,
+    /// Used for debugging tools, [TextureMemoryStats] and [TextureWriteFailed]
+    pub label: Option<String>,
+}
+
+impl TextureInitInfo {
+    /// A 2d texture with one mip level and no label
+    pub fn new(size: (u32, u32), usage: TextureUsages, format: TextureFormat) -> Self {
+        Self {
+            size,
+            usage,
+            mip_count: 1,
+            layers: None,
+            format,
+            label: None,
+        }
+    }
+
+    pub fn with_mip_count(mut self, mip_count: u32) -> Self {
+        self.mip_count = mip_count;
+        self
+    }
+
+    /// Makes a 2d array texture
+    pub fn with_layers(mut self, layers: u32) -> Self {
+        self.layers = Some(layers);
+        self
+    }
+
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.label = Some(label.to_owned());
+        self
+    }
 }
 
 fn load_textures(
     mut texture_queue: ResMut<TextureQueue>,
     mut texture_assets: ResMut<Assets<Texture>>,
+    mut mipmap_generator: ResMut<MipmapGenerator>,
+    mut memory_stats: ResMut<TextureMemoryStats>,
+    mut write_failed: EventWriter<TextureWriteFailed>,
     device: Res<DeviceRes>,
     queue: Res<QueueRes>,
 ) {
-    for op in texture_queue.queue.drain(..) {
-        match op {
-            TextureOperation::InitTexture(info) => {
-                init_texture(info, &mut texture_assets, &device.0)
+    let operations = partition_operations(texture_queue.queue.drain(..), |asset_id| {
+        texture_assets.get(asset_id).is_some()
+    });
+    for asset_id in operations.missing {
+        write_failed.send(TextureWriteFailed {
+            asset_id,
+            label: None,
+            reason: TextureWriteError::MissingTexture,
+        });
+    }
+    for (asset_id, info) in operations.inits {
+        let label = info.label.clone();
+        if let Err(reason) = init_texture(
+            asset_id,
+            info,
+            &mut texture_assets,
+            &mut memory_stats,
+            &device.0,
+        ) {
+            write_failed.send(TextureWriteFailed {
+                asset_id,
+                label,
+                reason,
+            });
+        }
+    }
+    // textures to generate mip levels for on the GPU, with the level count
+    let mut gpu_mips: Vec<(AssetId<Texture>, u32)> = Vec::new();
+    for op in operations.writes {
+        let asset_id = op.asset_id();
+        let Some(texture) = texture_assets.get(asset_id) else {
+            // the init queued in the same frame failed
+            write_failed.send(TextureWriteFailed {
+                asset_id,
+                label: None,
+                reason: TextureWriteError::MissingTexture,
+            });
+            continue;
+        };
+        let result = match op {
+            TextureOperation::WriteTexture(mut info) => {
+                info.image = color::resolve_color_space(
+                    info.image,
+                    texture.format(),
+                    texture_queue.color_space_mismatch,
+                );
+                write_texture(info, texture, &queue.0, &mut gpu_mips)
             }
-            TextureOperation::WriteTexture(info) => write_texture(info, &texture_assets, &queue.0),
+            TextureOperation::WriteCompressed(info) => info
+                .image
+                .check_writable(info.origin, texture.format())
+                .map(|_| info.image.write_to_texture(&queue.0, info.origin, texture))
+                .map_err(TextureWriteError::InvalidImage),
+            TextureOperation::InitTexture(..) => unreachable!("inits were handled above"),
+        };
+        if let Err(reason) = result {
+            write_failed.send(TextureWriteFailed {
+                asset_id,
+                label: memory_stats.label(texture).map(str::to_owned),
+                reason,
+            });
         }
     }
+    if gpu_mips.is_empty() {
+        return;
+    }
+    let mut encoder = device.0.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("Mipmap CommandEncoder"),
+    });
+    for (asset_id, mip_count) in gpu_mips {
+        let texture = texture_assets.get(asset_id).unwrap();
+        mipmap_generator.generate_mipmaps(
+            &mut encoder,
+            &device.0,
+            texture,
+            mip_count,
+            texture.format(),
+        );
+    }
+    // the writes above are done before the submitted commands
+    queue.0.submit([encoder.finish()]);
 }
 
-fn write_texture(info: TextureWriteInfo, texture_assets: &Assets<Texture>, queue: &Queue) {
-    info.image.write_to_texture(
+/// Writes if the image matches and fits in the texture, and adds the texture to gpu_mips if needed
+fn write_texture(
+    info: TextureWriteInfo,
+    texture: &Texture,
+    queue: &Queue,
+    gpu_mips: &mut Vec<(AssetId<Texture>, u32)>,
+) -> Result<(), TextureWriteError> {
+    info.image
+        .check_write(info.origin, texture, info.mip_level, info.mip_generation)?;
+    if info.mip_generation == MipGeneration::Gpu
+        && !gpu_mips.iter().any(|(id, _)| *id == info.asset_id)
+    {
+        gpu_mips.push((info.asset_id, info.image.level_count() as u32));
+    }
+    info.image.write_levels(
         queue,
         info.origin,
-        texture_assets.get(info.asset_id).unwrap(),
+        texture,
+        info.mip_generation,
+        info.mip_level,
     );
+    Ok(())
 }
 
-fn init_texture(info: TextureInitInfo, texture_assets: &mut Assets<Texture>, device: &Device) {
+fn init_texture(
+    asset_id: AssetId<Texture>,
+    info: TextureInitInfo,
+    texture_assets: &mut Assets<Texture>,
+    memory_stats: &mut TextureMemoryStats,
+    device: &Device,
+) -> Result<(), TextureWriteError> {
+    CompressedImage::check_features(info.format, device.features())
+        .map_err(|e| TextureWriteError::UnsupportedFormat(e.to_string()))?;
     let texture = device.create_texture(&TextureDescriptor {
-        label: None,
+        label: info.label.as_deref(),
         size: Extent3d {
             width: info.size.0,
             height: info.size.1,
             depth_or_array_layers: info.layers.unwrap_or(1),
         },
-        mip_level_count: info.mip_count as u32,
+        mip_level_count: info.mip_count,
         sample_count: 1,
         dimension: TextureDimension::D2,
-        format: TextureFormat::Rgba8UnormSrgb,
+        format: info.format,
         usage: info.usage,
         view_formats: &[],
     });
-    texture_assets.replace(info.asset_id, texture);
+    memory_stats.add(&texture, info.label.as_deref());
+    if let Some(old) = texture_assets.replace(asset_id, texture) {
+        destroy_texture(old, memory_stats);
+    }
+    Ok(())
+}
+
+fn destroy_texture(texture: Texture, memory_stats: &mut TextureMemoryStats) {
+    memory_stats.remove_destroyed(&texture);
+    texture.destroy();
+}
+
+/// Removes a texture asset and frees its memory right away instead of when wgpu drops it.  
+/// Returns false if the texture did not exist.
+/// Bind groups using the texture can not be used after this
+pub fn remove_texture(world: &mut World, asset_id: AssetId<Texture>) -> bool {
+    match world.resource_mut::<Assets<Texture>>().remove(asset_id) {
+        Some(texture) => {
+            destroy_texture(texture, &mut world.resource_mut::<TextureMemoryStats>());
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::system::RunSystemOnce;
+    use modula_core::request_headless_device;
+
+    use super::*;
+    use crate::test_utils::image;
+
+    fn two_levels(size: u32, format: PixelFormat) -> MipMapImage {
+        MipMapImage::with_images(vec![
+            image(size, size, format),
+            image(size / 2, size / 2, format),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn layers_with_equal_chains_are_valid() {
+        let layers = vec![two_levels(4, PixelFormat::Rgba8); 3];
+        assert_eq!(validate_layers(&layers), Ok(()));
+        // generated levels count as levels of the chain
+        let mut layers = layers;
+        layers[1] = MipMapImage::from_level(image(4, 4, PixelFormat::Rgba8), 2);
+        assert_eq!(validate_layers(&layers), Ok(()));
+    }
+
+    #[test]
+    fn mismatched_layer_is_named() {
+        let mut layers = vec![two_levels(4, PixelFormat::Rgba8); 3];
+        layers[2] = two_levels(8, PixelFormat::Rgba8);
+        assert_eq!(
+            validate_layers(&layers),
+            Err(LayeredTextureError::InvalidLayer {
+                layer_index: 2,
+                expected_sizes: vec![(4, 4), (2, 2)],
+                actual_sizes: vec![(8, 8), (4, 4)],
+            })
+        );
+
+        let mut layers = vec![two_levels(4, PixelFormat::Rgba8); 3];
+        layers[1] = two_levels(4, PixelFormat::R8);
+        assert_eq!(
+            validate_layers(&layers),
+            Err(LayeredTextureError::LayerFormat {
+                layer_index: 1,
+                expected: PixelFormat::Rgba8,
+                actual: PixelFormat::R8,
+            })
+        );
+
+        let mut layers = vec![two_levels(4, PixelFormat::Rgba8); 3];
+        layers[1] = MipMapImage::WithImages(vec![
+            image(4, 4, PixelFormat::Rgba8),
+            image(1, 1, PixelFormat::Rgba8),
+        ]);
+        assert!(matches!(
+            validate_layers(&layers),
+            Err(LayeredTextureError::InvalidMipChain {
+                layer_index: 1,
+                error: MipChainError::LevelSize { level: 1, .. },
+            })
+        ));
+        assert_eq!(validate_layers(&[]), Err(LayeredTextureError::NoLayers));
+    }
+
+    #[test]
+    fn raw_rgba8_checks_length() {
+        assert_eq!(
+            Image::from_raw_rgba8(3, 2, vec![0; 23]).err(),
+            Some(ImageSizeMismatch {
+                expected: 24,
+                actual: 23,
+            })
+        );
+        assert!(Image::from_raw_rgba8(3, 2, vec![0; 25]).is_err());
+        assert!(Image::from_raw_rgba8(0, 2, vec![0; 4]).is_err());
+        let image = Image::from_raw_rgba8(3, 2, vec![0; 24]).unwrap();
+        assert_eq!((image.width, image.height), (3, 2));
+        assert_eq!(image.alpha_mode, AlphaMode::Straight);
+    }
+
+    #[test]
+    fn premultiply_alpha() {
+        let data = vec![255, 128, 0, 255, 255, 128, 0, 128, 200, 100, 50, 0];
+        let mut image = Image::from_raw_rgba8(3, 1, data).unwrap();
+        image.premultiply_alpha();
+        assert_eq!(image.alpha_mode, AlphaMode::Premultiplied);
+        // 255 * 128 / 255 = 128 and 128 * 128 / 255 = 64.25, rounded to nearest
+        assert_eq!(image.data, [255, 128, 0, 255, 128, 64, 0, 128, 0, 0, 0, 0]);
+
+        let mut float = Image {
+            data: [0.5f32, 1.0, 0.25, 0.5]
+                .into_iter()
+                .flat_map(f32::to_ne_bytes)
+                .collect(),
+            width: 1,
+            height: 1,
+            format: PixelFormat::Rgba32Float,
+            color_space: None,
+            alpha_mode: AlphaMode::Straight,
+        };
+        float.premultiply_alpha();
+        let values: Vec<f32> = (0..4).map(|i| float.sample(i)).collect();
+        assert_eq!(values, [0.25, 0.5, 0.125, 0.5]);
+    }
+
+    #[test]
+    fn premultiplying_twice_does_nothing() {
+        let data = vec![255, 128, 0, 128, 90, 60, 30, 200];
+        let mut image = Image::from_raw_rgba8(2, 1, data).unwrap();
+        image.premultiply_alpha();
+        let once = image.data.clone();
+        image.premultiply_alpha();
+        assert_eq!(image.data, once);
+        assert_eq!(image.alpha_mode, AlphaMode::Premultiplied);
+    }
+
+    fn init(asset_id: AssetId<Texture>) -> TextureOperation {
+        TextureOperation::InitTexture(
+            asset_id,
+            TextureInitInfo::new(
+                (4, 4),
+                TextureUsages::TEXTURE_BINDING,
+                TextureFormat::Rgba8Unorm,
+            ),
+        )
+    }
+
+    fn write(asset_id: AssetId<Texture>) -> TextureOperation {
+        TextureOperation::WriteTexture(TextureWriteInfo {
+            image: MipMapImage::WithImages(vec![image(4, 4, PixelFormat::Rgba8)]),
+            asset_id,
+            origin: Origin3d::ZERO,
+            mip_level: 0,
+            mip_generation: MipGeneration::default(),
+        })
+    }
+
+    fn ids(operations: &[TextureOperation]) -> Vec<AssetId<Texture>> {
+        operations.iter().map(TextureOperation::asset_id).collect()
+    }
+
+    #[test]
+    fn write_queued_before_init_is_done_after_it() {
+        let mut assets = Assets::<Texture>::new();
+        let (a, b) = (assets.add_empty(), assets.add_empty());
+        let ops = partition_operations([write(a), write(b), init(b), init(a)], |_| false);
+        let inits: Vec<_> = ops.inits.iter().map(|(asset_id, _)| *asset_id).collect();
+        assert_eq!(inits, [b, a]);
+        assert_eq!(ids(&ops.writes), [a, b]);
+        assert!(ops.missing.is_empty());
+    }
+
+    #[test]
+    fn write_without_init_is_missing() {
+        let mut assets = Assets::<Texture>::new();
+        let (existing, never_initialized, initialized) =
+            (assets.add_empty(), assets.add_empty(), assets.add_empty());
+        let ops = partition_operations(
+            [
+                write(never_initialized),
+                write(existing),
+                init(initialized),
+                write(never_initialized),
+            ],
+            |asset_id| asset_id == existing,
+        );
+        assert_eq!(ids(&ops.writes), [existing]);
+        assert_eq!(ops.missing, [never_initialized, never_initialized]);
+        // the init is still done even without writes
+        assert_eq!(ops.inits.len(), 1);
+    }
+
+    /// A world with what [load_textures] needs
+    fn texture_world() -> World {
+        let (device, queue) =
+            request_headless_device().expect("no adapter found, not even the fallback adapter");
+        let mut world = World::new();
+        world.insert_resource(MipmapGenerator::new(&device));
+        world.insert_resource(DeviceRes(device));
+        world.insert_resource(QueueRes(queue));
+        world.insert_resource(TextureQueue::new());
+        world.insert_resource(Assets::<Texture>::new());
+        world.insert_resource(TextureMemoryStats::default());
+        world.insert_resource(Events::<TextureWriteFailed>::default());
+        world
+    }
+
+    /// Uploads the image to a texture of its format and reads it back
+    fn round_trip(world: &mut World, image: Image) -> Vec<u8> {
+        let asset_id = world.resource_mut::<Assets<Texture>>().add_empty();
+        let mut texture_queue = world.resource_mut::<TextureQueue>();
+        texture_queue.init(
+            asset_id,
+            TextureInitInfo::new(
+                (image.width, image.height),
+                TextureUsages::COPY_DST | TextureUsages::COPY_SRC,
+                image.format.texture_format(ColorSpace::Linear),
+            ),
+        );
+        texture_queue.write(image, asset_id, Origin3d::ZERO);
+        world.run_system_once(load_textures);
+        assert!(world.resource::<Events<TextureWriteFailed>>().is_empty());
+        let texture = world.resource::<Assets<Texture>>().get(asset_id).unwrap();
+        read_texture(
+            &world.resource::<DeviceRes>().0,
+            &world.resource::<QueueRes>().0,
+            texture,
+            0,
+        )
+    }
+
+    #[test]
+    fn single_channel_round_trip() {
+        let mut world = texture_world();
+        // rows of 5 bytes are padded to 256 when copied
+        let mut mask = image(5, 3, PixelFormat::R8);
+        mask.data = (0..15).map(|i| i * 17).collect();
+        let data = mask.data.clone();
+        assert_eq!(round_trip(&mut world, mask), data);
+
+        let mut rg = image(3, 2, PixelFormat::Rg8);
+        rg.data = (0..12).map(|i| 255 - i * 20).collect();
+        let data = rg.data.clone();
+        assert_eq!(round_trip(&mut world, rg), data);
+    }
+
+    /// Inits an RGBA8 texture of the size on the asset and runs [load_textures]
+    fn init_rgba8(world: &mut World, asset_id: AssetId<Texture>, size: (u32, u32)) {
+        world.resource_mut::<TextureQueue>().init(
+            asset_id,
+            TextureInitInfo::new(
+                size,
+                TextureUsages::TEXTURE_BINDING,
+                TextureFormat::Rgba8Unorm,
+            ),
+        );
+        world.run_system_once(load_textures);
+    }
+
+    #[test]
+    fn replaced_and_removed_textures_are_destroyed() {
+        let mut world = texture_world();
+        let asset_id = world.resource_mut::<Assets<Texture>>().add_empty();
+        init_rgba8(&mut world, asset_id, (4, 4));
+        let stats = world.resource::<TextureMemoryStats>();
+        assert_eq!((stats.texture_count(), stats.total_bytes()), (1, 64));
+
+        assert_eq!(stats.destroyed_count(), 0);
+
+        init_rgba8(&mut world, asset_id, (8, 8));
+        let stats = world.resource::<TextureMemoryStats>();
+        assert_eq!((stats.texture_count(), stats.total_bytes()), (1, 256));
+        assert_eq!(stats.destroyed_count(), 1);
+
+        assert!(remove_texture(&mut world, asset_id));
+        assert!(world.resource::<Assets<Texture>>().get(asset_id).is_none());
+        let stats = world.resource::<TextureMemoryStats>();
+        assert_eq!((stats.texture_count(), stats.total_bytes()), (0, 0));
+        assert_eq!(stats.destroyed_count(), 2);
+        assert!(!remove_texture(&mut world, asset_id));
+        assert_eq!(world.resource::<TextureMemoryStats>().destroyed_count(), 2);
+    }
+
+    #[test]
+    fn unsupported_format_fails_the_init() {
+        let mut world = texture_world();
+        let features = world.resource::<DeviceRes>().0.features();
+        let formats = [
+            TextureFormat::Bc1RgbaUnorm,
+            TextureFormat::Etc2Rgb8Unorm,
+            TextureFormat::Astc {
+                block: wgpu::AstcBlock::B4x4,
+                channel: wgpu::AstcChannel::Unorm,
+            },
+        ];
+        let Some(format) = formats
+            .into_iter()
+            .find(|format| !features.contains(format.required_features()))
+        else {
+            // the device supports every compressed format
+            return;
+        };
+        let asset_id = world.resource_mut::<Assets<Texture>>().add_empty();
+        let mut texture_queue = world.resource_mut::<TextureQueue>();
+        texture_queue.init(
+            asset_id,
+            TextureInitInfo::new(
+                (4, 4),
+                TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                format,
+            )
+            .with_label("compressed"),
+        );
+        texture_queue.write(image(4, 4, PixelFormat::Rgba8), asset_id, Origin3d::ZERO);
+        world.run_system_once(load_textures);
+
+        assert!(world.resource::<Assets<Texture>>().get(asset_id).is_none());
+        assert_eq!(world.resource::<TextureMemoryStats>().texture_count(), 0);
+        let failed: Vec<_> = world
+            .resource_mut::<Events<TextureWriteFailed>>()
+            .drain()
+            .collect();
+        assert_eq!(failed.len(), 2);
+        assert!(matches!(
+            failed[0].reason,
+            TextureWriteError::UnsupportedFormat(_)
+        ));
+        assert_eq!(failed[0].label.as_deref(), Some("compressed"));
+        assert_eq!(failed[1].reason, TextureWriteError::MissingTexture);
+    }
 }
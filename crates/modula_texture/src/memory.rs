@@ -0,0 +1,155 @@
+use std::cmp::Reverse;
+
+use bevy_ecs::prelude::*;
+use modula_utils::HashMap;
+use wgpu::{Id, Texture, TextureAspect, TextureFormat};
+
+/// Size in bytes of a 2d texture, from the size of its format and all mip levels and layers.
+/// This is the size of the data, drivers may use more memory
+pub fn texture_byte_size(
+    format: TextureFormat,
+    size: (u32, u32),
+    layers: u32,
+    mip_count: u32,
+) -> u64 {
+    // depth stencil formats have no single block size, so their aspects are added
+    let block_bytes = format.block_copy_size(None).unwrap_or_else(|| {
+        [TextureAspect::DepthOnly, TextureAspect::StencilOnly]
+            .into_iter()
+            .filter_map(|aspect| format.block_copy_size(Some(aspect)))
+            .sum()
+    }) as u64;
+    let (block_width, block_height) = format.block_dimensions();
+    (0..mip_count)
+        .map(|level| {
+            let width = (size.0 >> level).max(1).div_ceil(block_width) as u64;
+            let height = (size.1 >> level).max(1).div_ceil(block_height) as u64;
+            width * height * block_bytes
+        })
+        .sum::<u64>()
+        * layers as u64
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextureMemoryEntry {
+    pub bytes: u64,
+    pub label: Option<String>,
+}
+
+/// Bookkeeping of the memory used by textures created by [TextureQueue](crate::TextureQueue) and [atlases](crate::atlas).
+/// Textures are removed when replaced, or removed with [remove_texture](crate::remove_texture).
+/// Textures dropped in other ways, such as removing the asset directly, are still counted
+#[derive(Resource, Default)]
+pub struct TextureMemoryStats {
+    textures: HashMap<Id<Texture>, TextureMemoryEntry>,
+    total_bytes: u64,
+    destroyed: usize,
+    /// Used to only send [TextureBudgetExceeded] when the budget is crossed
+    over_budget: bool,
+}
+
+impl TextureMemoryStats {
+    #[inline]
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    #[inline]
+    pub fn texture_count(&self) -> usize {
+        self.textures.len()
+    }
+
+    /// Number of textures destroyed when they were replaced by [TextureQueue::init](crate::TextureQueue::init) or removed with [remove_texture](crate::remove_texture),
+    /// useful to find leaks when replacing textures
+    #[inline]
+    pub fn destroyed_count(&self) -> usize {
+        self.destroyed
+    }
+
+    /// The label the texture was created with, None if it has no label or is not counted
+    pub fn label(&self, texture: &Texture) -> Option<&str> {
+        self.textures.get(&texture.global_id())?.label.as_deref()
+    }
+
+    /// The n largest textures, largest first
+    pub fn largest(&self, n: usize) -> Vec<&TextureMemoryEntry> {
+        let mut entries: Vec<_> = self.textures.values().collect();
+        entries.sort_by_key(|entry| Reverse(entry.bytes));
+        entries.truncate(n);
+        entries
+    }
+
+    /// The total size of textures with each label, largest first
+    pub fn bytes_by_label(&self) -> Vec<(Option<&str>, u64)> {
+        let mut labels: HashMap<Option<&str>, u64> = HashMap::new();
+        for entry in self.textures.values() {
+            *labels.entry(entry.label.as_deref()).or_default() += entry.bytes;
+        }
+        let mut labels: Vec<_> = labels.into_iter().collect();
+        labels.sort_by_key(|(_, bytes)| Reverse(*bytes));
+        labels
+    }
+
+    pub(crate) fn add(&mut self, texture: &Texture, label: Option<&str>) {
+        let bytes = texture_byte_size(
+            texture.format(),
+            (texture.width(), texture.height()),
+            texture.depth_or_array_layers(),
+            texture.mip_level_count(),
+        );
+        let entry = TextureMemoryEntry {
+            bytes,
+            label: label.map(str::to_owned),
+        };
+        if let Some(old) = self.textures.insert(texture.global_id(), entry) {
+            self.total_bytes -= old.bytes;
+        }
+        self.total_bytes += bytes;
+    }
+
+    pub(crate) fn remove(&mut self, texture: &Texture) {
+        if let Some(entry) = self.textures.remove(&texture.global_id()) {
+            self.total_bytes -= entry.bytes;
+        }
+    }
+
+    /// Removes a texture that is about to be destroyed, counting it as destroyed
+    pub(crate) fn remove_destroyed(&mut self, texture: &Texture) {
+        self.remove(texture);
+        self.destroyed += 1;
+    }
+}
+
+/// Optional resource, when the memory in [TextureMemoryStats] goes above the soft limit [TextureBudgetExceeded] is sent
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextureBudget {
+    pub soft_limit_bytes: u64,
+}
+
+/// Sent during [TextureLoadSet](crate::TextureLoadSet) when texture memory goes above [TextureBudget],
+/// it is sent again after going below and then above the budget
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureBudgetExceeded {
+    pub total_bytes: u64,
+    pub soft_limit_bytes: u64,
+}
+
+pub(crate) fn check_texture_budget(
+    mut stats: ResMut<TextureMemoryStats>,
+    budget: Option<Res<TextureBudget>>,
+    mut exceeded: EventWriter<TextureBudgetExceeded>,
+) {
+    let over = budget
+        .as_ref()
+        .is_some_and(|b| stats.total_bytes > b.soft_limit_bytes);
+    if over && !stats.over_budget {
+        exceeded.send(TextureBudgetExceeded {
+            total_bytes: stats.total_bytes,
+            soft_limit_bytes: budget.unwrap().soft_limit_bytes,
+        });
+    }
+    // only written on change, to not trigger change detection every frame
+    if stats.over_budget != over {
+        stats.over_budget = over;
+    }
+}
@@ -0,0 +1,188 @@
+use half::f16;
+
+use crate::{Image, MipMapImage, PixelFormat};
+
+mod gpu;
+
+pub use gpu::*;
+
+/// Filter used to generate mip levels on the CPU
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MipFilter {
+    /// Averages 2x2 blocks of the previous level
+    #[default]
+    Box,
+    /// Weights 4x4 blocks of the previous level with a tent filter, smoother than [Box](MipFilter::Box) but slower
+    Triangle,
+}
+
+/// How the missing levels of [MipMapImage::FromLevel] are generated when written to a texture
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MipGeneration {
+    /// Generated with [MipMapImage::generate_levels] before writing
+    Cpu(MipFilter),
+    /// Only the first level is written, and the rest are rendered using the [MipmapGenerator].  
+    /// Faster for large textures, but the texture needs [RENDER_ATTACHMENT](wgpu::TextureUsages::RENDER_ATTACHMENT) usage and a renderable format
+    Gpu,
+}
+
+impl Default for MipGeneration {
+    fn default() -> Self {
+        Self::Cpu(MipFilter::default())
+    }
+}
+
+impl Image {
+    /// Makes the next mip level of the image, half the size rounded down and at least 1.
+    /// Channels are filtered as stored, so sRGB data is not converted to linear first
+    pub fn downsample(&self, filter: MipFilter) -> Image {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut data =
+            Vec::with_capacity(width as usize * height as usize * self.format.bytes_per_pixel());
+        for y in 0..height {
+            for x in 0..width {
+                for channel in 0..self.format.channels() {
+                    let value = match filter {
+                        MipFilter::Box => self.filter(x, y, channel, &[1, 1], 0),
+                        MipFilter::Triangle => self.filter(x, y, channel, &[1, 3, 3, 1], 1),
+                    };
+                    self.push_sample(&mut data, value);
+                }
+            }
+        }
+        Image {
+            data,
+            width,
+            height,
+            format: self.format,
+            color_space: self.color_space,
+            alpha_mode: self.alpha_mode,
+        }
+    }
+
+    /// Filters the source pixels under the pixel at (x, y) of the next level with separable weights, starting offset pixels before it.
+    /// Pixels outside the image are clamped to the edge
+    fn filter(&self, x: u32, y: u32, channel: usize, weights: &[u32], offset: i64) -> f32 {
+        let clamp = |v: i64, max: u32| v.clamp(0, max as i64 - 1) as usize;
+        let mut sum = 0.0;
+        for (wy, sy) in weights.iter().zip(2 * y as i64 - offset..) {
+            for (wx, sx) in weights.iter().zip(2 * x as i64 - offset..) {
+                let idx = clamp(sy, self.height) * self.width as usize + clamp(sx, self.width);
+                sum += (wx * wy) as f32 * self.sample(idx * self.format.channels() + channel);
+            }
+        }
+        sum / weights.iter().sum::<u32>().pow(2) as f32
+    }
+
+    /// The value of the channel at sample_idx, counted in channels from the start of data.  
+    /// Integer channels are returned as stored, not normalized
+    pub(crate) fn sample(&self, sample_idx: usize) -> f32 {
+        let size = self.format.bytes_per_channel();
+        let bytes = &self.data[sample_idx * size..(sample_idx + 1) * size];
+        match self.format {
+            PixelFormat::Rgba16Float => f16::from_ne_bytes([bytes[0], bytes[1]]).to_f32(),
+            PixelFormat::Rgba32Float => f32::from_ne_bytes(bytes.try_into().unwrap()),
+            _ if size == 2 => u16::from_ne_bytes([bytes[0], bytes[1]]) as f32,
+            _ => bytes[0] as f32,
+        }
+    }
+
+    /// Pushes a value returned by [filter](Self::filter) to data
+    pub(crate) fn push_sample(&self, data: &mut Vec<u8>, value: f32) {
+        match self.format {
+            PixelFormat::Rgba16Float => data.extend(f16::from_f32(value).to_ne_bytes()),
+            PixelFormat::Rgba32Float => data.extend(value.to_ne_bytes()),
+            // rounded to nearest
+            _ if self.format.bytes_per_channel() == 2 => {
+                data.extend((value.round() as u16).to_ne_bytes())
+            }
+            _ => data.push(value.round() as u8),
+        }
+    }
+}
+
+impl MipMapImage {
+    /// Generates the missing levels of [FromLevel](MipMapImage::FromLevel), returning [WithImages](MipMapImage::WithImages).
+    /// Images that already have all levels are returned as is
+    pub fn generate_levels(self, filter: MipFilter) -> MipMapImage {
+        match self {
+            MipMapImage::FromLevel(base, count) => {
+                let mut levels = Vec::with_capacity(count);
+                levels.push(base);
+                levels.extend(generate_levels(&levels[0], count, filter));
+                MipMapImage::WithImages(levels)
+            }
+            with_images => with_images,
+        }
+    }
+}
+
+/// The levels after base, for a mip chain with level_count levels
+pub(crate) fn generate_levels(base: &Image, level_count: usize, filter: MipFilter) -> Vec<Image> {
+    let mut levels: Vec<Image> = Vec::with_capacity(level_count.saturating_sub(1));
+    for _ in 1..level_count {
+        let next = levels.last().unwrap_or(base).downsample(filter);
+        levels.push(next);
+    }
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, pixel: [u8; 4]) -> Image {
+        let data = pixel.repeat(width as usize * height as usize);
+        Image::from_raw_rgba8(width, height, data).unwrap()
+    }
+
+    fn level_sizes(base: Image, level_count: usize, filter: MipFilter) -> Vec<(u32, u32)> {
+        let image = MipMapImage::from_level(base, level_count).generate_levels(filter);
+        for level in image.levels() {
+            let pixels = level.width as usize * level.height as usize;
+            assert_eq!(level.data.len(), pixels * level.format.bytes_per_pixel());
+        }
+        image.sizes()
+    }
+
+    #[test]
+    fn levels_halve_the_size() {
+        for filter in [MipFilter::Box, MipFilter::Triangle] {
+            assert_eq!(
+                level_sizes(solid(16, 8, [0; 4]), 5, filter),
+                [(16, 8), (8, 4), (4, 2), (2, 1), (1, 1)]
+            );
+        }
+    }
+
+    #[test]
+    fn odd_sizes_round_down() {
+        for filter in [MipFilter::Box, MipFilter::Triangle] {
+            assert_eq!(
+                level_sizes(solid(7, 5, [0; 4]), 3, filter),
+                [(7, 5), (3, 2), (1, 1)]
+            );
+            assert_eq!(
+                level_sizes(solid(12, 10, [0; 4]), 4, filter),
+                [(12, 10), (6, 5), (3, 2), (1, 1)]
+            );
+            // the short side stays 1 while the long side is halved
+            assert_eq!(
+                level_sizes(solid(9, 1, [0; 4]), 4, filter),
+                [(9, 1), (4, 1), (2, 1), (1, 1)]
+            );
+        }
+    }
+
+    #[test]
+    fn solid_color_stays_solid() {
+        let pixel = [10, 200, 31, 128];
+        for filter in [MipFilter::Box, MipFilter::Triangle] {
+            let image = MipMapImage::from_level(solid(13, 7, pixel), 4).generate_levels(filter);
+            for level in image.levels() {
+                assert!(level.data.chunks(4).all(|texel| texel == pixel));
+            }
+        }
+    }
+}
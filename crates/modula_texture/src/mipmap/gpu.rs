@@ -0,0 +1,208 @@
+use bevy_ecs::system::Resource;
+use modula_render::shader::ShaderBundler;
+use modula_utils::HashMap;
+use wgpu::{
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindingResource, ColorTargetState, ColorWrites, CommandEncoder, Device, FragmentState, LoadOp,
+    MultisampleState, Operations, PipelineCompilationOptions, PipelineLayout,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPassColorAttachment, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerDescriptor, ShaderModule,
+    ShaderModuleDescriptor, ShaderSource, StoreOp, Texture, TextureFormat, TextureUsages,
+    TextureViewDescriptor, TextureViewDimension, VertexState,
+};
+
+use crate::SamplerConfig;
+
+/// Generates mip levels on the GPU, by rendering every level from the previous one.
+/// A pipeline is created for every format the first time it is used
+#[derive(Resource)]
+pub struct MipmapGenerator {
+    shader: ShaderModule,
+    sampler: Sampler,
+    bind_group_layout: BindGroupLayout,
+    pipeline_layout: PipelineLayout,
+    pipelines: HashMap<TextureFormat, RenderPipeline>,
+}
+
+impl MipmapGenerator {
+    pub fn new(device: &Device) -> Self {
+        let source = include_str!("blit.wgsl");
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("MipmapGenerator Shader"),
+            source: ShaderSource::Wgsl(source.into()),
+        });
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("MipmapGenerator Sampler"),
+            ..SamplerConfig::linear_clamp().into()
+        });
+        let interface = ShaderBundler::reflect(source).expect("the blit shader is valid");
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("MipmapGenerator BindGroupLayout"),
+            entries: &interface.group_entries(0),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("MipmapGenerator PipelineLayout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        Self {
+            shader,
+            sampler,
+            bind_group_layout,
+            pipeline_layout,
+            pipelines: HashMap::new(),
+        }
+    }
+
+    /// Renders levels 1 to mip_count of every layer of texture, each from the level before.
+    /// format is used for the views, so it must be the format of the texture or one of its view formats, and must be renderable and filterable.
+    /// sRGB formats are filtered in linear space.  
+    /// Layers are rendered through single layer views, which the GL backend does not support for array textures.
+    /// ## Panics
+    /// If the texture does not have [RENDER_ATTACHMENT](TextureUsages::RENDER_ATTACHMENT) and [TEXTURE_BINDING](TextureUsages::TEXTURE_BINDING) usages
+    pub fn generate_mipmaps(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        device: &Device,
+        texture: &Texture,
+        mip_count: u32,
+        format: TextureFormat,
+    ) {
+        assert!(
+            texture
+                .usage()
+                .contains(TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING),
+            "generating mipmaps requires RENDER_ATTACHMENT and TEXTURE_BINDING usages"
+        );
+        self.create_pipeline(device, format);
+        let pipeline = &self.pipelines[&format];
+        for layer in 0..texture.depth_or_array_layers() {
+            let view = |mip_level| {
+                texture.create_view(&TextureViewDescriptor {
+                    label: Some("MipmapGenerator TextureView"),
+                    format: Some(format),
+                    dimension: Some(TextureViewDimension::D2),
+                    base_mip_level: mip_level,
+                    mip_level_count: Some(1),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            };
+            for mip_level in 1..mip_count.min(texture.mip_level_count()) {
+                let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                    label: Some("MipmapGenerator BindGroup"),
+                    layout: &self.bind_group_layout,
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: BindingResource::TextureView(&view(mip_level - 1)),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: BindingResource::Sampler(&self.sampler),
+                        },
+                    ],
+                });
+                let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                    label: Some("MipmapGenerator RenderPass"),
+                    color_attachments: &[Some(RenderPassColorAttachment {
+                        view: &view(mip_level),
+                        resolve_target: None,
+                        ops: Operations {
+                            load: LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.draw(0..3, 0..1);
+            }
+        }
+    }
+
+    /// Creates the pipeline for the format if it does not exist
+    fn create_pipeline(&mut self, device: &Device, format: TextureFormat) {
+        self.pipelines.entry(format).or_insert_with(|| {
+            device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some("MipmapGenerator Pipeline"),
+                layout: Some(&self.pipeline_layout),
+                vertex: VertexState {
+                    module: &self.shader,
+                    entry_point: "vs_main",
+                    compilation_options: PipelineCompilationOptions::default(),
+                    buffers: &[],
+                },
+                primitive: PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: MultisampleState::default(),
+                fragment: Some(FragmentState {
+                    module: &self.shader,
+                    entry_point: "fs_main",
+                    compilation_options: PipelineCompilationOptions::default(),
+                    targets: &[Some(ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+                cache: None,
+            })
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use modula_core::request_headless_device;
+    use wgpu::{Extent3d, Origin3d, TextureDescriptor, TextureDimension};
+
+    use super::*;
+    use crate::{read_texture, Image, MipMapImage};
+
+    #[test]
+    fn solid_levels_are_generated() {
+        let (device, queue) =
+            request_headless_device().expect("no adapter found, not even the fallback adapter");
+        let mut generator = MipmapGenerator::new(&device);
+        let format = TextureFormat::Rgba8Unorm;
+        let texture = device.create_texture(&TextureDescriptor {
+            label: None,
+            size: Extent3d {
+                width: 8,
+                height: 8,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 4,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT
+                | TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC
+                | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let pixel = [40, 80, 160, 255];
+        let base = Image::from_raw_rgba8(8, 8, pixel.repeat(64)).unwrap();
+        MipMapImage::from(base).write_to_texture(&queue, Origin3d::ZERO, &texture);
+
+        // the layout made from the reflected shader must match the bind group and pipeline
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        generator.generate_mipmaps(&mut encoder, &device, &texture, 4, format);
+        queue.submit([encoder.finish()]);
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            panic!("{error}");
+        }
+        for level in 1..4 {
+            let data = read_texture(&device, &queue, &texture, level);
+            assert!(data.chunks(4).all(|texel| texel == pixel), "level {level}");
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use wgpu::{
+    BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Device, ImageCopyBuffer,
+    ImageCopyTexture, ImageDataLayout, Maintain, MapMode, Origin3d, Queue, Texture, TextureAspect,
+    TextureUsages, COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+
+/// Copies a mip level of the texture to the CPU, with the rows tightly packed and the layers after each other.
+/// Blocks until the copy is done, so it can not be used on the web, this is meant for tests and screenshots
+/// ## Panics
+/// If the texture does not have [COPY_SRC](TextureUsages::COPY_SRC) usage, or has a compressed or depth/stencil format
+pub fn read_texture(device: &Device, queue: &Queue, texture: &Texture, mip_level: u32) -> Vec<u8> {
+    assert!(
+        texture.usage().contains(TextureUsages::COPY_SRC),
+        "the texture must have COPY_SRC usage to be read"
+    );
+    let format = texture.format();
+    assert_eq!(
+        format.block_dimensions(),
+        (1, 1),
+        "compressed textures can not be read"
+    );
+    let pixel_size = format
+        .block_copy_size(Some(TextureAspect::All))
+        .expect("depth/stencil textures can not be read");
+    let size = texture
+        .size()
+        .mip_level_size(mip_level, texture.dimension());
+    let row_size = size.width * pixel_size;
+    let padded_row_size = row_size.next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT);
+    let rows = size.height * size.depth_or_array_layers;
+    let buffer = device.create_buffer(&BufferDescriptor {
+        label: Some("Readback Buffer"),
+        size: padded_row_size as u64 * rows as u64,
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("Readback CommandEncoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        ImageCopyTexture {
+            texture,
+            mip_level,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        ImageCopyBuffer {
+            buffer: &buffer,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row_size),
+                rows_per_image: Some(size.height),
+            },
+        },
+        size,
+    );
+    queue.submit([encoder.finish()]);
+    let slice = buffer.slice(..);
+    slice.map_async(MapMode::Read, |result| {
+        result.expect("readback buffer could not be mapped")
+    });
+    device.poll(Maintain::Wait);
+    let padded = slice.get_mapped_range();
+    padded
+        .chunks(padded_row_size as usize)
+        .flat_map(|row| &row[..row_size as usize])
+        .copied()
+        .collect()
+}
@@ -0,0 +1,147 @@
+use bevy_ecs::{prelude::*, system::SystemParam};
+use modula_asset::{AssetId, Assets};
+use modula_core::{DeviceRes, PreInit, ScheduleBuilder};
+use modula_render::PreDraw;
+use wgpu::{AddressMode, CompareFunction, FilterMode, Sampler, SamplerDescriptor};
+
+use crate::TextureLoadSet;
+
+/// Inits [Sampler] assets, which are created in [TextureLoadSet]
+pub fn init_sampler_assets(schedule_builder: &mut ScheduleBuilder) {
+    modula_asset::init_assets::<Sampler>(schedule_builder);
+    schedule_builder.add_systems(PreInit, |mut commands: Commands| {
+        commands.insert_resource(SamplerQueue { queue: Vec::new() });
+    });
+    schedule_builder.add_systems(PreDraw, load_samplers.in_set(TextureLoadSet));
+}
+
+/// A simpler [SamplerDescriptor], can be converted into one using [From]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerConfig {
+    pub address_mode_u: AddressMode,
+    pub address_mode_v: AddressMode,
+    pub address_mode_w: AddressMode,
+    pub mag_filter: FilterMode,
+    pub min_filter: FilterMode,
+    pub mipmap_filter: FilterMode,
+    /// Maximum anisotropy, clamped to 1-16 which all devices with anisotropic filtering support.
+    /// Only used if all filters are [Linear](FilterMode::Linear), otherwise 1 is used
+    pub anisotropy: u16,
+    /// Makes a comparison sampler, used for shadow maps
+    pub compare: Option<CompareFunction>,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        Self::nearest()
+    }
+}
+
+impl SamplerConfig {
+    /// Nearest filtering and clamped to edge, for pixel art
+    pub fn nearest() -> Self {
+        Self::new(AddressMode::ClampToEdge, FilterMode::Nearest)
+    }
+
+    /// Linear filtering and repeated
+    pub fn linear_repeat() -> Self {
+        Self::new(AddressMode::Repeat, FilterMode::Linear)
+    }
+
+    /// Linear filtering and clamped to edge
+    pub fn linear_clamp() -> Self {
+        Self::new(AddressMode::ClampToEdge, FilterMode::Linear)
+    }
+
+    /// Same address mode and filter in all directions
+    pub fn new(address_mode: AddressMode, filter: FilterMode) -> Self {
+        Self {
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+            address_mode_w: address_mode,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter: filter,
+            anisotropy: 1,
+            compare: None,
+        }
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: u16) -> Self {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    pub fn with_compare(mut self, compare: CompareFunction) -> Self {
+        self.compare = Some(compare);
+        self
+    }
+
+    /// The anisotropy that will be used, see [anisotropy](Self::anisotropy)
+    pub fn anisotropy_clamp(&self) -> u16 {
+        let all_linear = [self.mag_filter, self.min_filter, self.mipmap_filter]
+            .iter()
+            .all(|f| *f == FilterMode::Linear);
+        if all_linear {
+            self.anisotropy.clamp(1, 16)
+        } else {
+            1
+        }
+    }
+}
+
+impl From<SamplerConfig> for SamplerDescriptor<'static> {
+    fn from(value: SamplerConfig) -> Self {
+        Self {
+            label: None,
+            address_mode_u: value.address_mode_u,
+            address_mode_v: value.address_mode_v,
+            address_mode_w: value.address_mode_w,
+            mag_filter: value.mag_filter,
+            min_filter: value.min_filter,
+            mipmap_filter: value.mipmap_filter,
+            anisotropy_clamp: value.anisotropy_clamp(),
+            compare: value.compare,
+            ..Default::default()
+        }
+    }
+}
+
+/// used to put samplers in assets, if the goal is to just load a sampler consider [SamplerLoader]
+#[derive(Resource)]
+pub struct SamplerQueue {
+    queue: Vec<(AssetId<Sampler>, SamplerConfig)>,
+}
+
+impl SamplerQueue {
+    /// creates a sampler on the given asset, replaces the current sampler if it already exists
+    pub fn create(&mut self, asset_id: AssetId<Sampler>, config: SamplerConfig) {
+        self.queue.push((asset_id, config));
+    }
+}
+
+#[derive(SystemParam)]
+pub struct SamplerLoader<'w> {
+    sampler_queue: ResMut<'w, SamplerQueue>,
+    sampler_assets: ResMut<'w, Assets<Sampler>>,
+}
+
+impl SamplerLoader<'_> {
+    /// loads a sampler, it will be created during the next [PreDraw]
+    pub fn load_sampler(&mut self, config: SamplerConfig) -> AssetId<Sampler> {
+        let asset_id = self.sampler_assets.add_empty();
+        self.sampler_queue.create(asset_id, config);
+        asset_id
+    }
+}
+
+fn load_samplers(
+    mut sampler_queue: ResMut<SamplerQueue>,
+    mut sampler_assets: ResMut<Assets<Sampler>>,
+    device: Res<DeviceRes>,
+) {
+    for (asset_id, config) in sampler_queue.queue.drain(..) {
+        let sampler = device.0.create_sampler(&config.into());
+        sampler_assets.replace(asset_id, sampler);
+    }
+}
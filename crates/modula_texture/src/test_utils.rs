@@ -0,0 +1,15 @@
+//! Fixtures shared by the tests of the crate
+
+use crate::{AlphaMode, Image, PixelFormat};
+
+/// An image where every byte is 255, so it is opaque white in every format with alpha
+pub(crate) fn image(width: u32, height: u32, format: PixelFormat) -> Image {
+    Image {
+        data: vec![255; width as usize * height as usize * format.bytes_per_pixel()],
+        width,
+        height,
+        format,
+        color_space: None,
+        alpha_mode: AlphaMode::Straight,
+    }
+}
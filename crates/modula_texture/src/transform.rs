@@ -0,0 +1,267 @@
+use crate::{atlas::Trim, Image, MipMapImage};
+
+/// Filter used by [Image::resized]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ResizeFilter {
+    /// Uses the closest pixel, for pixel art
+    Nearest,
+    /// Weights the pixels under the new pixel with a tent filter, so downscaling by large factors does not alias
+    #[default]
+    Linear,
+}
+
+/// An image with its transparent border removed, made by [Image::trimmed]
+#[derive(Clone)]
+pub struct TrimmedImage {
+    pub image: Image,
+    /// Position of the trimmed image in the original image
+    pub offset: (u32, u32),
+    pub original_size: (u32, u32),
+}
+
+impl TrimmedImage {
+    #[inline]
+    pub fn trim(&self) -> Trim {
+        Trim {
+            offset: self.offset,
+            original_size: self.original_size,
+        }
+    }
+}
+
+impl Image {
+    /// Removes the border of pixels with alpha at or below threshold_alpha, which is normalized to 0-1.  
+    /// Fully transparent images become the 1x1 top left pixel, and formats without alpha are not trimmed
+    pub fn trimmed(&self, threshold_alpha: f32) -> TrimmedImage {
+        let original_size = (self.width, self.height);
+        if self.format.channels() != 4 {
+            return TrimmedImage {
+                image: self.clone(),
+                offset: (0, 0),
+                original_size,
+            };
+        }
+        let max = if self.format.is_float() {
+            1.0
+        } else if self.format.bytes_per_channel() == 2 {
+            u16::MAX as f32
+        } else {
+            u8::MAX as f32
+        };
+        let opaque = |x: u32, y: u32| {
+            let pixel = y as usize * self.width as usize + x as usize;
+            self.sample(pixel * 4 + 3) / max > threshold_alpha
+        };
+        // (min x, min y, max x, max y) of the opaque pixels
+        let mut bounds: Option<(u32, u32, u32, u32)> = None;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if opaque(x, y) {
+                    bounds = Some(match bounds {
+                        Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+                        None => (x, y, x, y),
+                    });
+                }
+            }
+        }
+        let (x0, y0, x1, y1) = bounds.unwrap_or((0, 0, 0, 0));
+        TrimmedImage {
+            image: self.remap(x1 - x0 + 1, y1 - y0 + 1, |x, y| (x0 + x, y0 + y)),
+            offset: (x0, y0),
+            original_size,
+        }
+    }
+
+    /// Resizes the image, channels are filtered as stored, so sRGB data is not converted to linear first
+    /// ## Panics
+    /// If width or height is 0
+    pub fn resized(&self, width: u32, height: u32, filter: ResizeFilter) -> Image {
+        assert!(width > 0 && height > 0, "can not resize image to 0 pixels");
+        match filter {
+            ResizeFilter::Nearest => self.remap(width, height, |x, y| {
+                (
+                    (x as u64 * self.width as u64 / width as u64) as u32,
+                    (y as u64 * self.height as u64 / height as u64) as u32,
+                )
+            }),
+            ResizeFilter::Linear => self.resized_linear(width, height),
+        }
+    }
+
+    /// The part of the image starting at (x, y), or None if it is not inside the image
+    pub fn cropped(&self, x: u32, y: u32, width: u32, height: u32) -> Option<Image> {
+        let inside = x
+            .checked_add(width)
+            .zip(y.checked_add(height))
+            .is_some_and(|(end_x, end_y)| end_x <= self.width && end_y <= self.height);
+        if !inside || width == 0 || height == 0 {
+            return None;
+        }
+        Some(self.remap(width, height, |cx, cy| (x + cx, y + cy)))
+    }
+
+    /// Adds amount pixels on every side, copied from the closest edge pixel
+    pub fn extruded(&self, amount: u32) -> Image {
+        self.remap(self.width + amount * 2, self.height + amount * 2, |x, y| {
+            (
+                x.saturating_sub(amount).min(self.width - 1),
+                y.saturating_sub(amount).min(self.height - 1),
+            )
+        })
+    }
+
+    /// Flips the rows, for sources stored bottom up
+    pub fn flipped_vertical(&self) -> Image {
+        self.remap(self.width, self.height, |x, y| (x, self.height - 1 - y))
+    }
+
+    pub fn flipped_horizontal(&self) -> Image {
+        self.remap(self.width, self.height, |x, y| (self.width - 1 - x, y))
+    }
+
+    /// Rotates 90 degrees clockwise
+    pub fn rotated_90(&self) -> Image {
+        self.remap(self.height, self.width, |x, y| (y, self.height - 1 - x))
+    }
+
+    pub fn rotated_180(&self) -> Image {
+        self.remap(self.width, self.height, |x, y| {
+            (self.width - 1 - x, self.height - 1 - y)
+        })
+    }
+
+    /// Rotates 270 degrees clockwise, or 90 degrees counterclockwise
+    pub fn rotated_270(&self) -> Image {
+        self.remap(self.height, self.width, |x, y| (self.width - 1 - y, x))
+    }
+
+    /// Makes a new image where every pixel is copied from the pixel of this image returned by source
+    fn remap(&self, width: u32, height: u32, source: impl Fn(u32, u32) -> (u32, u32)) -> Image {
+        let pixel_size = self.format.bytes_per_pixel();
+        let mut data = Vec::with_capacity(width as usize * height as usize * pixel_size);
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = source(x, y);
+                let start = (sy as usize * self.width as usize + sx as usize) * pixel_size;
+                data.extend_from_slice(&self.data[start..start + pixel_size]);
+            }
+        }
+        Image {
+            data,
+            width,
+            height,
+            format: self.format,
+            color_space: self.color_space,
+            alpha_mode: self.alpha_mode,
+        }
+    }
+
+    /// Resamples rows and then columns with a tent filter, widened when downscaling
+    fn resized_linear(&self, width: u32, height: u32) -> Image {
+        let channels = self.format.channels();
+        let samples: Vec<f32> = (0..self.data.len() / self.format.bytes_per_channel())
+            .map(|i| self.sample(i))
+            .collect();
+        let horizontal = resample(&samples, self.width, self.height, channels, width, true);
+        let resampled = resample(&horizontal, width, self.height, channels, height, false);
+        let mut data =
+            Vec::with_capacity(width as usize * height as usize * self.format.bytes_per_pixel());
+        for value in resampled {
+            self.push_sample(&mut data, value);
+        }
+        Image {
+            data,
+            width,
+            height,
+            format: self.format,
+            color_space: self.color_space,
+            alpha_mode: self.alpha_mode,
+        }
+    }
+}
+
+/// Resamples samples with the size (width, height) to new_size along one axis
+fn resample(
+    samples: &[f32],
+    width: u32,
+    height: u32,
+    channels: usize,
+    new_size: u32,
+    horizontal: bool,
+) -> Vec<f32> {
+    let (size, other) = if horizontal {
+        (width, height)
+    } else {
+        (height, width)
+    };
+    let scale = size as f32 / new_size as f32;
+    let support = scale.max(1.0);
+    // the source pixels and weights of every new pixel
+    let kernels: Vec<Vec<(usize, f32)>> = (0..new_size)
+        .map(|i| {
+            let center = (i as f32 + 0.5) * scale - 0.5;
+            let start = (center - support).floor() as i64;
+            let end = (center + support).ceil() as i64;
+            let mut kernel: Vec<(usize, f32)> = (start..=end)
+                .map(|s| {
+                    let weight = (1.0 - (s as f32 - center).abs() / support).max(0.0);
+                    (s.clamp(0, size as i64 - 1) as usize, weight)
+                })
+                .filter(|(_, weight)| *weight > 0.0)
+                .collect();
+            let total: f32 = kernel.iter().map(|(_, weight)| weight).sum();
+            kernel.iter_mut().for_each(|(_, weight)| *weight /= total);
+            kernel
+        })
+        .collect();
+    let (new_width, new_height) = if horizontal {
+        (new_size, height)
+    } else {
+        (width, new_size)
+    };
+    let mut res = vec![0.0; new_width as usize * new_height as usize * channels];
+    for o in 0..other as usize {
+        for (i, kernel) in kernels.iter().enumerate() {
+            let (dst, stride, base) = if horizontal {
+                (o * new_width as usize + i, 1, o * width as usize)
+            } else {
+                (i * new_width as usize + o, width as usize, o)
+            };
+            for channel in 0..channels {
+                res[dst * channels + channel] = kernel
+                    .iter()
+                    .map(|(s, weight)| samples[(base + s * stride) * channels + channel] * weight)
+                    .sum();
+            }
+        }
+    }
+    res
+}
+
+/// Makes the image fit in max_size by removing the levels that are too large,
+/// or by resizing if the last level is too large, returns None if it already fits
+pub(crate) fn fit_to_size(image: &MipMapImage, max_size: u32) -> Option<MipMapImage> {
+    let too_large = |img: &Image| img.width > max_size || img.height > max_size;
+    if !too_large(&image.levels()[0]) {
+        return None;
+    }
+    let fit = |img: &Image| {
+        let scale = max_size as f64 / img.width.max(img.height) as f64;
+        img.resized(
+            ((img.width as f64 * scale) as u32).max(1),
+            ((img.height as f64 * scale) as u32).max(1),
+            ResizeFilter::Linear,
+        )
+    };
+    Some(match image {
+        MipMapImage::WithImages(levels) => match levels.iter().position(|img| !too_large(img)) {
+            Some(first) => MipMapImage::WithImages(levels[first..].to_vec()),
+            None => MipMapImage::WithImages(vec![fit(levels.last().unwrap())]),
+        },
+        MipMapImage::FromLevel(base, count) => {
+            let base = fit(base);
+            let max_levels = 32 - base.width.max(base.height).leading_zeros() as usize;
+            MipMapImage::FromLevel(base, (*count).min(max_levels))
+        }
+    })
+}
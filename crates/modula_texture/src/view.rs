@@ -0,0 +1,106 @@
+use bevy_ecs::prelude::*;
+use modula_asset::{AssetId, Assets};
+use wgpu::{
+    Id, Texture, TextureAspect, TextureFormat, TextureView, TextureViewDescriptor,
+    TextureViewDimension,
+};
+
+use crate::TextureQueue;
+
+/// A simpler [TextureViewDescriptor], can be converted into one using [From].
+/// The default is a view of the whole texture
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct TextureViewConfig {
+    /// The format of the texture if None
+    pub format: Option<TextureFormat>,
+    /// Picked from the texture if None, layered textures are [D2Array](TextureViewDimension::D2Array)
+    pub dimension: Option<TextureViewDimension>,
+    pub aspect: TextureAspect,
+    pub base_mip_level: u32,
+    /// All levels after base_mip_level if None
+    pub mip_level_count: Option<u32>,
+    pub base_array_layer: u32,
+    /// All layers after base_array_layer if None
+    pub array_layer_count: Option<u32>,
+}
+
+impl TextureViewConfig {
+    /// A [D2](TextureViewDimension::D2) view of a single layer of a layered texture
+    pub fn layer(layer: u32) -> Self {
+        Self {
+            dimension: Some(TextureViewDimension::D2),
+            base_array_layer: layer,
+            array_layer_count: Some(1),
+            ..Default::default()
+        }
+    }
+
+    /// A view of count mip levels, starting at base
+    pub fn mips(base: u32, count: u32) -> Self {
+        Self {
+            base_mip_level: base,
+            mip_level_count: Some(count),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_dimension(mut self, dimension: TextureViewDimension) -> Self {
+        self.dimension = Some(dimension);
+        self
+    }
+
+    pub fn with_format(mut self, format: TextureFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+}
+
+impl From<TextureViewConfig> for TextureViewDescriptor<'static> {
+    fn from(value: TextureViewConfig) -> Self {
+        Self {
+            label: None,
+            format: value.format,
+            dimension: value.dimension,
+            aspect: value.aspect,
+            base_mip_level: value.base_mip_level,
+            mip_level_count: value.mip_level_count,
+            base_array_layer: value.base_array_layer,
+            array_layer_count: value.array_layer_count,
+        }
+    }
+}
+
+/// A view made by [TextureQueue::create_view], kept so it can be made again when the texture is replaced
+pub(crate) struct ViewInfo {
+    pub(crate) asset_id: AssetId<TextureView>,
+    pub(crate) texture_id: AssetId<Texture>,
+    pub(crate) config: TextureViewConfig,
+    /// The texture the current view was created from, None if there is no view
+    pub(crate) created_from: Option<Id<Texture>>,
+}
+
+/// Creates new views, and makes views again if their texture was replaced, runs after the textures are created.
+/// The view is removed if its texture is removed, and made again if a new texture is put in the asset
+pub(crate) fn update_texture_views(
+    mut texture_queue: ResMut<TextureQueue>,
+    texture_assets: Res<Assets<Texture>>,
+    mut view_assets: ResMut<Assets<TextureView>>,
+) {
+    let texture_queue = &mut *texture_queue;
+    for asset_id in texture_queue.removed_views.drain(..) {
+        view_assets.remove(asset_id);
+    }
+    for info in texture_queue.views.iter_mut() {
+        match texture_assets.get(info.texture_id) {
+            Some(texture) if info.created_from != Some(texture.global_id()) => {
+                view_assets.replace(info.asset_id, texture.create_view(&info.config.into()));
+                info.created_from = Some(texture.global_id());
+            }
+            None if info.created_from.is_some() => {
+                view_assets.remove(info.asset_id);
+                info.created_from = None;
+            }
+            _ => {}
+        }
+    }
+}
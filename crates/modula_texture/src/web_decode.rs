@@ -0,0 +1,83 @@
+use std::path::Path;
+
+use js_sys::{Array, ArrayBuffer, Uint8Array};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    Blob, ColorSpaceConversion, ImageBitmap, ImageBitmapOptions, OffscreenCanvas,
+    OffscreenCanvasRenderingContext2d, PremultiplyAlpha, Response, Window,
+};
+
+use crate::{Image, ImageLoadError};
+
+impl Image {
+    /// Decodes file data with the browser using createImageBitmap, which is faster than the image crate on the web.
+    /// The result is always [Rgba8](crate::PixelFormat::Rgba8) with straight alpha, like [load_from_data](Image::load_from_data).
+    /// Decoding in the browser is async, so this can not be used by [load_from_data](Image::load_from_data)
+    pub async fn decode_web(data: &[u8]) -> Result<Self, ImageLoadError> {
+        let parts = Array::of1(&Uint8Array::from(data));
+        let blob = Blob::new_with_u8_array_sequence(&parts).map_err(web_error)?;
+        let options = ImageBitmapOptions::new();
+        // the raw pixels are wanted, the color space is handled by the texture format
+        options.set_premultiply_alpha(PremultiplyAlpha::None);
+        options.set_color_space_conversion(ColorSpaceConversion::None);
+        let promise = window()?
+            .create_image_bitmap_with_blob_and_image_bitmap_options(&blob, &options)
+            .map_err(web_error)?;
+        let bitmap: ImageBitmap = JsFuture::from(promise)
+            .await
+            .map_err(web_error)?
+            .unchecked_into();
+        let result = read_pixels(&bitmap);
+        bitmap.close();
+        result
+    }
+}
+
+/// Draws the bitmap to a canvas to read its pixels
+fn read_pixels(bitmap: &ImageBitmap) -> Result<Image, ImageLoadError> {
+    let (width, height) = (bitmap.width(), bitmap.height());
+    let canvas = OffscreenCanvas::new(width, height).map_err(web_error)?;
+    let context: OffscreenCanvasRenderingContext2d = canvas
+        .get_context("2d")
+        .map_err(web_error)?
+        .ok_or_else(|| ImageLoadError::WebDecode("2d canvas context is not available".into()))?
+        .unchecked_into();
+    context
+        .draw_image_with_image_bitmap(bitmap, 0.0, 0.0)
+        .map_err(web_error)?;
+    let data = context
+        .get_image_data(0.0, 0.0, width as f64, height as f64)
+        .map_err(web_error)?
+        .data()
+        .0;
+    Image::from_raw_rgba8(width, height, data).map_err(|e| ImageLoadError::WebDecode(e.to_string()))
+}
+
+/// Reads a file from the server with fetch, the path is relative to the page
+pub(crate) async fn fetch(path: &Path) -> Result<Vec<u8>, ImageLoadError> {
+    let url = path.to_string_lossy();
+    let response: Response = JsFuture::from(window()?.fetch_with_str(&url))
+        .await
+        .map_err(web_error)?
+        .unchecked_into();
+    if !response.ok() {
+        return Err(ImageLoadError::WebDecode(format!(
+            "fetching {url} failed with status {}",
+            response.status()
+        )));
+    }
+    let buffer: ArrayBuffer = JsFuture::from(response.array_buffer().map_err(web_error)?)
+        .await
+        .map_err(web_error)?
+        .unchecked_into();
+    Ok(Uint8Array::new(&buffer).to_vec())
+}
+
+fn window() -> Result<Window, ImageLoadError> {
+    web_sys::window().ok_or_else(|| ImageLoadError::WebDecode("no window".into()))
+}
+
+fn web_error(value: JsValue) -> ImageLoadError {
+    ImageLoadError::WebDecode(format!("{value:?}"))
+}
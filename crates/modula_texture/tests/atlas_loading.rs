@@ -0,0 +1,69 @@
+//! Atlas loading as an app sets it up, through the schedules of [ScheduleBuilder]
+
+use bevy_ecs::{event::Events, world::World};
+use modula_asset::{AssetWorldExt, Assets};
+use modula_core::{
+    request_headless_device, DeviceRes, Init, PreInit, QueueRes, ScheduleBuilder, WorldExt,
+};
+use modula_render::PreDraw;
+use modula_texture::{
+    atlas::{
+        init_atlas_loading, AtlasBuildFailed, AtlasGroup, AtlasGroupBindGroupLayout,
+        AtlasGroupBuilder, AtlasGroupQueue,
+    },
+    init_texture_loading, AlphaMode, Image, PixelFormat,
+};
+
+/// Runs PreInit and Init
+fn atlas_world() -> World {
+    let mut schedule_builder = ScheduleBuilder::new();
+    init_texture_loading(&mut schedule_builder);
+    init_atlas_loading(&mut schedule_builder);
+    let mut world = schedule_builder.finish();
+    world.try_add_schedule(PreInit);
+    world.run_and_apply_deferred(PreInit);
+    // the queue can be used before there is a device
+    assert!(world.contains_resource::<AtlasGroupQueue>());
+    assert!(world.contains_resource::<Assets<AtlasGroup>>());
+    let (device, queue) =
+        request_headless_device().expect("no adapter found, not even the fallback adapter");
+    world.insert_resource(DeviceRes(device));
+    world.insert_resource(QueueRes(queue));
+    world.run_and_apply_deferred(Init);
+    world
+}
+
+fn solid(color: [u8; 4], (width, height): (u32, u32)) -> Image {
+    Image {
+        data: color.repeat((width * height) as usize),
+        width,
+        height,
+        format: PixelFormat::Rgba8,
+        color_space: None,
+        alpha_mode: AlphaMode::Straight,
+    }
+}
+
+#[test]
+fn queued_group_is_built() {
+    let mut world = atlas_world();
+    assert!(world.contains_resource::<AtlasGroupBindGroupLayout>());
+
+    let mut builder = AtlasGroupBuilder::new(1);
+    let red = builder.add_image_named("red", solid([255, 0, 0, 255], (4, 4)));
+    let blue = builder.add_image_named("blue", solid([0, 0, 255, 255], (2, 6)));
+    let group = world.add_empty_asset();
+    world
+        .resource_mut::<AtlasGroupQueue>()
+        .init_group(group, builder);
+    assert!(world.get_asset::<AtlasGroup>(group).is_none());
+
+    world.run_and_apply_deferred(PreDraw);
+    assert!(world.resource::<Events<AtlasBuildFailed>>().is_empty());
+    let built = world
+        .get_asset::<AtlasGroup>(group)
+        .expect("the group was not built");
+    assert_eq!(built.entry("red"), Some(red));
+    assert_eq!(built.entry("blue"), Some(blue));
+    assert_eq!(built.atlases().len(), 1);
+}
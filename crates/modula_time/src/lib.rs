@@ -6,13 +6,7 @@ use winit::event::{Event, WindowEvent};
 
 pub fn init_time(schedule_builder: &mut ScheduleBuilder) {
     schedule_builder.add_systems(EventOccurred, update_time);
-    schedule_builder.add_systems(Init, |mut c: Commands| {
-        c.insert_resource(Time {
-            delta: Duration::from_secs_f64(1.0 / 30.0),
-            elapsed: Duration::from_secs(0),
-            frame_start: None,
-        })
-    });
+    schedule_builder.add_systems(Init, |mut c: Commands| c.insert_resource(Time::new()));
 }
 
 #[derive(Resource)]
@@ -20,9 +14,21 @@ pub struct Time {
     delta: Duration,
     elapsed: Duration,
     frame_start: Option<Instant>,
+    frame_count: u64,
+    startup: Instant,
 }
 
 impl Time {
+    fn new() -> Self {
+        Self {
+            delta: Duration::from_secs_f64(1.0 / 30.0),
+            elapsed: Duration::from_secs(0),
+            frame_start: None,
+            frame_count: 0,
+            startup: Instant::now(),
+        }
+    }
+
     /// Time since last frame
     pub fn delta(&self) -> Duration {
         self.delta
@@ -56,6 +62,40 @@ impl Time {
         self.frame_start
             .expect("frame_start called before fisrt frame")
     }
+
+    /// Number of frames started, incremented every time the window is to be redrawn, so it is 1 during the first frame
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// True every n-th frame, for example to spread work over frames.
+    /// Always false if n is 0
+    pub fn every_n_frames(&self, n: u64) -> bool {
+        n != 0 && self.frame_count.is_multiple_of(n)
+    }
+
+    /// When the Time was made during [Init], before the first frame
+    pub fn startup(&self) -> Instant {
+        self.startup
+    }
+
+    /// Time since [startup](Self::startup), unlike [elapsed](Self::elapsed) this includes the time before the first frame and between frames
+    pub fn time_since_startup(&self) -> Duration {
+        self.startup.elapsed()
+    }
+
+    fn start_frame(&mut self, now: Instant) {
+        let delta = if let Some(prev) = self.frame_start {
+            now - prev
+        } else {
+            // kinda arbitrary but initial delta should not really be important
+            Duration::from_secs_f64(1.0 / 30.0)
+        };
+        self.elapsed += delta;
+        self.frame_start = Some(now);
+        self.delta = delta;
+        self.frame_count += 1;
+    }
 }
 
 fn update_time(event: Res<EventRes>, mut time: ResMut<Time>) {
@@ -66,14 +106,61 @@ fn update_time(event: Res<EventRes>, mut time: ResMut<Time>) {
         } => {}
         _ => return,
     }
-    let now = Instant::now();
-    let delta = if let Some(prev) = time.frame_start {
-        now - prev
-    } else {
-        // kinda arbitrary but initial delta should not really be important
-        Duration::from_secs_f64(1.0 / 30.0)
-    };
-    time.elapsed += delta;
-    time.frame_start = Some(now);
-    time.delta = delta
+    time.start_frame(Instant::now());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Time after a frame started every step from startup
+    fn time(frames: u32, step: Duration) -> Time {
+        let mut time = Time::new();
+        for i in 1..=frames {
+            time.start_frame(time.startup() + step * i);
+        }
+        time
+    }
+
+    #[test]
+    fn frames_are_counted() {
+        let step = Duration::from_millis(10);
+        assert_eq!(Time::new().frame_count(), 0);
+        let first = time(1, step);
+        assert_eq!(first.frame_count(), 1);
+        assert_eq!(first.frame_start(), first.startup() + step);
+        let later = time(5, step);
+        assert_eq!(later.frame_count(), 5);
+        assert_eq!(later.delta(), step);
+        // the first frame has no previous frame to measure from
+        assert_eq!(
+            later.elapsed(),
+            Duration::from_secs_f64(1.0 / 30.0) + step * 4
+        );
+    }
+
+    #[test]
+    fn every_n_frames_matches_multiples() {
+        let step = Duration::from_millis(10);
+        let matching = |n| {
+            (1..=12)
+                .filter(|&frames| time(frames, step).every_n_frames(n))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(matching(1), (1..=12).collect::<Vec<_>>());
+        assert_eq!(matching(3), [3, 6, 9, 12]);
+        assert_eq!(matching(5), [5, 10]);
+        assert!(matching(0).is_empty());
+    }
+
+    #[test]
+    fn startup_is_before_the_first_frame() {
+        let before = Instant::now();
+        let time = time(3, Duration::from_millis(10));
+        assert!(before <= time.startup());
+        assert!(time.startup() < time.frame_start());
+        let since_startup = time.time_since_startup();
+        assert!(since_startup <= before.elapsed());
+        assert!(time.time_since_startup() >= since_startup);
+    }
 }
@@ -1,4 +1,4 @@
-use std::ops::{Add, Div, Range};
+use std::ops::Range;
 
 use bevy_ecs::prelude::*;
 pub use hashbrown;
@@ -13,12 +13,12 @@ pub fn init_window_closing(schedule_builder: &mut ScheduleBuilder) {
 }
 
 fn handle_window_close(mut commands: Commands, event: Res<EventRes>) {
-    match event.0 {
-        Event::WindowEvent {
-            window_id: _,
-            event: WindowEvent::CloseRequested,
-        } => commands.insert_resource(ShuoldExit),
-        _ => {}
+    if let Event::WindowEvent {
+        window_id: _,
+        event: WindowEvent::CloseRequested,
+    } = event.0
+    {
+        commands.insert_resource(ShuoldExit)
     }
 }
 
@@ -31,17 +31,26 @@ pub fn binsearch<T, E>(
     if range.is_empty() {
         panic!("binsearch on empty range");
     }
-    let mut res = None;
+    let mut ok = None;
+    let mut err = None;
     while range.start < range.end {
         let mid = (range.start + range.end) / 2;
-        res = Some(f(mid));
-        if res.as_ref().unwrap().is_ok() {
-            range.end = mid;
-        } else {
-            range.start = mid + 1;
+        match f(mid) {
+            Ok(value) => {
+                ok = Some(value);
+                range.end = mid;
+            }
+            Err(e) => {
+                err = Some(e);
+                range.start = mid + 1;
+            }
         }
     }
-    res.unwrap()
+    // the last value tried may have failed after a lower one worked
+    match ok {
+        Some(value) => Ok(value),
+        None => Err(err.unwrap()),
+    }
 }
 
 /// Binary searches from start and up, returning the lowest value giving ok, if all values give error, the error returned by the end of the range is returned
@@ -50,6 +59,9 @@ pub fn binsearch_upwards<T, E>(mut f: impl FnMut(i32) -> Result<T, E>, start: i3
     loop {
         let res = f(start + i - 1);
         if res.is_ok() {
+            if i == 1 {
+                return res;
+            }
             break;
         }
         i *= 2;
@@ -57,5 +69,6 @@ pub fn binsearch_upwards<T, E>(mut f: impl FnMut(i32) -> Result<T, E>, start: i3
             return res;
         }
     }
-    binsearch(f, (start + i / 4..start + i / 2))
+    // start + i / 2 - 1 failed and start + i - 1 worked
+    binsearch(f, start + i / 2..start + i)
 }
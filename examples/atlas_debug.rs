@@ -0,0 +1,37 @@
+//! Saves how the atlas layouters pack the same images, to atlas_default_*.png and atlas_shelf_*.png
+
+use modula::texture::{
+    atlas::{AtlasGroupBuilder, AtlasLayouter, DefaultLayouter, MaxAtlasSize, ShelfLayouter},
+    Image,
+};
+
+fn main() {
+    let mut builder = AtlasGroupBuilder::new(1);
+    // a fixed LCG, so every run packs the same images
+    let mut state = 0x2545f491u32;
+    let mut next = |max: u32| {
+        state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+        (state >> 16) % max + 4
+    };
+    for _ in 0..300 {
+        let (width, height) = (next(60), next(60));
+        let data = vec![255; width as usize * height as usize * 4];
+        builder.add_image(Image::from_raw_rgba8(width, height, data).unwrap());
+    }
+    save_preview::<DefaultLayouter>(&builder, "atlas_default");
+    save_preview::<ShelfLayouter>(&builder, "atlas_shelf");
+}
+
+fn save_preview<L: AtlasLayouter>(builder: &AtlasGroupBuilder, name: &str) {
+    let images = builder
+        .debug_pack_preview::<L>(MaxAtlasSize::new(512, 2))
+        .expect("packing failed");
+    for (i, image) in images.into_iter().enumerate() {
+        let path = format!("{name}_{i}.png");
+        image::RgbaImage::from_raw(image.width, image.height, image.data)
+            .expect("debug images are rgba8")
+            .save(&path)
+            .expect("failed to save image");
+        println!("saved {path}");
+    }
+}
@@ -0,0 +1,183 @@
+//! Draws a grid of sprites where the right half uses a grayscale material, showing that sprites are batched per material.
+//! The SpriteStats are printed every 120 frames
+
+use bevy_ecs::{prelude::*, system::SystemParam};
+use modula::{
+    core::{App, DeviceRes, Init, ScheduleBuilder},
+    render::{
+        self,
+        shader::{self, ShaderBundler, ShaderModuleSource},
+        ClearNext, Draw, RenderTarget, Sequence, SequenceBuilder, SequenceQueue, SurfaceTargetRes,
+    },
+    sprite::{
+        self, Camera2D, CameraBindGroupLayout, CameraViewport, MaterialId, SpriteMaterial,
+        SpriteMaterials, SpriteOperation, SpritePipeline, SpritePipelineBuilder, SpriteQueue,
+        SpriteStats, SpriteStyle, SpriteTransform,
+    },
+    texture::{
+        atlas::{
+            self, AtlasGroup, AtlasGroupBindGroupLayout, AtlasGroupEntry, AtlasLoadOptions,
+            AtlasLoader,
+        },
+        Image,
+    },
+    utils,
+};
+use modula_asset::{AssetId, Assets};
+use winit::window::WindowAttributes;
+
+const GRID_SIZE: i32 = 8;
+const SPACING: f32 = 72.0;
+
+/// The implementor of the sprite shader, using the luminance of the tinted color
+const GRAYSCALE_MATERIAL: &str = "
+fn sprite_material(fragment: SpriteFragment) -> vec4<f32> {
+    let color = fragment.color * fragment.tint;
+    let gray = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));
+    return vec4(vec3(gray), color.a);
+}
+";
+
+fn main() {
+    let mut schedule_builder = ScheduleBuilder::new();
+    render::init_render(&mut schedule_builder);
+    shader::init_shader_bundling(&mut schedule_builder);
+    atlas::init_atlas_loading(&mut schedule_builder);
+    sprite::init_sprites(&mut schedule_builder);
+    utils::init_window_closing(&mut schedule_builder);
+    schedule_builder.add_systems(Init, init_scene);
+    schedule_builder.add_systems(Draw, draw_sprites);
+    App { schedule_builder }.run(wgpu::PowerPreference::LowPower, WindowAttributes::default());
+}
+
+#[derive(Resource)]
+struct Scene {
+    atlas: AssetId<AtlasGroup>,
+    entries: Vec<AtlasGroupEntry>,
+    queue: AssetId<SpriteQueue>,
+    /// Made once the bind group layouts exist, after Init
+    materials: Option<(AssetId<SpritePipeline>, MaterialId)>,
+    sequence: AssetId<Sequence>,
+    frame: u32,
+}
+
+fn init_scene(
+    mut commands: Commands,
+    mut atlas_loader: AtlasLoader,
+    mut queues: ResMut<Assets<SpriteQueue>>,
+    mut cameras: ResMut<Assets<Camera2D>>,
+    mut sequences: ResMut<Assets<Sequence>>,
+    surface_target: Res<SurfaceTargetRes>,
+) {
+    let images = [
+        ("red", solid([230, 40, 40, 255])),
+        ("green", solid([40, 200, 60, 255])),
+        ("blue", solid([50, 80, 230, 255])),
+    ];
+    let (atlas, entries) = atlas_loader.load_atlas(images, AtlasLoadOptions::default());
+    let queue = queues.add(SpriteQueue::new());
+    let camera = cameras.add(Camera2D::new(CameraViewport::Surface));
+    let sequence = SequenceBuilder::new()
+        .add(ClearNext {
+            render_target: surface_target.0,
+        })
+        .add(SpriteOperation::new(surface_target.0, queue).with_camera(camera))
+        .finish(&mut sequences);
+    commands.insert_resource(Scene {
+        atlas,
+        entries,
+        queue,
+        materials: None,
+        sequence,
+        frame: 0,
+    });
+}
+
+/// A square with a darker border
+fn solid(color: [u8; 4]) -> Image {
+    let size = 48;
+    let data = (0..size * size)
+        .flat_map(|i| {
+            let (x, y) = (i % size, i / size);
+            let border = x < 4 || y < 4 || x >= size - 4 || y >= size - 4;
+            let shade = if border { 2 } else { 1 };
+            [
+                color[0] / shade,
+                color[1] / shade,
+                color[2] / shade,
+                color[3],
+            ]
+        })
+        .collect();
+    Image::from_raw_rgba8(size, size, data).unwrap()
+}
+
+#[derive(SystemParam)]
+struct Gpu<'w> {
+    device: Res<'w, DeviceRes>,
+    bundler: Res<'w, ShaderBundler>,
+    atlas_layout: Res<'w, AtlasGroupBindGroupLayout>,
+    camera_layout: Res<'w, CameraBindGroupLayout>,
+    surface_target: Res<'w, SurfaceTargetRes>,
+    render_targets: Res<'w, Assets<RenderTarget>>,
+}
+
+fn draw_sprites(
+    mut scene: ResMut<Scene>,
+    mut queues: ResMut<Assets<SpriteQueue>>,
+    mut pipelines: ResMut<Assets<SpritePipeline>>,
+    mut materials: ResMut<SpriteMaterials>,
+    mut sequence_queue: ResMut<SequenceQueue>,
+    stats: Res<SpriteStats>,
+    gpu: Gpu,
+) {
+    let (pipeline, grayscale) = *scene.materials.get_or_insert_with(|| {
+        let target = gpu.render_targets.get(gpu.surface_target.0).unwrap();
+        let build = |builder: SpritePipelineBuilder| {
+            builder
+                .build(
+                    &gpu.device.0,
+                    &gpu.bundler,
+                    &gpu.atlas_layout,
+                    &gpu.camera_layout,
+                    target,
+                )
+                .unwrap()
+        };
+        let standard = pipelines.add(build(SpritePipelineBuilder::new()));
+        let source = ShaderModuleSource::new(GRAYSCALE_MATERIAL.into()).with_name("grayscale");
+        let grayscale = pipelines.add(build(
+            SpritePipelineBuilder::new()
+                .with_label("Grayscale sprite pipeline")
+                .with_material(&source),
+        ));
+        (standard, materials.add(SpriteMaterial::new(grayscale)))
+    });
+    scene.frame += 1;
+    sequence_queue.schedule(scene.sequence);
+    if scene.frame.is_multiple_of(120) {
+        // the sprites of each material are adjacent at the same z, so there are two batches
+        println!("{:?}", *stats);
+    }
+    let queue = queues.get_mut(scene.queue).unwrap();
+    queue.set_atlas(scene.atlas);
+    queue.set_pipeline(pipeline);
+    let offset = (GRID_SIZE - 1) as f32 / 2.0;
+    for x in 0..GRID_SIZE {
+        for y in 0..GRID_SIZE {
+            let wobble = (scene.frame as f32 * 0.03 + (x + y) as f32 * 0.4).sin() * 0.3;
+            let transform = SpriteTransform::from_position([
+                (x as f32 - offset) * SPACING,
+                (y as f32 - offset) * SPACING,
+            ])
+            .with_rotation(wobble);
+            let style = if x < GRID_SIZE / 2 {
+                SpriteStyle::default()
+            } else {
+                SpriteStyle::default().with_material(grayscale)
+            };
+            let entry = scene.entries[(x + y) as usize % scene.entries.len()];
+            queue.draw(entry, transform, style, 0.0);
+        }
+    }
+}
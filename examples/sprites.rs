@@ -0,0 +1,221 @@
+//! Draws a handful of textured quads from an atlas group with a SpriteOperation, along with animated, spinning and tinted Sprite entities,
+//! seen through a camera circling the origin.
+//! Image files such as PNGs given as arguments are loaded into the atlas group in place of the generated images
+
+use std::time::Duration;
+
+use bevy_ecs::{prelude::*, system::SystemParam};
+use modula::{
+    core::{App, DeviceRes, Init, ScheduleBuilder},
+    render::{
+        self,
+        shader::{self, ShaderBundler},
+        ClearNext, Draw, RenderTarget, Sequence, SequenceBuilder, SequenceQueue, SurfaceTargetRes,
+    },
+    sprite::{
+        self, AnimationClip, Animator, Camera2D, CameraBindGroupLayout, CameraViewport, Sprite,
+        SpriteBlend, SpriteOperation, SpritePipeline, SpritePipelineBuilder, SpriteQueue,
+        SpriteStyle, SpriteTransform, Transform2D,
+    },
+    texture::{
+        atlas::{
+            self, AtlasGroup, AtlasGroupBindGroupLayout, AtlasGroupEntry, AtlasLoadOptions,
+            AtlasLoader,
+        },
+        Image,
+    },
+    utils,
+};
+use modula_asset::{AssetId, Assets};
+use modula_time::Time;
+use wgpu::Color;
+use winit::window::WindowAttributes;
+
+const SPRITE_COUNT: usize = 8;
+const ENTITY_COUNT: usize = 5;
+
+fn main() {
+    let mut schedule_builder = ScheduleBuilder::new();
+    render::init_render(&mut schedule_builder);
+    shader::init_shader_bundling(&mut schedule_builder);
+    atlas::init_atlas_loading(&mut schedule_builder);
+    sprite::init_sprites(&mut schedule_builder);
+    sprite::init_sprite_animation(&mut schedule_builder);
+    modula_time::init_time(&mut schedule_builder);
+    utils::init_window_closing(&mut schedule_builder);
+    schedule_builder.add_systems(Init, init_scene);
+    schedule_builder.add_systems(Draw, (draw_sprites, spin_sprites, move_camera));
+    App { schedule_builder }.run(wgpu::PowerPreference::LowPower, WindowAttributes::default());
+}
+
+#[derive(Resource)]
+struct Scene {
+    atlas: AssetId<AtlasGroup>,
+    entries: Vec<AtlasGroupEntry>,
+    queue: AssetId<SpriteQueue>,
+    camera: AssetId<Camera2D>,
+    /// Made once the bind group layouts exist, after Init
+    pipeline: Option<AssetId<SpritePipeline>>,
+    sequence: AssetId<Sequence>,
+    frame: u32,
+}
+
+/// Radians per second a Sprite entity is rotated by
+#[derive(Component)]
+struct Spin(f32);
+
+fn init_scene(
+    mut commands: Commands,
+    mut atlas_loader: AtlasLoader,
+    mut queues: ResMut<Assets<SpriteQueue>>,
+    mut cameras: ResMut<Assets<Camera2D>>,
+    mut clips: ResMut<Assets<AnimationClip>>,
+    mut sequences: ResMut<Assets<Sequence>>,
+    surface_target: Res<SurfaceTargetRes>,
+) {
+    let paths: Vec<_> = std::env::args().skip(1).collect();
+    let (atlas, entries) = if paths.is_empty() {
+        let images = [
+            (
+                "checker",
+                pattern(|x, y| ((x / 8 + y / 8) % 2) * 255, 64, 64),
+            ),
+            ("stripes", pattern(|x, _| (x / 4 % 2) * 200, 32, 64)),
+            ("gradient", pattern(|x, y| (x + y) * 2, 64, 32)),
+        ];
+        atlas_loader.load_atlas(images, AtlasLoadOptions::default())
+    } else {
+        let mut builder = AtlasLoadOptions::default().builder();
+        let entries = builder
+            .add_images_from_paths(&paths)
+            .unwrap_or_else(|failed| panic!("failed to load images: {failed:?}"));
+        (atlas_loader.load_builder(builder), entries)
+    };
+    let queue = queues.add(SpriteQueue::new());
+    // every image is a frame, each entity plays them at its own speed
+    let clip = clips.add(AnimationClip::new(
+        entries.clone(),
+        Duration::from_millis(400),
+    ));
+    for i in 0..ENTITY_COUNT {
+        let x = (i as f32 - (ENTITY_COUNT - 1) as f32 / 2.0) * 96.0;
+        let shade = i as f64 / ENTITY_COUNT as f64;
+        commands.spawn((
+            Sprite::new(queue, atlas, entries[0]).with_tint(Color {
+                r: 1.0 - shade * 0.5,
+                g: 0.5 + shade * 0.5,
+                b: 1.0,
+                a: 1.0,
+            }),
+            Transform2D::from_translation([x, 0.0]).with_scale([0.75, 0.75]),
+            Animator::playing(clip).with_speed(1.0 + i as f32 * 0.5),
+            Spin(if i % 2 == 0 { 1.0 } else { -0.5 }),
+        ));
+    }
+    // one world unit is one pixel, with the origin in the center of the window
+    let camera = cameras.add(Camera2D::new(CameraViewport::Surface));
+    let sequence = SequenceBuilder::new()
+        .add(ClearNext {
+            render_target: surface_target.0,
+        })
+        .add(SpriteOperation::new(surface_target.0, queue).with_camera(camera))
+        .finish(&mut sequences);
+    commands.insert_resource(Scene {
+        atlas,
+        entries,
+        queue,
+        camera,
+        pipeline: None,
+        sequence,
+        frame: 0,
+    });
+}
+
+/// An image with red from value and green from its inverse
+fn pattern(value: impl Fn(u32, u32) -> u32, width: u32, height: u32) -> Image {
+    let data = (0..width * height)
+        .flat_map(|i| {
+            let v = value(i % width, i / width).min(255) as u8;
+            [v, 255 - v, 128, 255]
+        })
+        .collect();
+    Image::from_raw_rgba8(width, height, data).unwrap()
+}
+
+#[derive(SystemParam)]
+struct Gpu<'w> {
+    device: Res<'w, DeviceRes>,
+    bundler: Res<'w, ShaderBundler>,
+    atlas_layout: Res<'w, AtlasGroupBindGroupLayout>,
+    camera_layout: Res<'w, CameraBindGroupLayout>,
+    surface_target: Res<'w, SurfaceTargetRes>,
+    render_targets: Res<'w, Assets<RenderTarget>>,
+}
+
+fn draw_sprites(
+    mut scene: ResMut<Scene>,
+    mut queues: ResMut<Assets<SpriteQueue>>,
+    mut pipelines: ResMut<Assets<SpritePipeline>>,
+    mut sequence_queue: ResMut<SequenceQueue>,
+    gpu: Gpu,
+) {
+    let pipeline = *scene.pipeline.get_or_insert_with(|| {
+        let target = gpu.render_targets.get(gpu.surface_target.0).unwrap();
+        let pipeline = SpritePipelineBuilder::new()
+            .build(
+                &gpu.device.0,
+                &gpu.bundler,
+                &gpu.atlas_layout,
+                &gpu.camera_layout,
+                target,
+            )
+            .unwrap();
+        pipelines.add(pipeline)
+    });
+    scene.frame += 1;
+    sequence_queue.schedule(scene.sequence);
+    let queue = queues.get_mut(scene.queue).unwrap();
+    queue.set_atlas(scene.atlas);
+    queue.set_pipeline(pipeline);
+    // submitted during Draw, so these are batched and drawn next frame
+    for i in 0..SPRITE_COUNT {
+        let angle =
+            i as f32 / SPRITE_COUNT as f32 * std::f32::consts::TAU + scene.frame as f32 * 0.01;
+        let transform = SpriteTransform::from_position([angle.cos() * 200.0, angle.sin() * 200.0])
+            .with_rotation(angle)
+            .with_scale([1.5, 1.5]);
+        let shade = i as f64 / SPRITE_COUNT as f64;
+        let style = SpriteStyle::tinted(Color {
+            r: 1.0,
+            g: 1.0,
+            b: 0.5 + shade * 0.5,
+            a: 1.0,
+        });
+        // every other sprite glows, which splits the sprites into a batch each
+        let style = if i % 2 == 0 {
+            style
+        } else {
+            style.with_blend(SpriteBlend::Additive).with_opacity(0.6)
+        };
+        // later sprites are drawn on top
+        queue.draw(
+            scene.entries[i % scene.entries.len()],
+            transform,
+            style,
+            i as f32,
+        );
+    }
+}
+
+fn spin_sprites(time: Res<Time>, mut sprites: Query<(&Spin, &mut Transform2D)>) {
+    for (spin, mut transform) in &mut sprites {
+        transform.rotation += spin.0 * time.delta_f32();
+    }
+}
+
+/// The camera is updated during PreDraw, so the new position is seen next frame
+fn move_camera(scene: Res<Scene>, time: Res<Time>, mut cameras: ResMut<Assets<Camera2D>>) {
+    let angle = time.elapsed_f32() * 0.5;
+    let camera = cameras.get_mut(scene.camera).unwrap();
+    camera.position = [angle.cos() * 60.0, angle.sin() * 40.0];
+}